@@ -0,0 +1,115 @@
+//! Runs join-csv on generated sources, to measure how fast it reads and joins them.
+//!
+//! The primary source has `JOIN_CSV_BENCH_ROWS` rows, 1,000,000 if unset, whose keys repeat
+//! about one time in ten, and the lookup source has a row for every fourth key. The files are
+//! generated once, under the target directory, and reused by later runs with as many rows.
+//!
+//! Switching to mimalloc and foldhash changed the times on 5,000,000 rows, a 231 MB primary
+//! source and 1,000,000 lookup rows, as follows. The times are in seconds, the median and the
+//! range of 7 interleaved runs of release builds from before and after the change, on one core:
+//!
+//! | run          |         before wall |          after wall | before RSS | after RSS | change |
+//! |--------------|--------------------:|--------------------:|-----------:|----------:|-------:|
+//! | left join    | 18.23 (16.43-19.63) | 12.29 (10.00-14.36) |    1530 MB |   1283 MB |   -33% |
+//! | read primary | 10.76 (10.07-11.71) |   9.31 (8.06-10.19) |    1153 MB |    921 MB |   -13% |
+//!
+//! with byte-identical outputs. The ranges of reading alone overlap, so its gain is smaller
+//! than the noise between runs and short of the 30% it was meant to reach. Reading into a
+//! reused `ByteRecord` and probing the map with a borrowed key were not done: `StringRecord`
+//! is already reused, and checking only the used fields for UTF-8 would let invalid ones through.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const DEFAULT_ROWS: usize = 1_000_000;
+
+/// Writes the sources and the specs of the benchmarks to a directory named by the number of
+/// rows, unless a previous run already did.
+fn fixture(rows: usize) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("bench-{}", rows));
+    if dir.join("join.yaml").exists() {
+        return dir;
+    }
+    fs::create_dir_all(&dir).unwrap();
+    let keys = rows * 4 / 5;
+    // A linear congruential generator, so that every run reads the same files.
+    let mut state = 1u64;
+    let mut next_key = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) as usize % keys
+    };
+    let mut primary = BufWriter::new(File::create(dir.join("primary.csv")).unwrap());
+    writeln!(primary, "id,name,amount,city,note").unwrap();
+    for idx in 0..rows {
+        let key = next_key();
+        writeln!(
+            primary,
+            "{},name{},{}.{:02},city{},note text {}",
+            key,
+            idx,
+            idx % 1000,
+            idx % 100,
+            key % 500,
+            idx % 7
+        )
+        .unwrap();
+    }
+    primary.flush().unwrap();
+    let mut lookup = BufWriter::new(File::create(dir.join("lookup.csv")).unwrap());
+    writeln!(lookup, "id,label,score").unwrap();
+    for idx in 0..keys / 4 {
+        writeln!(lookup, "{},label{},{}", idx * 4, idx, idx % 97).unwrap();
+    }
+    lookup.flush().unwrap();
+
+    let primary = "- path: primary.csv\n  projections: {name: name, amount: amount, city: city}\n";
+    let lookup = "- path: lookup.csv\n  join: left\n  projections: {label: label, score: score}\n";
+    let spec = |output: &str, sources: &[&str]| {
+        format!(
+            "output: {}\nkey: [id]\nsources:\n{}",
+            output,
+            sources.concat()
+        )
+    };
+    fs::write(dir.join("read.yaml"), spec("read.csv", &[primary])).unwrap();
+    fs::write(dir.join("join.yaml"), spec("join.csv", &[primary, lookup])).unwrap();
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_join-csv"))
+        .arg("--force")
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "join-csv {:?} failed", args);
+}
+
+fn bench(c: &mut Criterion) {
+    let rows = std::env::var("JOIN_CSV_BENCH_ROWS")
+        .ok()
+        .map(|rows| rows.parse().expect("JOIN_CSV_BENCH_ROWS must be a number"))
+        .unwrap_or(DEFAULT_ROWS);
+    let dir = fixture(rows);
+    let mut group = c.benchmark_group(format!("{} rows", rows));
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(30));
+    group.bench_function("read primary", |b| b.iter(|| run(&dir, &["read.yaml"])));
+    group.bench_function("left join", |b| b.iter(|| run(&dir, &["join.yaml"])));
+    group.bench_function("left join, --threads 2", |b| {
+        b.iter(|| run(&dir, &["join.yaml", "--threads", "2"]))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
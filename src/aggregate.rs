@@ -0,0 +1,148 @@
+//! Rows of a lookup source with `agg` projections, combined per key as they are read.
+
+use anyhow::{bail, Result};
+use indexmap::map::Entry;
+use indexmap::IndexMap;
+
+use crate::error::DataError;
+use crate::spec::{Aggregate, Source};
+use crate::{Data, Key, Projection};
+
+/// The value of one projection so far, over the rows of a key read.
+enum Accumulator {
+    /// The value of the last row, for projections without `agg`.
+    Last(String),
+    Count(usize),
+    Sum(Option<f64>),
+    /// The smallest or largest value, along with the value as read.
+    Min(Option<(f64, String)>),
+    Max(Option<(f64, String)>),
+    Avg {
+        sum: f64,
+        count: usize,
+    },
+}
+
+impl Accumulator {
+    fn new(agg: Option<Aggregate>) -> Self {
+        match agg {
+            None => Self::Last(String::new()),
+            Some(Aggregate::Count) => Self::Count(0),
+            Some(Aggregate::Sum) => Self::Sum(None),
+            Some(Aggregate::Min) => Self::Min(None),
+            Some(Aggregate::Max) => Self::Max(None),
+            Some(Aggregate::Avg) => Self::Avg { sum: 0.0, count: 0 },
+        }
+    }
+
+    /// Adds the value of a row, with `parse` to read it as a number.
+    fn add(&mut self, value: String, parse: impl FnOnce(&str) -> Result<f64>) -> Result<()> {
+        if let Self::Last(last) = self {
+            *last = value;
+            return Ok(());
+        }
+        if value.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Self::Last(_) => unreachable!(),
+            Self::Count(count) => *count += 1,
+            Self::Sum(sum) => *sum = Some(sum.unwrap_or(0.0) + parse(&value)?),
+            Self::Min(min) => {
+                let number = parse(&value)?;
+                if min.as_ref().is_none_or(|(min, _)| number < *min) {
+                    *min = Some((number, value));
+                }
+            }
+            Self::Max(max) => {
+                let number = parse(&value)?;
+                if max.as_ref().is_none_or(|(max, _)| number > *max) {
+                    *max = Some((number, value));
+                }
+            }
+            Self::Avg { sum, count } => {
+                *sum += parse(&value)?;
+                *count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// The combined value, empty if no row had a number to combine.
+    fn finish(self) -> String {
+        match self {
+            Self::Last(value) => value,
+            Self::Count(count) => count.to_string(),
+            Self::Sum(sum) => sum.map(|sum| sum.to_string()).unwrap_or_default(),
+            Self::Min(value) | Self::Max(value) => {
+                value.map(|(_, value)| value).unwrap_or_default()
+            }
+            Self::Avg { count: 0, .. } => String::new(),
+            Self::Avg { sum, count } => (sum / count as f64).to_string(),
+        }
+    }
+}
+
+/// The combined projections of each key of a source, in the order the keys were first read.
+pub struct Aggregates {
+    rows: IndexMap<Key, Vec<Accumulator>>,
+    /// The `agg` of each projection, with the names to report parse errors under.
+    aggs: Vec<(Option<Aggregate>, String)>,
+}
+
+impl Aggregates {
+    pub fn new(source: &Source) -> Self {
+        Self {
+            rows: IndexMap::new(),
+            aggs: source
+                .projections
+                .values()
+                .map(|col| (col.agg(), col.name().to_owned()))
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Adds the projection of a row to those of its key, returning whether the key is new.
+    pub fn insert(&mut self, key: Key, projection: Projection) -> Result<bool> {
+        let (accumulators, new) = match self.rows.entry(key) {
+            Entry::Occupied(entry) => (entry.into_mut(), false),
+            Entry::Vacant(entry) => {
+                // Values past the projections, such as the provenance, keep the last row's.
+                let accumulators = (0..projection.len())
+                    .map(|pos| Accumulator::new(self.aggs.get(pos).and_then(|(agg, _)| *agg)))
+                    .collect();
+                (entry.insert(accumulators), true)
+            }
+        };
+        for (pos, (accumulator, value)) in accumulators.iter_mut().zip(projection).enumerate() {
+            accumulator.add(value, |value| match value.trim().parse() {
+                Ok(number) => Ok(number),
+                Err(_) => {
+                    let (agg, name) = &self.aggs[pos];
+                    bail!(DataError(format!(
+                        "projection {}: cannot compute the {} of {:?}, which is not a number",
+                        name,
+                        agg.unwrap(),
+                        value
+                    )))
+                }
+            })?;
+        }
+        Ok(new)
+    }
+
+    /// The combined projection of each key.
+    pub fn finish(self) -> Data {
+        self.rows
+            .into_iter()
+            .map(|(key, accumulators)| {
+                let projection = accumulators.into_iter().map(Accumulator::finish).collect();
+                (key, projection)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,161 @@
+//! Data-quality assertions and schema types of the columns of a source, checked while the source
+//! is read.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use csv::StringRecord;
+
+use crate::error::{DataError, RecordError};
+use crate::spec::{AssertMode, Assertion, SchemaMode, Source};
+
+/// Number of violations of each assertion that are reported individually.
+const REPORTED_VIOLATIONS: usize = 20;
+
+/// The state of the assertions of a source, kept across all of its files.
+pub struct Checks<'s> {
+    source: &'s Source,
+    states: Vec<State>,
+    /// Number of values of each column of the schema that don't have its type.
+    mistyped: Vec<usize>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Values seen so far, for `unique` assertions.
+    seen: HashSet<String>,
+    violations: usize,
+}
+
+impl<'s> Checks<'s> {
+    pub fn new(source: &'s Source) -> Self {
+        let states = source.assertions.iter().map(|_| State::default()).collect();
+        Self {
+            source,
+            states,
+            mistyped: vec![0; source.schema.len()],
+        }
+    }
+
+    /// Checks a record of the file at `path`, given the positions of the asserted columns.
+    pub fn check(&mut self, path: &Path, idx: usize, record: &StringRecord, cols: &[usize]) {
+        for ((assertion, state), &col) in self
+            .source
+            .assertions
+            .iter()
+            .zip(&mut self.states)
+            .zip(cols)
+        {
+            let value = record.get(col).unwrap();
+            let Some(violation) = violation(assertion, state, value) else {
+                continue;
+            };
+            state.violations += 1;
+            if state.violations <= REPORTED_VIOLATIONS {
+                tracing::warn!(
+                    "{}: record {}: column {} {}",
+                    path.display(),
+                    idx,
+                    assertion.column,
+                    violation
+                );
+            }
+        }
+    }
+
+    /// Checks the values of a record against the schema, given the positions of its columns,
+    /// returning whether the record is kept.
+    pub fn check_schema(
+        &mut self,
+        path: &Path,
+        idx: usize,
+        record: &StringRecord,
+        cols: &[usize],
+    ) -> Result<bool> {
+        let mut keep = true;
+        for (((name, ty), mistyped), &col) in
+            self.source.schema.iter().zip(&mut self.mistyped).zip(cols)
+        {
+            let value = record.get(col).unwrap();
+            if ty.accepts(value) {
+                continue;
+            }
+            let err = RecordError::new(
+                path,
+                idx,
+                format!(
+                    "column {} has {:?}, which is not of type {}",
+                    name, value, ty
+                ),
+            )
+            .with_column(name);
+            if self.source.schema_mode == SchemaMode::Error {
+                bail!(err);
+            }
+            *mistyped += 1;
+            if *mistyped <= REPORTED_VIOLATIONS {
+                tracing::warn!("{}", err);
+            }
+            keep &= self.source.schema_mode != SchemaMode::SkipRow;
+        }
+        Ok(keep)
+    }
+
+    /// Reports the number of violations of each assertion, failing under `AssertMode::Error`
+    /// if there were any.
+    pub fn finish(self, mode: AssertMode) -> Result<()> {
+        let path = self.source.path.display();
+        let mut total = 0;
+        for (assertion, state) in self.source.assertions.iter().zip(&self.states) {
+            if state.violations == 0 {
+                continue;
+            }
+            total += state.violations;
+            tracing::warn!(
+                "{}: {} record(s) violate the assertions on column {}{}",
+                path,
+                state.violations,
+                assertion.column,
+                match state.violations > REPORTED_VIOLATIONS {
+                    true => format!(" (first {} shown)", REPORTED_VIOLATIONS),
+                    false => String::new(),
+                }
+            );
+        }
+        for ((name, ty), &mistyped) in self.source.schema.iter().zip(&self.mistyped) {
+            if mistyped > 0 {
+                tracing::warn!(
+                    "{}: {} value(s) of column {} are not of type {}",
+                    path,
+                    mistyped,
+                    name,
+                    ty
+                );
+            }
+        }
+        if total > 0 && mode == AssertMode::Error {
+            bail!(DataError(format!(
+                "{}: {} assertion violation(s)",
+                path, total
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Describes how the value violates the assertion, if it does.
+fn violation(assertion: &Assertion, state: &mut State, value: &str) -> Option<String> {
+    if assertion.not_empty && value.trim().is_empty() {
+        return Some("is empty".to_owned());
+    }
+    if let Some(allowed) = &assertion.one_of {
+        if !allowed.iter().any(|allowed| allowed == value) {
+            return Some(format!("has {:?}, not one of {:?}", value, allowed));
+        }
+    }
+    if assertion.unique && !state.seen.insert(value.to_owned()) {
+        return Some(format!("repeats {:?}", value));
+    }
+    None
+}
@@ -0,0 +1,231 @@
+//! Leaving out the rows that are the same as in a previous output, for `changed_since`.
+//!
+//! The previous output is read whole, keyed by its key columns. Each joined row is compared to
+//! the previous row of its key, cell by cell but for the ignored columns, and only written if
+//! it is new or changed. Once all rows are written, the keys of the previous output that no
+//! joined row had may be written as deleted rows.
+
+use std::collections::HashSet;
+
+use anyhow::{bail, Context, Result};
+use csv::StringRecord;
+use indexmap::IndexMap;
+
+use crate::error::RecordError;
+use crate::interrupt;
+use crate::logging::Logger;
+use crate::output::OutputWriter;
+use crate::spec::{ChangedSince, JoinSpec};
+use crate::{Key, Layout};
+
+const NEW: &str = "new";
+const CHANGED: &str = "changed";
+const DELETED: &str = "deleted";
+
+/// The previous output, and the counts of the rows compared to it.
+pub struct Changes<'s> {
+    options: &'s ChangedSince,
+    /// Written for absent cells, as the previous output has them.
+    null_value: &'s str,
+    /// The output header, followed by the `change_type` column if there is one.
+    header: Vec<String>,
+    key_cols: Vec<usize>,
+    /// Positions of the compared columns in output rows, with those in the previous output.
+    compared: Vec<(usize, usize)>,
+    /// Length of output rows, before the `change_type` column.
+    width: usize,
+    /// Whether output rows start with the row number.
+    numbered: bool,
+    /// The rows of the previous output by key, with whether a joined row had the key.
+    previous: IndexMap<Key, (StringRecord, bool)>,
+    /// Scratch space for rows with a change type.
+    row: Vec<Option<String>>,
+    new: usize,
+    changed: usize,
+    unchanged: usize,
+}
+
+impl<'s> Changes<'s> {
+    /// Reads the previous output of `changed_since`, if the spec has it, failing unless its
+    /// header is that of the output laid out by `layout`, but for the ignored columns and the
+    /// `change_type` column, which an earlier run with it wrote.
+    pub fn load(spec: &'s JoinSpec, layout: &Layout) -> Result<Option<Self>> {
+        let Some(options) = &spec.changed_since else {
+            return Ok(None);
+        };
+        let path = &options.path;
+        let mut reader = csv::Reader::from_path(path)
+            .with_context(|| format!("{}: cannot open previous output", path.display()))?;
+        let previous_header = reader.headers()?.clone();
+        let ignored: HashSet<&str> = options
+            .ignore
+            .iter()
+            .map(String::as_str)
+            .chain(spec.row_number.as_deref())
+            .chain(options.change_type.as_deref())
+            .collect();
+        let current: Vec<_> = (0..layout.header.len())
+            .filter(|&idx| !ignored.contains(layout.header[idx].as_str()))
+            .collect();
+        let previous: Vec<_> = (0..previous_header.len())
+            .filter(|&idx| !ignored.contains(&previous_header[idx]))
+            .collect();
+        let current_names: Vec<_> = current.iter().map(|&idx| &layout.header[idx]).collect();
+        let previous_names: Vec<_> = previous.iter().map(|&idx| &previous_header[idx]).collect();
+        if current_names != previous_names {
+            bail!(
+                "{}: without the ignored columns, the header of the previous output is {:?}, \
+                 but that of the output is {:?}",
+                path.display(),
+                previous_names,
+                current_names
+            );
+        }
+        let compared: Vec<_> = current.into_iter().zip(previous).collect();
+        let previous_key_cols: Vec<_> = layout
+            .key_cols
+            .iter()
+            .map(|key_col| {
+                compared
+                    .iter()
+                    .find(|(current, _)| current == key_col)
+                    .map(|&(_, previous)| previous)
+                    .with_context(|| {
+                        format!(
+                            "changed_since: key column {} cannot be ignored",
+                            layout.header[*key_col]
+                        )
+                    })
+            })
+            .collect::<Result<_>>()?;
+        let mut rows = IndexMap::new();
+        for (idx, record) in reader.records().enumerate() {
+            let record = record?;
+            let key: Vec<_> = previous_key_cols
+                .iter()
+                .map(|&col| record[col].to_owned())
+                .collect();
+            if rows.contains_key(&key) {
+                bail!(RecordError::new(
+                    path,
+                    idx + 1,
+                    format!(
+                        "key {:?} appears more than once in the previous output",
+                        key
+                    )
+                )
+                .with_key(&key));
+            }
+            rows.insert(key, (record, false));
+        }
+        let mut header = layout.header.clone();
+        header.extend(options.change_type.iter().cloned());
+        Ok(Some(Self {
+            options,
+            null_value: &spec.outputs()[0].options.null_value,
+            header,
+            key_cols: layout.key_cols.clone(),
+            compared,
+            width: layout.header.len(),
+            numbered: layout.numbered,
+            previous: rows,
+            row: Vec::new(),
+            new: 0,
+            changed: 0,
+            unchanged: 0,
+        }))
+    }
+
+    /// The header of the output, with the `change_type` column.
+    pub fn header(&self) -> &[String] {
+        &self.header
+    }
+
+    /// The row to write for an output row, with its change type if the output has one, or
+    /// `None` if it is the same as in the previous output.
+    pub fn filter<'r>(&'r mut self, row: &'r [Option<String>]) -> Option<&'r [Option<String>]> {
+        let key: Vec<_> = self
+            .key_cols
+            .iter()
+            .map(|&col| row[col].clone().unwrap_or_default())
+            .collect();
+        let change = match self.previous.get_mut(&key) {
+            None => {
+                self.new += 1;
+                NEW
+            }
+            Some((previous, seen)) => {
+                *seen = true;
+                let same = self.compared.iter().all(|&(current, prev)| {
+                    row[current].as_deref().unwrap_or(self.null_value) == &previous[prev]
+                });
+                if same {
+                    self.unchanged += 1;
+                    return None;
+                }
+                self.changed += 1;
+                CHANGED
+            }
+        };
+        if self.options.change_type.is_none() {
+            return Some(row);
+        }
+        self.row.clear();
+        self.row.extend_from_slice(row);
+        self.row.push(Some(change.to_owned()));
+        Some(&self.row)
+    }
+
+    /// Writes a row for each key of the previous output that no output row had, under
+    /// `emit_deleted`, with only the key columns and the row number. Keys not among
+    /// `only_keys` aren't read, so they aren't deleted.
+    pub fn write_deleted(
+        &self,
+        only_keys: Option<&HashSet<Key>>,
+        writers: &mut [OutputWriter],
+        written: &mut usize,
+    ) -> Result<()> {
+        if !self.options.emit_deleted {
+            return Ok(());
+        }
+        for key in self.deleted() {
+            if only_keys.is_some_and(|keys| !keys.contains(key)) {
+                continue;
+            }
+            let mut row = vec![None; self.width];
+            if self.numbered {
+                row[0] = Some((*written + 1).to_string());
+            }
+            for (&col, value) in self.key_cols.iter().zip(key) {
+                row[col] = Some(value.clone());
+            }
+            row.push(Some(DELETED.to_owned()));
+            interrupt::check(|| format!("writing {} row(s)", written))?;
+            for writer in writers.iter_mut() {
+                writer.write_record(&row)?;
+            }
+            *written += 1;
+        }
+        Ok(())
+    }
+
+    /// The keys of the previous output that no output row had.
+    fn deleted(&self) -> impl Iterator<Item = &Key> {
+        self.previous
+            .iter()
+            .filter(|(_, (_, seen))| !seen)
+            .map(|(key, _)| key)
+    }
+
+    /// Reports how many rows were new, changed, unchanged and deleted.
+    pub fn report(&self, log: &Logger) {
+        log.info(format_args!(
+            "{}: {} new, {} changed, {} unchanged and {} deleted row(s)",
+            self.options.path.display(),
+            self.new,
+            self.changed,
+            self.unchanged,
+            self.deleted().count()
+        ));
+    }
+}
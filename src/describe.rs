@@ -0,0 +1,179 @@
+//! Describing the output of a spec without joining it, for `--describe`.
+//!
+//! Only the header of the first file of each source is read: the key columns are named as in
+//! the primary source, and the columns of every source are resolved as a join would, so that a
+//! spec that describes fine fails no later than on its data. The output columns are laid out
+//! by the same code as those of a join.
+
+use std::borrow::Cow;
+use std::io::stdin;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::dictionary::Dictionaries;
+use crate::error::ErrorCollector;
+use crate::input::{self, RecordReader};
+use crate::logging::Logger;
+use crate::spec::{JoinSpec, JoinType};
+use crate::union::union_columns;
+use crate::{
+    joined_columns, output_key_header, presence_columns, with_read_retries, ColumnKind,
+    JoinedColumn, Layout, ReadContext, RecordParser,
+};
+
+/// The output of a spec, as printed.
+#[derive(Serialize)]
+struct Description<'a> {
+    /// The join type, or `cross` for a join without a key.
+    join: String,
+    /// The names of the key columns of the output.
+    key: Vec<&'a str>,
+    columns: Vec<Column<'a>>,
+}
+
+/// A column of the output, as described.
+#[derive(Serialize)]
+struct Column<'a> {
+    name: &'a str,
+    kind: ColumnKind,
+    /// The names of the sources its values come from; none for key columns, which come from
+    /// every source.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<Cow<'a, str>>,
+}
+
+impl<'a> Column<'a> {
+    fn new(spec: &'a JoinSpec, name: &'a str, kind: ColumnKind, sources: &[usize]) -> Self {
+        Self {
+            name,
+            kind,
+            sources: sources
+                .iter()
+                .map(|&idx| spec.sources[idx].name())
+                .collect(),
+        }
+    }
+}
+
+/// Prints the columns of the output of the spec, in order, as a JSON object on stdout, with
+/// the join and the names of the key columns.
+pub fn describe(spec: &JoinSpec) -> Result<()> {
+    let key_header = read_key_header(spec)?;
+    let joined = match spec.join {
+        JoinType::Union => union_columns(spec, key_header),
+        JoinType::Presence => presence_columns(spec, key_header),
+        _ => joined_columns(spec, key_header),
+    };
+    // The output of `join: presence` is not laid out, and already has its `row_number`.
+    let layout = match spec.join {
+        JoinType::Presence => None,
+        _ => {
+            let names: Vec<_> = joined.iter().map(|col| col.name.clone()).collect();
+            Some(Layout::new(spec, &names)?)
+        }
+    };
+    let mut columns = Vec::with_capacity(joined.len() + 2);
+    let key_cols = match &layout {
+        None => {
+            columns.extend(
+                joined
+                    .iter()
+                    .map(|col| Column::new(spec, &col.name, col.kind, &col.sources)),
+            );
+            let offset = usize::from(spec.row_number.is_some());
+            (offset..offset + spec.key.len()).collect()
+        }
+        Some(layout) => {
+            let mut names = layout.header.iter();
+            if layout.numbered {
+                let name = names.next().unwrap();
+                columns.push(Column::new(spec, name, ColumnKind::RowNumber, &[]));
+            }
+            for (group, name) in layout.groups.iter().zip(names) {
+                columns.push(laid_out(spec, &joined, &group.positions, name));
+            }
+            layout.key_cols.clone()
+        }
+    };
+    if let Some(name) = spec
+        .changed_since
+        .as_ref()
+        .and_then(|changed| changed.change_type.as_deref())
+    {
+        columns.push(Column::new(spec, name, ColumnKind::ChangeType, &[]));
+    }
+    let join = match spec.key.is_empty() && spec.join != JoinType::Union {
+        true => "cross".to_owned(),
+        false => spec.join.to_string(),
+    };
+    let description = Description {
+        join,
+        key: key_cols.iter().map(|&idx| columns[idx].name).collect(),
+        columns,
+    };
+    println!("{}", serde_json::to_string_pretty(&description)?);
+    Ok(())
+}
+
+/// The output column of the joined columns at `positions`, several if it coalesces them, which
+/// is of the kind of the first, and has the sources of them all.
+fn laid_out<'a>(
+    spec: &'a JoinSpec,
+    joined: &[JoinedColumn],
+    positions: &[usize],
+    name: &'a str,
+) -> Column<'a> {
+    let mut sources = Vec::new();
+    for &pos in positions {
+        for &idx in joined[pos].sources.iter() {
+            if !sources.contains(&idx) {
+                sources.push(idx);
+            }
+        }
+    }
+    Column::new(spec, name, joined[positions[0]].kind, &sources)
+}
+
+/// Opens the first file of each source, resolving its columns as reading it would, and returns
+/// the output header of the key columns.
+fn read_key_header(spec: &JoinSpec) -> Result<Vec<String>> {
+    let dictionaries = Dictionaries::load(spec)?;
+    let match_on: Vec<_> = spec.sources[1..]
+        .iter()
+        .flat_map(|source| source.match_on.iter().cloned())
+        .collect();
+    let errors = ErrorCollector::new(None);
+    let primary_ctx = ReadContext {
+        spec,
+        dictionaries: &dictionaries,
+        match_on: &match_on,
+        keep_all: spec.primary_passthrough,
+        only_keys: None,
+        errors: &errors,
+        reread: false,
+    };
+    let ctx = ReadContext {
+        match_on: &[],
+        keep_all: false,
+        ..primary_ctx
+    };
+    let log = Logger::new(0, false, None, &spec.key)?;
+    let mut key_header = Vec::new();
+    for (idx, source) in spec.sources.iter().enumerate() {
+        let ctx = match idx {
+            0 => &primary_ctx,
+            _ => &ctx,
+        };
+        let path = input::source_paths(source)?.remove(0);
+        let records = with_read_retries(spec, source, || match path.as_os_str() == "-" {
+            true => RecordReader::from_reader(source, stdin()),
+            false => RecordReader::open(source, &path),
+        })?;
+        let parser = RecordParser::new(source, &path, &records, ctx, &log)?;
+        if idx == 0 {
+            key_header = parser.key_header;
+        }
+    }
+    Ok(output_key_header(spec, key_header))
+}
@@ -0,0 +1,81 @@
+//! Mappings of coded values to labels, applied to projected columns.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use csv::Reader;
+
+use crate::spec::{JoinSpec, ValueLookup};
+
+/// The value mappings of all projection lookups, each loaded once no matter how many
+/// projections share it.
+#[derive(Default)]
+pub struct Dictionaries {
+    maps: HashMap<DictionaryKey, HashMap<String, String>>,
+}
+
+/// Lookups differing only in their `missing` policy share a mapping.
+type DictionaryKey = (std::path::PathBuf, String, String);
+
+fn dictionary_key(lookup: &ValueLookup) -> DictionaryKey {
+    (
+        lookup.path.clone(),
+        lookup.key.clone(),
+        lookup.value.clone(),
+    )
+}
+
+impl Dictionaries {
+    pub fn load(spec: &JoinSpec) -> Result<Self> {
+        let mut dictionaries = Self::default();
+        let lookups = spec
+            .sources
+            .iter()
+            .flat_map(|source| source.projections.values())
+            .filter_map(|col| col.lookup());
+        for lookup in lookups {
+            if let Entry::Vacant(entry) = dictionaries.maps.entry(dictionary_key(lookup)) {
+                entry.insert(load_map(lookup)?);
+            }
+        }
+        Ok(dictionaries)
+    }
+
+    pub fn get(&self, lookup: &ValueLookup) -> &HashMap<String, String> {
+        &self.maps[&dictionary_key(lookup)]
+    }
+}
+
+fn load_map(lookup: &ValueLookup) -> Result<HashMap<String, String>> {
+    let path = &lookup.path;
+    let mut reader = Reader::from_path(path)
+        .with_context(|| format!("{}: cannot open lookup file", path.display()))?;
+    let header = reader.headers()?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|col| col == name)
+            .with_context(|| format!("{}: missing column {}", path.display(), name))
+    };
+    let (key_idx, value_idx) = (column(&lookup.key)?, column(&lookup.value)?);
+
+    let mut map = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let key = record.get(key_idx).unwrap_or_default();
+        let value = record.get(value_idx).unwrap_or_default();
+        if let Some(existing) = map.insert(key.to_owned(), value.to_owned()) {
+            if existing != value {
+                bail!(
+                    "{}: {:?} maps to both {:?} and {:?}",
+                    path.display(),
+                    key,
+                    existing,
+                    value
+                );
+            }
+        }
+    }
+    Ok(map)
+}
@@ -0,0 +1,192 @@
+//! Comparison of two versions of a file by key, for the `diff` subcommand.
+//!
+//! Both files are read as the sources of a join on the key, projecting the columns they share, so
+//! that the last record of a key is the one compared, as in a join.
+
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{stdout, Write};
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use csv::Writer;
+use serde_yaml::{Mapping, Value};
+
+use crate::dictionary::Dictionaries;
+use crate::error::ErrorCollector;
+use crate::input::RecordReader;
+use crate::logging::Logger;
+use crate::spec::{JoinSpec, ProjectedColumn, ProjectionFrom, Source};
+use crate::table::Table;
+use crate::{read_file, Projection, ReadContext};
+
+/// Compares the records of `new` with those of `old` by key, writing a record for every key
+/// added, removed, or with values changed in `columns`, or in any shared column if empty.
+///
+/// Returns whether there are any differences.
+pub fn diff(
+    mut old: Source,
+    mut new: Source,
+    key: &[String],
+    columns: &[String],
+    output: Option<&Path>,
+) -> Result<bool> {
+    if key.is_empty() {
+        bail!("diff requires --key");
+    }
+    let old_header = RecordReader::open(&old, &old.path)?.header().clone();
+    let new_header = RecordReader::open(&new, &new.path)?.header().clone();
+    let shared: Vec<_> = old_header
+        .iter()
+        .filter(|col| !key.iter().any(|key| key == col) && new_header.iter().any(|new| new == *col))
+        .map(str::to_owned)
+        .collect();
+    for col in columns {
+        if !shared.contains(col) {
+            bail!(
+                "--columns: {} is not a column of both {} and {}, besides the key",
+                col,
+                old.path.display(),
+                new.path.display()
+            );
+        }
+    }
+    for source in [&mut old, &mut new] {
+        source.projections = shared
+            .iter()
+            .map(|col| {
+                (
+                    ProjectionFrom::Column(col.clone()),
+                    ProjectedColumn::Name(col.clone()),
+                )
+            })
+            .collect();
+    }
+
+    let mut spec = Mapping::new();
+    spec.insert("key".into(), key.to_vec().into());
+    spec.insert("sources".into(), Value::Sequence(Vec::new()));
+    spec.insert("output".into(), "-".into());
+    let mut spec: JoinSpec = serde_yaml::from_value(Value::Mapping(spec))?;
+    spec.sources = vec![old, new];
+    let dictionaries = Dictionaries::load(&spec)?;
+    let errors = ErrorCollector::new(None);
+    let ctx = ReadContext {
+        spec: &spec,
+        dictionaries: &dictionaries,
+        match_on: &[],
+        keep_all: false,
+        only_keys: None,
+        errors: &errors,
+        reread: false,
+    };
+    let log = Logger::new(0, false, None, &spec.key)?;
+    let (key_header, old, _) = read_file(&spec.sources[0], &ctx, 0, &log)?;
+    let (_, new, _) = read_file(&spec.sources[1], &ctx, old.memory(), &log)?;
+
+    // The positions in the projections of the columns compared.
+    let compared: Vec<_> = match columns.is_empty() {
+        true => (0..shared.len()).collect(),
+        false => columns
+            .iter()
+            .map(|col| shared.iter().position(|shared| shared == col).unwrap())
+            .collect(),
+    };
+    let mut changes = Vec::new();
+    let mut differing = vec![false; shared.len()];
+    for idx in 0..new.len() {
+        let key = new.key_at(idx)?;
+        match old.get_full(&key)? {
+            None => changes.push((Change::Added, None, Some(idx))),
+            Some((old_idx, old_projection)) => {
+                let new_projection = new.projection_at(idx)?;
+                let mut changed = false;
+                for &pos in compared.iter() {
+                    if old_projection[pos] != new_projection[pos] {
+                        differing[pos] = true;
+                        changed = true;
+                    }
+                }
+                if changed {
+                    changes.push((Change::Changed, Some(old_idx), Some(idx)));
+                }
+            }
+        }
+    }
+    for idx in 0..old.len() {
+        if new.get_full(&old.key_at(idx)?)?.is_none() {
+            changes.push((Change::Removed, Some(idx), None));
+        }
+    }
+
+    // Without --columns, only the columns changed in some record are output.
+    let output_cols: Vec<_> = match columns.is_empty() {
+        true => (0..shared.len()).filter(|&pos| differing[pos]).collect(),
+        false => compared,
+    };
+    let stream: Box<dyn Write> = match output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout()),
+    };
+    let mut writer = Writer::from_writer(stream);
+    let mut header = key_header;
+    header.push("change_type".to_owned());
+    for &pos in output_cols.iter() {
+        header.push(format!("{}_old", shared[pos]));
+        header.push(format!("{}_new", shared[pos]));
+    }
+    writer.write_record(&header)?;
+    let mut counts = [0usize; 3];
+    for (change, old_idx, new_idx) in changes.iter() {
+        counts[*change as usize] += 1;
+        let key = match new_idx {
+            Some(idx) => new.key_at(*idx)?,
+            None => old.key_at(old_idx.unwrap())?,
+        };
+        let old_projection = projection(&old, *old_idx)?;
+        let new_projection = projection(&new, *new_idx)?;
+        let mut row = key.into_owned();
+        row.push(change.to_string());
+        for &pos in output_cols.iter() {
+            for projection in [&old_projection, &new_projection] {
+                row.push(
+                    projection
+                        .as_ref()
+                        .map_or("", |values| &values[pos])
+                        .to_owned(),
+                );
+            }
+        }
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    eprintln!(
+        "{} added, {} removed, {} changed row(s)",
+        counts[Change::Added as usize],
+        counts[Change::Removed as usize],
+        counts[Change::Changed as usize]
+    );
+    Ok(!changes.is_empty())
+}
+
+fn projection(table: &Table, idx: Option<usize>) -> Result<Option<Cow<'_, Projection>>> {
+    idx.map(|idx| table.projection_at(idx)).transpose()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+        })
+    }
+}
@@ -0,0 +1,343 @@
+//! Classification of errors into exit codes, so that scripts can tell transient failures from
+//! ones that need the spec or the data fixed, and their report on stderr.
+
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Exit code of `diff` for files that differ.
+pub const EXIT_DIFFERENCES: u8 = 1;
+/// Exit code for an invalid spec, or one that doesn't fit the input files.
+pub const EXIT_SPEC: u8 = 2;
+/// Exit code for a failure to read or write a file or URL.
+pub const EXIT_IO: u8 = 3;
+/// Exit code for input data that violates a policy of the spec.
+pub const EXIT_DATA: u8 = 4;
+/// Exit code for a source or output whose row count is outside its `expect_rows`.
+pub const EXIT_EXPECTATION: u8 = 5;
+/// Exit code for a run stopped by SIGINT or SIGTERM, as shells report for SIGINT.
+pub const EXIT_INTERRUPTED: u8 = 130;
+/// Exit code for a bug, which panics or fails an internal check, as Rust exits after a panic.
+pub const EXIT_INTERNAL: u8 = 101;
+
+/// An error caused by the contents of an input rather than by the spec or the environment.
+#[derive(Debug)]
+pub struct DataError(pub String);
+
+impl Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for DataError {}
+
+/// A [`DataError`] in a record of an input, with where it was found, so that a JSON report
+/// can tell it in fields of its own.
+#[derive(Debug)]
+pub struct RecordError {
+    pub path: PathBuf,
+    /// Number of the record in its file, counting data records from 1.
+    pub record: usize,
+    /// The column whose value is at fault, if it is a single one.
+    pub column: Option<String>,
+    /// The key of the record, if it was read.
+    pub key: Option<Vec<String>>,
+    pub message: String,
+}
+
+impl RecordError {
+    pub fn new(path: &Path, record: usize, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_owned(),
+            record,
+            column: None,
+            key: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn with_column(mut self, column: impl Display) -> Self {
+        self.column = Some(column.to_string());
+        self
+    }
+
+    pub fn with_key(mut self, key: &[String]) -> Self {
+        self.key = Some(key.to_vec());
+        self
+    }
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: record {}: {}",
+            self.path.display(),
+            self.record,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+/// An error caused by a source or an output that doesn't meet the spec's expectations.
+#[derive(Debug)]
+pub struct ExpectationError(pub String);
+
+impl Display for ExpectationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ExpectationError {}
+
+/// A run stopped by a signal, with how far it got.
+#[derive(Debug)]
+pub struct Interrupted(pub String);
+
+impl Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "interrupted after {}; files being written were removed, but rows already \
+             appended or written to stdout were kept",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// A failed check of an invariant of join-csv itself, which is a bug rather than a fault of the
+/// spec or the data.
+#[derive(Debug)]
+pub struct InternalError(pub String);
+
+impl Display for InternalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "internal error, please report it as a bug: {}", self.0)
+    }
+}
+
+impl std::error::Error for InternalError {}
+
+/// The errors found under `error_mode: collect`, reported together as a numbered list.
+#[derive(Debug)]
+pub struct CollectedErrors {
+    pub errors: Vec<anyhow::Error>,
+    /// Whether reading stopped at `max_errors`, so that later errors weren't looked for.
+    pub stopped: bool,
+}
+
+impl Display for CollectedErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error(s)", self.errors.len())?;
+        if self.stopped {
+            f.write_str(", stopping at max_errors")?;
+        }
+        f.write_str(":")?;
+        for (idx, err) in self.errors.iter().enumerate() {
+            write!(f, "\n  {}. {:#}", idx + 1, err)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CollectedErrors {}
+
+/// Takes the errors found reading the sources under `error_mode: collect`, to report them
+/// together once reading is done; otherwise, each error is passed on as it is found.
+pub struct ErrorCollector {
+    /// The number of errors to stop at, if errors are collected.
+    limit: Option<usize>,
+    errors: Mutex<Vec<anyhow::Error>>,
+}
+
+impl ErrorCollector {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Takes an error to report later, failing with every error taken so far once there are
+    /// as many as the limit. Fails with the error itself if errors aren't collected, or if it
+    /// is an interruption or the failure of collecting.
+    pub fn add(&self, err: anyhow::Error) -> anyhow::Result<()> {
+        let Some(limit) = self.limit else {
+            return Err(err);
+        };
+        if err.is::<CollectedErrors>() || Category::of(&err) == Category::Interrupted {
+            return Err(err);
+        }
+        let mut errors = self.errors.lock().unwrap();
+        errors.push(err);
+        if errors.len() >= limit {
+            bail!(CollectedErrors {
+                errors: std::mem::take(&mut errors),
+                stopped: true,
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails with the errors taken, if there are any.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        let errors = std::mem::take(&mut *self.errors.lock().unwrap());
+        if !errors.is_empty() {
+            bail!(CollectedErrors {
+                errors,
+                stopped: false,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Whether an error was caused by a failure to read or write a file or URL.
+pub fn is_io(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if cause.is::<std::io::Error>() {
+            return true;
+        }
+        #[cfg(feature = "http")]
+        if cause.is::<ureq::Error>() {
+            return true;
+        }
+        #[cfg(feature = "sqlite")]
+        if cause.is::<rusqlite::Error>() {
+            return true;
+        }
+        cause
+            .downcast_ref::<csv::Error>()
+            .is_some_and(|err| err.is_io_error())
+    })
+}
+
+/// The kind of an error, which decides its exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Spec,
+    Io,
+    Data,
+    Expectation,
+    Interrupted,
+    /// A bug, reported from a panic or a failed internal check.
+    Internal,
+}
+
+impl Category {
+    /// The category of an error, based on the most specific cause in its chain; that of
+    /// collected errors is the category of the first one.
+    pub fn of(err: &anyhow::Error) -> Self {
+        if let Some(collected) = err.downcast_ref::<CollectedErrors>() {
+            return collected.errors.first().map_or(Self::Spec, Self::of);
+        }
+        if err.chain().any(|cause| cause.is::<InternalError>()) {
+            Self::Internal
+        } else if err.chain().any(|cause| cause.is::<Interrupted>()) {
+            Self::Interrupted
+        } else if err.chain().any(|cause| cause.is::<ExpectationError>()) {
+            Self::Expectation
+        } else if is_io(err) {
+            Self::Io
+        } else if err.chain().any(|cause| {
+            cause.is::<DataError>() || cause.is::<RecordError>() || cause.is::<csv::Error>()
+        }) {
+            Self::Data
+        } else {
+            Self::Spec
+        }
+    }
+
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Spec => EXIT_SPEC,
+            Self::Io => EXIT_IO,
+            Self::Data => EXIT_DATA,
+            Self::Expectation => EXIT_EXPECTATION,
+            Self::Interrupted => EXIT_INTERRUPTED,
+            Self::Internal => EXIT_INTERNAL,
+        }
+    }
+}
+
+/// The exit code for an error, based on the most specific cause in its chain.
+pub fn exit_code(err: &anyhow::Error) -> ExitCode {
+    ExitCode::from(Category::of(err).exit_code())
+}
+
+/// How a run that fails reports the error on stderr.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// The error and its causes, as text.
+    #[default]
+    Human,
+    /// One line with a JSON object; see [`report`].
+    Json,
+}
+
+/// Reports an error on stderr in the given format.
+///
+/// The JSON object has `category` (one of `spec`, `io`, `data`, `expectation`, `interrupted`
+/// or `internal`), `exit_code` and `message`, the error with its causes. An error in a record
+/// of an input also has `path`, `record` and, where they apply, `column` and `key`. Errors
+/// collected under `error_mode: collect` have `errors`, an object as above for each one. These
+/// fields keep their names and meanings; fields may be added.
+pub fn report(err: &anyhow::Error, format: ErrorFormat) {
+    if format == ErrorFormat::Human {
+        eprintln!("Error: {:?}", err);
+        return;
+    }
+    eprintln!("{}", json_report(err));
+}
+
+fn json_report(err: &anyhow::Error) -> Value {
+    let category = Category::of(err);
+    let mut report = json!({
+        "category": category,
+        "exit_code": category.exit_code(),
+        "message": format!("{:#}", err),
+    });
+    if let Some(err) = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<RecordError>())
+    {
+        report["path"] = json!(err.path.display().to_string());
+        report["record"] = json!(err.record);
+        if let Some(column) = &err.column {
+            report["column"] = json!(column);
+        }
+        if let Some(key) = &err.key {
+            report["key"] = json!(key);
+        }
+    }
+    if let Some(collected) = err.downcast_ref::<CollectedErrors>() {
+        report["errors"] = collected.errors.iter().map(json_report).collect();
+    }
+    report
+}
+
+/// Reports panics as JSON objects of category `internal`, instead of as text, for
+/// `--error-format json`.
+pub fn report_panics_as_json() {
+    std::panic::set_hook(Box::new(|info| {
+        let report = json!({
+            "category": Category::Internal,
+            "exit_code": EXIT_INTERNAL,
+            "message": info.to_string(),
+        });
+        eprintln!("{}", report);
+    }));
+}
@@ -0,0 +1,409 @@
+//! Small expressions for computing values from the columns of a record.
+//!
+//! An expression is a column name, a `'single-quoted'` string literal, an integer literal, or a
+//! function call such as `concat(store_id, '-', register_id)`. Column names that aren't plain
+//! identifiers can be written in double quotes.
+
+use std::fmt::{self, Display};
+
+use anyhow::{anyhow, bail, Result};
+use csv::StringRecord;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Func {
+    Concat,
+    Substr,
+    Trim,
+    Lpad,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "concat" => Some(Self::Concat),
+            "substr" => Some(Self::Substr),
+            "trim" => Some(Self::Trim),
+            "lpad" => Some(Self::Lpad),
+            _ => None,
+        }
+    }
+
+    fn arity(self) -> (usize, usize) {
+        match self {
+            Self::Concat => (1, usize::MAX),
+            Self::Substr => (2, 3),
+            Self::Trim => (1, 1),
+            Self::Lpad => (2, 3),
+        }
+    }
+}
+
+impl Display for Func {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Concat => "concat",
+            Self::Substr => "substr",
+            Self::Trim => "trim",
+            Self::Lpad => "lpad",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr<C = String> {
+    Column(C),
+    Literal(String),
+    Call(Func, Vec<Expr<C>>),
+}
+
+/// An expression whose columns have been resolved to positions in a record.
+pub type Compiled = Expr<usize>;
+
+impl Expr {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+        };
+        let expr = parser.expr()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            bail!("unexpected {} after end of expression", token);
+        }
+        Ok(expr)
+    }
+
+    /// Resolves column names to record positions using `resolve`.
+    pub fn compile(&self, resolve: &mut dyn FnMut(&str) -> Result<usize>) -> Result<Compiled> {
+        Ok(match self {
+            Self::Column(name) => Expr::Column(resolve(name)?),
+            Self::Literal(value) => Expr::Literal(value.clone()),
+            Self::Call(func, args) => Expr::Call(
+                *func,
+                args.iter()
+                    .map(|arg| arg.compile(resolve))
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+}
+
+impl Compiled {
+    pub fn eval(&self, record: &StringRecord) -> Result<String> {
+        match self {
+            Self::Column(idx) => Ok(record[*idx].to_owned()),
+            Self::Literal(value) => Ok(value.clone()),
+            Self::Call(func, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(record))
+                    .collect::<Result<Vec<_>>>()?;
+                call(*func, &args)
+            }
+        }
+    }
+}
+
+fn int_arg(func: Func, value: &str) -> Result<usize> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("{}: expected a non-negative integer, got {:?}", func, value))
+}
+
+fn call(func: Func, args: &[String]) -> Result<String> {
+    match func {
+        Func::Concat => Ok(args.concat()),
+        Func::Substr => {
+            let start = int_arg(func, &args[1])?;
+            if start == 0 {
+                bail!("substr: start position is 1-based");
+            }
+            let chars = args[0].chars().skip(start - 1);
+            Ok(match args.get(2) {
+                Some(len) => chars.take(int_arg(func, len)?).collect(),
+                None => chars.collect(),
+            })
+        }
+        Func::Trim => Ok(args[0].trim().to_owned()),
+        Func::Lpad => {
+            let width = int_arg(func, &args[1])?;
+            let pad = args.get(2).map_or(" ", String::as_str);
+            if pad.is_empty() {
+                bail!("lpad: padding must not be empty");
+            }
+            let len = args[0].chars().count();
+            if len >= width {
+                return Ok(args[0].clone());
+            }
+            let mut padded: String = pad.chars().cycle().take(width - len).collect();
+            padded.push_str(&args[0]);
+            Ok(padded)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(String),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(name) => write!(f, "identifier {}", name),
+            Self::Str(value) => write!(f, "string '{}'", value),
+            Self::Int(value) => write!(f, "number {}", value),
+            Self::LParen => f.write_str("'('"),
+            Self::RParen => f.write_str("')'"),
+            Self::Comma => f.write_str("','"),
+        }
+    }
+}
+
+/// Reads a quoted token whose opening `quote` has been consumed; doubling it escapes it.
+fn quoted(chars: &mut std::iter::Peekable<std::str::Chars>, quote: char) -> Result<String> {
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some(c) if c == quote => {
+                if chars.peek() == Some(&quote) {
+                    chars.next();
+                    value.push(quote);
+                } else {
+                    return Ok(value);
+                }
+            }
+            Some(c) => value.push(c),
+            None => bail!("unterminated {}-quoted token", quote),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    _ => Token::Comma,
+                });
+            }
+            '\'' | '"' => {
+                chars.next();
+                let value = quoted(&mut chars, c)?;
+                tokens.push(match c {
+                    '\'' => Token::Str(value),
+                    _ => Token::Ident(value),
+                });
+            }
+            c if c.is_ascii_digit() => {
+                let mut value = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    value.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Int(value));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(name));
+            }
+            c => bail!("unexpected character {:?}", c),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Str(value)) | Some(Token::Int(value)) => Ok(Expr::Literal(value)),
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::LParen) => {
+                self.pos += 1;
+                let func =
+                    Func::from_name(&name).ok_or_else(|| anyhow!("unknown function {}", name))?;
+                let args = self.args()?;
+                let (min, max) = func.arity();
+                if args.len() < min || args.len() > max {
+                    bail!("{} does not take {} argument(s)", func, args.len());
+                }
+                Ok(Expr::Call(func, args))
+            }
+            Some(Token::Ident(name)) => Ok(Expr::Column(name)),
+            Some(token) => bail!("unexpected {}", token),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+
+    fn args(&mut self) -> Result<Vec<Expr>> {
+        let mut args = Vec::new();
+        if self.peek() == Some(&Token::RParen) {
+            self.pos += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::RParen) => return Ok(args),
+                Some(token) => bail!("expected ',' or ')', found {}", token),
+                None => bail!("unterminated argument list"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str) -> Expr {
+        Expr::Column(name.to_owned())
+    }
+
+    fn literal(value: &str) -> Expr {
+        Expr::Literal(value.to_owned())
+    }
+
+    fn error(src: &str) -> String {
+        Expr::parse(src).unwrap_err().to_string()
+    }
+
+    /// Evaluates `src` on a record with columns `a`, `b` and `c`.
+    fn eval(src: &str, record: &[&str]) -> Result<String> {
+        let names = ["a", "b", "c"];
+        let mut resolve = |name: &str| {
+            names
+                .iter()
+                .position(|&col| col == name)
+                .ok_or_else(|| anyhow!("no column {}", name))
+        };
+        Expr::parse(src)?
+            .compile(&mut resolve)?
+            .eval(&StringRecord::from(record.to_vec()))
+    }
+
+    #[test]
+    fn parses_columns_and_literals() {
+        assert_eq!(Expr::parse("store_id").unwrap(), column("store_id"));
+        assert_eq!(Expr::parse(" \"store id\" ").unwrap(), column("store id"));
+        assert_eq!(Expr::parse("\"a\"\"b\"").unwrap(), column("a\"b"));
+        assert_eq!(Expr::parse("'it''s'").unwrap(), literal("it's"));
+        assert_eq!(Expr::parse("042").unwrap(), literal("042"));
+    }
+
+    #[test]
+    fn parses_calls() {
+        assert_eq!(
+            Expr::parse("CONCAT(store_id, '-', register_id)").unwrap(),
+            Expr::Call(
+                Func::Concat,
+                vec![column("store_id"), literal("-"), column("register_id")]
+            )
+        );
+        assert_eq!(
+            Expr::parse("lpad(trim(id), 5, '0')").unwrap(),
+            Expr::Call(
+                Func::Lpad,
+                vec![
+                    Expr::Call(Func::Trim, vec![column("id")]),
+                    literal("5"),
+                    literal("0"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn nested_calls_bind_to_their_own_arguments() {
+        assert_eq!(
+            Expr::parse("concat(substr(a, 2), b, trim(c))").unwrap(),
+            Expr::Call(
+                Func::Concat,
+                vec![
+                    Expr::Call(Func::Substr, vec![column("a"), literal("2")]),
+                    column("b"),
+                    Expr::Call(Func::Trim, vec![column("c")]),
+                ]
+            )
+        );
+        assert_eq!(
+            error("concat(substr(a, 2, b, c))"),
+            "substr does not take 4 argument(s)"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert_eq!(error(""), "unexpected end of expression");
+        assert_eq!(
+            error("a b"),
+            "unexpected identifier b after end of expression"
+        );
+        assert_eq!(error("concat(a"), "unterminated argument list");
+        assert_eq!(
+            error("concat(a b)"),
+            "expected ',' or ')', found identifier b"
+        );
+        assert_eq!(error("concat(, a)"), "unexpected ','");
+        assert_eq!(error("upper(a)"), "unknown function upper");
+        assert_eq!(error("trim()"), "trim does not take 0 argument(s)");
+        assert_eq!(error("'abc"), "unterminated '-quoted token");
+        assert_eq!(error("a + b"), "unexpected character '+'");
+    }
+
+    #[test]
+    fn evaluates_calls() {
+        let record = ["  7 ", "héllo", "x"];
+        assert_eq!(eval("concat(a, '-', c)", &record).unwrap(), "  7 -x");
+        assert_eq!(eval("substr(b, 2, 3)", &record).unwrap(), "éll");
+        assert_eq!(eval("substr(b, 4)", &record).unwrap(), "lo");
+        assert_eq!(eval("lpad(trim(a), 3, '0')", &record).unwrap(), "007");
+        assert_eq!(eval("lpad(b, 3)", &record).unwrap(), "héllo");
+        assert_eq!(eval("lpad(c, 4, 'ab')", &record).unwrap(), "abax");
+    }
+
+    #[test]
+    fn reports_invalid_arguments() {
+        let record = ["7", "abc", "x"];
+        let error = |src| eval(src, &record).unwrap_err().to_string();
+        assert_eq!(error("substr(b, 0)"), "substr: start position is 1-based");
+        assert_eq!(
+            error("substr(b, c)"),
+            "substr: expected a non-negative integer, got \"x\""
+        );
+        assert_eq!(error("lpad(b, 5, '')"), "lpad: padding must not be empty");
+        assert_eq!(error("trim(d)"), "no column d");
+    }
+}
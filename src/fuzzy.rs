@@ -0,0 +1,99 @@
+//! Fallback matching of keys that differ from a lookup source's keys only in trivial ways.
+//!
+//! Keys are first compared after normalization: casefolding, stripping punctuation, and
+//! collapsing whitespace. When a normalized key has no match, it may optionally be matched to
+//! a key within a bounded edit distance. Ambiguous matches are never guessed: a key only
+//! matches if exactly one row of the source qualifies.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::table::Table;
+
+/// Separates key components in a normalized key, so that they can't run into each other.
+const COMPONENT_SEPARATOR: char = '\u{1f}';
+
+pub struct FuzzyIndex {
+    /// Positions of the source rows by normalized key.
+    rows: HashMap<String, Vec<usize>>,
+    max_distance: usize,
+}
+
+impl FuzzyIndex {
+    pub fn new(table: &Table, max_distance: usize) -> Result<Self> {
+        let mut rows: HashMap<_, Vec<_>> = HashMap::new();
+        for idx in 0..table.len() {
+            rows.entry(normalize(&table.key_at(idx)?))
+                .or_default()
+                .push(idx);
+        }
+        Ok(Self { rows, max_distance })
+    }
+
+    /// The position of the only source row matching the key, if there is exactly one.
+    pub fn lookup(&self, key: &[String]) -> Option<usize> {
+        let key = normalize(key);
+        if let Some(rows) = self.rows.get(&key) {
+            return only(rows.iter().copied());
+        }
+        if self.max_distance == 0 {
+            return None;
+        }
+        let key: Vec<char> = key.chars().collect();
+        only(
+            self.rows
+                .iter()
+                .filter(|(candidate, _)| within_distance(&key, candidate, self.max_distance))
+                .flat_map(|(_, rows)| rows.iter().copied()),
+        )
+    }
+}
+
+fn only(mut rows: impl Iterator<Item = usize>) -> Option<usize> {
+    let row = rows.next()?;
+    rows.next().is_none().then_some(row)
+}
+
+fn normalize(key: &[String]) -> String {
+    let mut normalized = String::new();
+    for (pos, component) in key.iter().enumerate() {
+        if pos > 0 {
+            normalized.push(COMPONENT_SEPARATOR);
+        }
+        let component: String = component
+            .chars()
+            .filter(|c| !c.is_ascii_punctuation())
+            .flat_map(char::to_lowercase)
+            .collect();
+        for (word_idx, word) in component.split_whitespace().enumerate() {
+            if word_idx > 0 {
+                normalized.push(' ');
+            }
+            normalized.push_str(word);
+        }
+    }
+    normalized
+}
+
+/// Whether the Levenshtein distance between the strings is at most `max`.
+pub fn within_distance(a: &[char], b: &str, max: usize) -> bool {
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return false;
+    }
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, &ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        if curr.iter().min().is_some_and(|&min| min > max) {
+            return false;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()] <= max
+}
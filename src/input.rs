@@ -0,0 +1,281 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use csv::{Reader, ReaderBuilder, StringRecord};
+use encoding_rs::UTF_8;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use memmap2::Mmap;
+
+use crate::logging::ProgressKind;
+use crate::paths;
+use crate::spec::{Delimiter, Source};
+
+/// Iterates over the records of a source, regardless of its file format.
+pub struct RecordReader {
+    header: StringRecord,
+    records: Records,
+    /// Size of the input in bytes, if known.
+    len: u64,
+    /// The field separator, for CSV input.
+    delimiter: Option<u8>,
+}
+
+enum Records {
+    Csv(Reader<Box<dyn Read>>),
+    #[cfg(feature = "xlsx")]
+    Sheet {
+        total: usize,
+        rows: std::vec::IntoIter<StringRecord>,
+    },
+}
+
+impl RecordReader {
+    /// Opens one of the files of a source, as returned by [`source_paths`].
+    pub fn open(source: &Source, path: &Path) -> Result<Self> {
+        if let Some(url) = as_url(path) {
+            if is_spreadsheet(path) {
+                bail!("{}: spreadsheet sources cannot be read from a URL", url);
+            }
+            let (input, len) = open_url(url, source.timeout)?;
+            return Self::open_csv(source, input, len);
+        }
+        if is_spreadsheet(path) {
+            return Self::open_sheet(source, path);
+        }
+
+        let file = File::open(path).with_context(|| format!("{}: cannot open", path.display()))?;
+        let len = file.metadata()?.len();
+        if source.mmap {
+            // SAFETY: the mapping is only read, and the `mmap` option warns that a file changed
+            // while it is read may kill the process.
+            match unsafe { Mmap::map(&file) } {
+                // The mapping is owned by the reader, so it lives as long as the file is read.
+                Ok(map) => return Self::open_csv(source, Box::new(Cursor::new(map)), len),
+                Err(err) => tracing::warn!(
+                    "{}: warning: cannot memory-map, reading it instead: {}",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        Self::open_csv(source, Box::new(file), len)
+    }
+
+    /// Reads CSV from any reader, such as stdin or an in-memory buffer.
+    pub fn from_reader(source: &Source, input: impl Read + 'static) -> Result<Self> {
+        Self::open_csv(source, Box::new(input), 0)
+    }
+
+    fn open_csv(source: &Source, input: Box<dyn Read>, len: u64) -> Result<Self> {
+        let encoding = source.encoding()?;
+        let input: Box<dyn Read> = if encoding == UTF_8 {
+            input
+        } else {
+            Box::new(
+                DecodeReaderBytesBuilder::new()
+                    .encoding(Some(encoding))
+                    .build(input),
+            )
+        };
+        let (input, delimiter): (Box<dyn Read>, _) = match source.delimiter {
+            None => (input, b','),
+            Some(Delimiter::Byte(byte)) => (input, byte),
+            Some(Delimiter::Auto) => {
+                let mut input = BufReader::new(input);
+                let delimiter = sniff_delimiter(input.fill_buf()?);
+                (Box::new(input), delimiter)
+            }
+        };
+        let mut reader = ReaderBuilder::new().delimiter(delimiter).from_reader(input);
+        Self {
+            header: reader.headers()?.clone(),
+            records: Records::Csv(reader),
+            len,
+            delimiter: Some(delimiter),
+        }
+        .skip_rows(source.skip_rows)
+    }
+
+    #[cfg(feature = "xlsx")]
+    fn open_sheet(source: &Source, path: &Path) -> Result<Self> {
+        let mut rows = crate::xlsx::read_sheet(path, source.sheet.as_ref())?.into_iter();
+        let header = rows.next().unwrap_or_default();
+        Self {
+            header,
+            len: rows.len() as u64,
+            delimiter: None,
+            records: Records::Sheet {
+                total: rows.len(),
+                rows,
+            },
+        }
+        .skip_rows(source.skip_rows)
+    }
+
+    /// Discards the first records after the header, which may have any number of fields.
+    fn skip_rows(mut self, count: usize) -> Result<Self> {
+        let mut record = StringRecord::new();
+        for _ in 0..count {
+            match self.read_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) if matches!(err.kind(), csv::ErrorKind::UnequalLengths { .. }) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(self)
+    }
+
+    #[cfg(not(feature = "xlsx"))]
+    fn open_sheet(_source: &Source, path: &Path) -> Result<Self> {
+        bail!(
+            "{}: spreadsheet sources require join-csv to be built with the xlsx feature",
+            path.display()
+        );
+    }
+
+    pub fn header(&self) -> &StringRecord {
+        &self.header
+    }
+
+    /// The field separator, or `None` for spreadsheets.
+    pub fn delimiter(&self) -> Option<u8> {
+        self.delimiter
+    }
+
+    /// The unit and total amount of progress for reading the whole file.
+    pub fn progress_len(&self) -> (ProgressKind, u64) {
+        match &self.records {
+            Records::Csv(_) => (ProgressKind::Bytes, self.len),
+            #[cfg(feature = "xlsx")]
+            Records::Sheet { .. } => (ProgressKind::Rows, self.len),
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        match &self.records {
+            Records::Csv(reader) => reader.position().byte(),
+            #[cfg(feature = "xlsx")]
+            Records::Sheet { total, rows } => (total - rows.len()) as u64,
+        }
+    }
+
+    /// Reads the next record into `record`, reusing its allocation; `false` at the end of input.
+    pub fn read_record(&mut self, record: &mut StringRecord) -> csv::Result<bool> {
+        match &mut self.records {
+            Records::Csv(reader) => reader.read_record(record),
+            #[cfg(feature = "xlsx")]
+            Records::Sheet { rows, .. } => Ok(rows.next().map(|row| *record = row).is_some()),
+        }
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = csv::Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = StringRecord::new();
+        match self.read_record(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// The files making up a source. A path containing glob metacharacters, unless it names an
+/// existing file, is expanded to all the matching files in lexicographic order, which are read
+/// one after another as a single source.
+pub fn source_paths(source: &Source) -> Result<Vec<PathBuf>> {
+    if as_url(&source.path).is_some() {
+        return Ok(vec![source.path.clone()]);
+    }
+    if !is_glob_path(&source.path) {
+        return Ok(vec![source.path.clone()]);
+    }
+    let pattern = source.path.to_string_lossy();
+    let paths = glob::glob(&pattern)
+        .with_context(|| format!("{}: invalid glob pattern", pattern))?
+        .map(|path| path.map(|path| paths::os_path(&path).into_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if paths.is_empty() {
+        bail!("{}: no files match the pattern", pattern);
+    }
+    Ok(paths)
+}
+
+/// The path as a URL, if it is an HTTP(S) one.
+pub fn as_url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Starts a GET request, returning the streamed response body and its length, if known.
+#[cfg(feature = "http")]
+fn open_url(url: &str, timeout: Option<u64>) -> Result<(Box<dyn Read>, u64)> {
+    let mut agent = ureq::AgentBuilder::new();
+    if let Some(secs) = timeout {
+        agent = agent.timeout(std::time::Duration::from_secs(secs));
+    }
+    // ureq errors already name the URL.
+    let response = agent.build().get(url).call()?;
+    let len = response
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok())
+        .unwrap_or(0);
+    Ok((response.into_reader(), len))
+}
+
+#[cfg(not(feature = "http"))]
+fn open_url(url: &str, _timeout: Option<u64>) -> Result<(Box<dyn Read>, u64)> {
+    bail!(
+        "{}: URL sources require join-csv to be built with the http feature",
+        url
+    );
+}
+
+/// Delimiters considered by `delimiter: auto`, in order of preference for ties.
+pub const DELIMITER_CANDIDATES: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+/// The candidate delimiter occurring most often outside of quotes in the first line.
+fn sniff_delimiter(start: &[u8]) -> u8 {
+    let line = start
+        .split(|&byte| byte == b'\n')
+        .next()
+        .unwrap_or_default();
+    let mut counts = [0usize; DELIMITER_CANDIDATES.len()];
+    let mut quoted = false;
+    for &byte in line {
+        if byte == b'"' {
+            quoted = !quoted;
+        } else if let Some(idx) = DELIMITER_CANDIDATES.iter().position(|&c| c == byte) {
+            counts[idx] += usize::from(!quoted);
+        }
+    }
+    let mut best = 0;
+    for (idx, &count) in counts.iter().enumerate() {
+        if count > counts[best] {
+            best = idx;
+        }
+    }
+    DELIMITER_CANDIDATES[best]
+}
+
+/// Whether the path is a glob pattern. The `?` of a Windows extended-length prefix, `\\?\`,
+/// isn't a metacharacter, and a path naming an existing file, such as `data[1].csv`, is read as
+/// that file rather than expanded.
+pub fn is_glob_path(path: &Path) -> bool {
+    let glob = path.to_str().is_some_and(|path| {
+        let path = path.strip_prefix(r"\\?\").unwrap_or(path);
+        path.contains(['*', '?', '['])
+    });
+    glob && !path.exists()
+}
+
+/// Whether the path is that of a spreadsheet, read as one instead of as CSV.
+pub fn is_spreadsheet(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx"))
+}
@@ -0,0 +1,73 @@
+//! Stopping a run on SIGINT or SIGTERM, so that interrupting it leaves no partial output behind.
+//!
+//! The signal only sets a flag, which the read and write loops check between records; they then
+//! fail with [`Interrupted`], and the temporary output files are removed as the writers are
+//! dropped, as for any other error.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, Result};
+
+use crate::error::Interrupted;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn handle(signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+    // A second signal terminates right away, in case the run is stuck outside of the loops.
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// Installs the handler for SIGINT and SIGTERM; elsewhere than on Unix, signals keep their
+/// default behavior.
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Whether a signal was received.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Fails once a signal was received, with how far the run got.
+pub fn check(progress: impl FnOnce() -> String) -> Result<()> {
+    if interrupted() {
+        bail!(Interrupted(progress()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Category, EXIT_INTERRUPTED};
+
+    #[cfg(unix)]
+    #[test]
+    fn signal_fails_the_next_check() {
+        check(|| unreachable!()).unwrap();
+        install();
+        unsafe {
+            libc::raise(libc::SIGTERM);
+        }
+        assert!(interrupted());
+        let err = check(|| "writing 3 row(s)".to_owned()).unwrap_err();
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        install();
+        assert_eq!(
+            err.to_string(),
+            "interrupted after writing 3 row(s); files being written were removed, but rows \
+             already appended or written to stdout were kept"
+        );
+        assert_eq!(Category::of(&err), Category::Interrupted);
+        assert_eq!(Category::of(&err).exit_code(), EXIT_INTERRUPTED);
+    }
+}
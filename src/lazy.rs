@@ -0,0 +1,103 @@
+//! Reading the projections of a lookup source with `lazy_projections` only for the keys that
+//! are matched.
+//!
+//! Such a source is read as any other, each record parsed and checked, but its table keeps only
+//! where the last record of each key starts. When a primary row matches a key, the record is
+//! read again from there and parsed anew, so a large source that few primary rows match takes
+//! little memory, for a seek and a read per match.
+
+use std::fs::File;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use anyhow::{bail, Context, Result};
+use csv::{Position, Reader, ReaderBuilder, StringRecord};
+
+use crate::assertions::Checks;
+use crate::error::DataError;
+use crate::input::RecordReader;
+use crate::logging::Logger;
+use crate::manifest::InputFile;
+use crate::spec::Source;
+use crate::table::RowOffset;
+use crate::{Projection, ReadContext, RecordParser};
+
+/// The files of a lookup source with `lazy_projections`, open to read its records again.
+pub struct LazyProjections<'a> {
+    /// Each behind a lock, so that the workers of `--parallel-join` can share them.
+    files: Vec<Mutex<LazyFile<'a>>>,
+}
+
+struct LazyFile<'a> {
+    reader: Reader<File>,
+    parser: RecordParser<'a>,
+    /// Never finished, as the records read again were checked when first read.
+    checks: Checks<'a>,
+    log: Logger,
+    record: StringRecord,
+}
+
+impl<'a> LazyProjections<'a> {
+    /// Opens the files the source was read from, with a context that has `reread` set.
+    pub fn open(source: &'a Source, files: &[InputFile], ctx: &ReadContext<'a>) -> Result<Self> {
+        let files = files
+            .iter()
+            .map(|file| {
+                let log = Logger::new(0, false, None, &ctx.spec.key)?;
+                let records = RecordReader::open(source, &file.path)?;
+                let parser = RecordParser::new(source, &file.path, &records, ctx, &log)?;
+                // Positioned by seeking, past the header.
+                let reader = ReaderBuilder::new()
+                    .delimiter(records.delimiter().unwrap_or(b','))
+                    .has_headers(false)
+                    .from_path(&file.path)
+                    .with_context(|| format!("{}: cannot open", file.path.display()))?;
+                Ok(Mutex::new(LazyFile {
+                    reader,
+                    parser,
+                    checks: Checks::new(source),
+                    log,
+                    record: StringRecord::new(),
+                }))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { files })
+    }
+
+    /// Reads the projection of the record at `offset` again, which must still be of `key`.
+    pub fn read(&self, offset: RowOffset, key: &[String]) -> Result<Projection> {
+        let mut file = self.file(offset.file);
+        let file = &mut *file;
+        let path = file.parser.path.display().to_string();
+        let changed = || {
+            DataError(format!(
+                "{}: record {} at byte {} no longer reads as it did; the file must not change \
+                 until the join is written, under lazy_projections",
+                path, offset.record, offset.byte
+            ))
+        };
+        let mut position = Position::new();
+        position.set_byte(offset.byte);
+        file.reader
+            .seek(position)
+            .with_context(|| format!("{}: cannot seek to byte {}", path, offset.byte))?;
+        let record = match file.reader.read_record(&mut file.record) {
+            Ok(true) => Ok(&file.record),
+            Ok(false) => bail!(changed()),
+            Err(err) => Err(err),
+        };
+        let parsed = file
+            .parser
+            .parse(offset.record, record, &mut file.checks, &file.log)
+            .with_context(changed)?;
+        match parsed {
+            Some((read_key, projection)) if read_key == key => Ok(projection),
+            _ => bail!(changed()),
+        }
+    }
+
+    fn file(&self, idx: usize) -> MutexGuard<'_, LazyFile<'a>> {
+        self.files[idx]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{stderr, IsTerminal};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use csv::Writer;
+use indicatif::{ProgressBar, ProgressStyle};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+
+use crate::spec::KeyColumn;
+
+const MAX_LOGGED_DROPS: usize = 1000;
+
+/// The events written when neither `--log` nor `RUST_LOG` says otherwise: the warnings of the
+/// run, and the progress of `--watch`.
+const DEFAULT_FILTER: &str = "warn,join_csv::watch=info";
+
+/// Writes the events of a run to stderr, up to `level`, or as `RUST_LOG` says if `level` is
+/// omitted. Without either, only the events of [`DEFAULT_FILTER`] are written, each as its
+/// message alone, and the others cost only a check that nothing receives them.
+pub fn init_tracing(level: Option<LevelFilter>) {
+    let filter = match level {
+        Some(level) => EnvFilter::default().add_directive(level.into()),
+        None if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() => EnvFilter::from_default_env(),
+        None => {
+            tracing_subscriber::fmt()
+                .with_env_filter(EnvFilter::new(DEFAULT_FILTER))
+                .with_writer(stderr)
+                .without_time()
+                .with_level(false)
+                .with_target(false)
+                .init();
+            return;
+        }
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(stderr)
+        .init();
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DropReason {
+    Unparseable,
+    Duplicate,
+    EmptyKey,
+    OversizedCell,
+    Mistyped,
+    InvalidLatest,
+    Unmatched,
+}
+
+impl Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Unparseable => "unparseable",
+            Self::Duplicate => "duplicate discarded",
+            Self::EmptyKey => "empty key",
+            Self::OversizedCell => "oversized cell",
+            Self::Mistyped => "value not of the schema type",
+            Self::InvalidLatest => "invalid latest_by value",
+            Self::Unmatched => "key_regex not matched",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ProgressKind {
+    Bytes,
+    Rows,
+}
+
+/// Reports on the rows of a run. It is shared by the threads that read sources with
+/// `--threads`, so the records they drop are logged in the order they are dropped.
+pub struct Logger {
+    verbosity: u8,
+    progress: bool,
+    key_len: usize,
+    drops: Mutex<Drops>,
+}
+
+/// The dropped records logged so far.
+struct Drops {
+    logged: usize,
+    suppressed: usize,
+    writer: Option<Writer<File>>,
+}
+
+impl Logger {
+    pub fn new(
+        verbosity: u8,
+        progress: bool,
+        log_dropped: Option<&Path>,
+        key_spec: &[KeyColumn],
+    ) -> Result<Self> {
+        let dropped_writer = match log_dropped {
+            Some(path) => {
+                let mut writer = Writer::from_path(path)?;
+                let mut header: Vec<_> = ["source", "record", "reason"]
+                    .into_iter()
+                    .map(str::to_owned)
+                    .collect();
+                header.extend(key_spec.iter().map(KeyColumn::to_string));
+                writer.write_record(&header)?;
+                Some(writer)
+            }
+            None => None,
+        };
+        Ok(Self {
+            verbosity,
+            progress: progress && stderr().is_terminal(),
+            key_len: key_spec.len(),
+            drops: Mutex::new(Drops {
+                logged: 0,
+                suppressed: 0,
+                writer: dropped_writer,
+            }),
+        })
+    }
+
+    pub fn info(&self, msg: fmt::Arguments) {
+        if self.verbosity >= 1 {
+            eprintln!("{}", msg);
+        }
+    }
+
+    pub fn progress_bar(&self, kind: ProgressKind, len: u64, msg: String) -> ProgressBar {
+        if !self.progress {
+            return ProgressBar::hidden();
+        }
+        let template = match kind {
+            ProgressKind::Bytes => "{msg} [{bar:40}] {bytes}/{total_bytes} ({eta})",
+            ProgressKind::Rows => "{msg} [{bar:40}] {pos}/{len} ({eta})",
+        };
+        ProgressBar::new(len)
+            .with_style(ProgressStyle::with_template(template).unwrap())
+            .with_message(msg)
+    }
+
+    pub fn dropped(
+        &self,
+        source: &Path,
+        record: usize,
+        key: Option<&[String]>,
+        reason: DropReason,
+    ) -> Result<()> {
+        let mut drops = self.drops.lock().unwrap();
+        if let Some(writer) = &mut drops.writer {
+            let mut row = vec![
+                source.to_string_lossy().into_owned(),
+                record.to_string(),
+                reason.to_string(),
+            ];
+            match key {
+                Some(key) => row.extend_from_slice(key),
+                None => row.resize(row.len() + self.key_len, String::new()),
+            }
+            writer.write_record(&row)?;
+        }
+
+        if self.verbosity < 2 {
+            return Ok(());
+        }
+        if drops.logged >= MAX_LOGGED_DROPS {
+            drops.suppressed += 1;
+            return Ok(());
+        }
+        drops.logged += 1;
+        match key {
+            Some(key) => eprintln!(
+                "{}: dropped record {} with key {:?}: {}",
+                source.display(),
+                record,
+                key,
+                reason
+            ),
+            None => eprintln!(
+                "{}: dropped record {}: {}",
+                source.display(),
+                record,
+                reason
+            ),
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> Result<()> {
+        let mut drops = self.drops.lock().unwrap();
+        if drops.suppressed > 0 {
+            eprintln!(
+                "{} more dropped record(s) not shown; use --log-dropped to capture all",
+                drops.suppressed
+            );
+        }
+        if let Some(writer) = &mut drops.writer {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
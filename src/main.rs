@@ -1,127 +1,3483 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{stdin, Read};
+/// Sources are read and joined as many small strings, which mimalloc allocates and frees
+/// faster than the system allocator.
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+mod aggregate;
+mod assertions;
+mod changes;
+mod describe;
+mod dictionary;
+mod diff;
+mod error;
+mod expr;
+mod fuzzy;
+mod input;
+mod interrupt;
+mod lazy;
+mod logging;
+mod manifest;
+mod merge;
+mod output;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod paths;
+mod profile;
+mod ranges;
+mod spec;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod table;
+mod union;
+mod watch;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::stdin;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::panic;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use csv::StringRecord;
+use indexmap::{IndexMap, IndexSet};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::aggregate::Aggregates;
+use crate::assertions::Checks;
+use crate::changes::Changes;
+use crate::dictionary::Dictionaries;
+use crate::error::{
+    Category, DataError, ErrorCollector, ErrorFormat, ExpectationError, InternalError, RecordError,
+};
+use crate::expr::{Compiled, Expr};
+use crate::fuzzy::FuzzyIndex;
+use crate::input::RecordReader;
+use crate::lazy::LazyProjections;
+use crate::logging::{DropReason, Logger, ProgressKind};
+use crate::manifest::InputFile;
+use crate::output::OutputWriter;
+use crate::ranges::Ranges;
+use crate::spec::{
+    load_column_map, load_spec, ByteSize, CellLimit, CollapsedKey, ColumnRef, CoverageCheck,
+    Delimiter, EmptyKeyPolicy, EmptyPolicy, InvalidLatest, JoinSpec, JoinType, KeyColumn, KeyType,
+    LatestBy, MissingLookup, NoMatchPolicy, OutputTarget, OversizePolicy, ParseErrorPolicy,
+    ProjectedColumn, ProjectionFrom, Source, TypeErrorPolicy, Unmapped, ValueLookup,
+};
+use crate::table::Table;
+
+#[derive(Debug, Clone, Parser)]
+#[command(about = "Join CSV files on a common key")]
+#[command(
+    after_help = "Exit status: 0 on success, 2 for an invalid spec or one that doesn't fit \
+    the input (e.g. a missing column), 3 for I/O errors (e.g. a missing file), 4 for input data \
+    that violates a policy of the spec (e.g. an empty key under empty_keys: error), 5 for a \
+    source or output row count outside of its expect_rows, or no rows under empty_output: \
+    error."
+)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the join spec; read from stdin if omitted
+    spec: Option<PathBuf>,
+
+    /// Increase log verbosity (-v for progress, -vv for dropped rows)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Log the steps of the run to stderr, with their row counts and durations, up to LEVEL:
+    /// off, error, warn, info, debug or trace; as RUST_LOG says if omitted, or only warnings,
+    /// each as a plain line, without it. Warnings are logged at warn, so off and error hide them
+    #[arg(long, value_name = "LEVEL")]
+    log: Option<tracing_subscriber::filter::LevelFilter>,
+
+    /// How to report a failure on stderr: as text, or as one line with a JSON object with its
+    /// category, exit code and message, and the path, record, column and key of a record at
+    /// fault
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    error_format: ErrorFormat,
+
+    /// Write every dropped record, with the reason, to a CSV file
+    #[arg(long, value_name = "PATH")]
+    log_dropped: Option<PathBuf>,
+
+    /// Show progress bars while reading and writing (only when stderr is a terminal)
+    #[arg(long)]
+    progress: bool,
+
+    /// Write the output to PATH instead of the spec's output; `-` writes to stdout
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Fail once the rows read from all sources take up more than about SIZE of memory (e.g.
+    /// 4G); overrides the spec's max_memory
+    #[arg(long, value_name = "SIZE")]
+    max_memory: Option<ByteSize>,
+
+    /// Look up columns missing from a source's header under the aliases in the column map at
+    /// PATH; overrides the spec's column_map
+    #[arg(long, value_name = "PATH")]
+    column_map: Option<PathBuf>,
+
+    /// Value of a parameter declared under the spec's params, replacing {{NAME}} in its
+    /// string fields; may be repeated
+    #[arg(long = "param", value_name = "NAME=VALUE", value_parser = parse_param)]
+    params: Vec<(String, String)>,
+
+    /// Timeout in seconds for fetching URL sources that don't set their own timeout
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
 
-use anyhow::Result;
-use csv::{Reader, Writer};
-use indexmap::IndexMap;
-use serde::Deserialize;
-use serde_yaml::from_reader;
+    /// Fail instead of fetching sources whose path is an HTTP(S) URL
+    #[arg(long)]
+    no_network: bool,
 
-#[derive(Debug, Deserialize)]
-struct JoinSpec {
-    key: Vec<String>,
-    sources: Vec<Source>,
-    output: PathBuf,
+    /// Write a JSON manifest of the run, with the inputs and their hashes, to PATH on success
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<PathBuf>,
+
+    /// Print the effective spec, with all includes merged, and exit without joining
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print the columns of the output as JSON, with what each holds and the sources it comes
+    /// from, and exit without joining; only the headers of the sources are read
+    #[arg(long)]
+    describe: bool,
+
+    /// After joining, watch the spec and the files of its sources, and join again whenever
+    /// one changes, until Ctrl-C
+    #[arg(long)]
+    watch: bool,
+
+    /// Make the outputs and the manifest depend only on the spec and the inputs, for
+    /// checksumming: the manifest leaves out the times of the run and of the input files.
+    ///
+    /// The outputs already depend on nothing else, so no optimization is disabled: sources read
+    /// with --threads are each read into a table of its own, the rows of each are kept in the
+    /// order they are read, header names are only looked up, and --parallel-join puts the rows
+    /// back in the order of the primary source before writing them
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Create missing directories of the outputs, as if every output set create_dirs
+    #[arg(long)]
+    mkdir: bool,
+
+    /// Replace outputs that already exist, as if every output set overwrite
+    #[arg(long)]
+    force: bool,
+
+    /// Read up to N sources at once, each on a thread of its own, into the same join as one
+    /// after another. The warnings, dropped records and errors collected under error_mode:
+    /// collect of different sources may be reported in another order, and the progress bars of
+    /// sources read at once share the terminal. Sources are still read one after another under
+    /// max_memory, and a sorted join or join: union reads them as it writes
+    #[arg(long, value_name = "N")]
+    threads: Option<NonZeroUsize>,
+
+    /// Join the primary rows with the lookup sources on N threads, writing the same output as
+    /// on one; only applies to a keyed join that isn't sorted or join: presence
+    #[arg(long, value_name = "N")]
+    parallel_join: Option<NonZeroUsize>,
+
+    /// Only read the rows with this key, for spot-checking; may be repeated. The components of
+    /// a composite key are separated by --key-sep
+    #[arg(long, value_name = "KEY")]
+    only_key: Vec<String>,
+
+    /// Only read the rows with the keys listed in the CSV file at PATH, with a column per key
+    /// component, as well as those of --only-key
+    #[arg(long, value_name = "PATH")]
+    only_keys_file: Option<PathBuf>,
+
+    /// Separator of the components of the composite keys of --only-key
+    #[arg(long, value_name = "SEP")]
+    key_sep: Option<String>,
+
+    /// Comma-separated sources to read, by name, path or zero-based position, leaving out the
+    /// others and their output columns, for debugging; must include the primary source
+    #[arg(long, value_delimiter = ',', value_name = "SOURCES")]
+    sources: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Source {
-    path: PathBuf,
-    projections: IndexMap<String, String>,
+/// Splits a `--param` into its name and value at the first `=`.
+fn parse_param(param: &str) -> Result<(String, String), String> {
+    match param.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+        _ => Err(format!("expected NAME=VALUE, got {:?}", param)),
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// List the header names of a file with their zero-based indices, and count its records
+    Columns {
+        /// CSV or spreadsheet file to describe
+        path: PathBuf,
+
+        /// Text encoding of the file (e.g. latin1); UTF-8 if omitted
+        #[arg(long)]
+        encoding: Option<String>,
+    },
+    /// Print statistics of each column of a file, and whether a key is unique in it
+    Profile {
+        /// CSV or spreadsheet file to profile
+        path: PathBuf,
+
+        /// Field separator of the file, or auto to guess it; a comma if omitted
+        #[arg(long, value_parser = |value: &str| Delimiter::try_from(value.to_owned()))]
+        delimiter: Option<Delimiter>,
+
+        /// Text encoding of the file (e.g. latin1); UTF-8 if omitted
+        #[arg(long)]
+        encoding: Option<String>,
+
+        /// Comma-separated columns whose combination is checked for uniqueness
+        #[arg(long, value_delimiter = ',')]
+        key: Vec<String>,
+
+        /// Print the statistics as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare two versions of a file by key, writing the records added, removed or changed;
+    /// exits with 1 if there are any
+    Diff {
+        /// Comma-separated columns identifying the records to compare
+        #[arg(long, value_delimiter = ',', required = true)]
+        key: Vec<String>,
+
+        /// The earlier version of the file
+        old: PathBuf,
+
+        /// The later version of the file
+        new: PathBuf,
+
+        /// Comma-separated columns to compare and output; every column of both files if
+        /// omitted, of which only those changed in some record are output
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<String>,
+
+        /// Field separator of both files, or auto to guess it; a comma if omitted
+        #[arg(long, value_parser = |value: &str| Delimiter::try_from(value.to_owned()))]
+        delimiter: Option<Delimiter>,
+
+        /// Text encoding of both files (e.g. latin1); UTF-8 if omitted
+        #[arg(long)]
+        encoding: Option<String>,
+
+        /// File to write the differences to; stdout if omitted
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
 }
 
 type Key = Vec<String>;
 type Projection = Vec<String>;
-type Data = IndexMap<Key, Projection>;
-type JoinInput = Vec<Data>;
+/// The rows of a source by key, hashed with foldhash, which is faster than the default SipHash
+/// for short keys. The order of the rows is that of insertion, whatever the hasher.
+type Data = IndexMap<Key, Projection, foldhash::fast::RandomState>;
+/// The positions of every occurrence of each header name in a file.
+type Headers = IndexMap<String, Vec<usize>>;
+
+/// The data of all sources: the primary one, which drives the output, and the lookup sources.
+struct JoinInput {
+    primary: Primary,
+    lookups: Vec<Table>,
+    /// The files read for each source.
+    files: Vec<Vec<InputFile>>,
+    /// The value mappings the sources were read with, to read the records of those with
+    /// `lazy_projections` again.
+    dictionaries: Dictionaries,
+}
+
+/// The rows of the primary source.
+enum Primary {
+    /// The last row read of each key.
+    Keyed(Data),
+    /// Every row as read, with `primary_passthrough`.
+    All(Vec<(Key, Projection)>),
+}
+
+impl Primary {
+    fn len(&self) -> usize {
+        match self {
+            Self::Keyed(data) => data.len(),
+            Self::All(rows) => rows.len(),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Key, &Projection)> + '_> {
+        match self {
+            Self::Keyed(data) => Box::new(data.iter()),
+            Self::All(rows) => Box::new(rows.iter().map(|(key, projection)| (key, projection))),
+        }
+    }
+
+    /// The row at `idx`, in the order of `iter`.
+    fn get(&self, idx: usize) -> (&Key, &Projection) {
+        match self {
+            Self::Keyed(data) => data.get_index(idx).unwrap(),
+            Self::All(rows) => {
+                let (key, projection) = &rows[idx];
+                (key, projection)
+            }
+        }
+    }
+
+    /// The rows by key, which the join modes other than a keyed join with `primary_passthrough`
+    /// read the primary source as.
+    fn keyed(&self) -> &Data {
+        match self {
+            Self::Keyed(data) => data,
+            Self::All(_) => unreachable!("primary_passthrough is only supported by a keyed join"),
+        }
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&Key) -> bool) {
+        match self {
+            Self::Keyed(data) => data.retain(|key, _| keep(key)),
+            Self::All(rows) => rows.retain(|(key, _)| keep(key)),
+        }
+    }
+}
+
+const PROGRESS_INTERVAL: usize = 1024;
 
-fn read_file<'k, 'p>(
+fn resolve_column(
     path: &Path,
-    key_spec: impl Iterator<Item = &'k str>,
-    proj_spec: impl Iterator<Item = &'p str>,
-) -> Result<Data> {
-    let mut reader = Reader::from_path(path)?;
+    headers: &Headers,
+    aliases: &HashMap<String, Vec<String>>,
+    col: &str,
+) -> Result<usize> {
+    let variants = aliases.get(col).into_iter().flatten().map(String::as_str);
+    let mut matches = std::iter::once(col)
+        .chain(variants)
+        .filter_map(|name| headers.get(name).map(|positions| (name, positions)));
+    let (found, positions) = matches.next().ok_or_else(|| {
+        // A file with another delimiter parses as a single column holding the whole header.
+        let delimiter = match headers.keys().collect::<Vec<_>>()[..] {
+            [only] => input::DELIMITER_CANDIDATES[1..]
+                .iter()
+                .find(|&&byte| only.contains(char::from(byte))),
+            _ => None,
+        };
+        let hint = match delimiter {
+            Some(&byte) => format!(
+                "; header appears to be delimited by {:?}, set delimiter on this source",
+                char::from(byte)
+            ),
+            None => String::new(),
+        };
+        anyhow!(
+            "{}: missing column {}; headers found: {:?}{}",
+            path.display(),
+            col,
+            headers.keys().collect::<Vec<_>>(),
+            hint
+        )
+    })?;
+    if let Some((other, _)) = matches.next() {
+        bail!(
+            "{}: column {} is ambiguous, matched both {} and {}",
+            path.display(),
+            col,
+            found,
+            other
+        );
+    }
+    if positions.len() > 1 {
+        bail!(
+            "{}: column {} appears at positions {:?}; use disambiguate to pick one",
+            path.display(),
+            found,
+            positions
+        );
+    }
+    Ok(positions[0])
+}
 
-    let headers: HashMap<_, _> = reader
-        .headers()?
-        .into_iter()
-        .enumerate()
-        .map(|(idx, col)| (col.to_owned(), idx))
+/// Logs the aliases that columns missing from the header of a file are read from.
+fn log_aliases(source: &Source, path: &Path, headers: &Headers, log: &Logger) {
+    let mut found: Vec<_> = source
+        .aliases
+        .iter()
+        .filter(|(col, _)| !headers.contains_key(col.as_str()))
+        .filter_map(|(col, aliases)| {
+            let alias = aliases.iter().find(|alias| headers.contains_key(*alias))?;
+            Some((col, alias))
+        })
         .collect();
-    let key_idx: Vec<_> = key_spec
+    found.sort();
+    for (col, alias) in found {
+        log.info(format_args!(
+            "{}: reading column {} from {}",
+            path.display(),
+            col,
+            alias
+        ));
+    }
+}
+
+/// Maps the header names of a file to their positions, applying the source's disambiguations.
+fn header_positions(source: &Source, path: &Path, header: &StringRecord) -> Result<Headers> {
+    let mut headers = Headers::new();
+    for (idx, col) in header.iter().enumerate() {
+        headers.entry(col.to_owned()).or_default().push(idx);
+    }
+    for (col, &occurrence) in source.disambiguate.iter() {
+        let Some(positions) = headers.get_mut(col) else {
+            bail!(
+                "{}: cannot disambiguate missing column {}",
+                path.display(),
+                col
+            );
+        };
+        let Some(&idx) = positions.get(occurrence) else {
+            bail!(
+                "{}: cannot pick occurrence {} of column {}, which appears {} time(s)",
+                path.display(),
+                occurrence,
+                col,
+                positions.len()
+            );
+        };
+        *positions = vec![idx];
+    }
+    Ok(headers)
+}
+
+fn resolve_key_column(
+    path: &Path,
+    headers: &Headers,
+    aliases: &HashMap<String, Vec<String>>,
+    width: usize,
+    col: &ColumnRef,
+) -> Result<usize> {
+    match col {
+        ColumnRef::Name(name) => resolve_column(path, headers, aliases, name),
+        ColumnRef::Index { index } if *index < width => Ok(*index),
+        ColumnRef::Index { index } => bail!(
+            "{}: key column index {} is out of range, the file has {} column(s)",
+            path.display(),
+            index,
+            width
+        ),
+    }
+}
+
+/// A key column, or what a projection reads, resolved against the header of a particular file.
+enum KeyPart<'k> {
+    Column(usize),
+    Concat {
+        columns: Vec<usize>,
+        sep: &'k str,
+    },
+    Expr {
+        expr: Compiled,
+        name: String,
+        /// Positions of the columns the expression refers to.
+        columns: Vec<usize>,
+    },
+    Regex {
+        column: usize,
+        regex: Regex,
+        group: usize,
+        on_no_match: NoMatchPolicy,
+        name: String,
+    },
+}
+
+impl<'k> KeyPart<'k> {
+    fn resolve(
+        source: &'k Source,
+        path: &Path,
+        headers: &Headers,
+        width: usize,
+        col: &'k KeyColumn,
+    ) -> Result<Self> {
+        let aliases = &source.aliases;
+        let name = col.to_string();
+        if let Some(alias) = source.key_aliases.get(&name) {
+            return Ok(Self::Column(resolve_column(path, headers, aliases, alias)?));
+        }
+        let expr = match col {
+            KeyColumn::Expr { expr, .. } => Some(expr),
+            _ => source.key_expr.get(&name),
+        };
+        if let Some(expr) = expr {
+            let mut columns = Vec::new();
+            let expr = Expr::parse(expr)?.compile(&mut |col| {
+                let idx = resolve_column(path, headers, aliases, col)?;
+                columns.push(idx);
+                Ok(idx)
+            })?;
+            return Ok(Self::Expr {
+                expr,
+                name,
+                columns,
+            });
+        }
+        if let Some(key_regex) = source.key_regex.get(&name) {
+            return Ok(Self::Regex {
+                column: resolve_column(path, headers, aliases, &key_regex.column)?,
+                regex: key_regex.compile()?,
+                group: key_regex.group,
+                on_no_match: key_regex.on_no_match,
+                name,
+            });
+        }
+
+        match col {
+            KeyColumn::Column(col) | KeyColumn::Renamed { from: col, .. } => Ok(Self::Column(
+                resolve_key_column(path, headers, aliases, width, col)?,
+            )),
+            KeyColumn::Concat { concat, sep, .. } => Ok(Self::Concat {
+                columns: concat
+                    .iter()
+                    .map(|col| resolve_column(path, headers, aliases, col))
+                    .collect::<Result<_>>()?,
+                sep,
+            }),
+            KeyColumn::Expr { .. } => unreachable!(),
+        }
+    }
+
+    /// Positions of the columns the key component is computed from.
+    fn columns(&self) -> &[usize] {
+        match self {
+            Self::Column(idx) | Self::Regex { column: idx, .. } => std::slice::from_ref(idx),
+            Self::Concat { columns, .. } | Self::Expr { columns, .. } => columns,
+        }
+    }
+
+    fn header(&self, header_record: &StringRecord) -> String {
+        match self {
+            Self::Column(idx) => header_record[*idx].to_owned(),
+            Self::Concat { columns, sep } => columns
+                .iter()
+                .map(|&idx| &header_record[idx])
+                .collect::<Vec<_>>()
+                .join(sep),
+            Self::Expr { name, .. } | Self::Regex { name, .. } => name.clone(),
+        }
+    }
+
+    fn extract(&self, record: &StringRecord) -> Result<String> {
+        Ok(match self {
+            Self::Column(idx) => record[*idx].to_owned(),
+            Self::Concat { columns, sep } => columns
+                .iter()
+                .map(|&idx| &record[idx])
+                .collect::<Vec<_>>()
+                .join(sep),
+            Self::Expr { expr, .. } => expr.eval(record)?,
+            Self::Regex { .. } => self.capture(record)?.unwrap_or_default(),
+        })
+    }
+
+    /// The key component captured by a `key_regex`, or `None` if it doesn't match and the
+    /// record is to be skipped.
+    fn capture(&self, record: &StringRecord) -> Result<Option<String>> {
+        let Self::Regex {
+            column,
+            regex,
+            group,
+            on_no_match,
+            name,
+        } = self
+        else {
+            return self.extract(record).map(Some);
+        };
+        let value = &record[*column];
+        match regex.captures(value).and_then(|caps| caps.get(*group)) {
+            Some(captured) => Ok(Some(captured.as_str().to_owned())),
+            None => match on_no_match {
+                NoMatchPolicy::Error => bail!(
+                    "key_regex {} does not match {:?}; on_no_match may skip the record or \
+                     read the component as empty",
+                    name,
+                    value
+                ),
+                NoMatchPolicy::Skip => Ok(None),
+                NoMatchPolicy::Empty => Ok(Some(String::new())),
+            },
+        }
+    }
+}
+
+/// Expands the `all_except` of each source, and the `passthrough` of the primary source, to
+/// projections of the columns of their first file.
+fn expand_projections(spec: &mut JoinSpec) -> Result<()> {
+    for source_idx in 0..spec.sources.len() {
+        let source = &spec.sources[source_idx];
+        if source.all_except.is_none() && !source.passthrough {
+            continue;
+        }
+        if source.path.as_os_str() == "-" {
+            bail!("all_except and passthrough are not supported for a source read from stdin");
+        }
+        let path = input::source_paths(source)?.remove(0);
+        let records = RecordReader::open(source, &path)?;
+        let header = records.header();
+        let headers = header_positions(source, &path, header)?;
+        let mut skip = vec![false; header.len()];
+        if let Some(excluded) = &source.all_except {
+            for (_, col) in spec.source_key(source) {
+                let part = KeyPart::resolve(source, &path, &headers, header.len(), col)?;
+                for &idx in part.columns() {
+                    skip[idx] = true;
+                }
+            }
+            let projected = source.projections.keys().flat_map(ProjectionFrom::columns);
+            for col in projected.chain(excluded) {
+                skip[resolve_column(&path, &headers, &source.aliases, col)?] = true;
+            }
+        }
+        let names: Vec<_> = header
+            .iter()
+            .zip(skip)
+            .filter(|(_, skip)| !skip)
+            .map(|(name, _)| name.to_owned())
+            .collect();
+        let projections = &mut spec.sources[source_idx].projections;
+        for name in names {
+            projections.push(name.clone(), ProjectedColumn::Name(name));
+        }
+    }
+    spec.check_output_names()
+}
+
+/// What is shared by the reading of all sources.
+struct ReadContext<'a> {
+    spec: &'a JoinSpec,
+    dictionaries: &'a Dictionaries,
+    /// The `match_on` columns of all lookup sources, in spec order, whose values are kept after
+    /// the projection of each row; only set when reading the primary source.
+    match_on: &'a [String],
+    /// Whether every row is kept as read, instead of the last row of each key; only set when
+    /// reading the primary source with `primary_passthrough`.
+    keep_all: bool,
+    /// The keys of `--only-key` and `--only-keys-file`, to which the rows read are restricted.
+    only_keys: Option<&'a HashSet<Key>>,
+    /// Takes the errors in records, under `error_mode: collect`, for the record to be skipped.
+    errors: &'a ErrorCollector,
+    /// Whether the files were already read, and are read again for the records of a source
+    /// with `lazy_projections`, so that what reading them reported is not reported again.
+    reread: bool,
+}
+
+/// Fails if a file of the source was last modified longer ago than its `max_age_secs`.
+fn check_freshness(spec: &JoinSpec, source: &Source) -> Result<()> {
+    let Some(max_age) = source.max_age_secs.or(spec.max_age_secs) else {
+        return Ok(());
+    };
+    if source.path.as_os_str() == "-" || input::as_url(&source.path).is_some() {
+        return Ok(());
+    }
+    for path in input::source_paths(source)? {
+        let modified = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .with_context(|| format!("{}: cannot read modification time", path.display()))?;
+        // A file modified after now, by a clock running ahead, is as fresh as can be.
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default()
+            .as_secs();
+        if age > max_age {
+            bail!(DataError(format!(
+                "{}: file was last modified {} s ago, more than max_age_secs of {}",
+                path.display(),
+                age,
+                max_age
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the source from its files, or from stdin if its path is `-`; see [`read_inputs`].
+fn read_file(
+    source: &Source,
+    ctx: &ReadContext,
+    memory_used: usize,
+    log: &Logger,
+) -> Result<(Vec<String>, Table, Vec<InputFile>)> {
+    if source.path.as_os_str() == "-" {
+        let records = RecordReader::from_reader(source, stdin());
+        let inputs = [records.map(|records| (records, source.path.clone()))];
+        return read_inputs(source, inputs, ctx, memory_used, log);
+    }
+    let inputs = input::source_paths(source)?
         .into_iter()
-        .map(|col| *headers.get(col).unwrap())
+        .map(|path| Ok((RecordReader::open(source, &path)?, path)));
+    read_inputs(source, inputs, ctx, memory_used, log)
+}
+
+/// Runs `read` for a source, and again from scratch after an I/O error, as many times in all
+/// as `read_retries` allows. A source read from stdin cannot be read again, so it is read once.
+fn with_read_retries<T>(
+    spec: &JoinSpec,
+    source: &Source,
+    mut read: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let retries = match spec.read_retries {
+        Some(retries) if source.path.as_os_str() != "-" => retries,
+        _ => return read(),
+    };
+    let mut backoff = Duration::from_millis(retries.backoff_ms);
+    let mut attempt = 1;
+    loop {
+        match read() {
+            Ok(value) => return Ok(value),
+            Err(err) if !error::is_io(&err) => return Err(err),
+            Err(err) if attempt >= retries.attempts => {
+                return Err(err.context(format!(
+                    "{}: giving up after {} attempt(s)",
+                    source.path.display(),
+                    attempt
+                )));
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "{}: warning: attempt {} of {} failed: {:#}; reading the source again in {} ms",
+                    source.path.display(),
+                    attempt,
+                    retries.attempts,
+                    err,
+                    backoff.as_millis()
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Reads the source into a table from key to projection, from one or more inputs, each with
+/// the path it is reported under.
+///
+/// Also returns the header names of the key columns, as they appear in the first input of the
+/// source.
+fn read_inputs(
+    source: &Source,
+    inputs: impl IntoIterator<Item = Result<(RecordReader, PathBuf)>>,
+    ctx: &ReadContext,
+    memory_used: usize,
+    log: &Logger,
+) -> Result<(Vec<String>, Table, Vec<InputFile>)> {
+    let spec = ctx.spec;
+    let started = Instant::now();
+    let memory_limit = spec
+        .max_memory
+        .map(|limit| (limit.0 as usize).saturating_sub(memory_used));
+    let mut data = match spec.range_position(source).zip(source.range.as_ref()) {
+        Some((pos, range)) => {
+            let ty = spec.key_types.get(pos).copied().unwrap_or_default();
+            let ranges = Ranges::new(pos, spec.key.len(), ty, range.on_overlap);
+            Table::with_ranges(ranges, memory_limit)
+        }
+        None if ctx.keep_all => Table::keeping_all(memory_limit),
+        None if source.is_aggregated() => {
+            Table::with_aggregates(Aggregates::new(source), memory_limit)
+        }
+        None if source.lazy_projections => Table::with_offsets(memory_limit),
+        None => Table::new(source.spill_after, memory_limit),
+    };
+    let mut key_header = None;
+    let mut files = Vec::new();
+    let mut checks = Checks::new(source);
+    let mut first: Option<(PathBuf, StringRecord)> = None;
+    for input in inputs {
+        let (records, path) = input?;
+        tracing::debug!(source = %source.name(), path = %path.display(), "reading file");
+        match &first {
+            None => first = Some((path.clone(), records.header().clone())),
+            Some((first_path, first_header)) if source.strict_schema => {
+                check_schema(&path, first_path, first_header, records.header())?
+            }
+            Some(_) => {}
+        }
+        let (file_key_header, file) =
+            read_records(source, &path, records, ctx, &mut checks, &mut data, log)?;
+        key_header.get_or_insert(file_key_header);
+        files.push(file);
+        data.finish_file();
+    }
+    data.finish_aggregates();
+    if let Some((first, second)) = data.sort_ranges() {
+        bail!(DataError(format!(
+            "{}: the ranges of keys {:?} and {:?} overlap, so a value in both matches either; \
+             set on_overlap: first to match the row read first",
+            source.path.display(),
+            first,
+            second
+        )));
+    }
+    finish_source(source, spec, checks, &files, log)?;
+    tracing::info!(
+        source = %source.name(),
+        files = files.len(),
+        rows = data.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "source read"
+    );
+    Ok((key_header.unwrap_or_default(), data, files))
+}
+
+/// Checks a source once all its files were read, and reports on its records.
+fn finish_source(
+    source: &Source,
+    spec: &JoinSpec,
+    checks: Checks,
+    files: &[InputFile],
+    log: &Logger,
+) -> Result<()> {
+    checks.finish(spec.assert_mode)?;
+    if files.iter().all(|file| file.rows == 0) {
+        let message = format!(
+            "source {} has no data rows (join: {})",
+            source.name(),
+            source.join.unwrap_or(spec.join)
+        );
+        match spec.empty_source {
+            EmptyPolicy::Error => {
+                bail!(DataError(format!("{}: {}", source.path.display(), message)))
+            }
+            EmptyPolicy::Warn => tracing::warn!("{}: warning: {}", source.path.display(), message),
+            EmptyPolicy::Allow => {}
+        }
+    }
+    let records: usize = files.iter().map(|file| file.rows).sum();
+    if let Some(expected) = source
+        .expect_rows
+        .and_then(|bounds| bounds.violation(records))
+    {
+        bail!(ExpectationError(format!(
+            "{}: source {} has {} data record(s), but its expect_rows requires {}",
+            source.path.display(),
+            source.name(),
+            records,
+            expected
+        )));
+    }
+    let overwritten: usize = files.iter().map(|file| file.overwritten).sum();
+    if overwritten > 0 {
+        log.info(format_args!(
+            "{}: {} record(s) replaced an earlier record with the same key",
+            source.path.display(),
+            overwritten
+        ));
+    }
+    Ok(())
+}
+
+/// Fails unless the header of a file of a multi-file source is identical to that of the first one.
+fn check_schema(
+    path: &Path,
+    first_path: &Path,
+    first: &StringRecord,
+    header: &StringRecord,
+) -> Result<()> {
+    if first == header {
+        return Ok(());
+    }
+    let missing: Vec<_> = first
+        .iter()
+        .filter(|col| !header.iter().any(|c| c == *col))
         .collect();
-    let proj_idx: Vec<_> = proj_spec
-        .into_iter()
-        .map(|col| *headers.get(col).unwrap())
+    let extra: Vec<_> = header
+        .iter()
+        .filter(|col| !first.iter().any(|c| c == *col))
         .collect();
+    let mut diffs = Vec::new();
+    if !missing.is_empty() {
+        diffs.push(format!("missing column(s) {:?}", missing));
+    }
+    if !extra.is_empty() {
+        diffs.push(format!("unexpected column(s) {:?}", extra));
+    }
+    if diffs.is_empty() {
+        diffs.push("columns in a different order".to_owned());
+    }
+    bail!(DataError(format!(
+        "{}: header does not match {}: {}",
+        path.display(),
+        first_path.display(),
+        diffs.join(", ")
+    )));
+}
 
-    let mut data = IndexMap::new();
-    for record in reader.into_records() {
-        let record = record?;
-        let key: Key = key_idx
+/// The columns of one file of a source, resolved for turning its records into keys and
+/// projections, along with counts of the records that were skipped or altered.
+struct RecordParser<'a> {
+    spec: &'a JoinSpec,
+    source: &'a Source,
+    path: PathBuf,
+    key_parts: Vec<KeyPart<'a>>,
+    /// The header names of the key columns, as they appear in this file.
+    key_header: Vec<String>,
+    proj_parts: Vec<KeyPart<'a>>,
+    proj_lookups: Vec<Option<(&'a ValueLookup, &'a HashMap<String, String>)>>,
+    assert_idx: Vec<usize>,
+    schema_idx: Vec<usize>,
+    match_idx: Vec<usize>,
+    /// The position of the key component matched by range, along with the column of its upper
+    /// bound; the lower bound is read as the component.
+    range: Option<(usize, usize)>,
+    /// The positions in the key of the components read, if the source is keyed by only some.
+    key_subset: Option<Vec<usize>>,
+    /// The column of `latest_by`, if the source has one.
+    latest: Option<(usize, &'a LatestBy)>,
+    /// The `latest_by` value of the last record parsed.
+    stamp: f64,
+    cell_limits: Vec<Option<&'a CellLimit>>,
+    /// The keys the rows are restricted to, unless the source matches other than by key.
+    only_keys: Option<&'a HashSet<Key>>,
+    /// Columns to trim, if values are trimmed at all, along with the characters to trim.
+    trim: Option<(Vec<bool>, Option<&'a str>)>,
+    /// The last record read, with its values trimmed.
+    trimmed: StringRecord,
+    /// The columns that key components are read from, if their cells are unquoted under
+    /// `unquote_padded_keys`.
+    unquote: Option<Vec<bool>>,
+    /// The last record read, with its key cells unquoted by [`unquote_padded`].
+    unquoted: StringRecord,
+    rows: usize,
+    skipped: usize,
+    empty: usize,
+    truncated: usize,
+    dropped_oversized: usize,
+    mistyped: usize,
+    restricted: usize,
+    /// Key components changed by `key_clean`.
+    cleaned: usize,
+    invalid_latest: usize,
+    unquoted_cells: usize,
+    /// Records that a `key_regex` with `on_no_match: skip` doesn't match.
+    unmatched: usize,
+}
+
+impl<'a> RecordParser<'a> {
+    fn new(
+        source: &'a Source,
+        path: &Path,
+        records: &RecordReader,
+        ctx: &ReadContext<'a>,
+        log: &Logger,
+    ) -> Result<Self> {
+        let ReadContext {
+            spec,
+            dictionaries,
+            match_on,
+            only_keys,
+            ..
+        } = *ctx;
+        log.info(format_args!(
+            "{}: opened (on_parse_error: {:?})",
+            path.display(),
+            source.on_parse_error
+        ));
+
+        if let (Some(Delimiter::Auto), Some(delimiter)) = (source.delimiter, records.delimiter()) {
+            log.info(format_args!(
+                "{}: detected delimiter {:?}",
+                path.display(),
+                char::from(delimiter)
+            ));
+        }
+        let header_record = records.header();
+        let headers = header_positions(source, path, header_record)?;
+        log_aliases(source, path, &headers, log);
+        // The key component matched by range is read from the lower bound of the range, and its
+        // upper bound is kept alongside.
+        let range = match spec.range_position(source).zip(source.range.as_ref()) {
+            Some((pos, range)) => Some((
+                pos,
+                resolve_column(path, &headers, &source.aliases, &range.from)?,
+                resolve_column(path, &headers, &source.aliases, &range.to)?,
+            )),
+            None => None,
+        };
+        let key_parts = spec
+            .source_key(source)
+            .map(|(pos, col)| match range {
+                Some((range_pos, from, _)) if pos == range_pos => Ok(KeyPart::Column(from)),
+                _ => KeyPart::resolve(source, path, &headers, header_record.len(), col),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let range = range.map(|(pos, _, to)| (pos, to));
+        let key_header = key_parts
             .iter()
-            .map(|&idx| record.get(idx).unwrap().to_owned())
+            .map(|part| part.header(header_record))
             .collect();
-        let projection: Projection = proj_idx
+        let resolve = |col| resolve_column(path, &headers, &source.aliases, col);
+        let proj_parts = source
+            .projections
+            .keys()
+            .map(|from| match from {
+                ProjectionFrom::Column(col) => Ok(KeyPart::Column(resolve(col)?)),
+                ProjectionFrom::Concat { concat, sep } => Ok(KeyPart::Concat {
+                    columns: concat
+                        .iter()
+                        .map(|col| resolve(col))
+                        .collect::<Result<_>>()?,
+                    sep,
+                }),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let proj_lookups = source
+            .projections
+            .values()
+            .map(|col| {
+                col.lookup()
+                    .map(|lookup| (lookup, dictionaries.get(lookup)))
+            })
+            .collect();
+        // Records read again were already checked.
+        let assert_idx = source
+            .assertions
+            .iter()
+            .filter(|_| !ctx.reread)
+            .map(|assertion| resolve_column(path, &headers, &source.aliases, &assertion.column))
+            .collect::<Result<Vec<_>>>()?;
+        let schema_idx = source
+            .schema
+            .keys()
+            .filter(|_| !ctx.reread)
+            .map(|col| resolve_column(path, &headers, &source.aliases, col))
+            .collect::<Result<Vec<_>>>()?;
+        let match_idx = match_on
             .iter()
-            .map(|&idx| record.get(idx).unwrap().to_owned())
+            .map(|col| resolve_column(path, &headers, &source.aliases, col))
+            .collect::<Result<Vec<_>>>()?;
+        let latest = match &source.latest_by {
+            Some(latest) => Some((
+                resolve_column(path, &headers, &source.aliases, &latest.column)?,
+                latest,
+            )),
+            None => None,
+        };
+        let cell_limits = source
+            .projections
+            .values()
+            .map(|col| col.max_cell_length().or(source.max_cell_length.as_ref()))
             .collect();
-        data.insert(key, projection);
+        let unquote = source.unquote_padded_keys.then(|| {
+            let mut cols = vec![false; header_record.len()];
+            let range_to = range.iter().map(|(_, to_idx)| to_idx);
+            for &idx in key_parts.iter().flat_map(KeyPart::columns).chain(range_to) {
+                cols[idx] = true;
+            }
+            cols
+        });
+        let trim = source.trim_values.unwrap_or(spec.trim_values).then(|| {
+            let mut cols = vec![false; header_record.len()];
+            let used = key_parts
+                .iter()
+                .chain(&proj_parts)
+                .flat_map(KeyPart::columns);
+            let range_to = range.iter().map(|(_, to_idx)| to_idx);
+            for &idx in used.chain(&match_idx).chain(range_to) {
+                cols[idx] = true;
+            }
+            let chars = source.trim_chars.as_deref().or(spec.trim_chars.as_deref());
+            (cols, chars)
+        });
+        let repeated = headers
+            .iter()
+            .filter(|(_, positions)| positions.len() > 1 && !ctx.reread);
+        for (col, positions) in repeated {
+            tracing::warn!(
+                "{}: warning: column {} appears at positions {:?}",
+                path.display(),
+                col,
+                positions
+            );
+        }
+
+        Ok(Self {
+            spec,
+            source,
+            path: path.to_owned(),
+            key_parts,
+            key_header,
+            proj_parts,
+            proj_lookups,
+            assert_idx,
+            schema_idx,
+            match_idx,
+            range,
+            key_subset: spec.key_subset(source),
+            latest,
+            stamp: 0.0,
+            cell_limits,
+            // Rows matched by range, by other columns, by only part of the key or fuzzily may
+            // match keys other than their own, so they are all kept.
+            only_keys: only_keys.filter(|_| {
+                source.range.is_none()
+                    && source.match_on.is_empty()
+                    && source.key.is_empty()
+                    && spec.fuzzy.is_none()
+            }),
+            trim,
+            trimmed: StringRecord::new(),
+            unquote,
+            unquoted: StringRecord::new(),
+            rows: 0,
+            skipped: 0,
+            empty: 0,
+            truncated: 0,
+            dropped_oversized: 0,
+            mistyped: 0,
+            restricted: 0,
+            cleaned: 0,
+            invalid_latest: 0,
+            unquoted_cells: 0,
+            unmatched: 0,
+        })
     }
 
-    Ok(data)
+    /// Turns record `idx` of the file into its key and projection, or `None` if a policy of the
+    /// spec drops it.
+    ///
+    /// The projected values are followed by those of the `match_on` columns, if any, and then by
+    /// the provenance of the record, if the source has `emit_provenance`.
+    fn parse(
+        &mut self,
+        idx: usize,
+        record: csv::Result<&StringRecord>,
+        checks: &mut Checks,
+        log: &Logger,
+    ) -> Result<Option<(Key, Projection)>> {
+        let (spec, source, path) = (self.spec, self.source, self.path.as_path());
+        let on_parse_error = source.on_parse_error;
+        let record = match record {
+            Ok(record) => record,
+            Err(err) if on_parse_error == ParseErrorPolicy::Fail => {
+                let location = record_location(idx, &err);
+                return Err(err)
+                    .with_context(|| format!("{}: failed to parse {}", path.display(), location));
+            }
+            Err(err) => {
+                if on_parse_error == ParseErrorPolicy::SkipAndLog {
+                    tracing::warn!("{}: skipping record {}: {}", path.display(), idx, err);
+                }
+                log.dropped(path, idx, None, DropReason::Unparseable)?;
+                self.skipped += 1;
+                return Ok(None);
+            }
+        };
+        // Before trimming, which would remove the whitespace that tells the quotes apart from
+        // ones in the value.
+        let record = match &self.unquote {
+            Some(cols) => match unquote_key_cells(record, cols, &mut self.unquoted) {
+                0 => record,
+                unquoted => {
+                    self.unquoted_cells += unquoted;
+                    &self.unquoted
+                }
+            },
+            None => record,
+        };
+        let record = match &self.trim {
+            Some((cols, chars)) => {
+                trim_record(record, cols, *chars, &mut self.trimmed);
+                &self.trimmed
+            }
+            None => record,
+        };
+        self.rows += 1;
+        checks.check(path, idx, record, &self.assert_idx);
+        if !checks.check_schema(path, idx, record, &self.schema_idx)? {
+            log.dropped(path, idx, None, DropReason::Mistyped)?;
+            self.mistyped += 1;
+            return Ok(None);
+        }
+        let key = self
+            .key_parts
+            .iter()
+            .map(|part| part.capture(record))
+            .collect::<Result<Option<Key>>>()
+            .map_err(|err| RecordError::new(path, idx, err.to_string()))?;
+        let Some(mut key) = key else {
+            log.dropped(path, idx, None, DropReason::Unmatched)?;
+            self.unmatched += 1;
+            return Ok(None);
+        };
+        let range = self.range.map(|(pos, _)| pos);
+        if let Some(pos) = key
+            .iter()
+            .enumerate()
+            .position(|(pos, value)| Some(pos) != range && value.trim().is_empty())
+        {
+            let pos = self.key_subset.as_ref().map_or(pos, |subset| subset[pos]);
+            match spec.empty_keys {
+                EmptyKeyPolicy::Error => bail!(RecordError::new(
+                    path,
+                    idx,
+                    format!("empty key component {}", spec.key[pos])
+                )
+                .with_column(&spec.key[pos])),
+                EmptyKeyPolicy::Skip => {
+                    log.dropped(path, idx, Some(&key), DropReason::EmptyKey)?;
+                    self.empty += 1;
+                    return Ok(None);
+                }
+                EmptyKeyPolicy::Keep => {}
+            }
+        }
+        if let Some((_, to_idx)) = self.range {
+            key.push(record[to_idx].to_owned());
+        }
+        let key = canonical_key(
+            spec,
+            path,
+            idx,
+            key,
+            range,
+            self.key_subset.as_deref(),
+            Some(&mut self.cleaned),
+        )?;
+        if self.only_keys.is_some_and(|keys| !keys.contains(&key)) {
+            self.restricted += 1;
+            return Ok(None);
+        }
+        // Projections are read from the record, not from the key, so that a projected key column
+        // keeps the value of the file rather than the canonical one, or the one of a key_expr.
+        let mut projection = self
+            .proj_parts
+            .iter()
+            .zip(&self.proj_lookups)
+            .zip(source.projections.values())
+            .map(|((part, lookup), col)| {
+                let cell = match part {
+                    KeyPart::Column(idx) => Cow::Borrowed(&record[*idx]),
+                    part => Cow::Owned(part.extract(record)?),
+                };
+                let value = col.fill_empty(&cell);
+                let value = col.normalize(value).map_err(|err| {
+                    RecordError::new(path, idx, format!("projection {}: {}", col.name(), err))
+                        .with_column(col.name())
+                        .with_key(&key)
+                })?;
+                if let Some((map, unmapped)) = col.value_map() {
+                    return match (map.get(value.as_ref()), unmapped) {
+                        (Some(mapped), _) => Ok(mapped.clone()),
+                        (None, Unmapped::Keep) => Ok(value.into_owned()),
+                        (None, Unmapped::Empty) => Ok(String::new()),
+                        (None, Unmapped::Literal(literal)) => Ok(literal.clone()),
+                        (None, Unmapped::Error) => Err(RecordError::new(
+                            path,
+                            idx,
+                            format!(
+                                "no entry for {:?} in the map of projection {}",
+                                value,
+                                col.name()
+                            ),
+                        )
+                        .with_column(col.name())
+                        .with_key(&key)
+                        .into()),
+                    };
+                }
+                let Some((lookup, map)) = lookup else {
+                    return Ok(value.into_owned());
+                };
+                match (map.get(value.as_ref()), lookup.missing) {
+                    (Some(mapped), _) => Ok(mapped.clone()),
+                    (None, MissingLookup::Keep) => Ok(value.into_owned()),
+                    (None, MissingLookup::Empty) => Ok(String::new()),
+                    (None, MissingLookup::Error) => Err(RecordError::new(
+                        path,
+                        idx,
+                        format!(
+                            "no entry for {:?} in {} for projection {}",
+                            value,
+                            lookup.path.display(),
+                            col.name()
+                        ),
+                    )
+                    .with_column(col.name())
+                    .with_key(&key)
+                    .into()),
+                }
+            })
+            .collect::<Result<Projection>>()?;
+        let mut oversized = false;
+        for ((value, limit), col) in projection
+            .iter_mut()
+            .zip(&self.cell_limits)
+            .zip(source.projections.keys())
+        {
+            let Some(limit) = limit else {
+                continue;
+            };
+            let Some((end, _)) = value.char_indices().nth(limit.length) else {
+                continue;
+            };
+            match limit.policy {
+                OversizePolicy::Truncate => {
+                    value.truncate(end);
+                    if limit.ellipsis {
+                        value.push('…');
+                    }
+                    self.truncated += 1;
+                }
+                OversizePolicy::Error => bail!(RecordError::new(
+                    path,
+                    idx,
+                    format!(
+                        "column {} is {} characters long, more than max_cell_length of {}",
+                        col,
+                        value.chars().count(),
+                        limit.length
+                    )
+                )
+                .with_column(col)
+                .with_key(&key)),
+                OversizePolicy::DropRow => oversized = true,
+            }
+        }
+        if oversized {
+            log.dropped(path, idx, Some(&key), DropReason::OversizedCell)?;
+            self.dropped_oversized += 1;
+            return Ok(None);
+        }
+        if let Some((col, latest)) = self.latest {
+            self.stamp = match (LatestBy::timestamp(&record[col]), latest.on_invalid) {
+                (Some(stamp), _) => stamp,
+                (None, InvalidLatest::Oldest) => f64::NEG_INFINITY,
+                (None, InvalidLatest::Skip) => {
+                    log.dropped(path, idx, Some(&key), DropReason::InvalidLatest)?;
+                    self.invalid_latest += 1;
+                    return Ok(None);
+                }
+                (None, InvalidLatest::Error) => bail!(RecordError::new(
+                    path,
+                    idx,
+                    format!(
+                        "latest_by column {}: cannot parse {:?} as a number or a date",
+                        latest.column, &record[col]
+                    )
+                )
+                .with_column(&latest.column)
+                .with_key(&key)),
+            };
+        }
+        if !self.match_idx.is_empty() {
+            for cols in self.match_idx.chunks(spec.key.len()) {
+                let values: Key = cols.iter().map(|&col| record[col].to_owned()).collect();
+                // An empty value matches nothing, whatever the key types.
+                let values = match values.iter().any(|value| value.trim().is_empty()) {
+                    true => values,
+                    false => canonical_key(spec, path, idx, values, None, None, None)?,
+                };
+                projection.extend(values);
+            }
+        }
+        if source.emit_provenance {
+            projection.push(match source.provenance_path {
+                true => format!("{}:{}", path.display(), idx),
+                false => idx.to_string(),
+            });
+        }
+        Ok(Some((key, projection)))
+    }
+
+    /// Reports the records that were skipped or altered, once the whole file was parsed, given
+    /// the records that replaced an earlier one of their key, and the first of their keys.
+    fn finish(&self, overwritten: usize, duplicates: Vec<Key>) -> InputFile {
+        let path = self.path.display();
+        if self.skipped > 0 {
+            tracing::warn!("{}: skipped {} unparseable record(s)", path, self.skipped);
+        }
+        if self.empty > 0 {
+            tracing::warn!(
+                "{}: skipped {} record(s) with an empty key component",
+                path,
+                self.empty
+            );
+        }
+        if self.truncated > 0 {
+            tracing::warn!(
+                "{}: truncated {} cell(s) longer than max_cell_length",
+                path,
+                self.truncated
+            );
+        }
+        if self.dropped_oversized > 0 {
+            tracing::warn!(
+                "{}: skipped {} record(s) with a cell longer than max_cell_length",
+                path,
+                self.dropped_oversized
+            );
+        }
+        if self.mistyped > 0 {
+            tracing::warn!(
+                "{}: skipped {} record(s) with a value not of its schema type",
+                path,
+                self.mistyped
+            );
+        }
+        if self.unquoted_cells > 0 {
+            tracing::warn!(
+                "{}: warning: unquoted {} key cell(s) with whitespace before the opening quote",
+                path,
+                self.unquoted_cells
+            );
+        }
+        if let Some(clean) = self.spec.key_clean.filter(|_| self.cleaned > 0) {
+            tracing::warn!(
+                "{}: cleaned {} key cell(s) under key_clean: {}",
+                path,
+                self.cleaned,
+                clean
+            );
+        }
+        if self.restricted > 0 {
+            tracing::warn!(
+                "{}: skipped {} record(s) with a key not given with --only-key or \
+                 --only-keys-file",
+                path,
+                self.restricted
+            );
+        }
+        if self.invalid_latest > 0 {
+            tracing::warn!(
+                "{}: skipped {} record(s) with a latest_by value that is neither a number nor \
+                 a date",
+                path,
+                self.invalid_latest
+            );
+        }
+        if self.unmatched > 0 {
+            tracing::warn!(
+                "{}: skipped {} record(s) that a key_regex does not match",
+                path,
+                self.unmatched
+            );
+        }
+        InputFile {
+            path: self.path.clone(),
+            rows: self.rows,
+            overwritten,
+            duplicates,
+        }
+    }
 }
 
-fn read_input(spec: &JoinSpec) -> Result<JoinInput> {
-    let mut join_input = Vec::with_capacity(spec.sources.len());
-    for source in spec.sources.iter() {
-        let data = read_file(
-            &source.path,
-            spec.key.iter().map(String::as_str),
-            source.projections.keys().into_iter().map(String::as_str),
-        )?;
-        join_input.push(data);
+/// Reads the records of one file of the source into `data`, returning the header names of the key
+/// columns as they appear in this file.
+fn read_records<'a>(
+    source: &'a Source,
+    path: &Path,
+    mut records: RecordReader,
+    ctx: &ReadContext<'a>,
+    checks: &mut Checks,
+    data: &mut Table,
+    log: &Logger,
+) -> Result<(Vec<String>, InputFile)> {
+    let spec = ctx.spec;
+    let mut parser = RecordParser::new(source, path, &records, ctx, log)?;
+    let (progress_kind, progress_len) = records.progress_len();
+    let progress = log.progress_bar(progress_kind, progress_len, path.display().to_string());
+    let mut overwritten = 0usize;
+    let mut duplicates = Vec::new();
+    let mut idx = source.skip_rows;
+    let mut record = StringRecord::new();
+    loop {
+        let read = records.read_record(&mut record);
+        if let Ok(false) = read {
+            break;
+        }
+        idx += 1;
+        interrupt::check(|| format!("reading {} record(s) of {}", idx - 1, path.display()))?;
+        if idx.is_multiple_of(PROGRESS_INTERVAL) {
+            progress.set_position(records.position());
+        }
+        let byte = record.position().map_or(0, csv::Position::byte);
+        let record = read.map(|_| &record);
+        let parsed = match parser.parse(idx, record, checks, log) {
+            Err(err) if Category::of(&err) == Category::Data => {
+                ctx.errors.add(err)?;
+                continue;
+            }
+            parsed => parsed?,
+        };
+        let Some((key, projection)) = parsed else {
+            continue;
+        };
+        // Without a key, each row is kept on its own, to be combined with all rows of the other
+        // sources.
+        let key = match spec.key.is_empty() {
+            true => vec![data.len().to_string()],
+            false => key,
+        };
+        let was_spilled = data.is_spilled();
+        // The values of `agg` projections that aren't numbers are reported with their record.
+        let inserted = match parser.latest {
+            _ if source.lazy_projections => Ok(data.insert_offset(key, idx, byte)),
+            Some(_) => data.insert_latest(key, projection, parser.stamp),
+            None => data.insert(key, projection),
+        };
+        let replaced = match inserted {
+            Ok(replaced) => replaced,
+            Err(err) => match err.downcast::<DataError>() {
+                Ok(err) => {
+                    ctx.errors
+                        .add(anyhow!(RecordError::new(path, idx, err.0)))?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            },
+        };
+        if let Some(key) = replaced {
+            log.dropped(path, idx, Some(&key), DropReason::Duplicate)?;
+            overwritten += 1;
+            if duplicates.len() < DUPLICATE_SAMPLE_SIZE && !duplicates.contains(&key) {
+                duplicates.push(key);
+            }
+        }
+        if let Some(limit) = spec.max_memory.filter(|_| data.exceeds_memory_limit()) {
+            bail!(DataError(format!(
+                "{}: source {} exceeds max_memory of {} after {} row(s) ({} distinct key(s)); \
+                 raise --max-memory, or set spill_after to keep lookup sources on disk",
+                path.display(),
+                source.name(),
+                limit,
+                parser.rows,
+                data.len()
+            )));
+        }
+        if data.is_spilled() && !was_spilled {
+            log.info(format_args!(
+                "{}: spilled to disk after {} distinct key(s)",
+                path.display(),
+                data.len()
+            ));
+        }
+    }
+    progress.finish_and_clear();
+
+    log.info(format_args!(
+        "{}: closed after {} row(s), {} distinct key(s)",
+        path.display(),
+        parser.rows,
+        data.len()
+    ));
+    let file = parser.finish(overwritten, duplicates);
+    Ok((std::mem::take(&mut parser.key_header), file))
+}
+
+/// Describes where record `idx` of a file starts, with its line and byte offset if the error that
+/// it failed to parse with tells.
+fn record_location(idx: usize, err: &csv::Error) -> String {
+    match err.position() {
+        Some(pos) => format!("record {} at line {}, byte {}", idx, pos.line(), pos.byte()),
+        None => format!("record {}", idx),
+    }
+}
+
+/// Copies the record into `trimmed`, with the values of the flagged columns trimmed of the given
+/// characters, or of whitespace.
+fn trim_record(
+    record: &StringRecord,
+    cols: &[bool],
+    chars: Option<&str>,
+    trimmed: &mut StringRecord,
+) {
+    trimmed.clear();
+    for (value, &trim) in record
+        .iter()
+        .zip(cols.iter().chain(std::iter::repeat(&false)))
+    {
+        trimmed.push_field(match (trim, chars) {
+            (false, _) => value,
+            (true, Some(chars)) => value.trim_matches(|c| chars.contains(c)),
+            (true, None) => value.trim(),
+        });
+    }
+}
+
+/// The value held by a cell quoted with whitespace before its opening quote, such as ` "A 1"`,
+/// which CSV parsers read as it is, quotes and all, as the quote doesn't start the field. `None`
+/// for other cells.
+fn unquote_padded(cell: &str) -> Option<Cow<'_, str>> {
+    let padding = [' ', '\t'];
+    if !cell.starts_with(padding) {
+        return None;
+    }
+    let quoted = cell.trim_matches(padding);
+    let inner = quoted.strip_prefix('"')?.strip_suffix('"')?;
+    Some(match inner.contains('"') {
+        true => Cow::Owned(inner.replace("\"\"", "\"")),
+        false => Cow::Borrowed(inner),
+    })
+}
+
+/// Copies the record into `unquoted`, with the cells of the flagged columns unquoted by
+/// [`unquote_padded`], if there are any such cells; returns how many.
+fn unquote_key_cells(record: &StringRecord, cols: &[bool], unquoted: &mut StringRecord) -> usize {
+    let padded = |(cell, &key): (&str, &bool)| key && unquote_padded(cell).is_some();
+    let count = record.iter().zip(cols).filter(|&cell| padded(cell)).count();
+    if count == 0 {
+        return 0;
+    }
+    unquoted.clear();
+    for (idx, cell) in record.iter().enumerate() {
+        match cols.get(idx).copied().unwrap_or(false) {
+            true => unquoted.push_field(&unquote_padded(cell).unwrap_or(Cow::Borrowed(cell))),
+            false => unquoted.push_field(cell),
+        }
+    }
+    count
+}
+
+/// Converts the components of a key read from record `idx` of a file to their canonical form
+/// under `key_clean`, `key_normalization` and `key_types`, counting the components that
+/// `key_clean` changed in `cleaned`.
+///
+/// With the position of a range component, the key holds the lower bound of the range there
+/// and the upper bound after the other components, and empty bounds are kept as open.
+fn canonical_key(
+    spec: &JoinSpec,
+    path: &Path,
+    idx: usize,
+    mut key: Key,
+    range: Option<usize>,
+    subset: Option<&[usize]>,
+    mut cleaned: Option<&mut usize>,
+) -> Result<Key> {
+    for (pos, value) in key.iter_mut().enumerate() {
+        let pos = match (range, subset) {
+            (Some(range), _) if pos == spec.key.len() => range,
+            (_, Some(subset)) => subset[pos],
+            _ => pos,
+        };
+        if spec.normalize_key(value) {
+            if let Some(cleaned) = cleaned.as_deref_mut() {
+                *cleaned += 1;
+            }
+        }
+        let ty = spec.key_types.get(pos).copied().unwrap_or_default();
+        // Strings are their own canonical form, so they are kept without copying.
+        if ty == KeyType::String || (Some(pos) == range && value.trim().is_empty()) {
+            continue;
+        }
+        match ty.canonicalize(value) {
+            Some(canonical) => *value = canonical,
+            None if spec.lenient_key_types => {}
+            None => bail!(RecordError::new(
+                path,
+                idx,
+                format!(
+                    "cannot parse {:?} as {} for key column {}",
+                    value, ty, spec.key[pos]
+                )
+            )
+            .with_column(&spec.key[pos])),
+        }
     }
-    Ok(join_input)
+    Ok(key)
 }
 
-fn write_output(spec: &JoinSpec, input: JoinInput) -> Result<()> {
-    let num_cols = spec.key.len()
-        + spec
-            .sources
+/// Reads all sources, returning the output header of the key columns along with the data.
+fn read_input(
+    spec: &JoinSpec,
+    only_keys: Option<&HashSet<Key>>,
+    threads: usize,
+    log: &Logger,
+) -> Result<(Vec<String>, JoinInput)> {
+    let dictionaries = Dictionaries::load(spec)?;
+    let match_on: Vec<_> = spec.sources[1..]
+        .iter()
+        .flat_map(|source| source.match_on.iter().cloned())
+        .collect();
+    let errors = ErrorCollector::new(spec.error_limit());
+    let primary_ctx = ReadContext {
+        spec,
+        dictionaries: &dictionaries,
+        match_on: &match_on,
+        keep_all: spec.primary_passthrough,
+        only_keys,
+        errors: &errors,
+        reread: false,
+    };
+    let ctx = ReadContext {
+        match_on: &[],
+        keep_all: false,
+        ..primary_ctx
+    };
+    let read = |idx: usize, memory_used: usize| {
+        let source = &spec.sources[idx];
+        let ctx = if idx == 0 { &primary_ctx } else { &ctx };
+        with_read_retries(spec, source, || read_file(source, ctx, memory_used, log))
+    };
+    // Under max_memory, each source may only take what the sources before it left, so they
+    // are read one after another.
+    let mut concurrent = match threads > 1 && spec.max_memory.is_none() {
+        true => in_threads(spec.sources.len(), threads, |idx| read(idx, 0)),
+        false => Vec::new(),
+    }
+    .into_iter();
+    // A source that cannot be read is taken as empty under `error_mode: collect`, to go on to
+    // the errors of the other sources.
+    let unread = || (Vec::new(), Table::new(None, None), Vec::new());
+    let primary_source = &spec.sources[0];
+    let (primary_key_header, primary, primary_files) =
+        match concurrent.next().unwrap_or_else(|| read(0, 0)) {
+            Ok(read) => read,
+            Err(err) => {
+                errors.add(err)?;
+                unread()
+            }
+        };
+    if let Err(err) = check_unique_keys(spec, &primary_files) {
+        errors.add(err)?;
+    }
+    if only_keys.is_some() && primary.len() == 0 {
+        warn_no_only_keys(primary_source);
+    }
+    let key_header = output_key_header(spec, primary_key_header);
+    let mut memory_used = primary.memory();
+    let mut lookups = Vec::with_capacity(spec.sources.len() - 1);
+    let mut files = vec![primary_files];
+    for idx in 1..spec.sources.len() {
+        let (_, table, source_files) =
+            match concurrent.next().unwrap_or_else(|| read(idx, memory_used)) {
+                Ok(read) => read,
+                Err(err) => {
+                    errors.add(err)?;
+                    unread()
+                }
+            };
+        memory_used += table.memory();
+        lookups.push(table);
+        files.push(source_files);
+    }
+    errors.finish()?;
+    let mut primary = primary
+        .into_primary()
+        .expect("the primary source is never spilled");
+    if let Some(path) = &spec.key_filter {
+        filter_keys(spec, path, &mut primary, log)?;
+    }
+    Ok((
+        key_header,
+        JoinInput {
+            primary,
+            lookups,
+            files,
+            dictionaries,
+        },
+    ))
+}
+
+/// Calls `task` with each index up to `len` on up to `threads` threads, each taking the next
+/// index once it is done with one, and returns the results in the order of the indices.
+fn in_threads<T: Send>(len: usize, threads: usize, task: impl Fn(usize) -> T + Sync) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, T)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(len))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        if idx >= len {
+                            return results;
+                        }
+                        results.push((idx, task(idx)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    results.sort_unstable_by_key(|&(idx, _)| idx);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// The number of distinct keys of duplicate records that `require_unique_base_keys` lists.
+const DUPLICATE_SAMPLE_SIZE: usize = 5;
+
+/// Fails under `require_unique_base_keys` if records of the primary source replaced earlier
+/// ones of the same key, given the files it was read from.
+fn check_unique_keys(spec: &JoinSpec, files: &[InputFile]) -> Result<()> {
+    let overwritten: usize = files.iter().map(|file| file.overwritten).sum();
+    if !spec.require_unique_base_keys || overwritten == 0 {
+        return Ok(());
+    }
+    let mut sample: Vec<&Key> = Vec::new();
+    for key in files.iter().flat_map(|file| &file.duplicates) {
+        if sample.len() < DUPLICATE_SAMPLE_SIZE && !sample.contains(&key) {
+            sample.push(key);
+        }
+    }
+    bail!(DataError(format!(
+        "{}: {} record(s) have the key of an earlier record, but require_unique_base_keys \
+         requires the keys of the primary source to be unique, e.g. {:?}",
+        spec.sources[0].path.display(),
+        overwritten,
+        sample
+    )));
+}
+
+/// Warns that the primary source has none of the keys the rows are restricted to, which
+/// leaves the output without rows.
+fn warn_no_only_keys(primary: &Source) {
+    tracing::warn!(
+        "{}: warning: no row of the primary source has a key given with --only-key or \
+         --only-keys-file, so the output has no rows",
+        primary.path.display()
+    );
+}
+
+/// Reads the keys of `--only-key`, split at `key_sep`, and of `--only-keys-file`, in their
+/// canonical form, or `None` if neither is given.
+fn load_only_keys(
+    spec: &JoinSpec,
+    only_key: &[String],
+    key_sep: Option<&str>,
+    only_keys_file: Option<&Path>,
+) -> Result<Option<HashSet<Key>>> {
+    if only_key.is_empty() && only_keys_file.is_none() {
+        return Ok(None);
+    }
+    if spec.key.is_empty() {
+        bail!("--only-key and --only-keys-file require a key");
+    }
+    let mut keys = HashSet::new();
+    let origin = Path::new("--only-key");
+    for (idx, value) in only_key.iter().enumerate() {
+        let key: Key = match key_sep {
+            Some(sep) => value.split(sep).map(str::to_owned).collect(),
+            None => vec![value.clone()],
+        };
+        if key.len() != spec.key.len() {
+            bail!(
+                "--only-key {:?} has {} component(s) for {} key column(s); separate them \
+                 with --key-sep",
+                value,
+                key.len(),
+                spec.key.len()
+            );
+        }
+        keys.insert(canonical_key(spec, origin, idx + 1, key, None, None, None)?);
+    }
+    if let Some(path) = only_keys_file {
+        let mut reader = csv::Reader::from_path(path)
+            .with_context(|| format!("{}: cannot open keys file", path.display()))?;
+        let width = reader.headers()?.len();
+        if width != spec.key.len() {
+            bail!(
+                "{}: keys file has {} column(s) for {} key column(s)",
+                path.display(),
+                width,
+                spec.key.len()
+            );
+        }
+        for (idx, record) in reader.records().enumerate() {
+            let key = record?.iter().map(str::to_owned).collect();
+            keys.insert(canonical_key(spec, path, idx + 1, key, None, None, None)?);
+        }
+    }
+    Ok(Some(keys))
+}
+
+/// The output header of the key columns, given their header names in the primary source.
+fn output_key_header(spec: &JoinSpec, primary_key_header: Vec<String>) -> Vec<String> {
+    spec.key
+        .iter()
+        .zip(primary_key_header)
+        .map(|(col, header)| col.label().map_or(header, str::to_owned))
+        .collect()
+}
+
+/// Keeps only the rows of the primary source whose key is listed in the key filter file.
+fn filter_keys(spec: &JoinSpec, path: &Path, primary: &mut Primary, log: &Logger) -> Result<()> {
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("{}: cannot open key filter", path.display()))?;
+    let width = reader.headers()?.len();
+    if width != spec.key.len() {
+        bail!(
+            "{}: key filter has {} column(s) for {} key column(s)",
+            path.display(),
+            width,
+            spec.key.len()
+        );
+    }
+    let mut allowed = IndexSet::new();
+    for (idx, record) in reader.records().enumerate() {
+        let record = record?;
+        let key = record.iter().map(str::to_owned).collect();
+        let key = canonical_key(spec, path, idx + 1, key, None, None, None)?;
+        allowed.insert(key);
+    }
+
+    let before = primary.len();
+    primary.retain(|key| allowed.contains(key));
+    log.info(format_args!(
+        "{}: kept {} of {} primary row(s)",
+        path.display(),
+        primary.len(),
+        before
+    ));
+    let present: HashSet<_> = primary.iter().map(|(key, _)| key).collect();
+    let missing: Vec<_> = allowed
+        .iter()
+        .filter(|key| !present.contains(key))
+        .collect();
+    if !missing.is_empty() {
+        tracing::warn!(
+            "{}: warning: {} key(s) not present in the primary source, e.g. {:?}",
+            path.display(),
+            missing.len(),
+            &missing[..missing.len().min(COVERAGE_SAMPLE_SIZE)]
+        );
+    }
+    Ok(())
+}
+
+/// Maps each position of `order` to the position of that column in `header`, requiring `order`
+/// to name every output column exactly once.
+fn column_permutation(order: &[String], header: &[String]) -> Result<Vec<usize>> {
+    let mut positions = IndexMap::new();
+    for (idx, col) in header.iter().enumerate() {
+        if positions.insert(col.as_str(), idx).is_some() {
+            bail!(
+                "column_order: output column {} is produced more than once",
+                col
+            );
+        }
+    }
+    let mut permutation = Vec::with_capacity(order.len());
+    let mut extra = Vec::new();
+    for col in order {
+        match positions.shift_remove(col.as_str()) {
+            Some(idx) => permutation.push(idx),
+            None if header.contains(col) => bail!("column_order: column {} is listed twice", col),
+            None => extra.push(col.as_str()),
+        }
+    }
+    let missing: Vec<_> = positions.keys().collect();
+    if !missing.is_empty() || !extra.is_empty() {
+        bail!(
+            "column_order does not match the output columns: missing {:?}, unknown {:?}",
+            missing,
+            extra
+        );
+    }
+    Ok(permutation)
+}
+
+/// Takes the first non-empty cell of the group in the row, falling back to the first present
+/// one unless the group is of `coalesce_columns`.
+fn coalesce(row: &mut [Option<String>], group: &ColumnGroup) -> Option<String> {
+    let positions = &group.positions;
+    let non_empty = positions
+        .iter()
+        .copied()
+        .find(|&idx| row[idx].as_deref().is_some_and(|value| !value.is_empty()));
+    let pos = match group.null_if_empty {
+        true => non_empty?,
+        false => non_empty
+            .or_else(|| positions.iter().copied().find(|&idx| row[idx].is_some()))
+            .unwrap_or(positions[0]),
+    };
+    std::mem::take(&mut row[pos])
+}
+
+/// Joins the key values of the row into the value of the `collapse_key` column.
+fn collapse(row: &[Option<String>], positions: &[usize], sep: &str) -> String {
+    let mut value = String::new();
+    for (idx, &pos) in positions.iter().enumerate() {
+        if idx > 0 {
+            value.push_str(sep);
+        }
+        value.push_str(row[pos].as_deref().unwrap_or_default());
+    }
+    value
+}
+
+/// The positions of the joined row an output column is taken from.
+struct ColumnGroup {
+    positions: Vec<usize>,
+    /// Whether the output column is null, rather than empty, when no position has a value.
+    null_if_empty: bool,
+    /// The `collapse_key` of the key columns, whose values are joined rather than coalesced.
+    collapsed: Option<CollapsedKey>,
+}
+
+impl ColumnGroup {
+    fn new(positions: Vec<usize>) -> Self {
+        Self {
+            positions,
+            null_if_empty: false,
+            collapsed: None,
+        }
+    }
+
+    /// The name of the output column, given the names of the columns of joined rows.
+    fn name<'c>(&'c self, columns: &'c [String]) -> &'c str {
+        match &self.collapsed {
+            Some(collapsed) => &collapsed.name,
+            None => &columns[self.positions[0]],
+        }
+    }
+}
+
+/// How the columns of joined rows are arranged in the output.
+struct Layout {
+    header: Vec<String>,
+    /// Positions of the key columns in the header.
+    key_cols: Vec<usize>,
+    /// Each output column after the row number is taken from one position of the joined row,
+    /// or coalesced from several.
+    groups: Vec<ColumnGroup>,
+    numbered: bool,
+    /// Whether joined rows differ from output rows at all.
+    reshape: bool,
+}
+
+impl Layout {
+    /// Lays out joined rows with the given `columns`, the first of which are the key columns.
+    fn new(spec: &JoinSpec, columns: &[String]) -> Result<Self> {
+        let key_len = spec.key.len();
+        // The key columns of a passthrough source are output where they are in its file, as the
+        // projections of the same name.
+        let passthrough = spec.sources[0].passthrough;
+        let key_positions: Vec<_> = (0..key_len)
+            .map(|key_idx| match passthrough {
+                true => (key_len..)
+                    .find(|&idx| columns[idx] == columns[key_idx])
+                    .unwrap(),
+                false => key_idx,
+            })
+            .collect();
+        let output_cols = match passthrough {
+            true => key_len..columns.len(),
+            false => 0..columns.len(),
+        };
+        let mut groups: Vec<ColumnGroup> = match spec.coalesce {
+            true => {
+                let mut groups: IndexMap<&str, Vec<usize>> = IndexMap::new();
+                for idx in output_cols {
+                    groups.entry(&columns[idx]).or_default().push(idx);
+                }
+                groups.into_values().map(ColumnGroup::new).collect()
+            }
+            false => output_cols.map(|idx| ColumnGroup::new(vec![idx])).collect(),
+        };
+        for positions in coalesced_positions(spec) {
+            // The coalesced column takes the place of the first of its columns.
+            let first = groups
+                .iter()
+                .position(|group| group.positions.iter().any(|pos| positions.contains(pos)))
+                .unwrap();
+            for group in groups.iter_mut() {
+                group.positions.retain(|pos| !positions.contains(pos));
+            }
+            let first = first
+                - groups[..first]
+                    .iter()
+                    .filter(|group| group.positions.is_empty())
+                    .count();
+            groups.retain(|group| !group.positions.is_empty());
+            groups.insert(
+                first,
+                ColumnGroup {
+                    positions,
+                    null_if_empty: true,
+                    collapsed: None,
+                },
+            );
+        }
+        if let Some(collapsed) = &spec.collapse_key {
+            // The collapsed column takes the place of the first key column, and components
+            // coalesced with other columns leave those to the others.
+            let first = groups
+                .iter()
+                .position(|group| group.positions.contains(&key_positions[0]))
+                .unwrap();
+            for group in groups.iter_mut() {
+                group.positions.retain(|pos| !key_positions.contains(pos));
+            }
+            let first = first
+                - groups[..first]
+                    .iter()
+                    .filter(|group| group.positions.is_empty())
+                    .count();
+            groups.retain(|group| !group.positions.is_empty());
+            groups.insert(
+                first,
+                ColumnGroup {
+                    positions: key_positions.clone(),
+                    null_if_empty: false,
+                    collapsed: Some(collapsed.clone()),
+                },
+            );
+        }
+        if !spec.column_order.is_empty() {
+            let names: Vec<_> = groups
+                .iter()
+                .map(|group| group.name(columns).to_owned())
+                .collect();
+            let permutation = column_permutation(&spec.column_order, &names)?;
+            groups = permutation
+                .into_iter()
+                .map(|idx| std::mem::replace(&mut groups[idx], ColumnGroup::new(Vec::new())))
+                .collect();
+        }
+        let row_number = spec.row_number.as_ref();
+        let offset = usize::from(row_number.is_some());
+        let header = row_number
+            .cloned()
+            .into_iter()
+            .chain(groups.iter().map(|group| group.name(columns).to_owned()))
+            .collect();
+        let mut key_cols: Vec<_> = key_positions
             .iter()
-            .map(|source| source.projections.len())
-            .sum::<usize>();
-    let mut writer = Writer::from_path(&spec.output)?;
+            .map(|pos| {
+                offset
+                    + groups
+                        .iter()
+                        .position(|group| group.positions.contains(pos))
+                        .unwrap()
+            })
+            .collect();
+        // The collapsed column is the one key column.
+        key_cols.dedup();
+        let reshape = row_number.is_some()
+            || groups
+                .iter()
+                .enumerate()
+                .any(|(idx, group)| group.positions[..] != [idx] || group.null_if_empty);
+        Ok(Self {
+            header,
+            key_cols,
+            groups,
+            numbered: row_number.is_some(),
+            reshape,
+        })
+    }
 
-    let mut row = Vec::with_capacity(num_cols);
-    for col in spec.key.iter() {
-        row.push(col.clone());
+    /// Arranges a joined row into an output row, using `ordered` as scratch space if needed.
+    fn arrange<'r>(
+        &self,
+        row: &'r mut [Option<String>],
+        ordered: &'r mut Vec<Option<String>>,
+        row_number: usize,
+    ) -> &'r [Option<String>] {
+        if !self.reshape {
+            return row;
+        }
+        ordered.clear();
+        if self.numbered {
+            ordered.push(Some(row_number.to_string()));
+        }
+        ordered.extend(self.groups.iter().map(|group| match &group.collapsed {
+            Some(collapsed) => Some(collapse(row, &group.positions, &collapsed.sep)),
+            None => coalesce(row, group),
+        }));
+        ordered
     }
+}
+
+/// What a column of the output holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ColumnKind {
+    Key,
+    Projection,
+    FuzzyFlag,
+    MatchFlag,
+    DupFlag,
+    Provenance,
+    /// Whether the key is present in a source, under `join: presence`.
+    Presence,
+    RowNumber,
+    ChangeType,
+}
+
+/// A column of joined rows, before they are laid out, with the positions of the sources its
+/// values come from; key columns come from every source, and name none.
+struct JoinedColumn {
+    name: String,
+    kind: ColumnKind,
+    sources: Vec<usize>,
+}
+
+impl JoinedColumn {
+    fn new(name: String, kind: ColumnKind, sources: Vec<usize>) -> Self {
+        Self {
+            name,
+            kind,
+            sources,
+        }
+    }
+}
+
+fn column_names(columns: Vec<JoinedColumn>) -> Vec<String> {
+    columns.into_iter().map(|col| col.name).collect()
+}
+
+/// The columns of joined rows of a keyed or cross join: the key columns, named by
+/// `key_header`, the projections of the sources in spec order, then the fuzzy, match and
+/// duplicate flags and the provenance columns.
+fn joined_columns(spec: &JoinSpec, key_header: Vec<String>) -> Vec<JoinedColumn> {
+    let mut columns: Vec<_> = key_header
+        .into_iter()
+        .map(|name| JoinedColumn::new(name, ColumnKind::Key, Vec::new()))
+        .collect();
+    for (idx, source) in spec.sources.iter().enumerate() {
+        columns.extend(source.projections.values().map(|col| {
+            JoinedColumn::new(col.name().to_owned(), ColumnKind::Projection, vec![idx])
+        }));
+    }
+    let fuzzy_flag = spec
+        .fuzzy
+        .as_ref()
+        .and_then(|fuzzy| fuzzy.flag_column.clone());
+    columns
+        .extend(fuzzy_flag.map(|name| JoinedColumn::new(name, ColumnKind::FuzzyFlag, Vec::new())));
+    // A cross join has no match flags, as every row of a source is combined with every other.
+    if !spec.key.is_empty() {
+        for (idx, source) in spec.sources.iter().enumerate().skip(1) {
+            columns.extend(
+                spec.match_flag(source)
+                    .map(|name| JoinedColumn::new(name, ColumnKind::MatchFlag, vec![idx])),
+            );
+        }
+    }
+    columns.extend(
+        spec.dup_flag_column
+            .clone()
+            .map(|name| JoinedColumn::new(name, ColumnKind::DupFlag, vec![0])),
+    );
+    for (idx, source) in spec.sources.iter().enumerate() {
+        columns.extend(
+            spec.provenance_column(source)
+                .map(|name| JoinedColumn::new(name, ColumnKind::Provenance, vec![idx])),
+        );
+    }
+    columns
+}
+
+/// The columns of the output of `join: presence`: the `row_number`, if any, the key columns and
+/// a column named after each source.
+fn presence_columns(spec: &JoinSpec, key_header: Vec<String>) -> Vec<JoinedColumn> {
+    let mut columns: Vec<_> = spec
+        .row_number
+        .clone()
+        .map(|name| JoinedColumn::new(name, ColumnKind::RowNumber, Vec::new()))
+        .into_iter()
+        .collect();
+    columns.extend(
+        key_header
+            .into_iter()
+            .map(|name| JoinedColumn::new(name, ColumnKind::Key, Vec::new())),
+    );
+    columns.extend(spec.sources.iter().enumerate().map(|(idx, source)| {
+        JoinedColumn::new(source.name().into_owned(), ColumnKind::Presence, vec![idx])
+    }));
+    columns
+}
+
+/// The positions in joined rows of the columns of each of `coalesce_columns`, in priority
+/// order. The projections of the sources follow the key columns, in spec order.
+fn coalesced_positions(spec: &JoinSpec) -> Vec<Vec<usize>> {
+    let mut starts = Vec::with_capacity(spec.sources.len());
+    let mut start = spec.key.len();
     for source in spec.sources.iter() {
-        for col in source.projections.values() {
-            row.push(col.clone());
+        starts.push(start);
+        start += source.projections.len();
+    }
+    spec.coalesce_columns
+        .iter()
+        .map(|col| {
+            col.from
+                .iter()
+                .filter_map(|from| {
+                    let idx = spec
+                        .sources
+                        .iter()
+                        .position(|source| source.name() == from.source)?;
+                    let pos = spec.sources[idx].projections.iter().position(
+                        |(proj_from, projected)| from.is_projected(proj_from, projected, &col.name),
+                    )?;
+                    Some(starts[idx] + pos)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// What is shared by the joining of the primary rows with the lookup sources, which the workers
+/// of `--parallel-join` do at once.
+struct RowJoiner<'a> {
+    spec: &'a JoinSpec,
+    lookups: &'a [Table],
+    /// The files of each lookup source with `lazy_projections`, to read its projections from.
+    lazy: Vec<Option<LazyProjections<'a>>>,
+    fuzzy_indexes: Vec<FuzzyIndex>,
+    joins: Vec<JoinType>,
+    match_flags: Vec<Option<String>>,
+    /// The positions in primary projections of the keys of the lookup sources with a match_on.
+    match_groups: Vec<Option<Range<usize>>>,
+    /// The positions in the key of the components each lookup source is keyed by, if only some.
+    key_subsets: Vec<Option<Vec<usize>>>,
+    /// The number of rows of each key of the primary source, for the dup_flag_column.
+    key_counts: HashMap<&'a Key, usize>,
+    number_formats: Vec<(usize, &'a ProjectedColumn)>,
+    /// Whether rows without a match in some lookup source are rejected, even if left joined.
+    rejects: bool,
+    width: usize,
+}
+
+/// A primary row joined with the lookup sources, before it is numbered and written.
+struct JoinedRow {
+    /// The joined row, or `None` if the lookup source after those in `matches` has no row for
+    /// it, which drops or rejects it.
+    row: Option<Vec<Option<String>>>,
+    /// The position of the row matched in each lookup source, if any.
+    matches: Vec<Option<usize>>,
+    /// The lookup sources whose row was matched fuzzily, with its position.
+    fuzzy: Vec<(usize, usize)>,
+}
+
+impl RowJoiner<'_> {
+    /// The key that a lookup source is looked up by for a primary row.
+    fn lookup_key<'k>(
+        &self,
+        source_idx: usize,
+        primary_key: &'k Key,
+        projection: &'k Projection,
+    ) -> Cow<'k, [String]> {
+        if let Some(subset) = &self.key_subsets[source_idx] {
+            return subset.iter().map(|&pos| primary_key[pos].clone()).collect();
+        }
+        match &self.match_groups[source_idx] {
+            Some(range) => Cow::Borrowed(&projection[range.clone()]),
+            None => Cow::Borrowed(primary_key),
+        }
+    }
+
+    /// Looks up the position and projection of a key in a lookup source.
+    fn get_full(
+        &self,
+        source_idx: usize,
+        key: &[String],
+    ) -> Result<Option<(usize, Cow<'_, Projection>)>> {
+        let table = &self.lookups[source_idx];
+        let Some(lazy) = &self.lazy[source_idx] else {
+            return table.get_full(key);
+        };
+        match table.offset_of(key) {
+            Some((idx, offset)) => Ok(Some((idx, Cow::Owned(lazy.read(offset, key)?)))),
+            None => Ok(None),
+        }
+    }
+
+    /// The projection at the given position in a lookup source.
+    fn projection_at(&self, source_idx: usize, idx: usize) -> Result<Cow<'_, Projection>> {
+        let table = &self.lookups[source_idx];
+        match &self.lazy[source_idx] {
+            Some(lazy) => Ok(Cow::Owned(
+                lazy.read(table.offset_at(idx), &table.key_at(idx)?)?,
+            )),
+            None => table.projection_at(idx),
+        }
+    }
+
+    fn join(&self, primary_key: &Key, projection: &Projection) -> Result<JoinedRow> {
+        let spec = self.spec;
+        let mut row = Vec::with_capacity(self.width);
+        row.extend(primary_key.iter().cloned().map(Some));
+        let mut provenance = Vec::new();
+        extend_row(&spec.sources[0], projection, &mut row, &mut provenance);
+        let mut joined = JoinedRow {
+            row: None,
+            matches: Vec::with_capacity(self.lookups.len()),
+            fuzzy: Vec::new(),
+        };
+        let mut flags = Vec::new();
+        for source_idx in 0..self.lookups.len() {
+            let source = &spec.sources[source_idx + 1];
+            let key = self.lookup_key(source_idx, primary_key, projection);
+            let found = match self.get_full(source_idx, &key)? {
+                Some(found) => Some(found),
+                None => match self
+                    .fuzzy_indexes
+                    .get(source_idx)
+                    .and_then(|index| index.lookup(&key))
+                {
+                    Some(idx) => {
+                        joined.fuzzy.push((source_idx, idx));
+                        Some((idx, self.projection_at(source_idx, idx)?))
+                    }
+                    None => None,
+                },
+            };
+            if self.match_flags[source_idx].is_some() {
+                flags.push(Some(match found.is_some() {
+                    true => spec.present_value.clone(),
+                    false => spec.absent_value.clone(),
+                }));
+            }
+            match found {
+                Some((idx, projection)) => {
+                    extend_row(source, &projection, &mut row, &mut provenance);
+                    joined.matches.push(Some(idx));
+                }
+                None if self.joins[source_idx] == JoinType::Left && !self.rejects => {
+                    row.extend(
+                        source
+                            .projections
+                            .values()
+                            .map(|col| col.default().map(str::to_owned)),
+                    );
+                    if source.emit_provenance {
+                        provenance.push(None);
+                    }
+                    joined.matches.push(None);
+                }
+                None => return Ok(joined),
+            }
+        }
+        if spec
+            .fuzzy
+            .as_ref()
+            .is_some_and(|fuzzy| fuzzy.flag_column.is_some())
+        {
+            let sources: Vec<_> = joined
+                .fuzzy
+                .iter()
+                .map(|&(source_idx, _)| spec.sources[source_idx + 1].name())
+                .collect();
+            row.push(Some(sources.join(",")));
+        }
+        row.append(&mut flags);
+        if spec.dup_flag_column.is_some() {
+            row.push(Some(match self.key_counts[primary_key] > 1 {
+                true => spec.present_value.clone(),
+                false => spec.absent_value.clone(),
+            }));
+        }
+        row.append(&mut provenance);
+        format_numbers(spec, &self.number_formats, &mut row)?;
+        joined.row = Some(row);
+        Ok(joined)
+    }
+}
+
+/// Rows buffered between each worker of `--parallel-join` and the writer.
+const JOINED_ROWS_BUFFER: usize = 1024;
+
+/// Joins every primary row, passing the rows to `handle` in the order of the primary source.
+///
+/// With more than one thread, the primary keys are split by hash among that many workers, each
+/// sending its rows in order through its own channel, so that the writer can take every row
+/// from the channel of its key's worker and the output is the same as on one thread. Each key
+/// is hashed once, up front, into the positions of the rows of each worker.
+fn join_rows(
+    joiner: &RowJoiner,
+    primary: &Primary,
+    threads: usize,
+    mut handle: impl FnMut(&Key, &Projection, JoinedRow) -> Result<()>,
+) -> Result<()> {
+    if threads <= 1 {
+        for (key, projection) in primary.iter() {
+            handle(key, projection, joiner.join(key, projection)?)?;
+        }
+        return Ok(());
+    }
+    let shards: Vec<usize> = primary
+        .iter()
+        .map(|(key, _)| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            (hasher.finish() % threads as u64) as usize
+        })
+        .collect();
+    let mut rows = vec![Vec::new(); threads];
+    for (idx, &shard) in shards.iter().enumerate() {
+        rows[shard].push(idx);
+    }
+    std::thread::scope(|scope| {
+        let receivers: Vec<_> = rows
+            .into_iter()
+            .map(|rows| {
+                let (sender, receiver) = mpsc::sync_channel(JOINED_ROWS_BUFFER);
+                scope.spawn(move || {
+                    for idx in rows {
+                        let (key, projection) = primary.get(idx);
+                        let joined = joiner.join(key, projection);
+                        let failed = joined.is_err();
+                        // The writer stops receiving once it fails.
+                        if sender.send(joined).is_err() || failed {
+                            break;
+                        }
+                    }
+                });
+                receiver
+            })
+            .collect();
+        for ((key, projection), &shard) in primary.iter().zip(&shards) {
+            let joined = receivers[shard]
+                .recv()
+                .expect("a worker sends every row of its keys unless one fails")?;
+            handle(key, projection, joined)?;
+        }
+        Ok(())
+    })
+}
+
+fn write_output(
+    spec: &JoinSpec,
+    key_header: Vec<String>,
+    input: JoinInput,
+    only_keys: Option<&HashSet<Key>>,
+    threads: usize,
+    log: &Logger,
+) -> Result<usize> {
+    let columns = column_names(joined_columns(spec, key_header));
+    let fuzzy = spec.fuzzy.as_ref();
+    let match_flags: Vec<_> = spec.sources[1..]
+        .iter()
+        .map(|source| spec.match_flag(source))
+        .collect();
+    let mut audit = match fuzzy.and_then(|fuzzy| fuzzy.audit.as_ref()) {
+        Some(path) => {
+            let mut audit = csv::Writer::from_path(path)
+                .with_context(|| format!("{}: cannot create audit file", path.display()))?;
+            let mut header = vec!["source".to_owned()];
+            header.extend(columns[..spec.key.len()].iter().cloned());
+            header.extend(
+                columns[..spec.key.len()]
+                    .iter()
+                    .map(|col| format!("matched_{}", col)),
+            );
+            audit.write_record(&header)?;
+            Some(audit)
+        }
+        None => None,
+    };
+    // The rejects have the key and the primary projections, as read.
+    let primary_len = spec.sources[0].projections.len();
+    let mut rejects = match &spec.rejects {
+        Some(path) => {
+            let mut rejects = csv::Writer::from_path(path)
+                .with_context(|| format!("{}: cannot create rejects file", path.display()))?;
+            rejects.write_record(&columns[..spec.key.len() + primary_len])?;
+            Some(rejects)
+        }
+        None => None,
+    };
+    let mut rejected = 0usize;
+    let fuzzy_indexes = match fuzzy {
+        Some(fuzzy) => input
+            .lookups
+            .iter()
+            .map(|table| FuzzyIndex::new(table, fuzzy.max_distance))
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    let mut fuzzy_matches = vec![0usize; fuzzy_indexes.len()];
+
+    let layout = Layout::new(spec, &columns)?;
+    let mut changes = Changes::load(spec, &layout)?;
+    let header = changes.as_ref().map_or(&layout.header[..], Changes::header);
+    let mut writers = spec
+        .outputs()
+        .into_iter()
+        .map(|output| OutputWriter::open(output, &layout.key_cols, header))
+        .collect::<Result<Vec<_>>>()?;
+    let mut ordered = Vec::with_capacity(layout.header.len());
+
+    let track_coverage = spec.coverage_check != CoverageCheck::Ignore;
+    let mut matched: Vec<Vec<bool>> = match track_coverage {
+        true => input
+            .lookups
+            .iter()
+            .map(|source_data| vec![false; source_data.len()])
+            .collect(),
+        false => Vec::new(),
+    };
+
+    let progress = log.progress_bar(
+        ProgressKind::Rows,
+        input.primary.len() as u64,
+        "writing".to_owned(),
+    );
+    // Lookup sources with a match_on take their keys from the values kept after the projection
+    // of primary rows, in source order.
+    let mut match_keys = 0..;
+    let match_groups = spec.sources[1..]
+        .iter()
+        .map(|source| {
+            (!source.match_on.is_empty()).then(|| {
+                let start = primary_len + match_keys.next().unwrap() * spec.key.len();
+                start..start + spec.key.len()
+            })
+        })
+        .collect();
+    let mut key_counts: HashMap<&Key, usize> = HashMap::new();
+    if spec.dup_flag_column.is_some() {
+        for (key, _) in input.primary.iter() {
+            *key_counts.entry(key).or_default() += 1;
+        }
+    }
+    // The records of sources with lazy_projections are read again as when they were read.
+    let errors = ErrorCollector::new(None);
+    let ctx = ReadContext {
+        spec,
+        dictionaries: &input.dictionaries,
+        match_on: &[],
+        keep_all: false,
+        only_keys: None,
+        errors: &errors,
+        reread: true,
+    };
+    let lazy = spec.sources[1..]
+        .iter()
+        .zip(&input.files[1..])
+        .map(|(source, files)| {
+            source
+                .lazy_projections
+                .then(|| LazyProjections::open(source, files, &ctx))
+                .transpose()
+        })
+        .collect::<Result<_>>()?;
+    let joiner = RowJoiner {
+        spec,
+        lookups: &input.lookups,
+        lazy,
+        fuzzy_indexes,
+        joins: spec.sources[1..]
+            .iter()
+            .map(|source| source.join.unwrap_or(spec.join))
+            .collect(),
+        match_flags,
+        match_groups,
+        key_subsets: spec.sources[1..]
+            .iter()
+            .map(|source| spec.key_subset(source))
+            .collect(),
+        key_counts,
+        number_formats: number_formats(spec, spec.key.len()),
+        rejects: rejects.is_some(),
+        width: columns.len(),
+    };
+    let mut unmatched = vec![0usize; input.lookups.len()];
+    let mut written = 0usize;
+    join_rows(
+        &joiner,
+        &input.primary,
+        threads,
+        |primary_key, projection, joined| {
+            progress.inc(1);
+            for &(source_idx, idx) in joined.fuzzy.iter() {
+                fuzzy_matches[source_idx] += 1;
+                if let Some(audit) = &mut audit {
+                    let source = &spec.sources[source_idx + 1];
+                    let key = joiner.lookup_key(source_idx, primary_key, projection);
+                    let mut record = vec![source.name().into_owned()];
+                    record.extend(key.iter().cloned());
+                    record.extend(input.lookups[source_idx].key_at(idx)?.iter().cloned());
+                    audit.write_record(&record)?;
+                }
+            }
+            if track_coverage {
+                for (source_idx, idx) in joined.matches.iter().enumerate() {
+                    if let Some(idx) = idx {
+                        matched[source_idx][*idx] = true;
+                    }
+                }
+            }
+            let Some(mut row) = joined.row else {
+                unmatched[joined.matches.len()] += 1;
+                if let Some(rejects) = &mut rejects {
+                    rejects.write_record(primary_key.iter().chain(&projection[..primary_len]))?;
+                    rejected += 1;
+                }
+                return Ok(());
+            };
+            // Each column of the header is expanded from the projections as the rows are, so a
+            // row of another width would be written shifted under the header.
+            if row.len() != columns.len() {
+                bail!(InternalError(format!(
+                    "the joined row of key {:?} has {} field(s), but the header has {}",
+                    primary_key,
+                    row.len(),
+                    columns.len()
+                )));
+            }
+            let row = layout.arrange(&mut row, &mut ordered, written + 1);
+            let Some(row) = filter_changes(&mut changes, row) else {
+                return Ok(());
+            };
+            interrupt::check(|| format!("writing {} row(s)", written))?;
+            for writer in writers.iter_mut() {
+                writer.write_record(row)?;
+            }
+            written += 1;
+            Ok(())
+        },
+    )?;
+    if let Some(changes) = &changes {
+        changes.write_deleted(only_keys, &mut writers, &mut written)?;
+    }
+    finish_outputs(spec, writers, written, log)?;
+    progress.finish_and_clear();
+    log.info(format_args!("wrote {} row(s)", written));
+    if let Some(changes) = &changes {
+        changes.report(log);
+    }
+    for (source, count) in spec.sources[1..].iter().zip(unmatched) {
+        if count > 0 {
+            log.info(format_args!(
+                "{}: {} {} primary row(s) without a match",
+                source.path.display(),
+                match rejects {
+                    Some(_) => "rejected",
+                    None => "dropped",
+                },
+                count
+            ));
+        }
+    }
+    if let (Some(rejects), Some(path)) = (&mut rejects, &spec.rejects) {
+        rejects.flush()?;
+        tracing::warn!(
+            "wrote {} row(s) to the output and {} to {}",
+            written,
+            rejected,
+            path.display()
+        );
+    }
+    if let Some(audit) = &mut audit {
+        audit.flush()?;
+    }
+    for (source, count) in spec.sources[1..].iter().zip(fuzzy_matches) {
+        if count > 0 {
+            tracing::warn!(
+                "{}: matched {} key(s) fuzzily",
+                source.path.display(),
+                count
+            );
+        }
+    }
+
+    if track_coverage {
+        check_coverage(spec, &input, &matched, log)?;
+    }
+
+    Ok(written)
+}
+
+/// The row to write for an output row under `changed_since`, or `None` if it didn't change.
+fn filter_changes<'r>(
+    changes: &'r mut Option<Changes>,
+    row: &'r [Option<String>],
+) -> Option<&'r [Option<String>]> {
+    match changes {
+        Some(changes) => changes.filter(row),
+        None => Some(row),
+    }
+}
+
+/// Finishes all outputs, committing their files only once every one of them was written and
+/// the number of rows written is as expected.
+fn finish_outputs(
+    spec: &JoinSpec,
+    writers: Vec<OutputWriter>,
+    written: usize,
+    log: &Logger,
+) -> Result<()> {
+    let mut pending = Vec::new();
+    for writer in writers {
+        pending.extend(writer.finish(log)?);
+    }
+    if written == 0 {
+        let join = match spec.key.is_empty() {
+            true => "cross".to_owned(),
+            false => spec.join.to_string(),
+        };
+        let message = format!("the join produced no rows (join: {})", join);
+        match spec.empty_output {
+            EmptyPolicy::Error => bail!(ExpectationError(message)),
+            EmptyPolicy::Warn => tracing::warn!("warning: {}", message),
+            EmptyPolicy::Allow => {}
+        }
+    }
+    if let Some(expected) = spec
+        .expect_rows
+        .and_then(|bounds| bounds.violation(written))
+    {
+        bail!(ExpectationError(format!(
+            "produced {} row(s), but expect_rows requires {}",
+            written, expected
+        )));
+    }
+    for file in pending {
+        file.commit(written)?;
+    }
+    Ok(())
+}
+
+/// The projections with a number format, with their positions in joined rows that start with
+/// `offset` key columns.
+fn number_formats(spec: &JoinSpec, offset: usize) -> Vec<(usize, &ProjectedColumn)> {
+    spec.sources
+        .iter()
+        .flat_map(|source| source.projections.values())
+        .enumerate()
+        .filter(|(_, col)| col.number_format().is_some())
+        .map(|(idx, col)| (offset + idx, col))
+        .collect()
+}
+
+/// Renders the values of the projections with a number format in a joined row.
+fn format_numbers(
+    spec: &JoinSpec,
+    formats: &[(usize, &ProjectedColumn)],
+    row: &mut [Option<String>],
+) -> Result<()> {
+    for &(idx, col) in formats {
+        let Some(value) = &mut row[idx] else {
+            continue;
+        };
+        match col.number_format().unwrap().apply(value) {
+            Some(formatted) => *value = formatted,
+            None if spec.on_format_error == TypeErrorPolicy::Passthrough => {}
+            None => bail!(DataError(format!(
+                "projection {}: cannot format {:?} as a number",
+                col.name(),
+                value
+            ))),
+        }
+    }
+    Ok(())
+}
+
+/// Appends the projected values of a row of the source to `row`, and its provenance, if the
+/// source has `emit_provenance`, to `provenance`.
+fn extend_row(
+    source: &Source,
+    projection: &[String],
+    row: &mut Vec<Option<String>>,
+    provenance: &mut Vec<Option<String>>,
+) {
+    let len = source.projections.len();
+    row.extend(projection[..len].iter().cloned().map(Some));
+    if source.emit_provenance {
+        provenance.push(projection.last().cloned());
+    }
+}
+
+/// Writes every combination of one row from each source, for a spec without a key.
+fn write_cross(spec: &JoinSpec, input: JoinInput, log: &Logger) -> Result<usize> {
+    let columns = column_names(joined_columns(spec, Vec::new()));
+    let primary = input.primary.keyed();
+    let lens: Vec<_> = std::iter::once(primary.len())
+        .chain(input.lookups.iter().map(Table::len))
+        .collect();
+    let total = lens
+        .iter()
+        .fold(1u128, |total, &len| total.saturating_mul(len as u128));
+    if let Some(max_rows) = spec.max_rows {
+        if total > max_rows as u128 {
+            bail!(
+                "the cross join would produce {} row(s), more than max_rows of {}",
+                total,
+                max_rows
+            );
         }
     }
-    writer.write_record(&row)?;
+    log.info(format_args!("cross join of {} row(s)", total));
 
-    for (key, projection) in input[0].iter() {
+    let layout = Layout::new(spec, &columns)?;
+    let mut writers = spec
+        .outputs()
+        .into_iter()
+        .map(|output| OutputWriter::open(output, &layout.key_cols, &layout.header))
+        .collect::<Result<Vec<_>>>()?;
+    let mut row: Vec<Option<String>> = Vec::with_capacity(columns.len());
+    let mut ordered = Vec::with_capacity(layout.header.len());
+
+    let progress = log.progress_bar(
+        ProgressKind::Rows,
+        total.min(u64::MAX as u128) as u64,
+        "writing".to_owned(),
+    );
+    let number_formats = number_formats(spec, 0);
+    // Row positions within each source, advanced like an odometer so that rows of the last
+    // source vary fastest.
+    let mut positions = vec![0usize; lens.len()];
+    let mut provenance = Vec::new();
+    let mut written = 0usize;
+    for _ in 0..total {
         row.clear();
-        row.extend_from_slice(key);
-        row.extend_from_slice(projection);
-        for source_data in &input[1..] {
-            row.extend_from_slice(source_data.get(key).unwrap());
+        let (_, projection) = primary.get_index(positions[0]).unwrap();
+        extend_row(&spec.sources[0], projection, &mut row, &mut provenance);
+        for ((table, &pos), source) in input
+            .lookups
+            .iter()
+            .zip(&positions[1..])
+            .zip(&spec.sources[1..])
+        {
+            extend_row(
+                source,
+                &table.projection_at(pos)?,
+                &mut row,
+                &mut provenance,
+            );
+        }
+        row.append(&mut provenance);
+        format_numbers(spec, &number_formats, &mut row)?;
+        let row = layout.arrange(&mut row, &mut ordered, written + 1);
+        interrupt::check(|| format!("writing {} row(s)", written))?;
+        for writer in writers.iter_mut() {
+            writer.write_record(row)?;
+        }
+        written += 1;
+        progress.inc(1);
+        for (pos, &len) in positions.iter_mut().zip(&lens).rev() {
+            *pos += 1;
+            if *pos < len {
+                break;
+            }
+            *pos = 0;
         }
-        writer.write_record(&row)?;
     }
+    finish_outputs(spec, writers, written, log)?;
+    progress.finish_and_clear();
+    log.info(format_args!("wrote {} row(s)", written));
+    Ok(written)
+}
+
+/// Writes one row per distinct key across all sources, with a column per source telling whether
+/// the key is present in it.
+fn write_presence(
+    spec: &JoinSpec,
+    key_header: Vec<String>,
+    input: JoinInput,
+    log: &Logger,
+) -> Result<usize> {
+    let row_number = spec.row_number.as_ref();
+    let offset = usize::from(row_number.is_some());
+    let header = column_names(presence_columns(spec, key_header));
+    let key_cols: Vec<_> = (offset..offset + spec.key.len()).collect();
+    let mut writers = spec
+        .outputs()
+        .into_iter()
+        .map(|output| OutputWriter::open(output, &key_cols, &header))
+        .collect::<Result<Vec<_>>>()?;
 
+    let primary = input.primary.keyed();
+    let mut written = 0usize;
+    let mut write_key = |key: &Key| -> Result<()> {
+        let mut row = Vec::with_capacity(header.len());
+        if row_number.is_some() {
+            row.push(Some((written + 1).to_string()));
+        }
+        row.extend(key.iter().cloned().map(Some));
+        let flag = |present| match present {
+            true => Some(spec.present_value.clone()),
+            false => Some(spec.absent_value.clone()),
+        };
+        row.push(flag(primary.contains_key(key)));
+        for table in input.lookups.iter() {
+            row.push(flag(table.get_full(key)?.is_some()));
+        }
+        interrupt::check(|| format!("writing {} row(s)", written))?;
+        for writer in writers.iter_mut() {
+            writer.write_record(&row)?;
+        }
+        written += 1;
+        Ok(())
+    };
+    for key in primary.keys() {
+        write_key(key)?;
+    }
+    for (source_idx, table) in input.lookups.iter().enumerate() {
+        'keys: for idx in 0..table.len() {
+            let key = table.key_at(idx)?;
+            // Keys present in an earlier source were already written.
+            if primary.contains_key(key.as_ref()) {
+                continue;
+            }
+            for earlier in input.lookups[..source_idx].iter() {
+                if earlier.get_full(&key)?.is_some() {
+                    continue 'keys;
+                }
+            }
+            write_key(&key)?;
+        }
+    }
+    finish_outputs(spec, writers, written, log)?;
+    log.info(format_args!("wrote {} distinct key(s)", written));
+    Ok(written)
+}
+
+const COVERAGE_SAMPLE_SIZE: usize = 5;
+
+fn check_coverage(
+    spec: &JoinSpec,
+    input: &JoinInput,
+    matched: &[Vec<bool>],
+    log: &Logger,
+) -> Result<()> {
+    let uncovered = input
+        .lookups
+        .iter()
+        .zip(matched)
+        .map(|(source_data, matched)| {
+            let unmatched = matched.iter().filter(|&&hit| !hit).count();
+            let sample = matched
+                .iter()
+                .enumerate()
+                .filter(|(_, &hit)| !hit)
+                .map(|(idx, _)| source_data.key_at(idx).map(Cow::into_owned))
+                .take(COVERAGE_SAMPLE_SIZE)
+                .collect::<Result<Vec<_>>>()?;
+            Ok((unmatched, sample))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    report_coverage(spec, &uncovered, log)
+}
+
+/// Reports the keys of each lookup source missing from the primary source, given how many there
+/// are along with a sample of them.
+fn report_coverage(spec: &JoinSpec, uncovered: &[(usize, Vec<Key>)], log: &Logger) -> Result<()> {
+    let mut uncovered_sources = 0;
+    for (source, (unmatched, sample)) in spec.sources[1..].iter().zip(uncovered) {
+        if *unmatched == 0 {
+            log.info(format_args!(
+                "{}: all keys present in the primary source",
+                source.path.display()
+            ));
+            continue;
+        }
+        uncovered_sources += 1;
+        tracing::warn!(
+            "{}: {} key(s) not present in the primary source, e.g. {:?}",
+            source.path.display(),
+            unmatched,
+            sample
+        );
+    }
+
+    if spec.coverage_check == CoverageCheck::Error && uncovered_sources > 0 {
+        bail!(DataError(format!(
+            "coverage check failed: {} secondary source(s) have keys missing from the primary source",
+            uncovered_sources
+        )));
+    }
     Ok(())
 }
 
-fn load_spec(reader: impl Read) -> Result<JoinSpec> {
-    Ok(from_reader(reader)?)
+/// Warns about distinct keys of the sources that are the same once their components are trimmed,
+/// casefolded and, where they are numbers, parsed. Sources with a `range` are left out, as their
+/// keys hold bounds rather than values.
+fn warn_near_duplicate_keys(spec: &JoinSpec, input: &JoinInput) -> Result<()> {
+    let near = |key: &Key| -> Key {
+        key.iter()
+            .map(|value| {
+                let value = value.trim().to_lowercase();
+                match value.parse::<f64>() {
+                    Ok(number) if number.is_finite() => number.to_string(),
+                    _ => value,
+                }
+            })
+            .collect()
+    };
+    // The positions of the sources of each distinct key, by its near form.
+    let mut groups: HashMap<Key, IndexMap<Key, Vec<usize>>> = HashMap::new();
+    let mut add = |key: &Key, source_idx: usize| {
+        let sources = groups.entry(near(key)).or_default();
+        let sources = sources.entry(key.clone()).or_default();
+        if !sources.contains(&source_idx) {
+            sources.push(source_idx);
+        }
+    };
+    for (key, _) in input.primary.iter() {
+        add(key, 0);
+    }
+    for (source_idx, table) in input.lookups.iter().enumerate() {
+        if spec.sources[source_idx + 1].range.is_some() {
+            continue;
+        }
+        for idx in 0..table.len() {
+            add(table.key_at(idx)?.as_ref(), source_idx + 1);
+        }
+    }
+
+    let mut near_duplicates: Vec<_> = groups.into_values().filter(|keys| keys.len() > 1).collect();
+    near_duplicates.sort_by(|a, b| a.first().unwrap().0.cmp(b.first().unwrap().0));
+    if near_duplicates.is_empty() {
+        return Ok(());
+    }
+    tracing::warn!(
+        "warning: {} group(s) of distinct keys differ only in whitespace, case or number format, \
+         which key_normalization, key_types or trim_values can make the same, e.g.:",
+        near_duplicates.len()
+    );
+    for keys in near_duplicates.iter().take(COVERAGE_SAMPLE_SIZE) {
+        let keys: Vec<_> = keys
+            .iter()
+            .map(|(key, sources)| {
+                let sources: Vec<_> = sources
+                    .iter()
+                    .map(|&idx| spec.sources[idx].name())
+                    .collect();
+                format!("{:?} in {}", key, sources.join(", "))
+            })
+            .collect();
+        tracing::warn!("  {}", keys.join("; "));
+    }
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    let spec = match std::env::args_os().nth(1) {
-        Some(path) => load_spec(File::open(path)?)?,
-        None => load_spec(stdin())?,
+/// Keeps only the sources selected by name, path or zero-based position, in spec order, along
+/// with the `column_order` entries of the columns that remain and the `coalesce_columns` of
+/// the sources that remain.
+fn select_sources(spec: &mut JoinSpec, selected: &[String]) -> Result<()> {
+    let mut keep = vec![false; spec.sources.len()];
+    for sel in selected {
+        let idx = match sel.parse::<usize>() {
+            Ok(idx) if idx < spec.sources.len() => Some(idx),
+            _ => spec
+                .sources
+                .iter()
+                .position(|source| source.name() == *sel || source.path.as_os_str() == &**sel),
+        };
+        let Some(idx) = idx else {
+            let names: Vec<_> = spec.sources.iter().map(Source::name).collect();
+            bail!(
+                "--sources: no source {} by name, path or position; sources are: {}",
+                sel,
+                names.join(", ")
+            );
+        };
+        keep[idx] = true;
+    }
+    if !keep[0] {
+        bail!(
+            "--sources must include the primary source {}, which drives the output",
+            spec.sources[0].name()
+        );
+    }
+    let columns = |spec: &JoinSpec, source: &Source| -> Vec<String> {
+        let projections = source
+            .projections
+            .values()
+            .map(|col| source.output_name(col).into_owned());
+        projections
+            .chain(spec.match_flag(source))
+            .chain(spec.provenance_column(source))
+            .collect()
     };
-    let input = read_input(&spec)?;
-    write_output(&spec, input)?;
+    let mut dropped = HashSet::new();
+    let mut kept = HashSet::new();
+    for (source, &keep) in spec.sources.iter().zip(&keep) {
+        match keep {
+            true => kept.extend(columns(spec, source)),
+            false => dropped.extend(columns(spec, source)),
+        }
+    }
+    let mut keep_source = keep.iter();
+    let kept_names: HashSet<_> = spec
+        .sources
+        .iter()
+        .filter(|_| *keep_source.next().unwrap())
+        .map(|source| source.name().into_owned())
+        .collect();
+    for col in spec.coalesce_columns.iter_mut() {
+        col.from.retain(|from| kept_names.contains(&from.source));
+        match col.from.is_empty() {
+            true => dropped.insert(col.name.clone()),
+            false => kept.insert(col.name.clone()),
+        };
+    }
+    spec.coalesce_columns.retain(|col| !col.from.is_empty());
+    spec.column_order
+        .retain(|col| kept.contains(col) || !dropped.contains(col));
+    let mut keep = keep.into_iter();
+    spec.sources.retain(|_| keep.next().unwrap());
     Ok(())
 }
+
+/// Prints the header of a file as the join would read it, along with its record count.
+fn describe_columns(path: PathBuf, encoding: Option<String>) -> Result<()> {
+    let source = Source {
+        path,
+        encoding,
+        ..Default::default()
+    };
+    source.encoding()?;
+    let mut records = RecordReader::open(&source, &source.path)?;
+    for (idx, col) in records.header().iter().enumerate() {
+        println!("{}\t{}", idx, col);
+    }
+    let mut count = 0usize;
+    for record in records.by_ref() {
+        if let Err(err) = record {
+            let location = record_location(count + 1, &err);
+            return Err(err).with_context(|| {
+                format!("{}: failed to parse {}", source.path.display(), location)
+            });
+        }
+        count += 1;
+    }
+    println!("{} record(s)", count);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    logging::init_tracing(args.log);
+    let error_format = args.error_format;
+    if error_format == ErrorFormat::Json {
+        error::report_panics_as_json();
+    }
+    let result = match args.watch {
+        true => watch::watch(args),
+        false => run(args),
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            error::report(&err, error_format);
+            error::exit_code(&err)
+        }
+    }
+}
+
+fn run(args: Args) -> Result<ExitCode> {
+    match args.command {
+        Some(Command::Columns { path, encoding }) => {
+            describe_columns(path, encoding)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Profile {
+            path,
+            delimiter,
+            encoding,
+            key,
+            json,
+        }) => {
+            let source = Source {
+                path,
+                delimiter,
+                encoding,
+                ..Default::default()
+            };
+            profile::profile(&source, &key, json)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Diff {
+            key,
+            old,
+            new,
+            columns,
+            delimiter,
+            encoding,
+            output,
+        }) => {
+            let source = |path| Source {
+                path,
+                delimiter,
+                encoding: encoding.clone(),
+                ..Default::default()
+            };
+            let differs = diff::diff(source(old), source(new), &key, &columns, output.as_deref())?;
+            return Ok(match differs {
+                true => ExitCode::from(error::EXIT_DIFFERENCES),
+                false => ExitCode::SUCCESS,
+            });
+        }
+        None => {}
+    }
+    let mut spec = match &args.spec {
+        Some(path) => load_spec(
+            File::open(path).with_context(|| format!("{}: cannot open", path.display()))?,
+            path,
+            path.parent().unwrap_or(Path::new("")),
+            &args.params,
+        )?,
+        None => load_spec(stdin(), Path::new("<stdin>"), Path::new(""), &args.params)?,
+    };
+    tracing::info!(
+        spec = %args.spec.as_deref().unwrap_or(Path::new("<stdin>")).display(),
+        sources = spec.sources.len(),
+        "spec loaded"
+    );
+    if let Some(output) = args.output {
+        spec.output = OutputTarget::Path(output);
+    }
+    if let Some(max_memory) = args.max_memory {
+        spec.max_memory = Some(max_memory);
+    }
+    if let Some(path) = args.column_map {
+        spec.column_map = Some(path);
+    }
+    if let Some(path) = &spec.column_map {
+        let map = load_column_map(path)?;
+        spec.apply_column_map(&map);
+    }
+    if args.mkdir {
+        spec.output_options.create_dirs = true;
+        if let OutputTarget::Many(outputs) = &mut spec.output {
+            for output in outputs.iter_mut() {
+                output.options.create_dirs = true;
+            }
+        }
+    }
+    if args.force {
+        spec.output_options.overwrite = true;
+        if let OutputTarget::Many(outputs) = &mut spec.output {
+            for output in outputs.iter_mut() {
+                output.options.overwrite = true;
+            }
+        }
+    }
+    for source in spec.sources.iter_mut() {
+        if args.spec.is_none() && source.path.as_os_str() == "-" {
+            bail!("a source cannot be read from stdin when the spec is");
+        }
+        source.timeout = source.timeout.or(args.timeout);
+        if let Some(url) = input::as_url(&source.path).filter(|_| args.no_network) {
+            bail!("{}: URL sources are disabled by --no-network", url);
+        }
+    }
+    if !args.sources.is_empty() {
+        select_sources(&mut spec, &args.sources)?;
+    }
+    if args.dry_run {
+        print!("{}", serde_yaml::to_string(&spec)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    paths::normalize(&mut spec);
+    // Describing the output neither writes it nor reads the data of the sources.
+    if !args.describe {
+        for output in spec.outputs() {
+            output::prepare_dir(&output)?;
+            output::check_overwrite(&output)?;
+        }
+        for source in spec.sources.iter() {
+            check_freshness(&spec, source)?;
+        }
+    }
+    if spec.keys_only {
+        spec.drop_projections();
+    }
+    spec.project_coalesced_columns();
+    expand_projections(&mut spec)?;
+    spec.apply_prefixes();
+    if args.describe {
+        describe::describe(&spec)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let manifest = match args.manifest {
+        Some(path) => Some(path),
+        None if spec.manifest => {
+            let output = &spec.outputs()[0];
+            if output.writes_to_stdout() {
+                bail!("manifest: true requires the output to be a file; use --manifest PATH");
+            }
+            let mut path = output.path.as_os_str().to_owned();
+            path.push(".manifest.json");
+            Some(PathBuf::from(path))
+        }
+        None => None,
+    };
+    let only_keys = load_only_keys(
+        &spec,
+        &args.only_key,
+        args.key_sep.as_deref(),
+        args.only_keys_file.as_deref(),
+    )?;
+    interrupt::install();
+    let started = Utc::now();
+    let log = Logger::new(
+        args.verbose,
+        args.progress,
+        args.log_dropped.as_deref(),
+        &spec.key,
+    )?;
+    let read_threads = args.threads.map_or(1, NonZeroUsize::get);
+    let threads = args.parallel_join.map_or(1, NonZeroUsize::get);
+    if threads > 1
+        && (spec.sorted
+            || spec.key.is_empty()
+            || matches!(spec.join, JoinType::Presence | JoinType::Union))
+    {
+        tracing::warn!(
+            "warning: --parallel-join only applies to a keyed join that isn't sorted, join: \
+             presence or join: union; joining on one thread"
+        );
+    }
+    // A sorted join and a union read the sources as they write, so they are timed from the
+    // start.
+    let mut joining = Instant::now();
+    let (written, files) = match spec.sorted {
+        true => merge::write_sorted(&spec, only_keys.as_ref(), &log)?,
+        false if spec.join == JoinType::Union => {
+            union::write_union(&spec, only_keys.as_ref(), &log)?
+        }
+        false => {
+            let (key_header, input) = read_input(&spec, only_keys.as_ref(), read_threads, &log)?;
+            joining = Instant::now();
+            // Also kept in the input, for sources with lazy_projections to read again.
+            let files = input.files.clone();
+            if spec.warn_near_duplicate_keys {
+                warn_near_duplicate_keys(&spec, &input)?;
+            }
+            let written = match spec.join {
+                _ if spec.key.is_empty() => write_cross(&spec, input, &log)?,
+                JoinType::Presence => write_presence(&spec, key_header, input, &log)?,
+                _ => write_output(&spec, key_header, input, only_keys.as_ref(), threads, &log)?,
+            };
+            (written, files)
+        }
+    };
+    tracing::info!(
+        rows = written,
+        elapsed_ms = joining.elapsed().as_millis() as u64,
+        "join written"
+    );
+    log.finish()?;
+    if let Some(path) = manifest {
+        let started = Some(started).filter(|_| !args.deterministic);
+        manifest::write(&path, &spec, &files, written, started)?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,149 @@
+//! A record of the inputs, spec and outputs of a successful run, so that an output file can be
+//! traced back to exactly the inputs that produced it.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::input;
+use crate::spec::JoinSpec;
+use crate::Key;
+
+/// A file read for a source, with the number of records read from it.
+#[derive(Clone)]
+pub struct InputFile {
+    pub path: PathBuf,
+    pub rows: usize,
+    /// Records whose key was already present, replacing the earlier row.
+    pub overwritten: usize,
+    /// The first distinct keys of those records, for `require_unique_base_keys` to list.
+    pub duplicates: Vec<Key>,
+}
+
+/// Writes the manifest of a run that started at `started`, read `files` for each source, and
+/// wrote `written` rows to each output.
+///
+/// Without a start time, the manifest leaves out the times of the run and the modification
+/// times of the files, so that it only depends on the inputs.
+pub fn write(
+    path: &Path,
+    spec: &JoinSpec,
+    files: &[Vec<InputFile>],
+    written: usize,
+    started: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let sources = spec
+        .sources
+        .iter()
+        .zip(files)
+        .map(|(source, files)| {
+            Ok(json!({
+                "name": source.name(),
+                "rows": files.iter().map(|file| file.rows).sum::<usize>(),
+                "files": files
+                    .iter()
+                    .map(|file| describe_file(file, started.is_some()))
+                    .collect::<Result<Vec<_>>>()?,
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let outputs: Vec<_> = spec
+        .outputs()
+        .into_iter()
+        .map(|output| json!({ "path": output.path, "rows": written }))
+        .collect();
+    let mut manifest = json!({
+        "tool": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+        "spec": spec,
+        "sources": sources,
+        "outputs": outputs,
+    });
+    if let Some(started) = started {
+        manifest["started"] = timestamp(started).into();
+        manifest["finished"] = timestamp(Utc::now()).into();
+    }
+
+    let mut out = BufWriter::new(
+        File::create(path)
+            .with_context(|| format!("{}: cannot create manifest", path.display()))?,
+    );
+    serde_json::to_writer_pretty(&mut out, &manifest)?;
+    writeln!(out)?;
+    out.flush()
+        .with_context(|| format!("{}: cannot write manifest", path.display()))
+}
+
+fn describe_file(file: &InputFile, with_times: bool) -> Result<Value> {
+    if let Some(url) = input::as_url(&file.path) {
+        return Ok(json!({ "url": url, "rows": file.rows }));
+    }
+    let path = &file.path;
+    if path.as_os_str() == "-" {
+        return Ok(json!({ "path": "-", "rows": file.rows }));
+    }
+    let metadata = path
+        .metadata()
+        .with_context(|| format!("{}: cannot read metadata", path.display()))?;
+    let mut description = json!({
+        "path": std::path::absolute(path)?,
+        "size": metadata.len(),
+        "sha256": sha256(path)?,
+        "rows": file.rows,
+    });
+    if with_times {
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        description["modified"] = modified.map(timestamp).into();
+    }
+    Ok(description)
+}
+
+/// The hex digest of a file, read in chunks.
+fn sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("{}: cannot open", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => hasher.update(&buf[..len]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("{}: cannot read", path.display()))
+            }
+        }
+    }
+    Ok(hex(hasher))
+}
+
+fn hex(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Writes the checksum sidecar of an output file, with the number of rows written to it and
+/// the digest of its bytes.
+pub fn write_checksum(output: &Path, rows: usize, hasher: Sha256) -> Result<()> {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".sha256.json");
+    let path = PathBuf::from(path);
+    let mut out = BufWriter::new(
+        File::create(&path)
+            .with_context(|| format!("{}: cannot create checksum", path.display()))?,
+    );
+    serde_json::to_writer(&mut out, &json!({ "rows": rows, "sha256": hex(hasher) }))?;
+    writeln!(out)?;
+    out.flush()
+        .with_context(|| format!("{}: cannot write checksum", path.display()))
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
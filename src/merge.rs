@@ -0,0 +1,388 @@
+//! Streaming join of sources that are sorted by the key, for `sorted: true`.
+//!
+//! Each source is read one record at a time, and the primary source drives the output as in the
+//! hash join: every lookup source is advanced past the keys sorting before the current primary
+//! key, which are the keys missing from the primary source, and matches if its next key is equal.
+//! Consecutive records with the same key are collapsed to the last one, as the hash join keeps
+//! the last record of a key.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::io::stdin;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use csv::StringRecord;
+
+use crate::assertions::Checks;
+use crate::changes::Changes;
+use crate::dictionary::Dictionaries;
+use crate::error::{Category, ErrorCollector, RecordError};
+use crate::input::{self, RecordReader};
+use crate::interrupt;
+use crate::logging::{DropReason, Logger};
+use crate::manifest::InputFile;
+use crate::output::OutputWriter;
+use crate::spec::{CoverageCheck, JoinSpec, JoinType, Source};
+use crate::{
+    check_schema, check_unique_keys, column_names, extend_row, filter_changes, finish_outputs,
+    finish_source, format_numbers, joined_columns, number_formats, output_key_header,
+    report_coverage, warn_no_only_keys, with_read_retries, Key, Layout, Projection, ReadContext,
+    RecordParser, COVERAGE_SAMPLE_SIZE, DUPLICATE_SAMPLE_SIZE,
+};
+
+/// Orders keys by their components, under `key_types`.
+fn compare_keys(spec: &JoinSpec, a: &Key, b: &Key) -> Ordering {
+    a.iter()
+        .zip(b)
+        .enumerate()
+        .map(|(pos, (a, b))| {
+            let ty = spec.key_types.get(pos).copied().unwrap_or_default();
+            ty.compare(a, b)
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// The file of a source being read.
+struct OpenFile<'a> {
+    records: RecordReader,
+    parser: RecordParser<'a>,
+    /// The last record read, reused for every record of the file.
+    record: StringRecord,
+    idx: usize,
+    overwritten: usize,
+    /// The first distinct keys of the records that replaced an earlier one.
+    duplicates: Vec<Key>,
+}
+
+/// A source read as a stream of rows with distinct keys, in key order.
+struct SortedSource<'a> {
+    source: &'a Source,
+    ctx: &'a ReadContext<'a>,
+    paths: std::vec::IntoIter<PathBuf>,
+    file: Option<OpenFile<'a>>,
+    first: Option<(PathBuf, StringRecord)>,
+    key_header: Option<Vec<String>>,
+    checks: Checks<'a>,
+    files: Vec<InputFile>,
+    /// The row with the next key, read ahead to find the last record with the current key.
+    ahead: Option<(Key, Projection)>,
+}
+
+impl<'a> SortedSource<'a> {
+    fn open(source: &'a Source, ctx: &'a ReadContext<'a>, log: &Logger) -> Result<Self> {
+        let mut sorted = Self {
+            source,
+            ctx,
+            paths: input::source_paths(source)?.into_iter(),
+            file: None,
+            first: None,
+            key_header: None,
+            checks: Checks::new(source),
+            files: Vec::new(),
+            ahead: None,
+        };
+        sorted.ahead = sorted
+            .read(log)?
+            .map(|(key, projection, _)| (key, projection));
+        Ok(sorted)
+    }
+
+    /// The next key of the source, without consuming it.
+    fn peek(&self) -> Option<&Key> {
+        self.ahead.as_ref().map(|(key, _)| key)
+    }
+
+    /// Reads the next row of the source, with the last projection of its key.
+    fn next_row(&mut self, log: &Logger) -> Result<Option<(Key, Projection)>> {
+        let Some((key, mut projection)) = self.ahead.take() else {
+            return Ok(None);
+        };
+        while let Some((next_key, next_projection, idx)) = self.read(log)? {
+            match compare_keys(self.ctx.spec, &next_key, &key) {
+                Ordering::Equal => {
+                    let file = self.file.as_mut().unwrap();
+                    log.dropped(&file.parser.path, idx, Some(&key), DropReason::Duplicate)?;
+                    file.overwritten += 1;
+                    if file.duplicates.len() < DUPLICATE_SAMPLE_SIZE
+                        && !file.duplicates.contains(&key)
+                    {
+                        file.duplicates.push(key.clone());
+                    }
+                    projection = next_projection;
+                }
+                Ordering::Greater => {
+                    self.ahead = Some((next_key, next_projection));
+                    break;
+                }
+                Ordering::Less => {
+                    let path = &self.file.as_ref().unwrap().parser.path;
+                    bail!(RecordError::new(
+                        path,
+                        idx,
+                        format!(
+                            "key {:?} sorts before the previous key {:?}, but sorted: true \
+                             requires every source to be sorted by the key",
+                            next_key, key
+                        )
+                    )
+                    .with_key(&next_key));
+                }
+            }
+        }
+        Ok(Some((key, projection)))
+    }
+
+    /// Reads the next record that isn't dropped, from the current file or the ones after it,
+    /// along with its position in its file.
+    fn read(&mut self, log: &Logger) -> Result<Option<(Key, Projection, usize)>> {
+        loop {
+            let Some(file) = &mut self.file else {
+                let Some(path) = self.paths.next() else {
+                    return Ok(None);
+                };
+                self.open_file(path, log)?;
+                continue;
+            };
+            let read = file.records.read_record(&mut file.record);
+            if let Ok(false) = read {
+                let file = self.file.take().unwrap();
+                log.info(format_args!(
+                    "{}: closed after {} row(s)",
+                    file.parser.path.display(),
+                    file.parser.rows
+                ));
+                self.files
+                    .push(file.parser.finish(file.overwritten, file.duplicates));
+                continue;
+            }
+            file.idx += 1;
+            let idx = file.idx;
+            interrupt::check(|| {
+                format!(
+                    "reading {} record(s) of {}",
+                    idx - 1,
+                    file.parser.path.display()
+                )
+            })?;
+            let record = read.map(|_| &file.record);
+            match file.parser.parse(idx, record, &mut self.checks, log) {
+                Ok(Some((key, projection))) => return Ok(Some((key, projection, idx))),
+                Ok(None) => {}
+                Err(err) if Category::of(&err) == Category::Data => self.ctx.errors.add(err)?,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn open_file(&mut self, path: PathBuf, log: &Logger) -> Result<()> {
+        let records = match path.as_os_str() == "-" {
+            true => RecordReader::from_reader(self.source, stdin())?,
+            false => RecordReader::open(self.source, &path)?,
+        };
+        match &self.first {
+            None => self.first = Some((path.clone(), records.header().clone())),
+            Some((first_path, first_header)) if self.source.strict_schema => {
+                check_schema(&path, first_path, first_header, records.header())?
+            }
+            Some(_) => {}
+        }
+        let mut parser = RecordParser::new(self.source, &path, &records, self.ctx, log)?;
+        self.key_header
+            .get_or_insert_with(|| std::mem::take(&mut parser.key_header));
+        self.file = Some(OpenFile {
+            records,
+            parser,
+            record: StringRecord::new(),
+            idx: self.source.skip_rows,
+            overwritten: 0,
+            duplicates: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Checks the source once all of it was read, returning the files it was read from.
+    fn finish(self, log: &Logger) -> Result<Vec<InputFile>> {
+        finish_source(self.source, self.ctx.spec, self.checks, &self.files, log)?;
+        Ok(self.files)
+    }
+}
+
+/// The keys of a lookup source skipped over without a match in the primary source.
+#[derive(Default)]
+struct Uncovered {
+    count: usize,
+    sample: Vec<Key>,
+}
+
+impl Uncovered {
+    fn add(&mut self, key: Key) {
+        self.count += 1;
+        if self.sample.len() < COVERAGE_SAMPLE_SIZE {
+            self.sample.push(key);
+        }
+    }
+}
+
+/// Joins the sources as they are read, returning the number of rows written along with the
+/// files read for each source.
+pub fn write_sorted(
+    spec: &JoinSpec,
+    only_keys: Option<&HashSet<Key>>,
+    log: &Logger,
+) -> Result<(usize, Vec<Vec<InputFile>>)> {
+    let dictionaries = Dictionaries::load(spec)?;
+    let errors = ErrorCollector::new(spec.error_limit());
+    let ctx = ReadContext {
+        spec,
+        dictionaries: &dictionaries,
+        match_on: &[],
+        keep_all: false,
+        only_keys,
+        errors: &errors,
+        reread: false,
+    };
+    let open = |source, log: &Logger| {
+        with_read_retries(spec, source, || SortedSource::open(source, &ctx, log))
+    };
+    let mut primary = open(&spec.sources[0], log)?;
+    let mut lookups = spec.sources[1..]
+        .iter()
+        .map(|source| open(source, log))
+        .collect::<Result<Vec<_>>>()?;
+
+    let key_header = output_key_header(spec, primary.key_header.clone().unwrap_or_default());
+    let columns = column_names(joined_columns(spec, key_header));
+    let match_flags: Vec<_> = spec.sources[1..]
+        .iter()
+        .map(|source| spec.match_flag(source))
+        .collect();
+    let layout = Layout::new(spec, &columns)?;
+    let mut changes = Changes::load(spec, &layout)?;
+    let header = changes.as_ref().map_or(&layout.header[..], Changes::header);
+    let mut writers = spec
+        .outputs()
+        .into_iter()
+        .map(|output| OutputWriter::open(output, &layout.key_cols, header))
+        .collect::<Result<Vec<_>>>()?;
+    let mut row: Vec<Option<String>> = Vec::with_capacity(columns.len());
+    let mut ordered = Vec::with_capacity(layout.header.len());
+
+    let joins: Vec<_> = spec.sources[1..]
+        .iter()
+        .map(|source| source.join.unwrap_or(spec.join))
+        .collect();
+    let number_formats = number_formats(spec, spec.key.len());
+    let mut uncovered: Vec<_> = lookups.iter().map(|_| Uncovered::default()).collect();
+    let mut unmatched = vec![0usize; lookups.len()];
+    let mut written = 0usize;
+    while let Some((key, projection)) = primary.next_row(log)? {
+        row.clear();
+        row.extend(key.iter().cloned().map(Some));
+        let mut provenance = Vec::new();
+        extend_row(&spec.sources[0], &projection, &mut row, &mut provenance);
+        // Every lookup source is advanced to the key, even once the row is known to be dropped,
+        // so that the keys skipped over are exactly those missing from the primary source.
+        let mut dropped = false;
+        let mut flags = Vec::new();
+        for (source_idx, lookup) in lookups.iter_mut().enumerate() {
+            while lookup
+                .peek()
+                .is_some_and(|next| compare_keys(spec, next, &key).is_lt())
+            {
+                let (skipped, _) = lookup.next_row(log)?.unwrap();
+                uncovered[source_idx].add(skipped);
+            }
+            let found = match lookup.peek() {
+                Some(next) if compare_keys(spec, next, &key).is_eq() => lookup.next_row(log)?,
+                _ => None,
+            };
+            if match_flags[source_idx].is_some() {
+                flags.push(Some(match found.is_some() {
+                    true => spec.present_value.clone(),
+                    false => spec.absent_value.clone(),
+                }));
+            }
+            match found {
+                Some((_, projection)) => {
+                    extend_row(lookup.source, &projection, &mut row, &mut provenance)
+                }
+                None if joins[source_idx] == JoinType::Left => {
+                    row.extend(
+                        lookup
+                            .source
+                            .projections
+                            .values()
+                            .map(|col| col.default().map(str::to_owned)),
+                    );
+                    if lookup.source.emit_provenance {
+                        provenance.push(None);
+                    }
+                }
+                None => {
+                    unmatched[source_idx] += 1;
+                    dropped = true;
+                }
+            }
+        }
+        if dropped {
+            continue;
+        }
+        row.append(&mut flags);
+        row.append(&mut provenance);
+        format_numbers(spec, &number_formats, &mut row)?;
+        let row = layout.arrange(&mut row, &mut ordered, written + 1);
+        let Some(row) = filter_changes(&mut changes, row) else {
+            continue;
+        };
+        interrupt::check(|| format!("writing {} row(s)", written))?;
+        for writer in writers.iter_mut() {
+            writer.write_record(row)?;
+        }
+        written += 1;
+    }
+    for (lookup, uncovered) in lookups.iter_mut().zip(&mut uncovered) {
+        while let Some((key, _)) = lookup.next_row(log)? {
+            uncovered.add(key);
+        }
+    }
+
+    let mut files = vec![primary.finish(log)?];
+    if let Err(err) = check_unique_keys(spec, &files[0]) {
+        errors.add(err)?;
+    }
+    for lookup in lookups {
+        files.push(lookup.finish(log)?);
+    }
+    errors.finish()?;
+    if let Some(changes) = &changes {
+        changes.write_deleted(only_keys, &mut writers, &mut written)?;
+    }
+    if only_keys.is_some() && written == 0 {
+        warn_no_only_keys(&spec.sources[0]);
+    }
+    finish_outputs(spec, writers, written, log)?;
+    log.info(format_args!("wrote {} row(s)", written));
+    if let Some(changes) = &changes {
+        changes.report(log);
+    }
+    for (source, count) in spec.sources[1..].iter().zip(unmatched) {
+        if count > 0 {
+            log.info(format_args!(
+                "{}: dropped {} primary row(s) without a match",
+                source.path.display(),
+                count
+            ));
+        }
+    }
+    if spec.coverage_check != CoverageCheck::Ignore {
+        let uncovered: Vec<_> = uncovered
+            .into_iter()
+            .map(|uncovered| (uncovered.count, uncovered.sample))
+            .collect();
+        report_coverage(spec, &uncovered, log)?;
+    }
+
+    Ok((written, files))
+}
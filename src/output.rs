@@ -0,0 +1,1214 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, stdout, BufWriter, ErrorKind, Write};
+use std::path::{self, Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use csv::{ReaderBuilder, StringRecord, Writer, WriterBuilder};
+use sha2::{Digest, Sha256};
+
+use crate::error::DataError;
+use crate::logging::Logger;
+use crate::manifest;
+#[cfg(feature = "parquet")]
+use crate::parquet::{ParquetOptions, ParquetWriter};
+use crate::paths;
+use crate::spec::{
+    Align, Delimiter, FixedWidth, Output, OutputFormat, OutputOptions, OverflowPolicy, QuoteStyle,
+};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteWriter;
+
+/// The UTF-8 encoding of U+FEFF, which marks a file as UTF-8 for Excel.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Replaced by the number of the part in the path of csv output rolled over into parts.
+const PART_PLACEHOLDER: &str = "{part}";
+
+/// A file being written under a temporary name, renamed into place when committed.
+///
+/// Dropping it without committing removes the temporary file, so a failed run leaves nothing
+/// behind.
+pub struct PendingFile {
+    temp: PathBuf,
+    target: PathBuf,
+    committed: bool,
+    /// The digest of the bytes written, under `checksum`.
+    checksum: Option<Rc<RefCell<Sha256>>>,
+}
+
+impl PendingFile {
+    fn create(target: &Path) -> Result<(File, Self)> {
+        let file_name = target
+            .file_name()
+            .ok_or_else(|| anyhow!("{}: not a file path", target.display()))?;
+        let temp = target.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+        let file = File::create(&temp)?;
+        Ok((
+            file,
+            Self {
+                temp,
+                target: target.to_path_buf(),
+                committed: false,
+                checksum: None,
+            },
+        ))
+    }
+
+    /// Reopens the temporary file to continue writing at its end.
+    fn reopen(&self) -> Result<File> {
+        Ok(OpenOptions::new().append(true).open(&self.temp)?)
+    }
+
+    /// Renames the file into place, writing its checksum sidecar next to it if it has one.
+    pub fn commit(mut self, rows: usize) -> Result<()> {
+        fs::rename(&self.temp, &self.target)?;
+        self.committed = true;
+        if let Some(checksum) = self.checksum.take() {
+            let hasher = Rc::unwrap_or_clone(checksum).into_inner();
+            manifest::write_checksum(&self.target, rows, hasher)?;
+        }
+        Ok(())
+    }
+}
+
+/// Passes writes on to a file, adding the bytes written to a digest.
+struct HashingWriter {
+    file: File,
+    checksum: Rc<RefCell<Sha256>>,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.file.write(buf)?;
+        self.checksum.borrow_mut().update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Collects writes in a buffer shared with its owner.
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Makes sure that the directory the output is written to exists, creating it under
+/// `create_dirs`, so that a mistyped directory fails the run before any source is read.
+///
+/// Partition files are written to a directory created as needed, so it is its parent that has
+/// to exist, or that of the first directory of a path template that depends on the value.
+pub fn prepare_dir(output: &Output) -> Result<()> {
+    if output.writes_to_stdout() {
+        return Ok(());
+    }
+    paths::check_file_names(output.path)?;
+    let placeholder = output
+        .options
+        .partition_by
+        .as_ref()
+        .map(|col| format!("{{{}}}", col));
+    let Some(dir) = output.path.ancestors().skip(1).find(|dir| {
+        placeholder
+            .as_ref()
+            .is_none_or(|placeholder| !dir.to_string_lossy().contains(placeholder))
+    }) else {
+        return Ok(());
+    };
+    if dir.as_os_str().is_empty() {
+        return Ok(());
+    }
+    let resolved = || path::absolute(dir).unwrap_or_else(|_| dir.to_owned());
+    match fs::metadata(dir) {
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Ok(_) => bail!(
+            "{}: output directory {} is not a directory",
+            output.path.display(),
+            resolved().display()
+        ),
+        Err(err) if err.kind() == ErrorKind::NotFound && output.options.create_dirs => {
+            fs::create_dir_all(dir).with_context(|| {
+                format!(
+                    "{}: cannot create output directory {}",
+                    output.path.display(),
+                    resolved().display()
+                )
+            })
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Err(err).with_context(|| {
+            format!(
+                "{}: output directory {} does not exist; set create_dirs: true or pass --mkdir \
+                 to create it",
+                output.path.display(),
+                resolved().display()
+            )
+        }),
+        Err(err) => Err(err).with_context(|| {
+            format!(
+                "{}: cannot access output directory {}",
+                output.path.display(),
+                resolved().display()
+            )
+        }),
+    }
+}
+
+/// Fails if the output would replace an existing file, unless it sets `overwrite`, so that a
+/// rerun doesn't clobber a good output by mistake. Outputs appended to, stdout and sqlite
+/// databases, which may hold other tables, are never replaced; partition files and the parts
+/// of a rolled output are checked as they are created.
+pub fn check_overwrite(output: &Output) -> Result<()> {
+    let options = output.options;
+    if options.overwrite
+        || options.append
+        || options.partition_by.is_some()
+        || options.output_format == OutputFormat::Sqlite
+        || output.writes_to_stdout()
+    {
+        return Ok(());
+    }
+    refuse_existing(output.path)
+}
+
+/// Fails if the path exists, with an io error so that the run exits as an io failure.
+fn refuse_existing(path: &Path) -> Result<()> {
+    if path.exists() {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "{}: output already exists; pass --force or set overwrite: true to replace it",
+                path.display()
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Opens the output for writing, returning it along with whether a header should be written.
+fn open_stream(
+    output: &Output,
+    header: &[String],
+) -> Result<(Box<dyn Write>, Option<PendingFile>, bool)> {
+    if output.writes_to_stdout() {
+        return Ok((Box::new(stdout()), None, true));
+    }
+    if output.options.append {
+        let exists = output.path.metadata().is_ok_and(|meta| meta.len() > 0);
+        if exists && output.options.check_header {
+            check_existing_header(output, header)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(output.path)?;
+        return Ok((Box::new(file), None, !exists));
+    }
+    let (file, mut pending) = PendingFile::create(output.path)?;
+    if !output.options.checksum {
+        return Ok((Box::new(file), Some(pending), true));
+    }
+    let checksum = Rc::new(RefCell::new(Sha256::new()));
+    pending.checksum = Some(Rc::clone(&checksum));
+    Ok((
+        Box::new(HashingWriter { file, checksum }),
+        Some(pending),
+        true,
+    ))
+}
+
+fn check_existing_header(output: &Output, header: &[String]) -> Result<()> {
+    let existing = match output.options.output_format {
+        OutputFormat::Csv => {
+            let format = CsvFormat::new(output.options)?;
+            ReaderBuilder::new()
+                .delimiter(format.delimiter)
+                .quoting(format.quote_style != QuoteStyle::Escape)
+                .from_path(output.path)?
+                .headers()?
+                .clone()
+        }
+        _ => return Ok(()),
+    };
+    if existing.iter().ne(header.iter().map(String::as_str)) {
+        bail!(
+            "{}: cannot append, existing header {:?} differs from output header {:?}",
+            output.path.display(),
+            existing.iter().collect::<Vec<_>>(),
+            header
+        );
+    }
+    Ok(())
+}
+
+struct Partition {
+    file: PendingFile,
+    writer: Option<Writer<File>>,
+    rows: usize,
+    last_used: u64,
+}
+
+/// Routes rows into one CSV file per distinct value of a column.
+///
+/// The output path is either a template containing `{column}`, which is replaced by the value,
+/// or a directory in which `<value>.csv` files are created.
+pub struct PartitionedWriter<'s> {
+    path: &'s Path,
+    placeholder: Option<String>,
+    column: usize,
+    header: StringRecord,
+    null_name: &'s str,
+    format: CsvFormat<'s>,
+    max_open: usize,
+    excel_bom: bool,
+    overwrite: bool,
+    open: usize,
+    tick: u64,
+    partitions: HashMap<String, Partition>,
+}
+
+impl<'s> PartitionedWriter<'s> {
+    fn new(
+        output: Output<'s>,
+        column_name: &str,
+        column: usize,
+        header: StringRecord,
+    ) -> Result<Self> {
+        let placeholder = format!("{{{}}}", column_name);
+        let placeholder = match output.path.to_string_lossy().contains(&placeholder) {
+            true => Some(placeholder),
+            false => {
+                fs::create_dir_all(output.path)?;
+                None
+            }
+        };
+        if output.options.max_open_partitions == 0 {
+            bail!("max_open_partitions must be positive");
+        }
+        Ok(Self {
+            path: output.path,
+            placeholder,
+            column,
+            header,
+            null_name: &output.options.partition_null,
+            format: CsvFormat::new(output.options)?,
+            max_open: output.options.max_open_partitions,
+            excel_bom: output.options.excel_bom,
+            overwrite: output.options.overwrite,
+            open: 0,
+            tick: 0,
+            partitions: HashMap::new(),
+        })
+    }
+
+    fn partition_path(&self, value: &str) -> Result<PathBuf> {
+        let name = match value.is_empty() {
+            true => self.null_name,
+            false => value,
+        };
+        let unsafe_name = name == "."
+            || name == ".."
+            || name
+                .chars()
+                .any(|c| std::path::is_separator(c) || c.is_control());
+        if unsafe_name {
+            bail!("partition value {:?} cannot be used in a file name", value);
+        }
+        paths::check_file_name(name).with_context(|| {
+            format!("partition value {:?} cannot be used in a file name", value)
+        })?;
+        Ok(match &self.placeholder {
+            Some(placeholder) => {
+                PathBuf::from(self.path.to_string_lossy().replace(placeholder, name))
+            }
+            None => self.path.join(format!("{}.csv", name)),
+        })
+    }
+
+    /// Closes the least recently used partition file to stay within the open file limit.
+    fn close_one(&mut self) -> Result<()> {
+        let lru = self
+            .partitions
+            .values_mut()
+            .filter(|partition| partition.writer.is_some())
+            .min_by_key(|partition| partition.last_used);
+        if let Some(partition) = lru {
+            partition.writer.take().unwrap().flush()?;
+            self.open -= 1;
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, row: &[Option<String>]) -> Result<()> {
+        let value = row[self.column].as_deref().unwrap_or_default();
+        self.tick += 1;
+        let needs_open = match self.partitions.get(value) {
+            Some(partition) => partition.writer.is_none(),
+            None => true,
+        };
+        if needs_open && self.open == self.max_open {
+            self.close_one()?;
+        }
+
+        if !self.partitions.contains_key(value) {
+            let path = self.partition_path(value)?;
+            if !self.overwrite {
+                refuse_existing(&path)?;
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let (mut file, pending) = PendingFile::create(&path)?;
+            if self.excel_bom {
+                file.write_all(UTF8_BOM)?;
+            }
+            let mut writer = self.format.builder().from_writer(file);
+            writer.write_record(self.header.iter().map(|name| self.format.field(name)))?;
+            self.open += 1;
+            let partition = Partition {
+                file: pending,
+                writer: Some(writer),
+                rows: 0,
+                last_used: self.tick,
+            };
+            self.partitions.insert(value.to_owned(), partition);
+        }
+
+        let partition = self.partitions.get_mut(value).unwrap();
+        if partition.writer.is_none() {
+            partition.writer = Some(self.format.builder().from_writer(partition.file.reopen()?));
+            self.open += 1;
+        }
+        partition.last_used = self.tick;
+        partition.rows += 1;
+        partition
+            .writer
+            .as_mut()
+            .unwrap()
+            .write_record(self.format.cells(row))?;
+        Ok(())
+    }
+}
+
+/// Writes csv output into numbered parts, each with its own header, starting the next part
+/// before one would grow beyond `max_size` bytes or past `max_rows` rows.
+///
+/// A part always takes at least one row, even one larger than `max_size` on its own. The first
+/// part is written under the output path itself, unless it has `{part}` or `always_suffix` is
+/// set, and only renamed to its numbered path once there is a second one.
+pub struct RollingWriter<'s> {
+    path: &'s Path,
+    max_size: Option<u64>,
+    max_rows: Option<usize>,
+    always_suffix: bool,
+    overwrite: bool,
+    buffer_size: Option<usize>,
+    format: CsvFormat<'s>,
+    /// The header, as written at the start of each part.
+    header: Vec<u8>,
+    /// Encodes each record into `encoded` before it is written, to tell its size.
+    encoder: Writer<SharedBuffer>,
+    encoded: Rc<RefCell<Vec<u8>>>,
+    /// The part being written, with the bytes written to it.
+    current: Option<(BufWriter<File>, u64)>,
+    /// The files of the parts, with the number of rows in each.
+    parts: Vec<(PendingFile, usize)>,
+}
+
+impl<'s> RollingWriter<'s> {
+    fn new(output: Output<'s>, header: &[String]) -> Result<Self> {
+        let options = output.options;
+        if options.max_output_size.is_some_and(|size| size.0 == 0) {
+            bail!("max_output_size must be positive");
+        }
+        if options.max_output_rows == Some(0) {
+            bail!("max_output_rows must be positive");
+        }
+        let format = CsvFormat::new(options)?;
+        let encoded = Rc::new(RefCell::new(Vec::new()));
+        let mut encoder = format
+            .builder()
+            .from_writer(SharedBuffer(Rc::clone(&encoded)));
+        encoder.write_record(header.iter().map(|name| format.field(name)))?;
+        encoder.flush()?;
+        let mut header = match options.excel_bom {
+            true => UTF8_BOM.to_vec(),
+            false => Vec::new(),
+        };
+        header.append(&mut encoded.borrow_mut());
+        Ok(Self {
+            path: output.path,
+            max_size: options.max_output_size.map(|size| size.0),
+            max_rows: options.max_output_rows,
+            always_suffix: options.always_suffix,
+            overwrite: options.overwrite,
+            buffer_size: options.buffer_size,
+            format,
+            header,
+            encoder,
+            encoded,
+            current: None,
+            parts: Vec::new(),
+        })
+    }
+
+    /// The path of the part numbered `number`, counting from 1.
+    fn part_path(&self, number: usize) -> PathBuf {
+        let part = format!("{:04}", number);
+        let path = self.path.to_string_lossy();
+        if path.contains(PART_PLACEHOLDER) {
+            return PathBuf::from(path.replace(PART_PLACEHOLDER, &part));
+        }
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(ext) => format!("{}.part{}.{}", stem, part, ext.to_string_lossy()),
+            None => format!("{}.part{}", stem, part),
+        };
+        self.path.with_file_name(name)
+    }
+
+    /// Whether the first part is written under the output path itself until there is another.
+    fn unsuffixed(&self) -> bool {
+        !self.always_suffix && !self.path.to_string_lossy().contains(PART_PLACEHOLDER)
+    }
+
+    /// Ends the current part, if any, and starts the next one with the header.
+    fn open_part(&mut self) -> Result<()> {
+        if let Some((mut writer, _)) = self.current.take() {
+            writer.flush()?;
+        }
+        if self.parts.len() == 1 && self.unsuffixed() {
+            let first = self.part_path(1);
+            if !self.overwrite {
+                refuse_existing(&first)?;
+            }
+            self.parts[0].0.target = first;
+        }
+        let path = match self.parts.is_empty() && self.unsuffixed() {
+            true => self.path.to_owned(),
+            false => self.part_path(self.parts.len() + 1),
+        };
+        paths::check_file_names(&path)?;
+        if !self.overwrite {
+            refuse_existing(&path)?;
+        }
+        let (file, pending) = PendingFile::create(&path)
+            .with_context(|| format!("{}: cannot create output part", path.display()))?;
+        let mut writer = match self.buffer_size {
+            Some(size) => BufWriter::with_capacity(size, file),
+            None => BufWriter::new(file),
+        };
+        writer.write_all(&self.header)?;
+        self.current = Some((writer, self.header.len() as u64));
+        self.parts.push((pending, 0));
+        Ok(())
+    }
+
+    fn write_record(&mut self, row: &[Option<String>]) -> Result<()> {
+        self.encoder.write_record(self.format.cells(row))?;
+        self.encoder.flush()?;
+        let len = self.encoded.borrow().len() as u64;
+        let full = match (&self.current, self.parts.last()) {
+            (Some((_, size)), Some(&(_, rows))) => {
+                rows > 0
+                    && (self.max_rows.is_some_and(|max| rows >= max)
+                        || self.max_size.is_some_and(|max| size + len > max))
+            }
+            _ => true,
+        };
+        if full {
+            self.open_part()?;
+        }
+        let (writer, size) = self.current.as_mut().unwrap();
+        let mut encoded = self.encoded.borrow_mut();
+        writer.write_all(&encoded)?;
+        *size += len;
+        self.parts.last_mut().unwrap().1 += 1;
+        encoded.clear();
+        Ok(())
+    }
+
+    /// Flushes the last part, starting one for the header if there were no rows, and returns
+    /// the files of the parts.
+    fn finish(mut self, log: &Logger) -> Result<Vec<PendingFile>> {
+        if self.current.is_none() {
+            self.open_part()?;
+        }
+        if let Some((mut writer, _)) = self.current.take() {
+            writer.flush()?;
+        }
+        let mut pending = Vec::with_capacity(self.parts.len());
+        for (number, (file, rows)) in self.parts.into_iter().enumerate() {
+            log.info(format_args!(
+                "part {}: {} row(s) in {}",
+                number + 1,
+                rows,
+                file.target.display()
+            ));
+            pending.push(file);
+        }
+        Ok(pending)
+    }
+}
+
+/// Buffers a stream with a buffer of `size` bytes, or the default size.
+fn buffered(stream: Box<dyn Write>, size: Option<usize>) -> BufWriter<Box<dyn Write>> {
+    match size {
+        Some(size) => BufWriter::with_capacity(size, stream),
+        None => BufWriter::new(stream),
+    }
+}
+
+pub struct JsonlWriter {
+    output: BufWriter<Box<dyn Write>>,
+    header: Vec<String>,
+}
+
+/// How the fields of csv output are written, from its options.
+#[derive(Clone, Copy)]
+pub struct CsvFormat<'s> {
+    delimiter: u8,
+    terminator: csv::Terminator,
+    quote_style: QuoteStyle,
+    null_value: &'s str,
+}
+
+impl<'s> CsvFormat<'s> {
+    fn new(options: &'s OutputOptions) -> Result<Self> {
+        let delimiter = match options.delimiter {
+            None => b',',
+            Some(Delimiter::Byte(byte)) => byte,
+            Some(Delimiter::Auto) => bail!("delimiter: auto can only be used for sources"),
+        };
+        if matches!(delimiter, b'"' | b'\r' | b'\n') {
+            bail!(
+                "invalid output delimiter {:?}: it cannot be a quote or a line break",
+                char::from(delimiter)
+            );
+        }
+        Ok(Self {
+            delimiter,
+            terminator: options.line_terminator.terminator(),
+            quote_style: options.quote_style,
+            null_value: &options.null_value,
+        })
+    }
+
+    fn builder(&self) -> WriterBuilder {
+        let mut builder = WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .terminator(self.terminator)
+            .quote_style(self.quote_style.quote_style());
+        builder
+    }
+
+    /// A field as written, escaped under `quote_style: escape`.
+    fn field<'v>(&self, value: &'v str) -> Cow<'v, [u8]> {
+        let escaped =
+            |byte: u8| matches!(byte, b'\\' | b'\t' | b'\r' | b'\n') || byte == self.delimiter;
+        if self.quote_style != QuoteStyle::Escape || !value.bytes().any(escaped) {
+            return Cow::Borrowed(value.as_bytes());
+        }
+        let mut field = Vec::with_capacity(value.len() + 8);
+        for byte in value.bytes() {
+            match byte {
+                b'\t' => field.extend_from_slice(b"\\t"),
+                b'\r' => field.extend_from_slice(b"\\r"),
+                b'\n' => field.extend_from_slice(b"\\n"),
+                byte if escaped(byte) => field.extend_from_slice(&[b'\\', byte]),
+                byte => field.push(byte),
+            }
+        }
+        Cow::Owned(field)
+    }
+
+    /// The fields of a row, with absent cells written as `null_value`.
+    fn cells<'r>(&'r self, row: &'r [Option<String>]) -> impl Iterator<Item = Cow<'r, [u8]>> {
+        row.iter().map(|cell| match cell {
+            Some(value) => self.field(value),
+            None => Cow::Borrowed(self.null_value.as_bytes()),
+        })
+    }
+}
+
+impl JsonlWriter {
+    fn write_record(&mut self, row: &[Option<String>]) -> Result<()> {
+        let object: indexmap::IndexMap<_, _> = self.header.iter().zip(row).collect();
+        serde_json::to_writer(&mut self.output, &object)?;
+        self.output.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Writes rows as SQL `INSERT` statements of up to `batch_size` rows each.
+pub struct SqlWriter {
+    output: BufWriter<Box<dyn Write>>,
+    /// The statement up to its `VALUES`, with the table and column names quoted.
+    insert: String,
+    batch_size: usize,
+    /// Rows written of the current statement.
+    rows: usize,
+}
+
+/// Quotes an identifier as SQL does, doubling the double quotes within it.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+impl SqlWriter {
+    fn new(
+        output: BufWriter<Box<dyn Write>>,
+        table: &str,
+        header: &[String],
+        batch_size: usize,
+    ) -> Self {
+        let columns: Vec<_> = header.iter().map(|col| quote_identifier(col)).collect();
+        Self {
+            output,
+            insert: format!(
+                "INSERT INTO {} ({}) VALUES",
+                quote_identifier(table),
+                columns.join(", ")
+            ),
+            batch_size,
+            rows: 0,
+        }
+    }
+
+    fn write_record(&mut self, row: &[Option<String>]) -> Result<()> {
+        match self.rows {
+            0 => write!(self.output, "{} (", self.insert)?,
+            _ => self.output.write_all(b",\n(")?,
+        }
+        for (idx, cell) in row.iter().enumerate() {
+            if idx > 0 {
+                self.output.write_all(b", ")?;
+            }
+            match cell {
+                Some(value) => write!(self.output, "'{}'", value.replace('\'', "''"))?,
+                None => self.output.write_all(b"NULL")?,
+            }
+        }
+        self.output.write_all(b")")?;
+        self.rows += 1;
+        if self.rows == self.batch_size {
+            self.end_statement()?;
+        }
+        Ok(())
+    }
+
+    fn end_statement(&mut self) -> Result<()> {
+        if self.rows > 0 {
+            self.output.write_all(b";\n")?;
+            self.rows = 0;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.end_statement()?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes rows as lines of values padded to the widths of their columns, without delimiters.
+pub struct FixedWriter {
+    output: BufWriter<Box<dyn Write>>,
+    /// The columns of the header, with their widths.
+    columns: Vec<(String, FixedWidth)>,
+    on_overflow: OverflowPolicy,
+    null_value: String,
+    terminator: &'static str,
+    line: String,
+}
+
+impl FixedWriter {
+    fn new(
+        output: BufWriter<Box<dyn Write>>,
+        options: &OutputOptions,
+        header: &[String],
+    ) -> Result<Self> {
+        let columns = header
+            .iter()
+            .map(|name| match options.widths.get(name) {
+                Some(width) if width.width() == 0 => {
+                    bail!("widths: the width of column {} must be positive", name)
+                }
+                Some(&width) => Ok((name.clone(), width)),
+                None => bail!(
+                    "fixed output requires a width for every column; {} has none",
+                    name
+                ),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(name) = options.widths.keys().find(|name| !header.contains(name)) {
+            bail!("widths: {} is not an output column", name);
+        }
+        let terminator = options.line_terminator.as_str();
+        Ok(Self {
+            output,
+            columns,
+            on_overflow: options.on_overflow,
+            null_value: options.null_value.clone(),
+            terminator,
+            line: String::new(),
+        })
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let names: Vec<_> = self
+            .columns
+            .iter()
+            .map(|(name, _)| Some(name.clone()))
+            .collect();
+        self.write_line(&names, true)
+    }
+
+    fn write_record(&mut self, row: &[Option<String>]) -> Result<()> {
+        self.write_line(row, false)
+    }
+
+    /// Writes the cells of a row, or the names of the header, which are padded with spaces
+    /// rather than with the pad characters of their columns, such as the zeros of numbers.
+    fn write_line(&mut self, row: &[Option<String>], header: bool) -> Result<()> {
+        self.line.clear();
+        for ((name, width), cell) in self.columns.iter().zip(row) {
+            let value = cell.as_deref().unwrap_or(&self.null_value);
+            let len = value.chars().count();
+            let value = match len > width.width() {
+                false => value,
+                true if self.on_overflow == OverflowPolicy::Truncate => {
+                    let end = value
+                        .char_indices()
+                        .nth(width.width())
+                        .map_or(value.len(), |(end, _)| end);
+                    &value[..end]
+                }
+                true => bail!(DataError(format!(
+                    "fixed output column {}: value {:?} has {} character(s), more than its width \
+                     of {}",
+                    name,
+                    value,
+                    len,
+                    width.width()
+                ))),
+            };
+            let padding = width.width() - len.min(width.width());
+            let pad = match header {
+                true => ' ',
+                false => width.pad(),
+            };
+            let pad = std::iter::repeat_n(pad, padding);
+            match width.align() {
+                Align::Left => {
+                    self.line.push_str(value);
+                    self.line.extend(pad);
+                }
+                Align::Right => {
+                    self.line.extend(pad);
+                    self.line.push_str(value);
+                }
+            }
+        }
+        self.line.push_str(self.terminator);
+        self.output.write_all(self.line.as_bytes())?;
+        Ok(())
+    }
+}
+
+pub enum OutputWriter<'s> {
+    Csv(
+        Box<Writer<Box<dyn Write>>>,
+        Option<PendingFile>,
+        CsvFormat<'s>,
+    ),
+    Jsonl(Box<JsonlWriter>, Option<PendingFile>),
+    Sql(Box<SqlWriter>, Option<PendingFile>),
+    Fixed(Box<FixedWriter>, Option<PendingFile>),
+    Partitioned(PartitionedWriter<'s>),
+    Rolling(Box<RollingWriter<'s>>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Box<SqliteWriter>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<ParquetWriter>, PendingFile),
+}
+
+impl<'s> OutputWriter<'s> {
+    /// Opens the output and writes the header, if the format has one.
+    ///
+    /// `key_cols` are the positions of the key columns in the header.
+    pub fn open(output: Output<'s>, key_cols: &[usize], header: &[String]) -> Result<Self> {
+        let options = output.options;
+        if options.buffer_size == Some(0) {
+            bail!("buffer_size must be positive");
+        }
+        if options.partition_by.is_some() && options.output_format != OutputFormat::Csv {
+            bail!("partition_by is only supported with csv output");
+        }
+        if options.append {
+            if options.partition_by.is_some() {
+                bail!("append cannot be combined with partition_by");
+            }
+            if !matches!(
+                options.output_format,
+                OutputFormat::Csv | OutputFormat::Jsonl | OutputFormat::Sql | OutputFormat::Fixed
+            ) {
+                bail!("append is only supported with csv, jsonl, sql and fixed output");
+            }
+        }
+        if options.batch_size == Some(0) {
+            bail!("batch_size must be positive");
+        }
+        if options.checksum {
+            if output.writes_to_stdout() {
+                bail!("checksum requires the output to be a file");
+            }
+            if options.append {
+                bail!("checksum cannot be combined with append");
+            }
+            if options.partition_by.is_some() {
+                bail!("checksum cannot be combined with partition_by");
+            }
+            if !matches!(
+                options.output_format,
+                OutputFormat::Csv | OutputFormat::Jsonl | OutputFormat::Sql | OutputFormat::Fixed
+            ) {
+                bail!("checksum is only supported with csv, jsonl, sql and fixed output");
+            }
+        }
+        let rolling = options.max_output_size.is_some() || options.max_output_rows.is_some();
+        if rolling {
+            if options.output_format != OutputFormat::Csv {
+                bail!("max_output_size and max_output_rows are only supported with csv output");
+            }
+            if output.writes_to_stdout() {
+                bail!("max_output_size and max_output_rows require the output to be a file");
+            }
+            if options.append {
+                bail!("max_output_size and max_output_rows cannot be combined with append");
+            }
+            if options.partition_by.is_some() {
+                bail!("max_output_size and max_output_rows cannot be combined with partition_by");
+            }
+            if options.checksum {
+                bail!("max_output_size and max_output_rows cannot be combined with checksum");
+            }
+        } else if options.always_suffix {
+            bail!("always_suffix requires max_output_size or max_output_rows");
+        }
+        if options.excel_bom && options.output_format != OutputFormat::Csv {
+            bail!("excel_bom is only supported with csv output");
+        }
+        if options.excel_bom && output.writes_to_stdout() {
+            tracing::warn!(
+                "warning: excel_bom only applies to files; writing to stdout without it"
+            );
+        }
+        match options.output_format {
+            OutputFormat::Csv => {}
+            OutputFormat::Jsonl => {
+                let (stream, pending, _) = open_stream(&output, header)?;
+                let writer = JsonlWriter {
+                    output: buffered(stream, options.buffer_size),
+                    header: header.to_vec(),
+                };
+                return Ok(Self::Jsonl(Box::new(writer), pending));
+            }
+            OutputFormat::Sql => {
+                let (stream, pending, _) = open_stream(&output, header)?;
+                let writer = SqlWriter::new(
+                    buffered(stream, options.buffer_size),
+                    &options.table,
+                    header,
+                    options.batch_size.unwrap_or(1),
+                );
+                return Ok(Self::Sql(Box::new(writer), pending));
+            }
+            OutputFormat::Fixed => {
+                let (stream, pending, write_header) = open_stream(&output, header)?;
+                let mut writer =
+                    FixedWriter::new(buffered(stream, options.buffer_size), options, header)?;
+                if options.fixed_header && write_header {
+                    writer.write_header()?;
+                }
+                return Ok(Self::Fixed(Box::new(writer), pending));
+            }
+            OutputFormat::Sqlite => return Self::open_sqlite(output, key_cols, header),
+            OutputFormat::Parquet => return Self::open_parquet(output, key_cols, header),
+        }
+
+        match &options.partition_by {
+            Some(_) if output.writes_to_stdout() => {
+                bail!("partition_by cannot be used when writing to stdout")
+            }
+            Some(partition_col) => {
+                let column = header
+                    .iter()
+                    .position(|col| col == partition_col)
+                    .ok_or_else(|| {
+                        anyhow!("partition column {} is not an output column", partition_col)
+                    })?;
+                Ok(Self::Partitioned(PartitionedWriter::new(
+                    output,
+                    partition_col,
+                    column,
+                    StringRecord::from(header.to_vec()),
+                )?))
+            }
+            None if rolling => Ok(Self::Rolling(Box::new(RollingWriter::new(output, header)?))),
+            None => {
+                let (mut stream, pending, write_header) = open_stream(&output, header)?;
+                if options.excel_bom && write_header && !output.writes_to_stdout() {
+                    stream.write_all(UTF8_BOM)?;
+                }
+                let format = CsvFormat::new(options)?;
+                let mut builder = format.builder();
+                if let Some(size) = options.buffer_size {
+                    builder.buffer_capacity(size);
+                }
+                let mut writer = builder.from_writer(stream);
+                if write_header {
+                    writer.write_record(header.iter().map(|name| format.field(name)))?;
+                }
+                Ok(Self::Csv(Box::new(writer), pending, format))
+            }
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn open_sqlite(output: Output<'s>, key_cols: &[usize], header: &[String]) -> Result<Self> {
+        if output.writes_to_stdout() {
+            bail!("sqlite output cannot be written to stdout");
+        }
+        let options = output.options;
+        Ok(Self::Sqlite(Box::new(SqliteWriter::new(
+            output.path,
+            &options.table,
+            header,
+            key_cols,
+            options.index_key,
+            options.overwrite,
+        )?)))
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn open_sqlite(_output: Output<'s>, _key_cols: &[usize], _header: &[String]) -> Result<Self> {
+        bail!("sqlite output requires join-csv to be built with the sqlite feature");
+    }
+
+    #[cfg(feature = "parquet")]
+    const PARQUET_BATCH_SIZE: usize = 8192;
+
+    #[cfg(feature = "parquet")]
+    fn open_parquet(output: Output<'s>, key_cols: &[usize], header: &[String]) -> Result<Self> {
+        if output.writes_to_stdout() {
+            bail!("parquet output cannot be written to stdout");
+        }
+        let options = ParquetOptions {
+            schema: &output.options.schema,
+            lenient: output.options.lenient_schema,
+            batch_size: output
+                .options
+                .batch_size
+                .unwrap_or(Self::PARQUET_BATCH_SIZE),
+            compression: output.options.compression,
+        };
+        let (file, pending) = PendingFile::create(output.path)?;
+        let writer = ParquetWriter::new(file, header, key_cols, options)?;
+        Ok(Self::Parquet(Box::new(writer), pending))
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn open_parquet(_output: Output<'s>, _key_cols: &[usize], _header: &[String]) -> Result<Self> {
+        bail!("parquet output requires join-csv to be built with the parquet feature");
+    }
+
+    /// Writes a row, in which `None` marks a cell absent because a source had no row for the key.
+    pub fn write_record(&mut self, row: &[Option<String>]) -> Result<()> {
+        match self {
+            Self::Csv(writer, _, format) => writer.write_record(format.cells(row))?,
+            Self::Jsonl(writer, _) => writer.write_record(row)?,
+            Self::Sql(writer, _) => writer.write_record(row)?,
+            Self::Fixed(writer, _) => writer.write_record(row)?,
+            Self::Partitioned(writer) => writer.write_record(row)?,
+            Self::Rolling(writer) => writer.write_record(row)?,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(writer) => writer.write_record(row)?,
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer, _) => writer.write_record(row)?,
+        }
+        Ok(())
+    }
+
+    /// Flushes and closes the output, reporting any error that dropping it would swallow.
+    ///
+    /// Returns the files that still need to be committed to appear under their final names.
+    pub fn finish(self, log: &Logger) -> Result<Vec<PendingFile>> {
+        let pending = match self {
+            Self::Csv(mut writer, pending, _) => {
+                writer.flush()?;
+                pending.into_iter().collect()
+            }
+            Self::Jsonl(mut writer, pending) => {
+                writer.output.flush()?;
+                pending.into_iter().collect()
+            }
+            Self::Sql(mut writer, pending) => {
+                writer.finish()?;
+                pending.into_iter().collect()
+            }
+            Self::Fixed(mut writer, pending) => {
+                writer.output.flush()?;
+                pending.into_iter().collect()
+            }
+            Self::Partitioned(writer) => {
+                let mut partitions: Vec<_> = writer.partitions.into_iter().collect();
+                partitions.sort_by(|(a, _), (b, _)| a.cmp(b));
+                let mut pending = Vec::with_capacity(partitions.len());
+                for (value, partition) in partitions {
+                    if let Some(mut writer) = partition.writer {
+                        writer.flush()?;
+                    }
+                    log.info(format_args!(
+                        "partition {:?}: {} row(s) in {}",
+                        value,
+                        partition.rows,
+                        partition.file.target.display()
+                    ));
+                    pending.push(partition.file);
+                }
+                pending
+            }
+            Self::Rolling(writer) => writer.finish(log)?,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(writer) => {
+                writer.finish()?;
+                Vec::new()
+            }
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer, pending) => {
+                writer.finish()?;
+                vec![pending]
+            }
+        };
+        Ok(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Category, EXIT_IO};
+
+    /// A stream that takes every write, and fails when flushed, as a full disk may only report
+    /// once buffered bytes are written out.
+    struct FailingFlush;
+
+    impl Write for FailingFlush {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::new(
+                ErrorKind::StorageFull,
+                "no space left on device",
+            ))
+        }
+    }
+
+    fn row() -> Vec<Option<String>> {
+        vec![Some("1".to_owned()), None]
+    }
+
+    /// Writes a row and finishes the writer, which must fail as an I/O error.
+    fn assert_flush_fails(mut writer: OutputWriter) {
+        let log = Logger::new(0, false, None, &[]).unwrap();
+        writer.write_record(&row()).unwrap();
+        let Err(err) = writer.finish(&log) else {
+            panic!("the writer was flushed");
+        };
+        assert_eq!(Category::of(&err), Category::Io, "{:?}", err);
+        assert_eq!(Category::of(&err).exit_code(), EXIT_IO);
+    }
+
+    /// An empty directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("join-csv-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dropped_pending_file_leaves_nothing_behind() {
+        let dir = test_dir("dropped_pending_file_leaves_nothing_behind");
+        let target = dir.join("out.csv");
+        let (mut file, pending) = PendingFile::create(&target).unwrap();
+        file.write_all(b"id\n1\n").unwrap();
+        assert!(dir.join(".out.csv.tmp").exists());
+        drop(pending);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn committed_pending_file_replaces_the_target() {
+        let dir = test_dir("committed_pending_file_replaces_the_target");
+        let target = dir.join("out.csv");
+        fs::write(&target, "old\n").unwrap();
+        let (mut file, pending) = PendingFile::create(&target).unwrap();
+        file.write_all(b"id\n1\n").unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old\n");
+        pending.commit(1).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "id\n1\n");
+        assert!(!dir.join(".out.csv.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_flush_error_is_an_io_error() {
+        let format = CsvFormat {
+            delimiter: b',',
+            terminator: csv::Terminator::Any(b'\n'),
+            quote_style: QuoteStyle::Necessary,
+            null_value: "",
+        };
+        let stream: Box<dyn Write> = Box::new(FailingFlush);
+        let writer = format.builder().from_writer(stream);
+        assert_flush_fails(OutputWriter::Csv(Box::new(writer), None, format));
+    }
+
+    #[test]
+    fn jsonl_flush_error_is_an_io_error() {
+        let writer = JsonlWriter {
+            output: buffered(Box::new(FailingFlush), Some(1)),
+            header: vec!["id".to_owned(), "score".to_owned()],
+        };
+        assert_flush_fails(OutputWriter::Jsonl(Box::new(writer), None));
+    }
+}
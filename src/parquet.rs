@@ -0,0 +1,220 @@
+use std::fs::File;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use arrow_array::builder::{
+    ArrayBuilder, BooleanBuilder, Date32Builder, Float64Builder, Int64Builder, StringBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use chrono::NaiveDate;
+use indexmap::IndexMap;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+
+use crate::error::DataError;
+use crate::spec::{ParquetCompression, ParquetType};
+
+pub struct ParquetWriter {
+    writer: ArrowWriter<File>,
+    schema: Arc<Schema>,
+    header: Vec<String>,
+    types: Vec<Option<ParquetType>>,
+    key_cols: Vec<usize>,
+    lenient: bool,
+    batch_size: usize,
+    rows: Vec<Vec<Option<String>>>,
+}
+
+pub struct ParquetOptions<'s> {
+    pub schema: &'s IndexMap<String, ParquetType>,
+    pub lenient: bool,
+    pub batch_size: usize,
+    pub compression: ParquetCompression,
+}
+
+impl ParquetWriter {
+    /// Writes to `file` with a column per header name, typed per the schema and UTF-8 otherwise.
+    ///
+    /// The columns at `key_cols` identify each row in parse errors.
+    pub fn new(
+        file: File,
+        header: &[String],
+        key_cols: &[usize],
+        options: ParquetOptions,
+    ) -> Result<Self> {
+        if let Some(col) = options.schema.keys().find(|col| !header.contains(col)) {
+            bail!("schema column {} is not an output column", col);
+        }
+        if options.batch_size == 0 {
+            bail!("batch_size must be positive");
+        }
+
+        let types: Vec<_> = header
+            .iter()
+            .map(|col| options.schema.get(col).copied())
+            .collect();
+        let fields: Vec<_> = header
+            .iter()
+            .zip(&types)
+            .map(|(col, ty)| Field::new(col, data_type(*ty), true))
+            .collect();
+        let schema = Arc::new(Schema::new(fields));
+
+        let compression = match options.compression {
+            ParquetCompression::None => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        };
+        let props = WriterProperties::builder()
+            .set_compression(compression)
+            .build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(props))?;
+
+        Ok(Self {
+            writer,
+            schema,
+            header: header.to_vec(),
+            types,
+            key_cols: key_cols.to_vec(),
+            lenient: options.lenient,
+            batch_size: options.batch_size,
+            rows: Vec::with_capacity(options.batch_size),
+        })
+    }
+
+    pub fn write_record(&mut self, row: &[Option<String>]) -> Result<()> {
+        self.rows.push(row.to_vec());
+        if self.rows.len() == self.batch_size {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if !self.rows.is_empty() {
+            self.write_batch()?;
+        }
+        self.writer.close()?;
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(self.header.len());
+        for (idx, ty) in self.types.iter().enumerate() {
+            let mut builder = new_builder(*ty, self.rows.len());
+            for row in self.rows.iter() {
+                self.append(builder.as_mut(), *ty, row, idx)?;
+            }
+            columns.push(builder.finish());
+        }
+        self.rows.clear();
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        Ok(())
+    }
+
+    fn append(
+        &self,
+        builder: &mut dyn ArrayBuilder,
+        ty: Option<ParquetType>,
+        row: &[Option<String>],
+        idx: usize,
+    ) -> Result<()> {
+        let Some(value) = row[idx].as_deref() else {
+            match ty {
+                Some(ty) => append_null(builder, ty),
+                None => downcast::<StringBuilder>(builder).append_null(),
+            }
+            return Ok(());
+        };
+        let Some(ty) = ty else {
+            downcast::<StringBuilder>(builder).append_value(value);
+            return Ok(());
+        };
+        if value.is_empty() {
+            append_null(builder, ty);
+            return Ok(());
+        }
+
+        let parsed = match ty {
+            ParquetType::Int64 => value
+                .parse()
+                .ok()
+                .map(|value| downcast::<Int64Builder>(builder).append_value(value)),
+            ParquetType::Float64 => value
+                .parse()
+                .ok()
+                .map(|value| downcast::<Float64Builder>(builder).append_value(value)),
+            ParquetType::Bool => parse_bool(value)
+                .map(|value| downcast::<BooleanBuilder>(builder).append_value(value)),
+            ParquetType::Date => parse_date(value)
+                .map(|value| downcast::<Date32Builder>(builder).append_value(value)),
+        };
+        if parsed.is_none() {
+            if !self.lenient {
+                return Err(DataError(format!(
+                    "cannot parse {:?} as {:?} in column {} for key {:?}",
+                    value,
+                    ty,
+                    self.header[idx],
+                    self.key_cols
+                        .iter()
+                        .map(|&idx| row[idx].as_deref().unwrap_or_default())
+                        .collect::<Vec<_>>()
+                ))
+                .into());
+            }
+            append_null(builder, ty);
+        }
+        Ok(())
+    }
+}
+
+fn data_type(ty: Option<ParquetType>) -> DataType {
+    match ty {
+        None => DataType::Utf8,
+        Some(ParquetType::Int64) => DataType::Int64,
+        Some(ParquetType::Float64) => DataType::Float64,
+        Some(ParquetType::Bool) => DataType::Boolean,
+        Some(ParquetType::Date) => DataType::Date32,
+    }
+}
+
+fn new_builder(ty: Option<ParquetType>, capacity: usize) -> Box<dyn ArrayBuilder> {
+    match ty {
+        None => Box::new(StringBuilder::with_capacity(capacity, capacity * 8)),
+        Some(ParquetType::Int64) => Box::new(Int64Builder::with_capacity(capacity)),
+        Some(ParquetType::Float64) => Box::new(Float64Builder::with_capacity(capacity)),
+        Some(ParquetType::Bool) => Box::new(BooleanBuilder::with_capacity(capacity)),
+        Some(ParquetType::Date) => Box::new(Date32Builder::with_capacity(capacity)),
+    }
+}
+
+fn downcast<B: 'static>(builder: &mut dyn ArrayBuilder) -> &mut B {
+    builder.as_any_mut().downcast_mut().unwrap()
+}
+
+fn append_null(builder: &mut dyn ArrayBuilder, ty: ParquetType) {
+    match ty {
+        ParquetType::Int64 => downcast::<Int64Builder>(builder).append_null(),
+        ParquetType::Float64 => downcast::<Float64Builder>(builder).append_null(),
+        ParquetType::Bool => downcast::<BooleanBuilder>(builder).append_null(),
+        ParquetType::Date => downcast::<Date32Builder>(builder).append_null(),
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_date(value: &str) -> Option<i32> {
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    Some((date - NaiveDate::default()).num_days() as i32)
+}
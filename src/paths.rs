@@ -0,0 +1,234 @@
+//! Paths as Windows needs them: long and UNC paths in their extended-length form, and file names
+//! checked for what Windows doesn't allow. Elsewhere, paths are passed on unchanged.
+
+use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::input;
+use crate::spec::{JoinSpec, OutputTarget, ProjectedColumn};
+
+/// Length of the longest path that Windows APIs accept in their usual form.
+const MAX_PATH: usize = 260;
+
+/// Characters that Windows doesn't allow in file names, besides control characters.
+const RESERVED_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// Names of devices, which Windows doesn't allow as file names, whatever their extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The extended-length form of an absolute Windows path, which isn't limited to `MAX_PATH`:
+/// `\\?\` before a drive path, or instead of the `\\` of a UNC path `\\?\UNC\`. `None` for a
+/// path already in that form, or that isn't absolute.
+fn extended_length(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    // Extended-length paths are read literally, so they only have backslashes.
+    let path = path.replace('/', r"\");
+    let bytes = path.as_bytes();
+    match path.strip_prefix(r"\\") {
+        Some(unc) => Some(format!(r"\\?\UNC\{}", unc)),
+        None if bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == br":\" => {
+            Some(format!(r"\\?\{}", path))
+        }
+        None => None,
+    }
+}
+
+/// The path to give the OS for a file: on Windows, the extended-length form of those too long
+/// for the usual form.
+pub fn os_path(path: &Path) -> Cow<'_, Path> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(path);
+    }
+    let Ok(absolute) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    match absolute
+        .to_str()
+        .filter(|absolute| absolute.len() >= MAX_PATH)
+        .and_then(extended_length)
+    {
+        Some(extended) => Cow::Owned(PathBuf::from(extended)),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Joins a relative path to a directory. On Windows, the `/` of the path are made `\` under a
+/// directory in extended-length form, which would read them literally.
+pub fn join(dir: &Path, path: &str) -> PathBuf {
+    let verbatim = dir.to_str().is_some_and(|dir| dir.starts_with(r"\\?\"));
+    match cfg!(windows) && verbatim {
+        true => dir.join(path.replace('/', r"\")),
+        false => dir.join(path),
+    }
+}
+
+/// Replaces the file paths of the spec with the forms to give the OS, so that messages name
+/// them as the OS saw them. Glob patterns are left as they are, and their matches converted
+/// once expanded.
+pub fn normalize(spec: &mut JoinSpec) {
+    fn normalize_path(path: &mut PathBuf) {
+        if path.as_os_str() == "-" || input::as_url(path).is_some() {
+            return;
+        }
+        if let Cow::Owned(normalized) = os_path(path) {
+            *path = normalized;
+        }
+    }
+    if !cfg!(windows) {
+        return;
+    }
+    match &mut spec.output {
+        OutputTarget::Path(path) => normalize_path(path),
+        OutputTarget::Many(outputs) => {
+            for output in outputs.iter_mut() {
+                normalize_path(&mut output.path);
+            }
+        }
+    }
+    let paths = spec
+        .key_filter
+        .iter_mut()
+        .chain(spec.rejects.iter_mut())
+        .chain(
+            spec.fuzzy
+                .iter_mut()
+                .flat_map(|fuzzy| fuzzy.audit.iter_mut()),
+        )
+        .chain(
+            spec.changed_since
+                .iter_mut()
+                .map(|changed_since| &mut changed_since.path),
+        );
+    paths.for_each(normalize_path);
+    for source in spec.sources.iter_mut() {
+        if !input::is_glob_path(&source.path) {
+            normalize_path(&mut source.path);
+        }
+        for col in source.projections.values_mut() {
+            if let ProjectedColumn::Detailed {
+                lookup: Some(lookup),
+                ..
+            } = col
+            {
+                normalize_path(&mut lookup.path);
+            }
+        }
+    }
+}
+
+/// Fails, on Windows, for a file name that Windows doesn't allow.
+pub fn check_file_name(name: &str) -> Result<()> {
+    if !cfg!(windows) {
+        return Ok(());
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| RESERVED_CHARS.contains(c) || c.is_control())
+    {
+        bail!(
+            "file name {:?} has {:?}, which Windows doesn't allow",
+            name,
+            c
+        );
+    }
+    if name.ends_with(['.', ' ']) {
+        bail!(
+            "file name {:?} ends with a dot or a space, which Windows doesn't allow",
+            name
+        );
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        bail!("file name {:?} is the name of a device on Windows", name);
+    }
+    Ok(())
+}
+
+/// Fails, on Windows, for a path with a file or directory name that Windows doesn't allow.
+pub fn check_file_names(path: &Path) -> Result<()> {
+    for component in path.components() {
+        if let Component::Normal(name) = component {
+            check_file_name(&name.to_string_lossy())
+                .with_context(|| format!("{}: invalid path", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drive_and_unc_paths_get_their_extended_length_form() {
+        assert_eq!(
+            extended_length(r"C:\data\in.csv").as_deref(),
+            Some(r"\\?\C:\data\in.csv")
+        );
+        assert_eq!(
+            extended_length("C:/data/in.csv").as_deref(),
+            Some(r"\\?\C:\data\in.csv")
+        );
+        assert_eq!(
+            extended_length(r"\\server\share\in.csv").as_deref(),
+            Some(r"\\?\UNC\server\share\in.csv")
+        );
+    }
+
+    #[test]
+    fn other_paths_have_no_extended_length_form() {
+        for path in [
+            r"\\?\C:\data\in.csv",
+            r"\\.\pipe\in",
+            r"data\in.csv",
+            r"\data\in.csv",
+            "C:in.csv",
+            "C:",
+        ] {
+            assert_eq!(extended_length(path), None, "{}", path);
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn paths_are_unchanged_elsewhere_than_on_windows() {
+        let long = format!("/{}/in.csv", "d".repeat(MAX_PATH));
+        assert!(matches!(os_path(Path::new(&long)), Cow::Borrowed(_)));
+        assert_eq!(join(Path::new("dir"), "a/b.csv"), Path::new("dir/a/b.csv"));
+        for name in ["a:b.csv", "out.", "CON", "nul.csv"] {
+            check_file_name(name).unwrap();
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn names_windows_does_not_allow_are_rejected() {
+        for name in [
+            "a:b.csv",
+            "a?.csv",
+            "a\tb.csv",
+            "out.",
+            "out ",
+            "CON",
+            "nul.csv",
+            "Com1 .txt",
+        ] {
+            assert!(check_file_name(name).is_err(), "{}", name);
+        }
+        for name in ["out.csv", "console.csv", "com10.csv", ".hidden"] {
+            check_file_name(name).unwrap();
+        }
+        let err = check_file_names(Path::new(r"C:\data\aux\out.csv")).unwrap_err();
+        assert_eq!(err.to_string(), r"C:\data\aux\out.csv: invalid path");
+    }
+}
@@ -0,0 +1,236 @@
+//! Statistics of the columns of a file, for the `profile` subcommand, to help choose the key and
+//! projections of a spec.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::input::RecordReader;
+use crate::record_location;
+use crate::spec::Source;
+
+/// Number of distinct values counted exactly per column; past it, new values are no longer
+/// tracked and the distinct count is reported as a lower bound.
+const DISTINCT_CAP: usize = 100_000;
+/// Number of most frequent values reported per column.
+const TOP_VALUES: usize = 5;
+
+#[derive(Default)]
+struct Column {
+    non_empty: usize,
+    counts: HashMap<String, usize>,
+    capped: bool,
+    min_len: Option<usize>,
+    max_len: usize,
+}
+
+impl Column {
+    fn add(&mut self, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+        self.non_empty += 1;
+        let len = value.chars().count();
+        self.min_len = Some(self.min_len.map_or(len, |min| min.min(len)));
+        self.max_len = self.max_len.max(len);
+        if let Some(count) = self.counts.get_mut(value) {
+            *count += 1;
+        } else if self.counts.len() < DISTINCT_CAP {
+            self.counts.insert(value.to_owned(), 1);
+        } else {
+            self.capped = true;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ColumnProfile {
+    name: String,
+    position: usize,
+    non_empty: usize,
+    /// Exact unless `distinct_capped`, when it is the number of values tracked before the cap.
+    distinct: usize,
+    distinct_capped: bool,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    /// Counted among the tracked values only, once the distinct values are capped.
+    top_values: Vec<TopValue>,
+}
+
+#[derive(Serialize)]
+struct TopValue {
+    value: String,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct KeyProfile {
+    columns: Vec<String>,
+    unique: bool,
+    /// Number of key values that occur in more than one record.
+    duplicate_groups: usize,
+    /// Number of records whose key value occurs in another record as well.
+    duplicate_records: usize,
+}
+
+#[derive(Serialize)]
+struct Profile {
+    records: usize,
+    columns: Vec<ColumnProfile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<KeyProfile>,
+}
+
+/// Reads every record of the source, printing the statistics of each column, and of the key
+/// columns together if any, as a table or as JSON.
+pub fn profile(source: &Source, key: &[String], json: bool) -> Result<()> {
+    source.encoding()?;
+    let mut records = RecordReader::open(source, &source.path)?;
+    let header = records.header().clone();
+    let key_positions = key
+        .iter()
+        .map(|name| match header.iter().position(|col| col == name) {
+            Some(pos) => Ok(pos),
+            None => bail!(
+                "{}: missing key column {}; available columns are: {}",
+                source.path.display(),
+                name,
+                header.iter().collect::<Vec<_>>().join(", ")
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut columns: Vec<_> = header.iter().map(|_| Column::default()).collect();
+    let mut keys: HashMap<Vec<String>, usize> = HashMap::new();
+    let mut count = 0usize;
+    for record in records.by_ref() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                let location = record_location(count + 1, &err);
+                return Err(err).with_context(|| {
+                    format!("{}: failed to parse {}", source.path.display(), location)
+                });
+            }
+        };
+        count += 1;
+        for (column, value) in columns.iter_mut().zip(record.iter()) {
+            column.add(value);
+        }
+        if !key_positions.is_empty() {
+            let key = key_positions
+                .iter()
+                .map(|&pos| record.get(pos).unwrap_or_default().to_owned())
+                .collect();
+            *keys.entry(key).or_default() += 1;
+        }
+    }
+
+    let columns = header
+        .iter()
+        .zip(columns)
+        .enumerate()
+        .map(|(position, (name, column))| {
+            let mut top_values: Vec<_> = column.counts.iter().collect();
+            top_values.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+            ColumnProfile {
+                name: name.to_owned(),
+                position,
+                non_empty: column.non_empty,
+                distinct: column.counts.len(),
+                distinct_capped: column.capped,
+                min_length: column.min_len,
+                max_length: column.min_len.map(|_| column.max_len),
+                top_values: top_values
+                    .into_iter()
+                    .take(TOP_VALUES)
+                    .map(|(value, &count)| TopValue {
+                        value: value.clone(),
+                        count,
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    let key = (!key.is_empty()).then(|| {
+        let duplicates: Vec<_> = keys.values().filter(|&&count| count > 1).collect();
+        KeyProfile {
+            columns: key.to_vec(),
+            unique: duplicates.is_empty(),
+            duplicate_groups: duplicates.len(),
+            duplicate_records: duplicates.into_iter().sum(),
+        }
+    });
+    let profile = Profile {
+        records: count,
+        columns,
+        key,
+    };
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&profile)?),
+        false => print_table(&profile),
+    }
+    Ok(())
+}
+
+fn print_table(profile: &Profile) {
+    let mut rows = vec![[
+        "#".to_owned(),
+        "column".to_owned(),
+        "non-empty".to_owned(),
+        "distinct".to_owned(),
+        "length".to_owned(),
+        "top values".to_owned(),
+    ]];
+    for column in profile.columns.iter() {
+        let length = match (column.min_length, column.max_length) {
+            (Some(min), Some(max)) => format!("{}..{}", min, max),
+            _ => "-".to_owned(),
+        };
+        let top_values = column
+            .top_values
+            .iter()
+            .map(|top| format!("{:?} ({})", top.value, top.count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        rows.push([
+            column.position.to_string(),
+            column.name.clone(),
+            column.non_empty.to_string(),
+            match column.distinct_capped {
+                true => format!("> {}", column.distinct),
+                false => column.distinct.to_string(),
+            },
+            length,
+            top_values,
+        ]);
+    }
+    let mut widths = [0; 5];
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in rows.iter() {
+        let mut line = String::new();
+        for (width, cell) in widths.iter().zip(row) {
+            line.push_str(&format!("{:<width$}  ", cell, width = width));
+        }
+        line.push_str(&row[5]);
+        println!("{}", line);
+    }
+    println!("{} record(s)", profile.records);
+    if let Some(key) = &profile.key {
+        match key.unique {
+            true => println!("key {} is unique", key.columns.join(",")),
+            false => println!(
+                "key {} is not unique: {} value(s) occur in more than one record, {} record(s) \
+                 in all",
+                key.columns.join(","),
+                key.duplicate_groups,
+                key.duplicate_records
+            ),
+        }
+    }
+}
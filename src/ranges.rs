@@ -0,0 +1,131 @@
+//! Rows of a range source, matched by the range of one key component that contains the value of
+//! the key, and exactly by the other components.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::spec::{KeyType, OverlapPolicy};
+use crate::{Key, Projection};
+
+/// The rows of a range source. Their keys hold the lower bound of the range at the position of
+/// the ranged component and the upper bound at the end, either empty for an open range.
+pub struct Ranges {
+    rows: Vec<(Key, Projection)>,
+    /// Positions of the rows by the other key components, sorted by lower bound once sorted.
+    index: HashMap<Key, Vec<usize>>,
+    /// Position of the ranged component in the key.
+    pos: usize,
+    key_len: usize,
+    ty: KeyType,
+    on_overlap: OverlapPolicy,
+}
+
+impl Ranges {
+    pub fn new(pos: usize, key_len: usize, ty: KeyType, on_overlap: OverlapPolicy) -> Self {
+        Self {
+            rows: Vec::new(),
+            index: HashMap::new(),
+            pos,
+            key_len,
+            ty,
+            on_overlap,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn insert(&mut self, key: Key, projection: Projection) {
+        let prefix = self.prefix(&key);
+        self.index.entry(prefix).or_default().push(self.rows.len());
+        self.rows.push((key, projection));
+    }
+
+    /// The key components matched exactly, of a row or of a key looked up.
+    fn prefix(&self, key: &[String]) -> Key {
+        key[..self.key_len]
+            .iter()
+            .enumerate()
+            .filter(|&(pos, _)| pos != self.pos)
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn from(&self, idx: usize) -> &str {
+        &self.rows[idx].0[self.pos]
+    }
+
+    fn to(&self, idx: usize) -> &str {
+        self.rows[idx].0.last().unwrap()
+    }
+
+    /// Whether `value` is at or after the lower bound of the range of row `idx`.
+    fn starts_by(&self, idx: usize, value: &str) -> bool {
+        let from = self.from(idx);
+        from.is_empty() || self.ty.compare(from, value).is_le()
+    }
+
+    /// Whether `value` is at or before the upper bound of the range of row `idx`.
+    fn ends_after(&self, idx: usize, value: &str) -> bool {
+        let to = self.to(idx);
+        to.is_empty() || self.ty.compare(value, to).is_le()
+    }
+
+    /// Sorts the rows of each key by lower bound once all of them were read, returning the keys
+    /// of two rows with overlapping ranges unless overlaps are allowed.
+    pub fn sort(&mut self) -> Option<(Key, Key)> {
+        let mut index = std::mem::take(&mut self.index);
+        let compare_from = |a: &usize, b: &usize| match (self.from(*a), self.from(*b)) {
+            ("", "") => Ordering::Equal,
+            ("", _) => Ordering::Less,
+            (_, "") => Ordering::Greater,
+            (a, b) => self.ty.compare(a, b),
+        };
+        let mut overlap = None;
+        for rows in index.values_mut() {
+            rows.sort_by(compare_from);
+            if self.on_overlap == OverlapPolicy::Error && overlap.is_none() {
+                // Sorted by lower bound, ranges without overlaps each start after the previous one
+                // ends.
+                overlap = rows
+                    .windows(2)
+                    .find(|pair| {
+                        self.to(pair[0]).is_empty() || self.starts_by(pair[1], self.to(pair[0]))
+                    })
+                    .map(|pair| (self.rows[pair[0]].0.clone(), self.rows[pair[1]].0.clone()));
+            }
+        }
+        self.index = index;
+        overlap
+    }
+
+    /// Looks up the row whose range contains the ranged component of `key`, and whose other
+    /// components are equal. Of overlapping ranges, the row read first is used.
+    pub fn get_full(&self, key: &[String]) -> Option<(usize, &Projection)> {
+        let value = &key[self.pos];
+        let rows = self.index.get(&self.prefix(key))?;
+        let end = rows.partition_point(|&idx| self.starts_by(idx, value));
+        let idx = match self.on_overlap {
+            // Without overlaps, only the last range starting by the value can contain it.
+            OverlapPolicy::Error => rows[..end]
+                .last()
+                .copied()
+                .filter(|&idx| self.ends_after(idx, value)),
+            OverlapPolicy::First => rows[..end]
+                .iter()
+                .copied()
+                .filter(|&idx| self.ends_after(idx, value))
+                .min(),
+        }?;
+        Some((idx, &self.rows[idx].1))
+    }
+
+    pub fn key_at(&self, idx: usize) -> &Key {
+        &self.rows[idx].0
+    }
+
+    pub fn projection_at(&self, idx: usize) -> &Projection {
+        &self.rows[idx].1
+    }
+}
@@ -0,0 +1,3848 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use encoding_rs::Encoding;
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::{Mapping, Value};
+
+use crate::expr::Expr;
+use crate::paths;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JoinSpec {
+    /// Columns identifying the rows to join; if empty, every row of each source is joined with
+    /// every row of the others, which requires `allow_cross_join`.
+    #[serde(default)]
+    pub key: Vec<KeyColumn>,
+    pub sources: Vec<Source>,
+    pub output: OutputTarget,
+    #[serde(flatten)]
+    pub output_options: OutputOptions,
+    #[serde(default)]
+    pub coverage_check: CoverageCheck,
+    /// How lookup sources without their own `join` participate in the output.
+    #[serde(default)]
+    pub join: JoinType,
+    /// Output only the key columns, of the keys present in every source, as an inner join
+    /// would; the projections of the sources are ignored, and their columns not read.
+    #[serde(default)]
+    pub keys_only: bool,
+    /// Types of the key components, aligned with `key`; all strings if omitted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_types: Vec<KeyType>,
+    /// Whether typed key components that don't parse are compared as plain strings, instead of
+    /// failing.
+    #[serde(default)]
+    pub lenient_key_types: bool,
+    /// Transforms applied in order to every key component of every source as it is read, before
+    /// `key_types`, so that keys differing only in them match. The output key is the primary
+    /// source's transformed key; projections of key columns keep the values as read.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_normalization: Vec<KeyNormalization>,
+    /// Undoes the mangling of key components by a kind of tool before `key_normalization`, in
+    /// every source; projections of key columns keep the values as read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_clean: Option<KeyClean>,
+    /// Output the key columns as one column, their values joined with `sep`, e.g. a `period`
+    /// of `2024-03` for a key of `year` and `month`; rows are still joined, and merged under
+    /// `sorted: true`, by the key components.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collapse_key: Option<CollapsedKey>,
+    #[serde(default)]
+    pub empty_keys: EmptyKeyPolicy,
+    /// Fallback matching for keys missing from a lookup source; exact matching only if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuzzy: Option<FuzzyMatch>,
+    /// Names of all output columns in the order to write them; key columns first, then each
+    /// source's projections in spec order, if omitted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub column_order: Vec<String>,
+    /// Merge output columns of the same name into one, taking the first non-empty value in
+    /// source order.
+    #[serde(default)]
+    pub coalesce: bool,
+    /// Output columns each taking the first non-empty value of columns of several sources, in
+    /// the order listed, or the null value if all are empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coalesce_columns: Vec<CoalescedColumn>,
+    /// Sets the defaults of all data-quality policies at once; see [`Mode`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<Mode>,
+    /// Values of the per-source columns of `join: presence` output, of match flags and of the
+    /// `dup_flag_column`.
+    #[serde(default = "default_present_value")]
+    pub present_value: String,
+    #[serde(default = "default_absent_value")]
+    pub absent_value: String,
+    /// Name of a column, written first, numbering the output rows from 1.
+    #[serde(default)]
+    pub row_number: Option<String>,
+    /// Approximate limit on the memory held by the rows of all sources; unlimited if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory: Option<ByteSize>,
+    /// Acknowledges that an empty `key` makes a cross join. Beware: its output has as many rows
+    /// as the product of the sizes of all sources, so three sources of 10,000 rows each make a
+    /// trillion; only use it with small tables, and set `max_rows` as a safety net.
+    #[serde(default)]
+    pub allow_cross_join: bool,
+    /// Fails a cross join, before writing anything, if it would produce more rows than this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rows: Option<usize>,
+    /// Write a manifest of the run, naming the inputs with their hashes, next to the first
+    /// output as `<output>.manifest.json`.
+    #[serde(default)]
+    pub manifest: bool,
+    /// Whether violated source assertions fail the run or are only reported.
+    #[serde(default)]
+    pub assert_mode: AssertMode,
+    /// Allow the output header to name the same column more than once, instead of failing.
+    #[serde(default)]
+    pub allow_duplicate_headers: bool,
+    /// CSV file listing the keys to join, one per record with a column per key component; all
+    /// keys of the primary source if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_filter: Option<PathBuf>,
+    /// Bounds on the number of rows written, failing the run without replacing the outputs if
+    /// the count falls outside them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect_rows: Option<RowBounds>,
+    /// Trim the values of key and projected columns as they are read, before keys are built; a
+    /// projected key column is trimmed like the key.
+    #[serde(default)]
+    pub trim_values: bool,
+    /// The characters to trim from values; whitespace if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_chars: Option<String>,
+    /// What to do with values of projections with a `number_format` that aren't numbers.
+    #[serde(default = "default_on_format_error")]
+    pub on_format_error: TypeErrorPolicy,
+    /// What to do when a source has no data rows.
+    #[serde(default)]
+    pub empty_source: EmptyPolicy,
+    /// What to do when the join produces no rows; the outputs are left untouched on error.
+    #[serde(default)]
+    pub empty_output: EmptyPolicy,
+    /// Append a `<source>_matched` column per lookup source, telling with `present_value` or
+    /// `absent_value` whether the source had a row for the key.
+    #[serde(default)]
+    pub emit_match_flags: bool,
+    /// Every source is sorted by the key, so they can be merged as they are read, without
+    /// holding any of them in memory. A source found out of order fails the run.
+    #[serde(default)]
+    pub sorted: bool,
+    /// Warn, once all sources are read, about distinct keys that only differ in surrounding
+    /// whitespace, case or the formatting of numbers, e.g. `1` and `01`, which
+    /// `key_normalization`, `key_types` or `trim_values` would make one key.
+    #[serde(default)]
+    pub warn_near_duplicate_keys: bool,
+    /// Output every row of the primary source, each joined to the lookup sources, instead of
+    /// only the last row of each key.
+    #[serde(default)]
+    pub primary_passthrough: bool,
+    /// Name of a column telling with `present_value` or `absent_value` whether the key of the
+    /// row appears in more than one row of the primary source; requires `primary_passthrough`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dup_flag_column: Option<String>,
+    /// Fail once the primary source is read if it has several records with the same key,
+    /// which the join would otherwise take the last of, listing the first such keys.
+    #[serde(default)]
+    pub require_unique_base_keys: bool,
+    /// Don't warn about sources that project a column their key is read from, which outputs
+    /// its values twice.
+    #[serde(default)]
+    pub allow_key_projections: bool,
+    /// CSV file to write the key and primary projections of rows without a match in some
+    /// lookup source to, inner or left joined, instead of dropping them or filling in defaults.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejects: Option<PathBuf>,
+    /// Reads a source again, from scratch, when reading it fails with an I/O error, such as the
+    /// transient ones of network filesystems.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_retries: Option<ReadRetries>,
+    /// YAML file mapping canonical column names to the other names they are known under,
+    /// shared by specs reading the same kinds of files. A column missing from a source's
+    /// header is looked up under its aliases, unless the source has `aliases` of its own for
+    /// it; `key_aliases` apply before either.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column_map: Option<PathBuf>,
+    /// Fail before reading any source if a file of one was last modified more than this many
+    /// seconds ago, so that a report isn't silently made from stale data. URL sources and
+    /// stdin have no modification time and aren't checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    /// Output only the rows that changed since a previous output, for publishing changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_since: Option<ChangedSince>,
+    /// Whether reading the sources stops at the first error in their data, such as a missing
+    /// column or a record that fails a policy, or goes on to report every error found.
+    #[serde(default)]
+    pub error_mode: ErrorMode,
+    /// The number of errors that `error_mode: collect` stops reading at, to report them.
+    #[serde(default = "default_max_errors")]
+    pub max_errors: usize,
+}
+
+/// A previous output of the spec that the joined rows are compared to by key, in
+/// `changed_since`. Rows whose cells are all the same as in the previous output are left out;
+/// a run without changes warns of an empty output unless `empty_output: allow` is set.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangedSince {
+    /// CSV file written by an earlier run; its header must be that of the output, but for the
+    /// `ignore` columns and the `change_type` column.
+    pub path: PathBuf,
+    /// Columns not compared, such as timestamps, which may be missing from either header. The
+    /// `row_number` column is never compared.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    /// Name of a column appended to the output, telling whether each row is `new`, `changed`
+    /// or `deleted`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change_type: Option<String>,
+    /// Also output a row for each key of the previous output that the join no longer has, with
+    /// only its key columns; requires `change_type`.
+    #[serde(default)]
+    pub emit_deleted: bool,
+}
+
+/// How often a source is read again after an I/O error, in a spec's `read_retries`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct ReadRetries {
+    /// Number of times a source is read in all before giving up.
+    #[serde(default = "default_read_attempts")]
+    pub attempts: usize,
+    /// Time waited before the first retry, doubled before each further one.
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+}
+
+/// The output column the key columns are written as, in `collapse_key`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CollapsedKey {
+    pub name: String,
+    /// Written between the values of the key columns; nothing if omitted.
+    #[serde(default)]
+    pub sep: String,
+}
+
+/// An output column of `coalesce_columns`, written where the first of its source columns
+/// would be.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CoalescedColumn {
+    pub name: String,
+    /// The columns to take the value from, in order of priority.
+    pub from: Vec<SourceColumn>,
+}
+
+/// A column of a source, by the source's name and the column's header name.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SourceColumn {
+    pub source: String,
+    pub column: String,
+}
+
+impl SourceColumn {
+    /// Whether the projection reads this column into the output column `name`.
+    pub fn is_projected(
+        &self,
+        from: &ProjectionFrom,
+        projected: &ProjectedColumn,
+        name: &str,
+    ) -> bool {
+        matches!(from, ProjectionFrom::Column(col) if *col == self.column)
+            && projected.name() == name
+    }
+}
+
+fn default_max_errors() -> usize {
+    100
+}
+
+fn default_read_attempts() -> usize {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+/// The field separator of a CSV source: a single ASCII character, or `auto` to pick the most
+/// frequent of `,`, `;`, tab and `|` in the first line. Outputs take only a character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Delimiter {
+    Auto,
+    Byte(u8),
+}
+
+impl TryFrom<String> for Delimiter {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        match value.as_bytes() {
+            b"auto" => Ok(Self::Auto),
+            &[byte] if byte.is_ascii() => Ok(Self::Byte(byte)),
+            _ => bail!(
+                "invalid delimiter {:?}: expected a single ASCII character or auto",
+                value
+            ),
+        }
+    }
+}
+
+impl From<Delimiter> for String {
+    fn from(delimiter: Delimiter) -> Self {
+        match delimiter {
+            Delimiter::Auto => "auto".to_owned(),
+            Delimiter::Byte(byte) => char::from(byte).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct RowBounds {
+    #[serde(default)]
+    pub min: Option<usize>,
+    #[serde(default)]
+    pub max: Option<usize>,
+}
+
+impl RowBounds {
+    /// What the bounds require of a count they don't allow, e.g. `between 10 and 20`; `None`
+    /// for a count within them.
+    pub fn violation(&self, count: usize) -> Option<String> {
+        if self.min.is_none_or(|min| count >= min) && self.max.is_none_or(|max| count <= max) {
+            return None;
+        }
+        Some(match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("between {} and {}", min, max),
+            (Some(min), None) => format!("at least {}", min),
+            (None, Some(max)) => format!("at most {}", max),
+            (None, None) => unreachable!("unbounded counts are always within the bounds"),
+        })
+    }
+
+    /// Fails if the minimum is greater than the maximum, for the bounds in `field`.
+    fn check(&self, field: &str) -> Result<()> {
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                bail!("{}: min {} is greater than max {}", field, min, max);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Defaults for the data-quality policies that aren't set explicitly. Without a mode, each
+/// policy keeps its own default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Fail on anything suspicious.
+    Strict,
+    /// Skip, pad or keep suspicious data, counting what was affected.
+    Lenient,
+}
+
+impl Mode {
+    /// The value for this mode out of a strict and a lenient one.
+    fn pick(self, strict: impl Into<Value>, lenient: impl Into<Value>) -> Value {
+        match self {
+            Self::Strict => strict.into(),
+            Self::Lenient => lenient.into(),
+        }
+    }
+
+    /// Policy defaults for the top level of the spec, by field name.
+    fn spec_defaults(self) -> [(&'static str, Value); 7] {
+        [
+            ("empty_source", self.pick("error", "warn")),
+            ("empty_output", self.pick("error", "warn")),
+            ("on_format_error", self.pick("error", "passthrough")),
+            ("empty_keys", self.pick("error", "skip")),
+            ("lenient_key_types", self.pick(false, true)),
+            ("coverage_check", self.pick("error", "warn")),
+            ("assert_mode", self.pick("error", "warn")),
+        ]
+    }
+
+    /// Policy defaults for the output options, at the top level and for each output.
+    fn output_defaults(self) -> [(&'static str, Value); 1] {
+        [("lenient_schema", self.pick(false, true))]
+    }
+
+    /// Policy defaults for each source, by field name.
+    fn source_defaults(self) -> [(&'static str, Value); 2] {
+        [
+            ("on_parse_error", self.pick("fail", "skip")),
+            ("strict_schema", self.pick(true, false)),
+        ]
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FuzzyMatch {
+    /// Maximum edit distance between normalized keys; normalized matching only if zero.
+    #[serde(default)]
+    pub max_distance: usize,
+    /// Output column listing the sources whose row was matched fuzzily.
+    #[serde(default)]
+    pub flag_column: Option<String>,
+    /// CSV file recording every fuzzy match, with the original and the matched key.
+    #[serde(default)]
+    pub audit: Option<PathBuf>,
+}
+
+impl JoinSpec {
+    /// Checks the spec for mistakes that can be caught before reading any data.
+    pub fn validate(&self) -> Result<()> {
+        if self.key.is_empty() && self.join != JoinType::Union {
+            if !self.allow_cross_join {
+                bail!(
+                    "key is empty, which joins every row of each source with every row of the \
+                     others; set allow_cross_join: true if that is intended"
+                );
+            }
+            if self.join == JoinType::Presence {
+                bail!("join: presence requires a key");
+            }
+            if self.fuzzy.is_some() {
+                bail!("fuzzy matching requires a key");
+            }
+            if self.key_filter.is_some() {
+                bail!("key_filter requires a key");
+            }
+            if self.sorted {
+                bail!("sorted: true requires a key");
+            }
+            if self.rejects.is_some() {
+                bail!("rejects requires a key");
+            }
+            if self.warn_near_duplicate_keys {
+                bail!("warn_near_duplicate_keys requires a key");
+            }
+            if self.primary_passthrough {
+                bail!("primary_passthrough requires a key, as a cross join keeps every row");
+            }
+            if self.require_unique_base_keys {
+                bail!("require_unique_base_keys requires a key");
+            }
+        } else if self.allow_cross_join {
+            bail!("allow_cross_join requires an empty key");
+        } else if self.max_rows.is_some() {
+            bail!("max_rows only applies to a cross join, with an empty key");
+        }
+        if self.join == JoinType::Union {
+            if self.sorted {
+                bail!("sorted: true is not supported with join: union");
+            }
+            if self.keys_only {
+                bail!("keys_only is not supported with join: union");
+            }
+            if self.fuzzy.is_some() {
+                bail!("fuzzy matching is not supported with join: union");
+            }
+            if self.key_filter.is_some() {
+                bail!("key_filter is not supported with join: union");
+            }
+            if self.rejects.is_some() {
+                bail!("rejects is not supported with join: union, which drops no row");
+            }
+            if self.primary_passthrough {
+                bail!(
+                    "primary_passthrough is not supported with join: union, which keeps every row"
+                );
+            }
+            if self.require_unique_base_keys {
+                bail!(
+                    "require_unique_base_keys is not supported with join: union, which keeps \
+                     every row"
+                );
+            }
+            if self.emit_match_flags {
+                bail!("emit_match_flags is not supported with join: union");
+            }
+            if self.coalesce || !self.coalesce_columns.is_empty() {
+                bail!(
+                    "coalesce and coalesce_columns are not supported with join: union, which \
+                     already outputs the projections of a name as one column"
+                );
+            }
+            if self.warn_near_duplicate_keys {
+                bail!("warn_near_duplicate_keys is not supported with join: union");
+            }
+            if self.changed_since.is_some() {
+                bail!("changed_since is not supported with join: union");
+            }
+            if let Some(source) = self.sources.iter().find(|source| {
+                source.join.is_some()
+                    || source.passthrough
+                    || source.emit_match_flags.is_some()
+                    || source.emit_provenance
+                    || source.range.is_some()
+                    || !source.match_on.is_empty()
+                    || !source.key.is_empty()
+                    || source.is_aggregated()
+                    || source.spill_after.is_some()
+                    || source.latest_by.is_some()
+            }) {
+                bail!(
+                    "{}: join, passthrough, emit_match_flags, emit_provenance, range, match_on, \
+                     key, agg, spill_after and latest_by are not supported with join: union",
+                    source.path.display()
+                );
+            }
+        }
+        if self
+            .read_retries
+            .is_some_and(|retries| retries.attempts == 0)
+        {
+            bail!("read_retries.attempts must be positive");
+        }
+        if self.max_errors == 0 {
+            bail!("max_errors must be positive");
+        }
+        let mut coalesced = HashSet::new();
+        for col in self.coalesce_columns.iter() {
+            if !coalesced.insert(&col.name) {
+                bail!("coalesce_columns: column {} is listed twice", col.name);
+            }
+            if col.from.is_empty() {
+                bail!("coalesce_columns: column {} has no from columns", col.name);
+            }
+            if self.join == JoinType::Presence {
+                bail!("coalesce_columns is not supported with join: presence");
+            }
+            for from in col.from.iter() {
+                if !self
+                    .sources
+                    .iter()
+                    .any(|source| source.name() == from.source)
+                {
+                    let names: Vec<_> = self.sources.iter().map(Source::name).collect();
+                    bail!(
+                        "coalesce_columns: column {} reads from no source {}; sources are: {}",
+                        col.name,
+                        from.source,
+                        names.join(", ")
+                    );
+                }
+            }
+        }
+        if self.require_unique_base_keys && self.primary_passthrough {
+            bail!(
+                "require_unique_base_keys is not supported with primary_passthrough, which keeps \
+                 every row of a key; set dup_flag_column to tell those rows apart"
+            );
+        }
+        if self.sorted {
+            if self.join == JoinType::Presence {
+                bail!("sorted: true is not supported with join: presence");
+            }
+            if self.fuzzy.is_some() {
+                bail!("sorted: true is not supported with fuzzy matching");
+            }
+            if self.key_filter.is_some() {
+                bail!("sorted: true is not supported with key_filter");
+            }
+            if self.rejects.is_some() {
+                bail!("sorted: true is not supported with rejects");
+            }
+            if self.warn_near_duplicate_keys {
+                bail!("sorted: true is not supported with warn_near_duplicate_keys");
+            }
+            if self.primary_passthrough {
+                bail!("sorted: true is not supported with primary_passthrough");
+            }
+        }
+        if self.keys_only {
+            if self.key.is_empty() {
+                bail!("keys_only requires a key");
+            }
+            if self.join != JoinType::Inner {
+                bail!(
+                    "keys_only lists the keys present in every source, so it requires join: \
+                     inner, not join: {}",
+                    self.join
+                );
+            }
+            if let Some(source) = self
+                .sources
+                .iter()
+                .find(|source| source.join.is_some_and(|join| join != JoinType::Inner))
+            {
+                bail!(
+                    "{}: keys_only lists the keys present in every source, so it requires \
+                     every source to be inner joined",
+                    source.path.display()
+                );
+            }
+            if !self.coalesce_columns.is_empty() {
+                bail!("coalesce_columns is not supported with keys_only");
+            }
+            if !self.column_order.is_empty() {
+                bail!("column_order is not supported with keys_only");
+            }
+            if self.rejects.is_some() {
+                bail!("rejects is not supported with keys_only");
+            }
+            if self.primary_passthrough {
+                bail!("primary_passthrough is not supported with keys_only");
+            }
+            if self.emit_match_flags {
+                bail!("emit_match_flags is not supported with keys_only");
+            }
+            if let Some(source) = self.sources.iter().find(|source| {
+                source.passthrough
+                    || source.emit_provenance
+                    || source.emit_match_flags == Some(true)
+            }) {
+                bail!(
+                    "{}: passthrough, emit_provenance and emit_match_flags are not supported \
+                     with keys_only",
+                    source.path.display()
+                );
+            }
+        }
+        if let Some(changed_since) = &self.changed_since {
+            if self.key.is_empty() {
+                bail!("changed_since requires a key, to find the previous row of each row");
+            }
+            if self.join == JoinType::Presence {
+                bail!("changed_since is not supported with join: presence");
+            }
+            if self.primary_passthrough {
+                bail!(
+                    "changed_since is not supported with primary_passthrough, as rows would \
+                     share their key"
+                );
+            }
+            if changed_since.emit_deleted && self.key_filter.is_some() {
+                bail!(
+                    "changed_since.emit_deleted is not supported with key_filter, as the keys \
+                     it leaves out would be deleted"
+                );
+            }
+            if changed_since.emit_deleted && changed_since.change_type.is_none() {
+                bail!(
+                    "changed_since.emit_deleted requires change_type, to tell deleted rows \
+                     from others"
+                );
+            }
+        }
+        if self.rejects.is_some() && self.join == JoinType::Presence {
+            bail!("rejects is not supported with join: presence");
+        }
+        if self.primary_passthrough && self.join == JoinType::Presence {
+            bail!("primary_passthrough is not supported with join: presence");
+        }
+        if self.dup_flag_column.is_some() && !self.primary_passthrough {
+            bail!(
+                "dup_flag_column requires primary_passthrough, as the primary source otherwise \
+                 has one row per key"
+            );
+        }
+        if !self.key_types.is_empty() && self.key_types.len() != self.key.len() {
+            bail!(
+                "key_types has {} type(s) for {} key column(s)",
+                self.key_types.len(),
+                self.key.len()
+            );
+        }
+        if self.collapse_key.is_some() {
+            if self.key.len() < 2 {
+                bail!("collapse_key requires a key of two or more columns");
+            }
+            if self.join == JoinType::Presence
+                || self
+                    .sources
+                    .first()
+                    .is_some_and(|source| source.passthrough)
+            {
+                bail!(
+                    "collapse_key is not supported with join: presence, or with passthrough on \
+                     the primary source, which outputs the key columns where they are in its file"
+                );
+            }
+        }
+        for col in self.key.iter() {
+            if let KeyColumn::Expr { name, expr } = col {
+                Expr::parse(expr)
+                    .with_context(|| format!("key column {}: invalid expression", name))?;
+            }
+        }
+        if let Some(primary) = self.sources.first() {
+            if primary.spill_after.is_some() {
+                bail!(
+                    "{}: spill_after is only supported on lookup sources",
+                    primary.path.display()
+                );
+            }
+            if primary.join.is_some() {
+                bail!(
+                    "{}: join is only supported on lookup sources",
+                    primary.path.display()
+                );
+            }
+            if primary.emit_match_flags.is_some() {
+                bail!(
+                    "{}: emit_match_flags is only supported on lookup sources",
+                    primary.path.display()
+                );
+            }
+            if primary.passthrough {
+                if primary.prefix.is_some() {
+                    bail!(
+                        "{}: prefix is not supported with passthrough, which outputs the columns \
+                         under their own names",
+                        primary.path.display()
+                    );
+                }
+                if !primary.projections.is_empty() || primary.all_except.is_some() {
+                    bail!(
+                        "{}: passthrough outputs every column; remove projections and all_except",
+                        primary.path.display()
+                    );
+                }
+                if self.join == JoinType::Presence {
+                    bail!("passthrough is not supported with join: presence");
+                }
+                if !self
+                    .key
+                    .iter()
+                    .all(|col| matches!(col, KeyColumn::Column(_)))
+                {
+                    bail!(
+                        "{}: passthrough requires the key columns to be read as they are, \
+                         without a new name or a computed value",
+                        primary.path.display()
+                    );
+                }
+            }
+            if !primary.match_on.is_empty() {
+                bail!(
+                    "{}: match_on is only supported on lookup sources",
+                    primary.path.display()
+                );
+            }
+        }
+        for (idx, source) in self.sources.iter().enumerate() {
+            if idx > 0 && source.passthrough {
+                bail!(
+                    "{}: passthrough is only supported on the primary source",
+                    source.path.display()
+                );
+            }
+            if let Some(join @ (JoinType::Presence | JoinType::Union)) = source.join {
+                bail!(
+                    "{}: join: {} applies to the whole spec, not a single source",
+                    source.path.display(),
+                    join
+                );
+            }
+            if self.join == JoinType::Presence && !source.projections.is_empty() {
+                bail!(
+                    "{}: projections are not supported with join: presence",
+                    source.path.display()
+                );
+            }
+            let joined_inner = idx == 0 || source.join.unwrap_or(self.join) == JoinType::Inner;
+            if source.emit_provenance && self.join == JoinType::Presence {
+                bail!(
+                    "{}: emit_provenance is not supported with join: presence",
+                    source.path.display()
+                );
+            }
+            if source.mmap
+                && (source.path.as_os_str() == "-" || crate::input::as_url(&source.path).is_some())
+            {
+                bail!(
+                    "{}: mmap only applies to local files, not to stdin or URLs",
+                    source.path.display()
+                );
+            }
+            if source.provenance_path && !source.emit_provenance {
+                bail!(
+                    "{}: provenance_path requires emit_provenance",
+                    source.path.display()
+                );
+            }
+            if idx > 0 && self.match_flag(source).is_some() {
+                if self.key.is_empty() || self.join == JoinType::Presence {
+                    bail!(
+                        "{}: match flags require a key, and are not supported with join: presence",
+                        source.path.display()
+                    );
+                }
+                if joined_inner {
+                    tracing::warn!(
+                        "{}: warning: the match flag of an inner-joined source is always {:?}",
+                        source.path.display(),
+                        self.present_value
+                    );
+                }
+            }
+            if joined_inner
+                && source
+                    .projections
+                    .values()
+                    .any(|col| col.default().is_some())
+            {
+                tracing::warn!(
+                    "{}: warning: projection defaults never apply to an inner-joined source",
+                    source.path.display()
+                );
+            }
+            if !self.allow_key_projections {
+                for (col, key) in self.key_columns_read(source) {
+                    let projected_as_col = |(from, _): &(&ProjectionFrom, _)| matches!(from, ProjectionFrom::Column(name) if name == col);
+                    for (_, projected) in source.projections.iter().filter(projected_as_col) {
+                        tracing::warn!(
+                            "{}: warning: column {} is read as key column {} and also projected \
+                             as {}, so its values are output twice; set allow_key_projections: \
+                             true if intended",
+                            source.path.display(),
+                            col,
+                            key,
+                            projected.name()
+                        );
+                    }
+                }
+            }
+            for col in source.projections.values() {
+                col.validate().with_context(|| {
+                    format!("{}: projection {}", source.path.display(), col.name())
+                })?;
+            }
+            for assertion in source.assertions.iter() {
+                if !assertion.unique && !assertion.not_empty && assertion.one_of.is_none() {
+                    bail!(
+                        "{}: assertion on column {} checks nothing; set unique, not_empty or in",
+                        source.path.display(),
+                        assertion.column
+                    );
+                }
+            }
+            source.encoding()?;
+            if !source.match_on.is_empty() {
+                if source.match_on.len() != self.key.len() {
+                    bail!(
+                        "{}: match_on has {} column(s) for {} key column(s)",
+                        source.path.display(),
+                        source.match_on.len(),
+                        self.key.len()
+                    );
+                }
+                if self.join == JoinType::Presence || self.sorted {
+                    bail!(
+                        "{}: match_on is not supported with join: presence or sorted: true",
+                        source.path.display()
+                    );
+                }
+            }
+            if let Some(range) = &source.range {
+                if idx == 0 {
+                    bail!(
+                        "{}: range is only supported on lookup sources",
+                        source.path.display()
+                    );
+                }
+                if self.range_position(source).is_none() {
+                    bail!(
+                        "{}: range on unknown key column {}",
+                        source.path.display(),
+                        range.key
+                    );
+                }
+                if !matches!(
+                    self.key.iter().find(|col| col.to_string() == range.key),
+                    Some(KeyColumn::Column(_) | KeyColumn::Renamed { .. })
+                ) || source.key_aliases.contains_key(&range.key)
+                    || source.key_expr.contains_key(&range.key)
+                    || source.key_regex.contains_key(&range.key)
+                {
+                    bail!(
+                        "{}: range key column {} must be read from a column, and not from \
+                         key_aliases, key_expr or key_regex on the range source",
+                        source.path.display(),
+                        range.key
+                    );
+                }
+                if self.join == JoinType::Presence
+                    || self.sorted
+                    || self.fuzzy.is_some()
+                    || source.spill_after.is_some()
+                {
+                    bail!(
+                        "{}: range is not supported with join: presence, sorted: true, fuzzy \
+                         matching or spill_after",
+                        source.path.display()
+                    );
+                }
+            }
+            if !source.key.is_empty() {
+                if idx == 0 {
+                    bail!(
+                        "{}: key is only supported on lookup sources",
+                        source.path.display()
+                    );
+                }
+                let mut positions = Vec::with_capacity(source.key.len());
+                for name in source.key.iter() {
+                    match self.key.iter().position(|col| col.to_string() == *name) {
+                        Some(pos) => positions.push(pos),
+                        None => bail!(
+                            "{}: key has unknown key column {}",
+                            source.path.display(),
+                            name
+                        ),
+                    }
+                }
+                if positions.windows(2).any(|pair| pair[0] >= pair[1]) {
+                    bail!(
+                        "{}: key must list key columns once each, in the order of the spec's key",
+                        source.path.display()
+                    );
+                }
+                if self.join == JoinType::Presence
+                    || self.sorted
+                    || self.fuzzy.is_some()
+                    || source.range.is_some()
+                    || !source.match_on.is_empty()
+                {
+                    bail!(
+                        "{}: key is not supported with join: presence, sorted: true, fuzzy \
+                         matching, range or match_on",
+                        source.path.display()
+                    );
+                }
+                if let Some(name) = source
+                    .key_aliases
+                    .keys()
+                    .chain(source.key_expr.keys())
+                    .chain(source.key_regex.keys())
+                    .find(|name| !source.key.contains(name))
+                {
+                    bail!(
+                        "{}: key_aliases, key_expr or key_regex for key column {}, which is not \
+                         in the source's key",
+                        source.path.display(),
+                        name
+                    );
+                }
+            }
+            if source.latest_by.is_some() {
+                if self.key.is_empty() {
+                    bail!(
+                        "{}: latest_by requires a key, as a cross join keeps every row",
+                        source.path.display()
+                    );
+                }
+                if (idx == 0 && self.primary_passthrough)
+                    || self.sorted
+                    || source.range.is_some()
+                    || source.is_aggregated()
+                    || source.spill_after.is_some()
+                {
+                    bail!(
+                        "{}: latest_by is not supported with primary_passthrough, sorted: true, \
+                         range, agg or spill_after",
+                        source.path.display()
+                    );
+                }
+            }
+            if source.lazy_projections {
+                if idx == 0 {
+                    bail!(
+                        "{}: lazy_projections is only supported on lookup sources",
+                        source.path.display()
+                    );
+                }
+                if self.key.is_empty()
+                    || matches!(self.join, JoinType::Presence | JoinType::Union)
+                    || self.sorted
+                    || source.range.is_some()
+                    || source.is_aggregated()
+                    || source.spill_after.is_some()
+                    || source.latest_by.is_some()
+                {
+                    bail!(
+                        "{}: lazy_projections requires a key, and is not supported with join: \
+                         presence or union, sorted: true, range, agg, spill_after or latest_by",
+                        source.path.display()
+                    );
+                }
+                if source.path.as_os_str() == "-"
+                    || crate::input::as_url(&source.path).is_some()
+                    || crate::input::is_spreadsheet(&source.path)
+                    || source.encoding()? != encoding_rs::UTF_8
+                {
+                    bail!(
+                        "{}: lazy_projections requires local CSV files in UTF-8, to seek in, \
+                         not stdin, a URL, a spreadsheet or another encoding",
+                        source.path.display()
+                    );
+                }
+            }
+            if source.is_aggregated() {
+                if idx == 0 {
+                    bail!(
+                        "{}: agg is only supported on lookup sources",
+                        source.path.display()
+                    );
+                }
+                if self.key.is_empty()
+                    || self.join == JoinType::Presence
+                    || self.sorted
+                    || source.range.is_some()
+                    || source.spill_after.is_some()
+                {
+                    bail!(
+                        "{}: agg requires a key, and is not supported with join: presence, \
+                         sorted: true, range or spill_after",
+                        source.path.display()
+                    );
+                }
+            }
+            for name in source.key_aliases.keys() {
+                if !self.key.iter().any(|col| col.to_string() == *name) {
+                    bail!(
+                        "{}: key_aliases for unknown key column {}",
+                        source.path.display(),
+                        name
+                    );
+                }
+                if source.key_expr.contains_key(name) {
+                    bail!(
+                        "{}: key column {} has both a key_expr and a key_aliases entry",
+                        source.path.display(),
+                        name
+                    );
+                }
+            }
+            for (name, expr) in source.key_expr.iter() {
+                if !self.key.iter().any(|col| col.to_string() == *name) {
+                    bail!(
+                        "{}: key_expr for unknown key column {}",
+                        source.path.display(),
+                        name
+                    );
+                }
+                Expr::parse(expr).with_context(|| {
+                    format!(
+                        "{}: key_expr {}: invalid expression",
+                        source.path.display(),
+                        name
+                    )
+                })?;
+            }
+            for (name, regex) in source.key_regex.iter() {
+                if !self.key.iter().any(|col| col.to_string() == *name) {
+                    bail!(
+                        "{}: key_regex for unknown key column {}",
+                        source.path.display(),
+                        name
+                    );
+                }
+                if source.key_aliases.contains_key(name) || source.key_expr.contains_key(name) {
+                    bail!(
+                        "{}: key column {} has a key_regex and also a key_aliases or key_expr \
+                         entry",
+                        source.path.display(),
+                        name
+                    );
+                }
+                regex.compile().with_context(|| {
+                    format!(
+                        "{}: key_regex {}: invalid regex",
+                        source.path.display(),
+                        name
+                    )
+                })?;
+            }
+        }
+        if let Some(bounds) = self.expect_rows {
+            bounds.check("expect_rows")?;
+        }
+        for source in self.sources.iter() {
+            if let Some(bounds) = source.expect_rows {
+                bounds.check(&format!("{}: expect_rows", source.path.display()))?;
+            }
+        }
+        self.check_output_names()
+    }
+
+    /// The name of the match flag column of a lookup source, if it has one.
+    pub fn match_flag(&self, source: &Source) -> Option<String> {
+        source
+            .emit_match_flags
+            .unwrap_or(self.emit_match_flags)
+            .then(|| format!("{}_matched", source.name()))
+    }
+
+    /// The name of the provenance column of a source, if it has one.
+    pub fn provenance_column(&self, source: &Source) -> Option<String> {
+        source
+            .emit_provenance
+            .then(|| format!("{}_row", source.name()))
+    }
+
+    /// The header names of the columns that a source reads key components from, along with their
+    /// key columns; computed components have none.
+    fn key_columns_read<'a>(
+        &'a self,
+        source: &'a Source,
+    ) -> impl Iterator<Item = (&'a str, &'a KeyColumn)> {
+        self.source_key(source).flat_map(move |(_, key)| {
+            let name = key.to_string();
+            let cols: Vec<&str> = if let Some(alias) = source.key_aliases.get(&name) {
+                vec![alias]
+            } else if source.key_expr.contains_key(&name) || source.key_regex.contains_key(&name) {
+                Vec::new()
+            } else {
+                match key {
+                    KeyColumn::Column(ColumnRef::Name(col))
+                    | KeyColumn::Renamed {
+                        from: ColumnRef::Name(col),
+                        ..
+                    } => vec![col],
+                    KeyColumn::Concat { concat, .. } => concat.iter().map(String::as_str).collect(),
+                    _ => Vec::new(),
+                }
+            };
+            cols.into_iter().map(move |col| (col, key))
+        })
+    }
+
+    /// Transforms a key component by `key_clean` and `key_normalization`, returning whether
+    /// `key_clean` changed it.
+    pub fn normalize_key(&self, value: &mut String) -> bool {
+        let cleaned = match self.key_clean.map(|clean| clean.apply(value)) {
+            Some(Cow::Borrowed(clean)) if clean.len() == value.len() => false,
+            Some(clean) => {
+                *value = clean.into_owned();
+                true
+            }
+            None => false,
+        };
+        for normalization in self.key_normalization.iter() {
+            match normalization.apply(value) {
+                Cow::Borrowed(normalized) if normalized.len() == value.len() => {}
+                normalized => *value = normalized.into_owned(),
+            }
+        }
+        cleaned
+    }
+
+    /// Adds the aliases of a column map to every source, for the columns without aliases of
+    /// the source's own.
+    pub fn apply_column_map(&mut self, map: &IndexMap<String, Vec<String>>) {
+        for source in self.sources.iter_mut() {
+            for (col, aliases) in map.iter() {
+                source
+                    .aliases
+                    .entry(col.clone())
+                    .or_insert_with(|| aliases.clone());
+            }
+        }
+    }
+
+    /// For `keys_only`, removes the projections of every source, which leaves the output with
+    /// the key columns only.
+    pub fn drop_projections(&mut self) {
+        for source in self.sources.iter_mut() {
+            source.projections = Projections::default();
+            source.all_except = None;
+        }
+    }
+
+    /// Renames the projections of each source with a `prefix` to their output names, except
+    /// those of `coalesce_columns`, whose name is that of the coalesced column.
+    pub fn apply_prefixes(&mut self) {
+        for idx in 0..self.sources.len() {
+            let Some(prefix) = self.sources[idx].prefix.take() else {
+                continue;
+            };
+            let source = &self.sources[idx];
+            let coalesced: Vec<_> = source
+                .projections
+                .iter()
+                .map(|(from, projected)| self.is_coalesced(source, from, projected))
+                .collect();
+            let projections = self.sources[idx].projections.values_mut();
+            for (projected, coalesced) in projections.zip(coalesced) {
+                if !coalesced {
+                    projected.set_name(format!("{}{}", prefix, projected.name()));
+                }
+            }
+        }
+    }
+
+    /// Adds a projection of each column of `coalesce_columns` to its source, under the name of
+    /// the coalesced column, unless the source already has it.
+    pub fn project_coalesced_columns(&mut self) {
+        for col in self.coalesce_columns.iter() {
+            for from in col.from.iter() {
+                let Some(source) = self
+                    .sources
+                    .iter_mut()
+                    .find(|source| source.name() == from.source)
+                else {
+                    continue;
+                };
+                let projected = source.projections.iter().any(|(proj_from, projected)| {
+                    from.is_projected(proj_from, projected, &col.name)
+                });
+                if !projected {
+                    source
+                        .projections
+                        .push(from.column.clone(), ProjectedColumn::Name(col.name.clone()));
+                }
+            }
+        }
+    }
+
+    /// Whether a projection of the source reads a column of `coalesce_columns`.
+    pub fn is_coalesced(
+        &self,
+        source: &Source,
+        from: &ProjectionFrom,
+        projected: &ProjectedColumn,
+    ) -> bool {
+        self.coalesce_columns.iter().any(|col| {
+            col.from.iter().any(|source_col| {
+                source_col.source == source.name()
+                    && source_col.is_projected(from, projected, &col.name)
+            })
+        })
+    }
+
+    /// The key columns a source is read by, with their positions in the key: all of them,
+    /// unless the source declares a `key` of only some.
+    pub fn source_key<'a>(
+        &'a self,
+        source: &'a Source,
+    ) -> impl Iterator<Item = (usize, &'a KeyColumn)> {
+        self.key.iter().enumerate().filter(move |(_, col)| {
+            source.key.is_empty() || source.key.iter().any(|name| *name == col.to_string())
+        })
+    }
+
+    /// The positions in the key of the components a source is keyed by, if it declares a `key`
+    /// of only some of them.
+    pub fn key_subset(&self, source: &Source) -> Option<Vec<usize>> {
+        (!source.key.is_empty()).then(|| self.source_key(source).map(|(pos, _)| pos).collect())
+    }
+
+    /// The number of errors to stop reading at, if `error_mode` collects them.
+    pub fn error_limit(&self) -> Option<usize> {
+        match self.error_mode {
+            ErrorMode::First => None,
+            ErrorMode::Collect => Some(self.max_errors),
+        }
+    }
+
+    /// The position in the key of the component a source matches by range, if it has a range.
+    pub fn range_position(&self, source: &Source) -> Option<usize> {
+        let range = source.range.as_ref()?;
+        self.key.iter().position(|col| col.to_string() == range.key)
+    }
+
+    /// Fails if the output header would name a column more than once, listing where each
+    /// definition of a duplicate name comes from. Key columns referenced by position are named
+    /// by the primary source's header, which is only known once it is read.
+    pub fn check_output_names(&self) -> Result<()> {
+        if self.allow_duplicate_headers || self.coalesce {
+            return Ok(());
+        }
+        let mut defs: Vec<(Cow<'_, str>, String)> = Vec::new();
+        let mut add = |name, origin| defs.push((name, origin));
+        if let Some(name) = &self.row_number {
+            add(name.into(), "row_number".to_owned());
+        }
+        // Key columns of a passthrough source are output among its own columns.
+        let passthrough = self
+            .sources
+            .first()
+            .is_some_and(|source| source.passthrough);
+        if let Some(collapsed) = &self.collapse_key {
+            add(collapsed.name.as_str().into(), "collapse_key".to_owned());
+        }
+        let collapsed = self.collapse_key.is_some();
+        for (idx, col) in self
+            .key
+            .iter()
+            .enumerate()
+            .filter(|_| !passthrough && !collapsed)
+        {
+            let name = match col {
+                KeyColumn::Column(ColumnRef::Name(name)) => name,
+                KeyColumn::Column(ColumnRef::Index { .. }) => continue,
+                KeyColumn::Renamed { to, .. } => to,
+                KeyColumn::Concat { name, .. } | KeyColumn::Expr { name, .. } => name,
+            };
+            add(name.into(), format!("key column {}", idx + 1));
+        }
+        // A union outputs the projections of the same name in different sources as one column.
+        let mut unioned = HashSet::new();
+        for source in self.sources.iter() {
+            if self.join == JoinType::Presence {
+                add(source.name(), format!("source {} presence", source.name()));
+            }
+            if self.keys_only {
+                continue;
+            }
+            let mut projected_here = HashSet::new();
+            for (col, projected) in source.projections.iter() {
+                if self.is_coalesced(source, col, projected) {
+                    continue;
+                }
+                if self.join == JoinType::Union {
+                    let name = source.output_name(projected);
+                    if unioned.contains(&name) {
+                        continue;
+                    }
+                    projected_here.insert(name);
+                }
+                add(
+                    source.output_name(projected),
+                    format!("source {} projection {}", source.name(), col),
+                );
+            }
+            unioned.extend(projected_here);
+        }
+        for col in self.coalesce_columns.iter() {
+            add(col.name.as_str().into(), "coalesce_columns".to_owned());
+        }
+        if let Some(col) = self
+            .fuzzy
+            .as_ref()
+            .and_then(|fuzzy| fuzzy.flag_column.as_ref())
+        {
+            add(col.into(), "fuzzy.flag_column".to_owned());
+        }
+        for source in self.sources[1..].iter() {
+            if let Some(col) = self.match_flag(source) {
+                add(col.into(), format!("source {} match flag", source.name()));
+            }
+        }
+        if let Some(col) = &self.dup_flag_column {
+            add(col.into(), "dup_flag_column".to_owned());
+        }
+        if let Some(col) = self
+            .changed_since
+            .as_ref()
+            .and_then(|changed_since| changed_since.change_type.as_ref())
+        {
+            add(col.into(), "changed_since.change_type".to_owned());
+        }
+        for source in self.sources.iter() {
+            if let Some(col) = self.provenance_column(source) {
+                add(col.into(), format!("source {} provenance", source.name()));
+            }
+        }
+        let mut names: IndexMap<_, Vec<_>> = IndexMap::new();
+        for (name, origin) in defs {
+            names.entry(name).or_default().push(origin);
+        }
+        let duplicates: String = names
+            .iter()
+            .filter(|(_, origins)| origins.len() > 1)
+            .map(|(name, origins)| format!("\n  {}: {}", name, origins.join(", ")))
+            .collect();
+        if !duplicates.is_empty() {
+            bail!(
+                "output column names are not unique; rename them, or set \
+                 allow_duplicate_headers: true:{}",
+                duplicates
+            );
+        }
+        Ok(())
+    }
+
+    /// All outputs to write, each with its own options.
+    pub fn outputs(&self) -> Vec<Output<'_>> {
+        match &self.output {
+            OutputTarget::Path(path) => vec![Output {
+                path,
+                options: &self.output_options,
+            }],
+            OutputTarget::Many(outputs) => outputs
+                .iter()
+                .map(|output| Output {
+                    path: &output.path,
+                    options: &output.options,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Either a single output path, using the top-level output options, or a list of outputs, each
+/// using the top-level output options it doesn't set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum OutputTarget {
+    Path(PathBuf),
+    Many(Vec<OutputDef>),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutputDef {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub options: OutputOptions,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutputOptions {
+    #[serde(default, alias = "format")]
+    pub output_format: OutputFormat,
+    #[serde(default)]
+    pub partition_by: Option<String>,
+    /// File name used for rows whose partition value is empty.
+    #[serde(default = "default_partition_null")]
+    pub partition_null: String,
+    /// Maximum number of partition files kept open at once; least recently used files are
+    /// closed and reopened later as needed.
+    #[serde(default = "default_max_open_partitions")]
+    pub max_open_partitions: usize,
+    /// The table written by sqlite and sql output.
+    #[serde(default = "default_table")]
+    pub table: String,
+    #[serde(default)]
+    pub index_key: bool,
+    /// Replace an output file that already exists, which fails the run otherwise, and under
+    /// sqlite output an existing table.
+    #[serde(default)]
+    pub overwrite: bool,
+    /// Appends rows to an existing output instead of replacing it, writing the header only if
+    /// the file is new. The existing header is not compared to the output's unless
+    /// `check_header` is also set, so schema drift between runs goes unnoticed by default.
+    #[serde(default)]
+    pub append: bool,
+    #[serde(default)]
+    pub check_header: bool,
+    #[serde(default)]
+    pub schema: IndexMap<String, ParquetType>,
+    #[serde(default)]
+    pub lenient_schema: bool,
+    /// Rows per row group of parquet output, 8192 if omitted, and per INSERT statement of sql
+    /// output, 1 if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<usize>,
+    #[serde(default)]
+    pub compression: ParquetCompression,
+    /// Size in bytes of the write buffer for csv, jsonl and sql output; 8 KiB if omitted.
+    #[serde(default)]
+    pub buffer_size: Option<usize>,
+    /// Written in csv and fixed output for cells absent because a source had no row for the
+    /// key. Absent cells are always null in jsonl, sqlite, parquet and sql output.
+    #[serde(default)]
+    pub null_value: String,
+    /// Line terminator of csv and fixed output, for the header and data rows alike; `lf` on
+    /// every platform if omitted.
+    #[serde(default)]
+    pub line_terminator: LineTerminator,
+    /// Which fields of csv output are quoted; only those that need it if omitted.
+    #[serde(default)]
+    pub quote_style: QuoteStyle,
+    /// The field separator of csv output, e.g. `"\t"` for TSV; `,` if omitted. Fields holding
+    /// it are quoted, or escaped under `quote_style: escape`, like line breaks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<Delimiter>,
+    /// Create the directory the output is written to if it is missing, instead of failing
+    /// before any source is read.
+    #[serde(default)]
+    pub create_dirs: bool,
+    /// Write `<output>.sha256.json` next to the output once it is committed, with the number
+    /// of rows and the SHA-256 of the bytes written, for downstream jobs to verify the output
+    /// against. The digest is computed as the output is written.
+    #[serde(default)]
+    pub checksum: bool,
+    /// Start a CSV file with a UTF-8 byte order mark, without which Excel reads it in the
+    /// system's legacy encoding and garbles non-ASCII characters. Not written to stdout, or
+    /// to a file appended to that already has a header.
+    #[serde(default)]
+    pub excel_bom: bool,
+    /// Roll csv output over into a new part, with its own header, before a file would grow
+    /// beyond this size (e.g. `100MB`).
+    ///
+    /// Parts are numbered from 0001 in place of `{part}` in the output path, or else in a
+    /// `.part0001` before its extension. An output that never reaches a limit is one file,
+    /// under the output path itself unless it has `{part}` or `always_suffix` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_size: Option<ByteSize>,
+    /// Roll csv output over into a new part once a file has this many data rows, as for
+    /// `max_output_size`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_rows: Option<usize>,
+    /// Number the part even if the output has only one, under `max_output_size` or
+    /// `max_output_rows`.
+    #[serde(default)]
+    pub always_suffix: bool,
+    /// The width of each column of fixed output, by output column name, either a number of
+    /// characters or its `width` with an `align` and a `pad` character; every column needs one.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub widths: IndexMap<String, FixedWidth>,
+    /// What fixed output does with a value wider than its column.
+    #[serde(default)]
+    pub on_overflow: OverflowPolicy,
+    /// Start fixed output with a line of the column names, fitted to the widths as values are
+    /// but padded with spaces.
+    #[serde(default)]
+    pub fixed_header: bool,
+}
+
+/// The width of a column of fixed output, in characters.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum FixedWidth {
+    Width(usize),
+    Padded {
+        width: usize,
+        #[serde(default)]
+        align: Align,
+        /// The character filling the rest of the column; a space if omitted.
+        #[serde(default = "default_pad")]
+        pad: char,
+    },
+}
+
+impl FixedWidth {
+    pub fn width(self) -> usize {
+        match self {
+            Self::Width(width) | Self::Padded { width, .. } => width,
+        }
+    }
+
+    pub fn align(self) -> Align {
+        match self {
+            Self::Width(_) => Align::Left,
+            Self::Padded { align, .. } => align,
+        }
+    }
+
+    pub fn pad(self) -> char {
+        match self {
+            Self::Width(_) => default_pad(),
+            Self::Padded { pad, .. } => pad,
+        }
+    }
+}
+
+fn default_pad() -> char {
+    ' '
+}
+
+/// Which side of a column of fixed output a value is written at, the rest being padded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+}
+
+/// What fixed output does with a value wider than its column.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Fail the run, as a value cut short would be read as another.
+    #[default]
+    Error,
+    /// Keep only as many of its first characters as fit.
+    Truncate,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineTerminator {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineTerminator {
+    pub fn terminator(self) -> csv::Terminator {
+        match self {
+            Self::Lf => csv::Terminator::Any(b'\n'),
+            Self::Crlf => csv::Terminator::CRLF,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStyle {
+    Always,
+    /// Only fields with a delimiter, quote or line break, or that are empty in a row of one.
+    #[default]
+    Necessary,
+    /// Fields that don't parse as numbers, along with those that need it.
+    NonNumeric,
+    /// No fields, even those that need it, which can make the output unreadable.
+    Never,
+    /// No fields, with backslashes, tabs, carriage returns, line feeds and the delimiter
+    /// written as `\\`, `\t`, `\r`, `\n` and a backslash before it, as TSV readers that take
+    /// no quotes expect. Absent cells are written as `null_value` unescaped, so it can be `\N`.
+    Escape,
+}
+
+impl QuoteStyle {
+    pub fn quote_style(self) -> csv::QuoteStyle {
+        match self {
+            Self::Always => csv::QuoteStyle::Always,
+            Self::Necessary => csv::QuoteStyle::Necessary,
+            Self::NonNumeric => csv::QuoteStyle::NonNumeric,
+            Self::Never | Self::Escape => csv::QuoteStyle::Never,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Output<'s> {
+    pub path: &'s Path,
+    pub options: &'s OutputOptions,
+}
+
+impl Output<'_> {
+    /// Whether the output path is `-`, meaning the output goes to stdout.
+    pub fn writes_to_stdout(&self) -> bool {
+        self.path.as_os_str() == "-"
+    }
+}
+
+fn default_on_format_error() -> TypeErrorPolicy {
+    TypeErrorPolicy::Passthrough
+}
+
+fn default_present_value() -> String {
+    "1".to_owned()
+}
+
+fn default_absent_value() -> String {
+    "0".to_owned()
+}
+
+fn default_table() -> String {
+    "joined".to_owned()
+}
+
+fn default_partition_null() -> String {
+    "_null".to_owned()
+}
+
+fn default_max_open_partitions() -> usize {
+    256
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Jsonl,
+    Sqlite,
+    Parquet,
+    /// `INSERT INTO` statements for the `table`, with the output header as the column list.
+    Sql,
+    /// Lines of values padded to the `widths` of their columns, without delimiters.
+    Fixed,
+}
+
+/// A transform of key components, in a spec's `key_normalization`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyNormalization {
+    /// Remove surrounding whitespace.
+    Trim,
+    Lowercase,
+    Uppercase,
+    /// Remove zeros at the start that are followed by another digit, so `007` becomes `7`, and
+    /// `00.5` becomes `0.5`.
+    StripLeadingZeros,
+}
+
+impl KeyNormalization {
+    /// The transformed key component, borrowed from `value` where it is a part of it.
+    pub fn apply(self, value: &str) -> Cow<'_, str> {
+        match self {
+            Self::Trim => Cow::Borrowed(value.trim()),
+            Self::Lowercase => Cow::Owned(value.to_lowercase()),
+            Self::Uppercase => Cow::Owned(value.to_uppercase()),
+            Self::StripLeadingZeros => {
+                let mut rest = value;
+                while rest.starts_with('0') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                    rest = &rest[1..];
+                }
+                Cow::Borrowed(rest)
+            }
+        }
+    }
+}
+
+/// A preset of `key_clean`, for the ways a kind of tool mangles key values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyClean {
+    /// Values round-tripped through a spreadsheet: strips an `="..."` formula wrapper and a
+    /// leading apostrophe, which force a value to be text, removes zero-width characters and
+    /// surrounding non-breaking spaces, and converts Unicode digits, such as full-width ones,
+    /// to ASCII.
+    Spreadsheet,
+}
+
+/// Zero-width characters, which spreadsheets keep in values pasted from elsewhere.
+const ZERO_WIDTH_CHARS: [char; 5] = ['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}'];
+
+/// Non-breaking spaces, which spreadsheets keep in values pasted from elsewhere.
+const NON_BREAKING_SPACES: [char; 3] = ['\u{00A0}', '\u{2007}', '\u{202F}'];
+
+/// The zeros of the runs of ten decimal digits of Unicode scripts, and of full-width and
+/// mathematical digits.
+const UNICODE_ZEROS: [u32; 24] = [
+    0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66, 0x0BE6, 0x0C66, 0x0CE6, 0x0D66,
+    0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x17E0, 0x1810, 0xFF10, 0x1D7CE, 0x1D7D8, 0x1D7E2, 0x1D7EC,
+    0x1D7F6,
+];
+
+impl KeyClean {
+    /// The cleaned key component, borrowed from `value` where it is a part of it.
+    pub fn apply(self, value: &str) -> Cow<'_, str> {
+        match self {
+            Self::Spreadsheet if value.contains(ZERO_WIDTH_CHARS) => {
+                let value = value.replace(ZERO_WIDTH_CHARS, "");
+                Cow::Owned(clean_spreadsheet_value(&value).into_owned())
+            }
+            Self::Spreadsheet => clean_spreadsheet_value(value),
+        }
+    }
+}
+
+impl Display for KeyClean {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Spreadsheet => "spreadsheet",
+        })
+    }
+}
+
+/// Strips the markers that make a spreadsheet value text, and surrounding non-breaking spaces,
+/// and converts Unicode digits to ASCII, in a value without zero-width characters.
+fn clean_spreadsheet_value(value: &str) -> Cow<'_, str> {
+    let non_breaking = |c| NON_BREAKING_SPACES.contains(&c);
+    let mut text = value.trim_matches(non_breaking);
+    if let Some(inner) = text
+        .strip_prefix("=\"")
+        .and_then(|text| text.strip_suffix('"'))
+    {
+        text = inner.trim_matches(non_breaking);
+    }
+    if let Some(inner) = text.strip_prefix('\'') {
+        text = inner.trim_matches(non_breaking);
+    }
+    match text
+        .chars()
+        .any(|c| !c.is_ascii() && ascii_digit(c).is_some())
+    {
+        true => Cow::Owned(text.chars().map(|c| ascii_digit(c).unwrap_or(c)).collect()),
+        false => Cow::Borrowed(text),
+    }
+}
+
+/// The ASCII digit of the same value as a Unicode decimal digit.
+fn ascii_digit(c: char) -> Option<char> {
+    let code = u32::from(c);
+    UNICODE_ZEROS
+        .iter()
+        .find(|&&zero| (zero..zero + 10).contains(&code))
+        .map(|zero| char::from(b'0' + (code - zero) as u8))
+}
+
+/// How a key component is compared across sources.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyType {
+    #[default]
+    String,
+    Int,
+    Float,
+}
+
+impl KeyType {
+    /// The canonical form of a key component, or `None` if it doesn't parse as this type.
+    pub fn canonicalize(self, value: &str) -> Option<String> {
+        match self {
+            Self::String => Some(value.to_owned()),
+            Self::Int => match value.trim().parse::<i128>() {
+                Ok(int) => Some(int.to_string()),
+                Err(_) => value
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|float| float.is_finite() && float.fract() == 0.0)
+                    .map(|float| format!("{:.0}", float)),
+            },
+            Self::Float => value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|float| float.is_finite())
+                .map(|float| float.to_string()),
+        }
+    }
+
+    /// Orders canonical key components, numerically for numeric types. Components that don't
+    /// parse, under `lenient_key_types`, are ordered as strings.
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        let numeric = match self {
+            Self::String => None,
+            Self::Int => a
+                .parse::<i128>()
+                .ok()
+                .zip(b.parse::<i128>().ok())
+                .map(|(a, b)| a.cmp(&b)),
+            Self::Float => a
+                .parse::<f64>()
+                .ok()
+                .zip(b.parse::<f64>().ok())
+                .and_then(|(a, b)| a.partial_cmp(&b)),
+        };
+        numeric.unwrap_or_else(|| a.cmp(b))
+    }
+}
+
+impl Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::String => "string",
+            Self::Int => "int",
+            Self::Float => "float",
+        })
+    }
+}
+
+/// A number of bytes, written either as a plain number or with a binary unit suffix (e.g. `4G`
+/// or `512MiB`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "ByteSizeRepr", into = "String")]
+pub struct ByteSize(pub u64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ByteSizeRepr {
+    Bytes(u64),
+    Text(String),
+}
+
+const BYTE_UNITS: [&str; 5] = ["", "K", "M", "G", "T"];
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let text = s.trim();
+        let split = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let unit = unit.trim().to_ascii_uppercase();
+        let unit = unit
+            .strip_suffix("IB")
+            .or_else(|| unit.strip_suffix('B'))
+            .unwrap_or(&unit);
+        let exponent = BYTE_UNITS
+            .iter()
+            .position(|u| *u == unit)
+            .ok_or_else(|| anyhow!("invalid size {:?}: unknown unit", s))?;
+        let number: u64 = number
+            .parse()
+            .map_err(|_| anyhow!("invalid size {:?}: expected a number of bytes", s))?;
+        number
+            .checked_mul(1u64 << (10 * exponent))
+            .map(Self)
+            .ok_or_else(|| anyhow!("invalid size {:?}: too large", s))
+    }
+}
+
+impl TryFrom<ByteSizeRepr> for ByteSize {
+    type Error = anyhow::Error;
+
+    fn try_from(repr: ByteSizeRepr) -> Result<Self> {
+        match repr {
+            ByteSizeRepr::Bytes(bytes) => Ok(Self(bytes)),
+            ByteSizeRepr::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl From<ByteSize> for String {
+    fn from(size: ByteSize) -> Self {
+        size.to_string()
+    }
+}
+
+impl Display for ByteSize {
+    /// Uses the largest unit that represents the size exactly.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let exponent = (0..BYTE_UNITS.len())
+            .rev()
+            .find(|exp| self.0 != 0 && self.0.is_multiple_of(1u64 << (10 * exp)))
+            .unwrap_or(0);
+        write!(f, "{}{}", self.0 >> (10 * exponent), BYTE_UNITS[exponent])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetType {
+    Int64,
+    Float64,
+    Bool,
+    Date,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetCompression {
+    None,
+    #[default]
+    Snappy,
+    Zstd,
+}
+
+/// A reference to a column, either by header name or by zero-based position.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Name(String),
+    Index { index: usize },
+}
+
+impl Display for ColumnRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => f.write_str(name),
+            Self::Index { index } => write!(f, "#{}", index),
+        }
+    }
+}
+
+/// A key column, optionally renamed in the output header.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum KeyColumn {
+    Column(ColumnRef),
+    Renamed {
+        from: ColumnRef,
+        to: String,
+    },
+    /// A key component computed by joining several columns of each record with `sep`.
+    Concat {
+        name: String,
+        concat: Vec<String>,
+        #[serde(default)]
+        sep: String,
+    },
+    /// A key component computed by evaluating an expression against each record.
+    Expr {
+        name: String,
+        expr: String,
+    },
+}
+
+impl KeyColumn {
+    /// The output header name, if it differs from the source header.
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Self::Column(_) => None,
+            Self::Renamed { to, .. } => Some(to),
+            Self::Concat { name, .. } | Self::Expr { name, .. } => Some(name),
+        }
+    }
+}
+
+impl Display for KeyColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Column(col) => col.fmt(f),
+            Self::Renamed { to, .. } => f.write_str(to),
+            Self::Concat { name, .. } | Self::Expr { name, .. } => f.write_str(name),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverageCheck {
+    #[default]
+    Ignore,
+    Warn,
+    Error,
+}
+
+/// How a lookup source participates in the output: `inner` drops primary rows without a match
+/// in the source, `left` keeps them with absent values for the source's projections.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinType {
+    #[default]
+    Inner,
+    Left,
+    /// Instead of joining, list every distinct key with a column per source telling whether
+    /// the key is present in it. Only valid as the spec's `join`.
+    Presence,
+    /// Instead of joining, stack the rows of every source, in spec order, under the key columns
+    /// and every projected name; a source's rows are absent in the columns it doesn't project.
+    /// Only valid as the spec's `join`.
+    Union,
+}
+
+impl Display for JoinType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Inner => "inner",
+            Self::Left => "left",
+            Self::Presence => "presence",
+            Self::Union => "union",
+        })
+    }
+}
+
+/// Whether reading the sources stops at their first error, in a spec's `error_mode`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorMode {
+    #[default]
+    First,
+    /// Skip each record with an error, and each source that cannot be read, and fail once all
+    /// sources are read, or `max_errors` errors were found, listing them.
+    Collect,
+}
+
+/// What to do when a source or the output has no data rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyPolicy {
+    Error,
+    #[default]
+    Warn,
+    Allow,
+}
+
+/// What to do with records that have an empty or whitespace-only key component.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyKeyPolicy {
+    Error,
+    Skip,
+    /// Join the records like any others, so that empty keys match each other.
+    #[default]
+    Keep,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssertMode {
+    #[default]
+    Error,
+    Warn,
+}
+
+/// Checks that every record of a source must pass for one of its columns.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Assertion {
+    pub column: String,
+    /// No two records have the same value.
+    #[serde(default)]
+    pub unique: bool,
+    #[serde(default)]
+    pub not_empty: bool,
+    /// The only values allowed.
+    #[serde(default, rename = "in", skip_serializing_if = "Option::is_none")]
+    pub one_of: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Source {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub path: PathBuf,
+    /// Columns to output, by header name, with their output names. A key column may be
+    /// projected too, to output its value as read next to the key, e.g. as an `original_id`:
+    /// the projection has the cell of this file, before `key_normalization`, `key_types`,
+    /// `key_expr` or the key of any other source apply, and only the projection's own options
+    /// and `trim_values` change it.
+    #[serde(default)]
+    pub projections: Projections,
+    #[serde(default)]
+    pub on_parse_error: ParseErrorPolicy,
+    #[serde(default)]
+    pub aliases: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub sheet: Option<SheetRef>,
+    /// Expressions that compute key components for this source only, by key column name.
+    #[serde(default)]
+    pub key_expr: IndexMap<String, String>,
+    /// Columns to read key components from, by key column name, where this source names them
+    /// differently, e.g. `{id: manager_id}` to join a file to itself.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub key_aliases: IndexMap<String, String>,
+    /// Key components captured by a regex from a column of this source only, by key column
+    /// name, e.g. `{user_id: {column: session, regex: "^user:(\\d+):"}}` to join on the `1234` of
+    /// `user:1234:session`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub key_regex: IndexMap<String, KeyRegex>,
+    /// Columns of the primary source whose values are looked up in this source's key, one per
+    /// key column, instead of the primary source's own key; e.g. `[manager_id]` to find each
+    /// employee's manager in the same file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub match_on: Vec<String>,
+    /// The key columns this lookup source is keyed by, if only some of them, in key order;
+    /// e.g. `[region]` to look up a table of regions for rows keyed by region and product. Each
+    /// primary row matches the row with the same values of these columns.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key: Vec<String>,
+    /// Match one key component against a range of values given by two columns of this lookup
+    /// source, instead of a column equal to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<RangeMatch>,
+    /// Whether every file matched by a glob path must have the same header as the first one.
+    /// Otherwise, only the key and projection columns need to resolve in each file.
+    #[serde(default)]
+    pub strict_schema: bool,
+    /// The text encoding of the file, by WHATWG label (e.g. `latin1`); UTF-8 if omitted.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Move a lookup source to a temporary on-disk table once it has more than this many
+    /// distinct keys, to bound memory use.
+    #[serde(default)]
+    pub spill_after: Option<usize>,
+    /// Read local CSV files by mapping them into memory instead of through read calls. Parsing
+    /// and keying the records take most of the time, so this rarely makes reading noticeably
+    /// faster; files that can't be mapped are read as usual, with a warning. A mapped file must
+    /// not be truncated or rewritten while it is read: the pages past its new end are gone, and
+    /// reading them kills the process with SIGBUS instead of failing with an error, so only map
+    /// files that no other process writes during the run.
+    #[serde(default)]
+    pub mmap: bool,
+    /// Keep only where the record of each key starts in its file, instead of its projections,
+    /// and read the record again when a primary row matches the key. For a large lookup source
+    /// that few primary rows match, this takes far less memory, for a seek and a read per
+    /// match. The files must not change until the join is written.
+    #[serde(default)]
+    pub lazy_projections: bool,
+    /// Which zero-based occurrence to use of header names that appear more than once.
+    #[serde(default)]
+    pub disambiguate: IndexMap<String, usize>,
+    /// How this lookup source participates in the output, overriding the spec's `join`.
+    #[serde(default)]
+    pub join: Option<JoinType>,
+    /// Timeout in seconds for fetching a source whose path is an HTTP(S) URL.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Overrides the spec's `max_age_secs` for this source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    /// Data-quality checks on the source's columns, applied to every parsed record.
+    #[serde(default, rename = "assert", skip_serializing_if = "Vec::is_empty")]
+    pub assertions: Vec<Assertion>,
+    /// Types that the values of columns must have, checked as the source is read; the values
+    /// are still joined and output as read.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub schema: IndexMap<String, ColumnType>,
+    /// What to do with records whose values don't have the type of their column in `schema`.
+    #[serde(default)]
+    pub schema_mode: SchemaMode,
+    /// Overrides the spec's `trim_values` and `trim_chars` for this source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_values: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_chars: Option<String>,
+    /// Unquote key cells with whitespace before their opening quote, such as ` "A 1"`, which are
+    /// otherwise read as they are, quotes and all, as the quote doesn't start the field.
+    #[serde(default)]
+    pub unquote_padded_keys: bool,
+    /// Limit on the length of the source's projected values, unless a projection sets its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cell_length: Option<CellLimit>,
+    /// Overrides the spec's `emit_match_flags` for this lookup source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emit_match_flags: Option<bool>,
+    /// Append a `<source>_row` column with the number of the record each row of the source
+    /// came from, counting data records from 1.
+    #[serde(default)]
+    pub emit_provenance: bool,
+    /// Prefix the record number of `emit_provenance` with the path of the file it was read
+    /// from, as `<path>:<record>`, to tell apart the files of a glob.
+    #[serde(default)]
+    pub provenance_path: bool,
+    /// Output every column of the primary source as read, in file order and including the key
+    /// columns, followed by the projections of the lookup sources.
+    #[serde(default)]
+    pub passthrough: bool,
+    /// Project every column of the source except these and the key columns, in addition to
+    /// `projections`, keeping their header names.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub all_except: Option<Vec<String>>,
+    /// The field separator of the CSV files; a comma if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<Delimiter>,
+    /// Number of records after the header of each file to discard unread, such as summary
+    /// rows; record numbers still count them.
+    #[serde(default)]
+    pub skip_rows: usize,
+    /// Bounds on the number of data records read from the source's files, counting those later
+    /// dropped, failing the run before the join if the count falls outside them; e.g. to catch
+    /// a file that arrived with only its header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect_rows: Option<RowBounds>,
+    /// Prepended to the output names of the source's projections, e.g. `b_`, to tell them from
+    /// those of other sources without renaming each one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Keep the record of each key with the latest value of a column, instead of the last one
+    /// read, e.g. to deduplicate change data by its `updated_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_by: Option<LatestBy>,
+}
+
+/// The column that tells which record of a key a source keeps, in its `latest_by`; of records
+/// with the same value, the last one read is kept.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LatestBy {
+    /// Holds numbers, such as epoch seconds, or ISO-8601 dates or date-times, with `T` or a
+    /// space between the date and the time, and an optional offset; UTC without one.
+    pub column: String,
+    /// What to do with a record whose value of the column is neither a number nor a date.
+    #[serde(default)]
+    pub on_invalid: InvalidLatest,
+}
+
+impl LatestBy {
+    /// The value of the column as a number to compare: the number itself, or seconds since the
+    /// epoch for a date or date-time; `None` if it is neither.
+    pub fn timestamp(value: &str) -> Option<f64> {
+        let value = value.trim();
+        if let Ok(number) = value.parse::<f64>() {
+            return number.is_finite().then_some(number);
+        }
+        let seconds =
+            |datetime: NaiveDateTime| datetime.and_utc().timestamp_micros() as f64 / 1_000_000.0;
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+            return Some(seconds(datetime.naive_utc()));
+        }
+        ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .ok()
+                    .map(|date| date.and_time(NaiveTime::MIN))
+            })
+            .map(seconds)
+    }
+}
+
+/// A key component captured by a regex from a column, in a source's `key_regex`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeyRegex {
+    pub column: String,
+    pub regex: String,
+    /// The capture group that is the key component: its number, or 0 for the whole match.
+    #[serde(default = "KeyRegex::default_group")]
+    pub group: usize,
+    /// What to do with a record whose value of the column the regex doesn't match, or matches
+    /// without the group.
+    #[serde(default)]
+    pub on_no_match: NoMatchPolicy,
+}
+
+impl KeyRegex {
+    fn default_group() -> usize {
+        1
+    }
+
+    /// Compiles the regex, checking that it has the group.
+    pub fn compile(&self) -> Result<Regex> {
+        let regex = Regex::new(&self.regex)?;
+        if self.group >= regex.captures_len() {
+            bail!(
+                "the regex has no group {}, only {} group(s)",
+                self.group,
+                regex.captures_len() - 1
+            );
+        }
+        Ok(regex)
+    }
+}
+
+/// What to do with a record whose `key_regex` doesn't match.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoMatchPolicy {
+    #[default]
+    Error,
+    /// Drop the record, counting it.
+    Skip,
+    /// Read the key component as empty, for `empty_keys` to handle.
+    Empty,
+}
+
+/// What to do with a record whose `latest_by` value is neither a number nor a date.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidLatest {
+    #[default]
+    Error,
+    /// Drop the record, counting it.
+    Skip,
+    /// Keep the record only if no other record of its key has a valid value.
+    Oldest,
+}
+
+/// The projected columns of a source, in output order. Written either as a map from header name
+/// to output column, or as a list of `{from, to}` pairs, which may project a column more than
+/// once, and may instead project `{concat, sep, to}`, several columns joined with `sep`.
+#[derive(Debug, Default, Clone)]
+pub struct Projections(Vec<(ProjectionFrom, ProjectedColumn)>);
+
+/// What a projection reads from each record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectionFrom {
+    Column(String),
+    /// The values of several columns joined with `sep`.
+    Concat {
+        concat: Vec<String>,
+        sep: String,
+    },
+}
+
+impl ProjectionFrom {
+    /// The header names of the columns read.
+    pub fn columns(&self) -> &[String] {
+        match self {
+            Self::Column(col) => std::slice::from_ref(col),
+            Self::Concat { concat, .. } => concat,
+        }
+    }
+}
+
+impl Display for ProjectionFrom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Column(col) => f.write_str(col),
+            Self::Concat { concat, .. } => write!(f, "concat({})", concat.join(", ")),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct ProjectionPair {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    concat: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    sep: String,
+    to: ProjectedColumn,
+}
+
+impl ProjectionPair {
+    fn new(from: &ProjectionFrom, to: &ProjectedColumn) -> Self {
+        let (from, concat, sep) = match from {
+            ProjectionFrom::Column(col) => (Some(col.clone()), Vec::new(), String::new()),
+            ProjectionFrom::Concat { concat, sep } => (None, concat.clone(), sep.clone()),
+        };
+        Self {
+            from,
+            concat,
+            sep,
+            to: to.clone(),
+        }
+    }
+
+    fn into_projection(self) -> Result<(ProjectionFrom, ProjectedColumn)> {
+        let from = match (self.from, self.concat.is_empty()) {
+            (Some(_), false) => bail!("projection {} has both from and concat", self.to.name()),
+            (None, true) => bail!("projection {} has neither from nor concat", self.to.name()),
+            (Some(_), true) if !self.sep.is_empty() => {
+                bail!("projection {} has sep without concat", self.to.name())
+            }
+            (Some(col), true) => ProjectionFrom::Column(col),
+            (None, false) => ProjectionFrom::Concat {
+                concat: self.concat,
+                sep: self.sep,
+            },
+        };
+        Ok((from, self.to))
+    }
+}
+
+impl Projections {
+    pub fn iter(&self) -> impl Iterator<Item = (&ProjectionFrom, &ProjectedColumn)> {
+        self.0.iter().map(|(from, projected)| (from, projected))
+    }
+
+    /// What each projection reads.
+    pub fn keys(&self) -> impl Iterator<Item = &ProjectionFrom> {
+        self.0.iter().map(|(from, _)| from)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &ProjectedColumn> {
+        self.0.iter().map(|(_, projected)| projected)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut ProjectedColumn> {
+        self.0.iter_mut().map(|(_, projected)| projected)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn push(&mut self, col: String, projected: ProjectedColumn) {
+        self.0.push((ProjectionFrom::Column(col), projected));
+    }
+}
+
+impl FromIterator<(ProjectionFrom, ProjectedColumn)> for Projections {
+    fn from_iter<I: IntoIterator<Item = (ProjectionFrom, ProjectedColumn)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl<'de> Deserialize<'de> for Projections {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Mapping(map) => {
+                let map: IndexMap<String, ProjectedColumn> =
+                    serde_yaml::from_value(Value::Mapping(map)).map_err(de::Error::custom)?;
+                Ok(map
+                    .into_iter()
+                    .map(|(col, to)| (ProjectionFrom::Column(col), to))
+                    .collect())
+            }
+            Value::Sequence(pairs) => {
+                let pairs: Vec<ProjectionPair> =
+                    serde_yaml::from_value(Value::Sequence(pairs)).map_err(de::Error::custom)?;
+                pairs
+                    .into_iter()
+                    .map(ProjectionPair::into_projection)
+                    .collect::<Result<_>>()
+                    .map_err(de::Error::custom)
+            }
+            Value::Null => Ok(Self::default()),
+            _ => Err(de::Error::custom(
+                "expected a map of columns to output names, or a list of {from, to} pairs",
+            )),
+        }
+    }
+}
+
+/// Serialized as a map unless a column is projected more than once or columns are concatenated,
+/// so that specs written as a map stay maps.
+impl Serialize for Projections {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut cols: Vec<_> = self
+            .keys()
+            .map_while(|from| match from {
+                ProjectionFrom::Column(col) => Some(col),
+                ProjectionFrom::Concat { .. } => None,
+            })
+            .collect();
+        cols.sort();
+        cols.dedup();
+        match cols.len() == self.len() {
+            true => serializer.collect_map(
+                self.iter()
+                    .map(|(from, to)| (from.columns()[0].as_str(), to)),
+            ),
+            false => {
+                serializer.collect_seq(self.iter().map(|(from, to)| ProjectionPair::new(from, to)))
+            }
+        }
+    }
+}
+
+/// The output column of a projected source column.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ProjectedColumn {
+    Name(String),
+    Detailed {
+        name: String,
+        /// Value to fill in when a left-joined source has no row for the key.
+        #[serde(default)]
+        default: Option<String>,
+        /// Value to store instead of an empty cell of a row that is present.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        if_empty: Option<String>,
+        /// Maps the source's values to the ones to output.
+        #[serde(default)]
+        lookup: Option<Box<ValueLookup>>,
+        /// Maps the source's values to the ones to output, inline in the spec instead of a
+        /// lookup file.
+        #[serde(
+            default,
+            deserialize_with = "scalar_map",
+            skip_serializing_if = "Option::is_none"
+        )]
+        map: Option<Box<IndexMap<String, String>>>,
+        /// What to do with values that have no entry in `map`; kept unchanged if omitted.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unmapped: Option<Unmapped>,
+        /// Type to parse the values as, to output them in a canonical form; applied before
+        /// `lookup`.
+        #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+        value_type: Option<ValueType>,
+        /// The chrono format of `date` values; ISO-8601 if omitted.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        format: Option<String>,
+        #[serde(default)]
+        on_type_error: TypeErrorPolicy,
+        /// How to render numeric values in the output; applied when writing, so that keys and
+        /// lookups see the values as read.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        number_format: Option<Box<NumberFormat>>,
+        /// Overrides the source's `max_cell_length` for this projection.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_cell_length: Option<CellLimit>,
+        /// Combines the values of all rows of a key of a lookup source, instead of keeping the
+        /// value of the last row; the other projections of the source keep the last value.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        agg: Option<Aggregate>,
+    },
+}
+
+/// How the values of a projection are combined across the rows of a key. Empty values are
+/// skipped, and all but `count` parse the values as numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregate {
+    Sum,
+    /// The number of non-empty values.
+    Count,
+    /// The smallest value, as read.
+    Min,
+    /// The largest value, as read.
+    Max,
+    Avg,
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sum => "sum",
+            Self::Count => "count",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Avg => "avg",
+        })
+    }
+}
+
+impl ProjectedColumn {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Name(name) | Self::Detailed { name, .. } => name,
+        }
+    }
+
+    pub fn set_name(&mut self, new_name: String) {
+        match self {
+            Self::Name(name) | Self::Detailed { name, .. } => *name = new_name,
+        }
+    }
+
+    pub fn default(&self) -> Option<&str> {
+        match self {
+            Self::Name(_) => None,
+            Self::Detailed { default, .. } => default.as_deref(),
+        }
+    }
+
+    pub fn lookup(&self) -> Option<&ValueLookup> {
+        match self {
+            Self::Name(_) => None,
+            Self::Detailed { lookup, .. } => lookup.as_deref(),
+        }
+    }
+
+    /// The inline value map, with the policy for values missing from it.
+    pub fn value_map(&self) -> Option<(&IndexMap<String, String>, &Unmapped)> {
+        match self {
+            Self::Detailed {
+                map: Some(map),
+                unmapped,
+                ..
+            } => Some((&**map, unmapped.as_ref().unwrap_or(&Unmapped::Keep))),
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let Self::Detailed {
+            value_type,
+            format,
+            lookup,
+            map,
+            unmapped,
+            ..
+        } = self
+        else {
+            return Ok(());
+        };
+        if map.is_some() && lookup.is_some() {
+            bail!("map and lookup cannot both be set");
+        }
+        if unmapped.is_some() && map.is_none() {
+            bail!("unmapped only applies to map");
+        }
+        if let Some(format) = format {
+            if *value_type != Some(ValueType::Date) {
+                bail!("format only applies to type: date");
+            }
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                bail!("invalid date format {:?}", format);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn max_cell_length(&self) -> Option<&CellLimit> {
+        match self {
+            Self::Name(_) => None,
+            Self::Detailed {
+                max_cell_length, ..
+            } => max_cell_length.as_ref(),
+        }
+    }
+
+    pub fn agg(&self) -> Option<Aggregate> {
+        match self {
+            Self::Name(_) => None,
+            Self::Detailed { agg, .. } => *agg,
+        }
+    }
+
+    pub fn number_format(&self) -> Option<&NumberFormat> {
+        match self {
+            Self::Name(_) => None,
+            Self::Detailed { number_format, .. } => number_format.as_deref(),
+        }
+    }
+
+    /// The value to store for a cell read from the source.
+    pub fn fill_empty<'v>(&'v self, value: &'v str) -> &'v str {
+        match self {
+            Self::Detailed {
+                if_empty: Some(if_empty),
+                ..
+            } if value.is_empty() => if_empty,
+            _ => value,
+        }
+    }
+
+    /// Converts a value to the canonical form of the column's type, if it has one.
+    pub fn normalize<'v>(&self, value: &'v str) -> Result<Cow<'v, str>> {
+        let Self::Detailed {
+            value_type: Some(ty),
+            format,
+            on_type_error,
+            ..
+        } = self
+        else {
+            return Ok(Cow::Borrowed(value));
+        };
+        match ty.normalize(value, format.as_deref()) {
+            Some(normalized) => Ok(Cow::Owned(normalized)),
+            None if *on_type_error == TypeErrorPolicy::Passthrough => Ok(Cow::Borrowed(value)),
+            None => match format {
+                Some(format) => bail!("cannot parse {:?} as {} ({})", value, ty, format),
+                None => bail!("cannot parse {:?} as {}", value, ty),
+            },
+        }
+    }
+}
+
+/// The range of values of a key component that a row of a lookup source matches, such as the
+/// dates a rate is effective for. Both bounds are inclusive, and an empty bound leaves the range
+/// open on that side. Values are ordered under the component's `key_types`, so that dates
+/// compare correctly as strings in ISO 8601 form.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RangeMatch {
+    /// The key column matched against the range.
+    pub key: String,
+    /// The columns of the lower and upper bounds.
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub on_overlap: OverlapPolicy,
+}
+
+/// What to do about ranges of a key that overlap.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    #[default]
+    Error,
+    /// Match the row read first among those whose range contains the value.
+    First,
+}
+
+/// A maximum length of values, in characters, with what to do about longer ones.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct CellLimit {
+    pub length: usize,
+    #[serde(default)]
+    pub policy: OversizePolicy,
+    /// Append `…` to truncated values, after the first `length` characters.
+    #[serde(default)]
+    pub ellipsis: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OversizePolicy {
+    #[default]
+    Truncate,
+    Error,
+    /// Drop the whole record, as if the source didn't have it.
+    DropRow,
+}
+
+/// Rendering of numbers for the output, such as `1,234.50` or `12.5%`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct NumberFormat {
+    /// Number of digits after the decimal point; as many as needed if omitted.
+    #[serde(default)]
+    pub decimals: Option<usize>,
+    /// Inserted between groups of three digits of the integer part.
+    #[serde(default)]
+    pub thousands_sep: String,
+    /// Factor to multiply values by before rounding, such as 100 for percentages.
+    #[serde(default)]
+    pub scale: Option<f64>,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub suffix: String,
+}
+
+impl NumberFormat {
+    /// The formatted value, or `None` if it isn't a number.
+    pub fn apply(&self, value: &str) -> Option<String> {
+        let number = value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite())?
+            * self.scale.unwrap_or(1.0);
+        let text = match self.decimals {
+            Some(decimals) => format!("{:.*}", decimals, number),
+            None => number.to_string(),
+        };
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", text.as_str()),
+        };
+        let (int, frac) = match digits.split_once('.') {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (digits, None),
+        };
+        let mut formatted = format!("{}{}", sign, self.prefix);
+        for (idx, digit) in int.chars().enumerate() {
+            if idx > 0 && (int.len() - idx) % 3 == 0 {
+                formatted.push_str(&self.thousands_sep);
+            }
+            formatted.push(digit);
+        }
+        if let Some(frac) = frac {
+            formatted.push('.');
+            formatted.push_str(frac);
+        }
+        formatted.push_str(&self.suffix);
+        Some(formatted)
+    }
+}
+
+/// A type of projected values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    /// Integers, optionally with thousands separators; output without them.
+    Int,
+    Float,
+    /// Dates in the chrono format of the projection's `format`, ISO-8601 if omitted; output as
+    /// ISO-8601 (`YYYY-MM-DD`).
+    Date,
+}
+
+impl ValueType {
+    /// The canonical form of a value, or `None` if it doesn't parse as this type.
+    pub fn normalize(self, value: &str, format: Option<&str>) -> Option<String> {
+        let number = || value.trim().replace(',', "");
+        match self {
+            Self::Int => KeyType::Int.canonicalize(&number()),
+            Self::Float => KeyType::Float.canonicalize(&number()),
+            Self::Date => NaiveDate::parse_from_str(value.trim(), format.unwrap_or("%Y-%m-%d"))
+                .ok()
+                .map(|date| date.format("%Y-%m-%d").to_string()),
+        }
+    }
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Date => "date",
+        })
+    }
+}
+
+/// A type of the values of a column in a source's `schema`. Empty values have every type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnType {
+    String,
+    Int,
+    /// Numbers with an optional fractional part, without an exponent.
+    Decimal,
+    /// ISO-8601 dates (`YYYY-MM-DD`).
+    Date,
+    /// ISO-8601 date-times, with `T` or a space between the date and the time, and an optional
+    /// offset.
+    Datetime,
+}
+
+impl ColumnType {
+    pub fn accepts(self, value: &str) -> bool {
+        let value = value.trim();
+        if value.is_empty() {
+            return true;
+        }
+        let unsigned = value.strip_prefix(['+', '-']).unwrap_or(value);
+        match self {
+            Self::String => true,
+            Self::Int => !unsigned.is_empty() && unsigned.bytes().all(|b| b.is_ascii_digit()),
+            Self::Decimal => {
+                let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+                !(whole.is_empty() && fraction.is_empty())
+                    && whole
+                        .bytes()
+                        .chain(fraction.bytes())
+                        .all(|b| b.is_ascii_digit())
+            }
+            Self::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+            Self::Datetime => {
+                DateTime::parse_from_rfc3339(value).is_ok()
+                    || ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+                        .iter()
+                        .any(|format| NaiveDateTime::parse_from_str(value, format).is_ok())
+            }
+        }
+    }
+}
+
+impl Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::String => "string",
+            Self::Int => "int",
+            Self::Decimal => "decimal",
+            Self::Date => "date",
+            Self::Datetime => "datetime",
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaMode {
+    /// Fail on the first value that doesn't have its column's type.
+    #[default]
+    Error,
+    /// Report the values, and keep their records.
+    Warn,
+    /// Report the values, and drop their records.
+    SkipRow,
+}
+
+/// What to do with projected values that don't parse as the column's type.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TypeErrorPolicy {
+    #[default]
+    Error,
+    /// Output the value unchanged.
+    Passthrough,
+}
+
+/// A mapping of values read from two columns of a CSV file, such as codes to labels.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct ValueLookup {
+    pub path: PathBuf,
+    /// The column holding the source's values.
+    pub key: String,
+    /// The column holding the values to output instead.
+    pub value: String,
+    #[serde(default)]
+    pub missing: MissingLookup,
+}
+
+/// Reads a map with any scalar keys and values, such as `{1: active}`, as strings.
+fn scalar_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Box<IndexMap<String, String>>>, D::Error> {
+    fn scalar<E: de::Error>(value: Value) -> Result<String, E> {
+        match value {
+            Value::String(value) => Ok(value),
+            Value::Number(value) => Ok(value.to_string()),
+            Value::Bool(value) => Ok(value.to_string()),
+            value => Err(E::custom(format!("expected a scalar, found {:?}", value))),
+        }
+    }
+    IndexMap::<Value, Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| Ok((scalar(key)?, scalar(value)?)))
+        .collect::<Result<_, _>>()
+        .map(|map| Some(Box::new(map)))
+}
+
+/// What to do with values that have no entry in an inline `map`: `keep`, `empty` or `error`
+/// like for a lookup file, or any other string to output instead.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum Unmapped {
+    Keep,
+    Empty,
+    Error,
+    Literal(String),
+}
+
+impl From<String> for Unmapped {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "keep" => Self::Keep,
+            "empty" => Self::Empty,
+            "error" => Self::Error,
+            _ => Self::Literal(value),
+        }
+    }
+}
+
+impl From<Unmapped> for String {
+    fn from(unmapped: Unmapped) -> Self {
+        match unmapped {
+            Unmapped::Keep => "keep".to_owned(),
+            Unmapped::Empty => "empty".to_owned(),
+            Unmapped::Error => "error".to_owned(),
+            Unmapped::Literal(value) => value,
+        }
+    }
+}
+
+/// What to do with values that have no entry in a lookup file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingLookup {
+    /// Output the source's value unchanged.
+    #[default]
+    Keep,
+    Empty,
+    Error,
+}
+
+/// A worksheet of a spreadsheet source, by name or zero-based position.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SheetRef {
+    Index(usize),
+    Name(String),
+}
+
+impl Display for SheetRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "#{}", index),
+            Self::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+impl Source {
+    /// The explicit name of the source, falling back to the stem of its path.
+    pub fn name(&self) -> Cow<'_, str> {
+        match &self.name {
+            Some(name) => Cow::Borrowed(name),
+            None => self
+                .path
+                .file_stem()
+                .unwrap_or(self.path.as_os_str())
+                .to_string_lossy(),
+        }
+    }
+
+    /// The output name of a projection of the source, with its `prefix`.
+    pub fn output_name<'a>(&self, projected: &'a ProjectedColumn) -> Cow<'a, str> {
+        match &self.prefix {
+            Some(prefix) => Cow::Owned(format!("{}{}", prefix, projected.name())),
+            None => Cow::Borrowed(projected.name()),
+        }
+    }
+
+    /// The declared text encoding of the source.
+    pub fn encoding(&self) -> Result<&'static Encoding> {
+        match &self.encoding {
+            None => Ok(encoding_rs::UTF_8),
+            Some(label) => Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| anyhow!("{}: unknown encoding {:?}", self.path.display(), label)),
+        }
+    }
+
+    /// Whether any projection of the source combines the values of the rows of a key.
+    pub fn is_aggregated(&self) -> bool {
+        self.projections.values().any(|col| col.agg().is_some())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseErrorPolicy {
+    #[default]
+    Fail,
+    Skip,
+    SkipAndLog,
+}
+
+const INCLUDE_FIELD: &str = "include";
+const SOURCES_FIELD: &str = "sources";
+const OUTPUT_FIELD: &str = "output";
+const MODE_FIELD: &str = "mode";
+const PARAMS_FIELD: &str = "params";
+
+/// The fields of [`JoinSpec`] besides its flattened output options, which derived
+/// deserialization doesn't list for a struct with a flattened field.
+const SPEC_FIELDS: &[&str] = &[
+    "key",
+    "sources",
+    "output",
+    "coverage_check",
+    "join",
+    "key_types",
+    "lenient_key_types",
+    "empty_keys",
+    "fuzzy",
+    "column_order",
+    "coalesce",
+    "coalesce_columns",
+    "mode",
+    "present_value",
+    "absent_value",
+    "row_number",
+    "max_memory",
+    "allow_cross_join",
+    "max_rows",
+    "manifest",
+    "assert_mode",
+    "allow_duplicate_headers",
+    "key_filter",
+    "expect_rows",
+    "trim_values",
+    "trim_chars",
+    "on_format_error",
+    "empty_source",
+    "empty_output",
+    "emit_match_flags",
+    "sorted",
+    "warn_near_duplicate_keys",
+    "primary_passthrough",
+    "dup_flag_column",
+    "require_unique_base_keys",
+    "allow_key_projections",
+    "rejects",
+    "key_normalization",
+    "key_clean",
+    "collapse_key",
+    "read_retries",
+    "column_map",
+    "max_age_secs",
+    "keys_only",
+    "changed_since",
+    "error_mode",
+    "max_errors",
+];
+
+/// The names of the fields of a struct that derives `Deserialize`, without a flattened field.
+fn field_names<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    /// Records the field names a struct asks to be deserialized with, and deserializes nothing.
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for FieldNames<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: de::Visitor<'de>>(
+            self,
+            _visitor: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: de::Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only the field names are read"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// Fails on a field of `mapping` that isn't one of `fields`, naming it by its path in the spec
+/// along with the closest field name, and listing all of them.
+fn check_fields(mapping: &Mapping, path: &str, fields: &[&str]) -> Result<()> {
+    for key in mapping.keys() {
+        let name = match key {
+            Value::String(name) => name.as_str(),
+            _ => bail!("{}: field names must be strings, found {:?}", path, key),
+        };
+        if fields.contains(&name) {
+            continue;
+        }
+        let field_path = join_path(path, name);
+        let chars: Vec<char> = name.chars().collect();
+        // The closest field within a few edits, as a typo rarely gets further.
+        let suggestion = (1..=name.len().min(3)).find_map(|max| {
+            fields
+                .iter()
+                .find(|field| crate::fuzzy::within_distance(&chars, field, max))
+        });
+        let mut valid = fields.to_vec();
+        valid.sort_unstable();
+        match suggestion {
+            Some(field) => bail!(
+                "unknown field {}; did you mean {}? Valid fields are: {}",
+                field_path,
+                field,
+                valid.join(", ")
+            ),
+            None => bail!(
+                "unknown field {}; valid fields are: {}",
+                field_path,
+                valid.join(", ")
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Checks the fields of a spec, besides its sources and includes, and those of the structs
+/// within it.
+fn check_spec_fields(spec: &Mapping) -> Result<()> {
+    let options = field_names::<OutputOptions>();
+    let spec_fields: Vec<_> = SPEC_FIELDS.iter().chain(options).copied().collect();
+    check_fields(spec, "", &spec_fields)?;
+    check_nested(spec, "", "fuzzy", field_names::<FuzzyMatch>())?;
+    check_nested(spec, "", "expect_rows", field_names::<RowBounds>())?;
+    check_nested(spec, "", "changed_since", field_names::<ChangedSince>())?;
+    check_nested(spec, "", "collapse_key", field_names::<CollapsedKey>())?;
+    if let Some(Value::Sequence(cols)) = spec.get("coalesce_columns") {
+        for (idx, col) in cols.iter().enumerate() {
+            if let Value::Mapping(col) = col {
+                let path = format!("coalesce_columns[{}]", idx);
+                check_fields(col, &path, field_names::<CoalescedColumn>())?;
+                if let Some(Value::Sequence(from)) = col.get("from") {
+                    for (idx, from) in from.iter().enumerate() {
+                        if let Value::Mapping(from) = from {
+                            let path = format!("{}.from[{}]", path, idx);
+                            check_fields(from, &path, field_names::<SourceColumn>())?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if let Some(Value::Sequence(outputs)) = spec.get(OUTPUT_FIELD) {
+        let output_fields: Vec<_> = ["path"].iter().chain(options).copied().collect();
+        for (idx, output) in outputs.iter().enumerate() {
+            if let Value::Mapping(output) = output {
+                check_fields(
+                    output,
+                    &format!("{}[{}]", OUTPUT_FIELD, idx),
+                    &output_fields,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the fields of a source at `path` in the spec, and those of the structs within it.
+fn check_source_fields(source: &Value, path: &str) -> Result<()> {
+    let Value::Mapping(source) = source else {
+        return Ok(());
+    };
+    check_fields(source, path, field_names::<Source>())?;
+    check_nested(source, path, "range", field_names::<RangeMatch>())?;
+    check_nested(source, path, "max_cell_length", field_names::<CellLimit>())?;
+    check_nested(source, path, "expect_rows", field_names::<RowBounds>())?;
+    check_nested(source, path, "latest_by", field_names::<LatestBy>())?;
+    if let Some(Value::Mapping(key_regex)) = source.get("key_regex") {
+        for (name, regex) in key_regex.iter() {
+            if let (Value::String(name), Value::Mapping(regex)) = (name, regex) {
+                let path = format!("{}.key_regex.{}", path, name);
+                check_fields(regex, &path, field_names::<KeyRegex>())?;
+            }
+        }
+    }
+    if let Some(Value::Sequence(assertions)) = source.get("assert") {
+        for (idx, assertion) in assertions.iter().enumerate() {
+            if let Value::Mapping(assertion) = assertion {
+                let path = format!("{}.assert[{}]", path, idx);
+                check_fields(assertion, &path, field_names::<Assertion>())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the fields of the struct in `field` of `mapping`, if it is set.
+fn check_nested(mapping: &Mapping, path: &str, field: &str, fields: &[&str]) -> Result<()> {
+    match mapping.get(field) {
+        Some(Value::Mapping(nested)) => check_fields(nested, &join_path(path, field), fields),
+        _ => Ok(()),
+    }
+}
+
+/// The path of `field` within the struct at `path` in the spec.
+fn join_path(path: &str, field: &str) -> String {
+    match path.is_empty() {
+        true => field.to_owned(),
+        false => format!("{}.{}", path, field),
+    }
+}
+
+/// Loads the spec from `reader`, resolving includes relative to `base_dir`.
+///
+/// Each output of a list of outputs takes the top-level output options it doesn't set itself.
+/// Included specs are merged first, in order, and the including spec's fields override them.
+/// Sources are merged by name, so a local source replaces an included source of the same name;
+/// the local sources come first, followed by the included ones in order.
+/// `${VAR}` in paths, and only in paths, is replaced by the value of the environment variable.
+/// `{{name}}` in any string field is replaced by the value of a parameter declared under
+/// `params`, as passed in `params` or else its default.
+pub fn load_spec(
+    reader: impl Read,
+    origin: &Path,
+    base_dir: &Path,
+    params: &[(String, String)],
+) -> Result<JoinSpec> {
+    let mut chain: Vec<_> = origin.canonicalize().into_iter().collect();
+    let mut merged = load_fragment(reader, origin, base_dir, &mut chain)?;
+    let params = resolve_params(std::mem::take(&mut merged.params), params)
+        .with_context(|| format!("{}: invalid spec", origin.display()))?;
+    let mut mapping = merged.into_mapping();
+    for (field, value) in mapping.iter_mut() {
+        let field = field.as_str().unwrap_or_default();
+        substitute_params(value, &params, field)
+            .with_context(|| format!("{}: invalid spec", origin.display()))?;
+    }
+    expand_paths(&mut mapping).with_context(|| format!("{}: invalid spec", origin.display()))?;
+    inherit_output_options(&mut mapping);
+    apply_mode(&mut mapping).with_context(|| format!("{}: invalid spec", origin.display()))?;
+    let spec: JoinSpec = serde_yaml::from_value(Value::Mapping(mapping.clone())).with_context(
+        || match failing_field(&mapping) {
+            Some(field) => format!("{}: invalid spec: field {}", origin.display(), field),
+            None => format!("{}: invalid spec", origin.display()),
+        },
+    )?;
+    spec.validate()?;
+    Ok(spec)
+}
+
+/// Loads a `column_map`: a YAML mapping of canonical column names to lists of their aliases.
+pub fn load_column_map(path: &Path) -> Result<IndexMap<String, Vec<String>>> {
+    let file =
+        File::open(path).with_context(|| format!("{}: cannot open column map", path.display()))?;
+    serde_yaml::from_reader(file).with_context(|| format!("{}: invalid column map", path.display()))
+}
+
+/// The first field of a spec that fails to deserialize on its own, to name in the error of a
+/// spec that fails, since the error doesn't tell.
+fn failing_field(spec: &Mapping) -> Option<&str> {
+    spec.iter().find_map(|(key, value)| {
+        let mut alone = Mapping::new();
+        alone.insert(SOURCES_FIELD.into(), Value::Sequence(Vec::new()));
+        alone.insert(OUTPUT_FIELD.into(), "-".into());
+        alone.insert(key.clone(), value.clone());
+        let failed = serde_yaml::from_value::<JoinSpec>(Value::Mapping(alone)).is_err();
+        key.as_str().filter(|_| failed)
+    })
+}
+
+/// The value of each declared parameter: the one passed, or else its default. Fails for
+/// parameters passed but not declared, and for those with neither a value nor a default.
+fn resolve_params(
+    declared: IndexMap<String, Option<String>>,
+    passed: &[(String, String)],
+) -> Result<HashMap<String, String>> {
+    let unknown: Vec<_> = passed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| !declared.contains_key(*name))
+        .collect();
+    if !unknown.is_empty() {
+        let names: Vec<_> = declared.keys().map(String::as_str).collect();
+        bail!(
+            "unknown parameter(s) {}; the spec declares {}",
+            unknown.join(", "),
+            match names.is_empty() {
+                true => "none".to_owned(),
+                false => names.join(", "),
+            }
+        );
+    }
+    let mut values = HashMap::new();
+    let mut missing = Vec::new();
+    for (name, default) in declared {
+        let value = passed
+            .iter()
+            .rev()
+            .find(|(passed, _)| *passed == name)
+            .map(|(_, value)| value.clone())
+            .or(default);
+        match value {
+            Some(value) => {
+                values.insert(name, value);
+            }
+            None => missing.push(name),
+        }
+    }
+    if !missing.is_empty() {
+        bail!(
+            "missing parameter(s) {}; pass them with --param NAME=VALUE",
+            missing.join(", ")
+        );
+    }
+    Ok(values)
+}
+
+/// Replaces each `{{name}}` in the strings of a field, at any depth, with the value of the
+/// parameter, naming the field of a string that names no parameter.
+fn substitute_params(
+    value: &mut Value,
+    params: &HashMap<String, String>,
+    field: &str,
+) -> Result<()> {
+    match value {
+        Value::String(text) if text.contains("{{") => {
+            *text = substitute(text, params).with_context(|| format!("field {}", field))?;
+        }
+        Value::Sequence(items) => {
+            for (idx, item) in items.iter_mut().enumerate() {
+                substitute_params(item, params, &format!("{}[{}]", field, idx))?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (key, item) in mapping.iter_mut() {
+                let key = key.as_str().unwrap_or_default();
+                substitute_params(item, params, &join_path(field, key))?;
+            }
+        }
+        Value::Tagged(tagged) => substitute_params(&mut tagged.value, params, field)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces each `{{name}}` in a string with the value of the parameter.
+fn substitute(text: &str, params: &HashMap<String, String>) -> Result<String> {
+    let mut substituted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        substituted.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find("}}") else {
+            bail!("unterminated {{{{ in {:?}", text);
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        let value = params.get(name).ok_or_else(|| {
+            anyhow!(
+                "{{{{{}}}}} in {:?} isn't a parameter declared under params",
+                name,
+                text
+            )
+        })?;
+        substituted.push_str(value);
+        rest = &rest[start + len + 4..];
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+/// Replaces each `${VAR}` in a path with the value of the environment variable, failing if it
+/// isn't set.
+fn expand_env(path: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            bail!("unterminated ${{ in {:?}", path);
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        let value = std::env::var(name)
+            .map_err(|err| anyhow!("cannot expand ${{{}}} in {:?}: {}", name, path, err))?;
+        expanded.push_str(&value);
+        rest = &rest[start + len + 3..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expands the environment variables in the path fields of a merged spec, naming the field of
+/// a path that fails to expand.
+fn expand_paths(spec: &mut Mapping) -> Result<()> {
+    fn expand(value: Option<&mut Value>, field: &str) -> Result<()> {
+        if let Some(Value::String(path)) = value {
+            *path = expand_env(path).with_context(|| format!("field {}", field))?;
+        }
+        Ok(())
+    }
+    for field in [OUTPUT_FIELD, "key_filter", "rejects", "column_map"] {
+        expand(spec.get_mut(field), field)?;
+    }
+    if let Some(Value::Sequence(outputs)) = spec.get_mut(OUTPUT_FIELD) {
+        for (idx, output) in outputs.iter_mut().enumerate() {
+            expand(
+                output.get_mut("path"),
+                &format!("{}[{}].path", OUTPUT_FIELD, idx),
+            )?;
+        }
+    }
+    if let Some(fuzzy) = spec.get_mut("fuzzy") {
+        expand(fuzzy.get_mut("audit"), "fuzzy.audit")?;
+    }
+    if let Some(changed_since) = spec.get_mut("changed_since") {
+        expand(changed_since.get_mut("path"), "changed_since.path")?;
+    }
+    if let Some(Value::Sequence(sources)) = spec.get_mut(SOURCES_FIELD) {
+        for (idx, source) in sources.iter_mut().enumerate() {
+            let path = format!("{}[{}]", SOURCES_FIELD, idx);
+            expand(source.get_mut("path"), &join_path(&path, "path"))?;
+            // Projections are either a map of output columns or a list of {from, to} pairs.
+            let columns: Vec<&mut Value> = match source.get_mut("projections") {
+                Some(Value::Mapping(map)) => map.values_mut().collect(),
+                Some(Value::Sequence(pairs)) => pairs
+                    .iter_mut()
+                    .filter_map(|pair| pair.get_mut("to"))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            for col in columns {
+                if let Some(lookup) = col.get_mut("lookup") {
+                    expand(
+                        lookup.get_mut("path"),
+                        &join_path(&path, "projections.lookup.path"),
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fills in the policies left unset with the defaults of the spec's mode, if it has one.
+fn apply_mode(spec: &mut Mapping) -> Result<()> {
+    let Some(mode) = spec.get(MODE_FIELD) else {
+        return Ok(());
+    };
+    let mode: Mode = serde_yaml::from_value(mode.clone())?;
+    fn fill(mapping: &mut Mapping, defaults: impl IntoIterator<Item = (&'static str, Value)>) {
+        for (field, value) in defaults {
+            if !mapping.contains_key(field) {
+                mapping.insert(field.into(), value);
+            }
+        }
+    }
+    fill(spec, mode.spec_defaults());
+    fill(spec, mode.output_defaults());
+    if let Some(Value::Sequence(outputs)) = spec.get_mut(OUTPUT_FIELD) {
+        for output in outputs.iter_mut().filter_map(Value::as_mapping_mut) {
+            fill(output, mode.output_defaults());
+        }
+    }
+    if let Some(Value::Sequence(sources)) = spec.get_mut(SOURCES_FIELD) {
+        for source in sources.iter_mut().filter_map(Value::as_mapping_mut) {
+            fill(source, mode.source_defaults());
+        }
+    }
+    Ok(())
+}
+
+/// Copies the output options set at the top level of the spec into each output of a list of
+/// outputs that doesn't set them, before any mode fills in its defaults.
+fn inherit_output_options(spec: &mut Mapping) {
+    let options: Vec<_> = field_names::<OutputOptions>()
+        .iter()
+        .filter_map(|field| Some((*field, spec.get(field)?.clone())))
+        .collect();
+    if let Some(Value::Sequence(outputs)) = spec.get_mut(OUTPUT_FIELD) {
+        for output in outputs.iter_mut().filter_map(Value::as_mapping_mut) {
+            for (field, value) in &options {
+                if !output.contains_key(field) {
+                    output.insert((*field).into(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct SpecFragment {
+    fields: Mapping,
+    sources: IndexMap<String, Value>,
+    /// The declared parameters, with their defaults.
+    params: IndexMap<String, Option<String>>,
+}
+
+impl SpecFragment {
+    fn merge(&mut self, other: SpecFragment) {
+        for (field, value) in other.fields {
+            self.fields.insert(field, value);
+        }
+        for (name, source) in other.sources {
+            self.sources.insert(name, source);
+        }
+        self.params.extend(other.params);
+    }
+
+    fn into_mapping(self) -> Mapping {
+        let mut mapping = self.fields;
+        let sources = self.sources.into_values().collect();
+        mapping.insert(SOURCES_FIELD.into(), Value::Sequence(sources));
+        mapping
+    }
+}
+
+fn load_fragment(
+    reader: impl Read,
+    origin: &Path,
+    base_dir: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> Result<SpecFragment> {
+    let mut fields: Mapping = serde_yaml::from_reader(reader)
+        .with_context(|| format!("{}: failed to parse spec", origin.display()))?;
+
+    let mut fragment = SpecFragment::default();
+    if let Some(includes) = fields.remove(INCLUDE_FIELD) {
+        let includes: Vec<String> = serde_yaml::from_value(includes)
+            .with_context(|| format!("{}: invalid include list", origin.display()))?;
+        for include in includes {
+            let include = expand_env(&include)
+                .with_context(|| format!("{}: invalid include list", origin.display()))?;
+            let path = paths::join(base_dir, &include);
+            let canonical = path.canonicalize().with_context(|| {
+                format!("{}: cannot include {}", origin.display(), path.display())
+            })?;
+            if chain.contains(&canonical) {
+                let cycle: Vec<_> = chain
+                    .iter()
+                    .chain(std::iter::once(&canonical))
+                    .map(|path| path.display().to_string())
+                    .collect();
+                bail!("include cycle detected: {}", cycle.join(" -> "));
+            }
+            chain.push(canonical);
+            let file = File::open(&path).with_context(|| {
+                format!("{}: cannot include {}", origin.display(), path.display())
+            })?;
+            let include_dir = path.parent().unwrap_or(Path::new(""));
+            fragment.merge(load_fragment(file, &path, include_dir, chain)?);
+            chain.pop();
+        }
+    }
+
+    let mut local = SpecFragment::default();
+    if let Some(params) = fields.remove(PARAMS_FIELD) {
+        local.params = serde_yaml::from_value(params)
+            .with_context(|| format!("{}: invalid params", origin.display()))?;
+    }
+    if let Some(sources) = fields.remove(SOURCES_FIELD) {
+        let Value::Sequence(sources) = sources else {
+            bail!("{}: sources must be a list", origin.display());
+        };
+        for (idx, value) in sources.into_iter().enumerate() {
+            let path = format!("{}[{}]", SOURCES_FIELD, idx);
+            check_source_fields(&value, &path)
+                .with_context(|| format!("{}: invalid spec", origin.display()))?;
+            let source = Source::deserialize(value.clone()).map_err(|err| {
+                anyhow!(
+                    "{}: invalid spec: field {}: {}",
+                    origin.display(),
+                    path,
+                    err
+                )
+            })?;
+            // Sources of a file must be told apart by name, since includes are merged by name;
+            // this matters when a path is listed twice, as when joining a file to itself.
+            let (first, replaced) = local.sources.insert_full(source.name().into_owned(), value);
+            if replaced.is_some() {
+                bail!(
+                    "{}: {}[{}] and {} are both named {}; set a distinct name on one of them",
+                    origin.display(),
+                    SOURCES_FIELD,
+                    first,
+                    path,
+                    source.name()
+                );
+            }
+        }
+    }
+    check_spec_fields(&fields).with_context(|| format!("{}: invalid spec", origin.display()))?;
+    local.fields = fields;
+    // The local sources come first, so that the primary source is the spec's own rather than
+    // an included one; included sources follow, unless a local one of the same name replaces
+    // them.
+    for (name, source) in std::mem::take(&mut fragment.sources) {
+        local.sources.entry(name).or_insert(source);
+    }
+    fragment.merge(local);
+
+    Ok(fragment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(src: &str) -> Value {
+        serde_yaml::from_str(src).unwrap()
+    }
+
+    /// The error of checking the fields of `sources[0]`.
+    fn source_fields_error(src: &str) -> String {
+        check_source_fields(&yaml(src), "sources[0]")
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn int_keys_match_regardless_of_their_form() {
+        for value in ["007", "7", " 7 ", "+7", "7.0", "7e0"] {
+            assert_eq!(
+                KeyType::Int.canonicalize(value).as_deref(),
+                Some("7"),
+                "{}",
+                value
+            );
+        }
+        assert_eq!(KeyType::Int.canonicalize("-0").as_deref(), Some("0"));
+        for value in ["7.5", "seven", "", "inf", "NaN"] {
+            assert_eq!(KeyType::Int.canonicalize(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn float_keys_match_regardless_of_their_form() {
+        for value in ["1.0", "1", "01", "1.00", "1e0"] {
+            assert_eq!(
+                KeyType::Float.canonicalize(value).as_deref(),
+                Some("1"),
+                "{}",
+                value
+            );
+        }
+        assert_eq!(KeyType::Float.canonicalize("0.50").as_deref(), Some("0.5"));
+        for value in ["one", "", "inf", "NaN"] {
+            assert_eq!(KeyType::Float.canonicalize(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn string_keys_are_kept_as_they_are() {
+        for value in ["007", " 7 ", "1.0", "seven"] {
+            assert_eq!(KeyType::String.canonicalize(value).as_deref(), Some(value));
+        }
+    }
+
+    #[test]
+    fn numeric_keys_are_ordered_numerically() {
+        assert_eq!(KeyType::Int.compare("9", "10"), Ordering::Less);
+        assert_eq!(KeyType::Float.compare("-1.5", "-10"), Ordering::Greater);
+        assert_eq!(KeyType::String.compare("9", "10"), Ordering::Greater);
+        assert_eq!(KeyType::Int.compare("seven", "10"), Ordering::Greater);
+    }
+
+    #[test]
+    fn misspelled_source_field_is_named_with_a_suggestion() {
+        let err = source_fields_error("{path: a.csv, projectons: {x: x}}");
+        assert!(
+            err.starts_with(
+                "unknown field sources[0].projectons; did you mean projections? Valid fields are: "
+            ),
+            "{}",
+            err
+        );
+        assert!(err.contains(", projections, "), "{}", err);
+    }
+
+    #[test]
+    fn misspelled_nested_field_is_named_by_its_path() {
+        let err = source_fields_error("{path: a.csv, range: {key: day, form: start, to: end}}");
+        assert_eq!(
+            err,
+            "unknown field sources[0].range.form; did you mean from? Valid fields are: from, key, \
+             on_overlap, to"
+        );
+    }
+
+    #[test]
+    fn unknown_nested_field_without_a_close_name_lists_the_fields() {
+        let err = source_fields_error(
+            "{path: a.csv, range: {key: day, from: start, to: end, inclusive: true}}",
+        );
+        assert_eq!(
+            err,
+            "unknown field sources[0].range.inclusive; valid fields are: from, key, on_overlap, to"
+        );
+    }
+
+    #[test]
+    fn misspelled_spec_field_is_named_with_a_suggestion() {
+        let Value::Mapping(spec) = yaml("{ouptut: out.csv, key: [id]}") else {
+            unreachable!();
+        };
+        let err = check_spec_fields(&spec).unwrap_err().to_string();
+        assert!(
+            err.starts_with("unknown field ouptut; did you mean output? Valid fields are: "),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn known_fields_pass() {
+        let source = yaml("{path: a.csv, projections: {x: x}, range: {key: d, from: a, to: b}}");
+        check_source_fields(&source, "sources[0]").unwrap();
+    }
+
+    /// Deserializes projections from `src`, serializes them, and deserializes them again,
+    /// returning both serialized forms.
+    fn round_trip(src: &str) -> (String, String) {
+        let projections: Projections = serde_yaml::from_str(src).unwrap();
+        let first = serde_yaml::to_string(&projections).unwrap();
+        let projections: Projections = serde_yaml::from_str(&first).unwrap();
+        (first, serde_yaml::to_string(&projections).unwrap())
+    }
+
+    #[test]
+    fn projection_map_round_trips_identically() {
+        let src = "zip: postal_code\nname: full_name\ncity: city\n";
+        let (first, second) = round_trip(src);
+        assert_eq!(first, src);
+        assert_eq!(second, src);
+    }
+
+    #[test]
+    fn projection_map_with_options_stays_a_map() {
+        let (first, second) = round_trip(
+            "score: {name: points, if_empty: '0'}\nname: {name: full_name, default: none}\n",
+        );
+        let value: Value = serde_yaml::from_str(&first).unwrap();
+        let Value::Mapping(map) = value else {
+            panic!("not a map:\n{}", first);
+        };
+        let cols: Vec<_> = map.keys().map(|key| key.as_str().unwrap()).collect();
+        assert_eq!(cols, ["score", "name"]);
+        assert_eq!(map["score"]["name"], "points");
+        assert_eq!(map["score"]["if_empty"], "0");
+        assert_eq!(map["name"]["default"], "none");
+        assert_eq!(second, first);
+    }
+
+    #[test]
+    fn repeated_projections_stay_a_list() {
+        let (first, second) = round_trip(
+            "- {from: id, to: id_text}\n- {from: id, to: id_copy}\n- {from: x, to: x}\n",
+        );
+        let value: Value = serde_yaml::from_str(&first).unwrap();
+        let Value::Sequence(pairs) = value else {
+            panic!("not a list:\n{}", first);
+        };
+        let pairs: Vec<_> = pairs
+            .iter()
+            .map(|pair| (pair["from"].as_str().unwrap(), pair["to"].as_str().unwrap()))
+            .collect();
+        assert_eq!(pairs, [("id", "id_text"), ("id", "id_copy"), ("x", "x")]);
+        assert_eq!(second, first);
+    }
+
+    #[test]
+    fn key_normalization_transforms_one_component() {
+        assert_eq!(KeyNormalization::Trim.apply(" \tA b\n"), "A b");
+        assert_eq!(KeyNormalization::Lowercase.apply("ÀbC"), "àbc");
+        assert_eq!(KeyNormalization::Uppercase.apply("àbc"), "ÀBC");
+        assert!(matches!(
+            KeyNormalization::Trim.apply(" a "),
+            Cow::Borrowed("a")
+        ));
+    }
+
+    #[test]
+    fn strip_leading_zeros_keeps_a_digit() {
+        let cases = [
+            ("007", "7"),
+            ("0", "0"),
+            ("000", "0"),
+            ("00.5", "0.5"),
+            ("0012a", "12a"),
+            ("0a", "0a"),
+            ("-007", "-007"),
+            (" 007", " 007"),
+            ("700", "700"),
+            ("", ""),
+        ];
+        for (value, stripped) in cases {
+            let normalized = KeyNormalization::StripLeadingZeros.apply(value);
+            assert!(matches!(normalized, Cow::Borrowed(_)), "{}", value);
+            assert_eq!(normalized, stripped, "{:?}", value);
+        }
+    }
+}
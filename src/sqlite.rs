@@ -0,0 +1,180 @@
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use anyhow::{bail, Result};
+use rusqlite::{params_from_iter, Connection, OptionalExtension};
+
+const BATCH_SIZE: usize = 10_000;
+
+pub struct SqliteWriter {
+    conn: Connection,
+    insert_sql: String,
+    pending: usize,
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+impl SqliteWriter {
+    /// Opens the database and creates `table` with a TEXT column for each header name.
+    ///
+    /// The columns at `key_cols` form the primary key when `index_key` is set.
+    pub fn new(
+        path: &Path,
+        table: &str,
+        header: &[String],
+        key_cols: &[usize],
+        index_key: bool,
+        overwrite: bool,
+    ) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        let table_ident = quote_ident(table);
+
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+            [table],
+            |row| row.get(0),
+        )?;
+        if exists {
+            if !overwrite {
+                bail!(
+                    "{}: table {} already exists; set overwrite to replace it",
+                    path.display(),
+                    table
+                );
+            }
+            conn.execute_batch(&format!("DROP TABLE {}", table_ident))?;
+        }
+
+        let mut columns: Vec<_> = header
+            .iter()
+            .map(|col| format!("{} TEXT", quote_ident(col)))
+            .collect();
+        if index_key && !key_cols.is_empty() {
+            let key_cols: Vec<_> = key_cols
+                .iter()
+                .map(|&idx| quote_ident(&header[idx]))
+                .collect();
+            columns.push(format!("PRIMARY KEY ({})", key_cols.join(", ")));
+        }
+        conn.execute_batch(&format!(
+            "CREATE TABLE {} ({})",
+            table_ident,
+            columns.join(", ")
+        ))?;
+
+        let placeholders = vec!["?"; header.len()].join(", ");
+        let insert_sql = format!("INSERT INTO {} VALUES ({})", table_ident, placeholders);
+        conn.execute_batch("BEGIN")?;
+
+        Ok(Self {
+            conn,
+            insert_sql,
+            pending: 0,
+        })
+    }
+
+    pub fn write_record(&mut self, row: &[Option<String>]) -> Result<()> {
+        self.conn
+            .prepare_cached(&self.insert_sql)?
+            .execute(params_from_iter(row))?;
+        self.pending += 1;
+        if self.pending == BATCH_SIZE {
+            self.conn.execute_batch("COMMIT; BEGIN")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
+
+/// A key-to-projection map in a private temporary database, for lookup sources too large to
+/// keep in memory. Keys and projections are stored as JSON arrays.
+pub struct SpillTable {
+    /// Behind a lock, so that the workers of `--parallel-join` can share the table.
+    conn: Mutex<Connection>,
+    len: usize,
+}
+
+impl SpillTable {
+    pub fn new() -> Result<Self> {
+        // An empty path opens a temporary on-disk database that is deleted once closed.
+        let conn = Connection::open("")?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = OFF;
+             PRAGMA synchronous = OFF;
+             CREATE TABLE spill (key TEXT PRIMARY KEY, projection TEXT NOT NULL);
+             BEGIN",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            len: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Inserts or replaces the projection of a key, returning whether the key was already present.
+    pub fn insert(&mut self, key: &[String], projection: &[String]) -> Result<bool> {
+        let key = serde_json::to_string(key)?;
+        let projection = serde_json::to_string(projection)?;
+        let conn = self.conn();
+        let inserted = conn
+            .prepare_cached("INSERT OR IGNORE INTO spill (key, projection) VALUES (?1, ?2)")?
+            .execute([&key, &projection])?;
+        if inserted > 0 {
+            drop(conn);
+            self.len += 1;
+            return Ok(false);
+        }
+        conn.prepare_cached("UPDATE spill SET projection = ?2 WHERE key = ?1")?
+            .execute([&key, &projection])?;
+        Ok(true)
+    }
+
+    /// Looks up the zero-based insertion position and projection of a key.
+    pub fn get_full(&self, key: &[String]) -> Result<Option<(usize, Vec<String>)>> {
+        let key = serde_json::to_string(key)?;
+        let row = self
+            .conn()
+            .prepare_cached("SELECT rowid, projection FROM spill WHERE key = ?1")?
+            .query_row([&key], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .optional()?;
+        match row {
+            Some((rowid, projection)) => Ok(Some((
+                rowid as usize - 1,
+                serde_json::from_str(&projection)?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn key_at(&self, idx: usize) -> Result<Vec<String>> {
+        let key: String = self
+            .conn()
+            .prepare_cached("SELECT key FROM spill WHERE rowid = ?1")?
+            .query_row([idx as i64 + 1], |row| row.get(0))?;
+        Ok(serde_json::from_str(&key)?)
+    }
+
+    pub fn projection_at(&self, idx: usize) -> Result<Vec<String>> {
+        let projection: String = self
+            .conn()
+            .prepare_cached("SELECT projection FROM spill WHERE rowid = ?1")?
+            .query_row([idx as i64 + 1], |row| row.get(0))?;
+        Ok(serde_json::from_str(&projection)?)
+    }
+}
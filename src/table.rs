@@ -0,0 +1,373 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use indexmap::map::Entry;
+use indexmap::IndexMap;
+
+use crate::aggregate::Aggregates;
+use crate::ranges::Ranges;
+use crate::{Data, Key, Primary, Projection};
+
+/// The rows of a lookup source, kept in memory until they exceed the spill threshold.
+pub struct Table {
+    rows: Rows,
+    spill_after: Option<usize>,
+    /// Approximate number of bytes held by the in-memory rows.
+    memory: usize,
+    memory_limit: Option<usize>,
+    /// The `latest_by` value of each row, by position, for a table keeping the latest row of
+    /// each key.
+    stamps: Vec<f64>,
+}
+
+/// Estimated bookkeeping cost of a row beyond its strings: the key and projection vectors and
+/// the map's entry and index slot.
+const ROW_OVERHEAD: usize = 2 * size_of::<Vec<String>>() + 4 * size_of::<usize>();
+
+/// Approximate number of bytes held by a key or projection.
+fn estimate(values: &[String]) -> usize {
+    values
+        .iter()
+        .map(|value| size_of::<String>() + value.len())
+        .sum()
+}
+
+/// Where the record of a row of a source with `lazy_projections` starts: its file, by position
+/// among those of the source, its number in the file, and its byte offset.
+#[derive(Debug, Clone, Copy)]
+pub struct RowOffset {
+    pub file: usize,
+    pub record: usize,
+    pub byte: u64,
+}
+
+enum Rows {
+    Memory(Data),
+    /// Where the last record of each key starts, for a source with `lazy_projections`, whose
+    /// projections are read from there again when looked up; see [`crate::lazy`]. `file` is
+    /// the position of the file being read.
+    Offsets {
+        offsets: IndexMap<Key, RowOffset, foldhash::fast::RandomState>,
+        file: usize,
+    },
+    /// The rows of a source with a `range`, which are all kept, as rows of the same key may
+    /// have different ranges.
+    Ranges(Ranges),
+    /// Every row as read, for the primary source with `primary_passthrough`.
+    All(Vec<(Key, Projection)>),
+    /// The rows of a source with `agg` projections, combined per key until all were read.
+    Aggregated(Aggregates),
+    #[cfg(feature = "sqlite")]
+    Spilled(crate::sqlite::SpillTable),
+}
+
+impl Table {
+    pub fn new(spill_after: Option<usize>, memory_limit: Option<usize>) -> Self {
+        Self {
+            rows: Rows::Memory(Data::default()),
+            spill_after,
+            memory: 0,
+            memory_limit,
+            stamps: Vec::new(),
+        }
+    }
+
+    /// A table of the rows of a source with a `range`, which are never spilled.
+    pub fn with_ranges(ranges: Ranges, memory_limit: Option<usize>) -> Self {
+        Self {
+            rows: Rows::Ranges(ranges),
+            spill_after: None,
+            memory: 0,
+            memory_limit,
+            stamps: Vec::new(),
+        }
+    }
+
+    /// A table of every row as read, without replacing earlier rows of the same key.
+    pub fn keeping_all(memory_limit: Option<usize>) -> Self {
+        Self {
+            rows: Rows::All(Vec::new()),
+            spill_after: None,
+            memory: 0,
+            memory_limit,
+            stamps: Vec::new(),
+        }
+    }
+
+    /// A table of where the record of each key starts, for a source with `lazy_projections`,
+    /// which is never spilled.
+    pub fn with_offsets(memory_limit: Option<usize>) -> Self {
+        Self {
+            rows: Rows::Offsets {
+                offsets: IndexMap::default(),
+                file: 0,
+            },
+            spill_after: None,
+            memory: 0,
+            memory_limit,
+            stamps: Vec::new(),
+        }
+    }
+
+    /// A table of the rows of a source with `agg` projections, which are never spilled.
+    pub fn with_aggregates(aggregates: Aggregates, memory_limit: Option<usize>) -> Self {
+        Self {
+            rows: Rows::Aggregated(aggregates),
+            spill_after: None,
+            memory: 0,
+            memory_limit,
+            stamps: Vec::new(),
+        }
+    }
+
+    /// Inserts the projection of a key, unless a row of the key has a later `latest_by` stamp,
+    /// returning the key back if it was already present, whichever row is kept. Only for a
+    /// table created with [`Table::new`] that is never spilled.
+    pub fn insert_latest(
+        &mut self,
+        key: Key,
+        projection: Projection,
+        stamp: f64,
+    ) -> Result<Option<Key>> {
+        let Rows::Memory(data) = &self.rows else {
+            unreachable!("latest_by is only supported on tables kept in memory")
+        };
+        match data.get_index_of(&key) {
+            Some(idx) if stamp < self.stamps[idx] => return Ok(Some(key)),
+            Some(idx) => self.stamps[idx] = stamp,
+            None => {
+                self.memory += size_of::<f64>();
+                self.stamps.push(stamp);
+            }
+        }
+        self.insert(key, projection)
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.rows {
+            Rows::Memory(data) => data.len(),
+            Rows::Offsets { offsets, .. } => offsets.len(),
+            Rows::Ranges(ranges) => ranges.len(),
+            Rows::All(rows) => rows.len(),
+            Rows::Aggregated(aggregates) => aggregates.len(),
+            #[cfg(feature = "sqlite")]
+            Rows::Spilled(table) => table.len(),
+        }
+    }
+
+    /// Inserts or replaces the projection of a key, returning the key back if it was already
+    /// present.
+    pub fn insert(&mut self, key: Key, projection: Projection) -> Result<Option<Key>> {
+        let replaced = match &mut self.rows {
+            Rows::Memory(data) => match data.entry(key) {
+                Entry::Occupied(mut entry) => {
+                    self.memory += estimate(&projection);
+                    self.memory -= estimate(&entry.insert(projection));
+                    Some(entry.key().clone())
+                }
+                Entry::Vacant(entry) => {
+                    self.memory += ROW_OVERHEAD + estimate(entry.key()) + estimate(&projection);
+                    entry.insert(projection);
+                    None
+                }
+            },
+            Rows::Offsets { .. } => unreachable!("a table of offsets is added to by insert_offset"),
+            Rows::Ranges(ranges) => {
+                self.memory += ROW_OVERHEAD + estimate(&key) + estimate(&projection);
+                ranges.insert(key, projection);
+                None
+            }
+            Rows::All(rows) => {
+                self.memory += ROW_OVERHEAD + estimate(&key) + estimate(&projection);
+                rows.push((key, projection));
+                None
+            }
+            Rows::Aggregated(aggregates) => {
+                let size = ROW_OVERHEAD + estimate(&key) + estimate(&projection);
+                if aggregates.insert(key, projection)? {
+                    self.memory += size;
+                }
+                None
+            }
+            #[cfg(feature = "sqlite")]
+            Rows::Spilled(table) => {
+                return Ok(table.insert(&key, &projection)?.then_some(key));
+            }
+        };
+        if self.spill_after.is_some_and(|limit| self.len() > limit) {
+            self.spill()?;
+        }
+        Ok(replaced)
+    }
+
+    /// Inserts or replaces where the record of a key starts in the file being read, returning
+    /// the key back if it was already present. Only for a table created with
+    /// [`Table::with_offsets`].
+    pub fn insert_offset(&mut self, key: Key, record: usize, byte: u64) -> Option<Key> {
+        let Rows::Offsets { offsets, file } = &mut self.rows else {
+            unreachable!("offsets are only kept by a table created with with_offsets")
+        };
+        let offset = RowOffset {
+            file: *file,
+            record,
+            byte,
+        };
+        match offsets.entry(key) {
+            Entry::Occupied(mut entry) => {
+                entry.insert(offset);
+                Some(entry.key().clone())
+            }
+            Entry::Vacant(entry) => {
+                self.memory += ROW_OVERHEAD + estimate(entry.key()) + size_of::<RowOffset>();
+                entry.insert(offset);
+                None
+            }
+        }
+    }
+
+    /// Moves on to the next file of the source, once one was read.
+    pub fn finish_file(&mut self) {
+        if let Rows::Offsets { file, .. } = &mut self.rows {
+            *file += 1;
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn spill(&mut self) -> Result<()> {
+        let mut table = crate::sqlite::SpillTable::new()?;
+        if let Rows::Memory(data) = &mut self.rows {
+            for (key, projection) in data.drain(..) {
+                table.insert(&key, &projection)?;
+            }
+        }
+        self.rows = Rows::Spilled(table);
+        self.memory = 0;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn spill(&mut self) -> Result<()> {
+        anyhow::bail!("spill_after requires join-csv to be built with the sqlite feature");
+    }
+
+    /// Approximate number of bytes held in memory by the rows; zero once they are spilled.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Whether the in-memory rows take up more than the memory limit.
+    pub fn exceeds_memory_limit(&self) -> bool {
+        self.memory_limit.is_some_and(|limit| self.memory > limit)
+    }
+
+    /// Whether the rows have been moved to disk.
+    pub fn is_spilled(&self) -> bool {
+        !matches!(
+            self.rows,
+            Rows::Memory(_)
+                | Rows::Offsets { .. }
+                | Rows::Ranges(_)
+                | Rows::All(_)
+                | Rows::Aggregated(_)
+        )
+    }
+
+    /// Orders the rows of a source with a `range` for lookups, once all of them were read,
+    /// returning the keys of two rows with overlapping ranges unless overlaps are allowed.
+    pub fn sort_ranges(&mut self) -> Option<(Key, Key)> {
+        match &mut self.rows {
+            Rows::Ranges(ranges) => ranges.sort(),
+            _ => None,
+        }
+    }
+
+    /// Computes the combined projections of a source with `agg` projections, once all of its
+    /// rows were read.
+    pub fn finish_aggregates(&mut self) {
+        if let Rows::Aggregated(_) = &self.rows {
+            let Rows::Aggregated(aggregates) =
+                std::mem::replace(&mut self.rows, Rows::Memory(Data::default()))
+            else {
+                unreachable!();
+            };
+            self.rows = Rows::Memory(aggregates.finish());
+        }
+    }
+
+    /// Looks up the position of a key, and where its record starts, in a table created with
+    /// [`Table::with_offsets`].
+    pub fn offset_of(&self, key: &[String]) -> Option<(usize, RowOffset)> {
+        match &self.rows {
+            Rows::Offsets { offsets, .. } => {
+                offsets.get_full(key).map(|(idx, _, &offset)| (idx, offset))
+            }
+            _ => unreachable!("only a table created with with_offsets has offsets"),
+        }
+    }
+
+    /// Where the record at the given position starts, in a table created with
+    /// [`Table::with_offsets`].
+    pub fn offset_at(&self, idx: usize) -> RowOffset {
+        match &self.rows {
+            Rows::Offsets { offsets, .. } => *offsets.get_index(idx).unwrap().1,
+            _ => unreachable!("only a table created with with_offsets has offsets"),
+        }
+    }
+
+    /// Looks up the position and projection of a key.
+    pub fn get_full(&self, key: &[String]) -> Result<Option<(usize, Cow<'_, Projection>)>> {
+        match &self.rows {
+            Rows::Memory(data) => Ok(data
+                .get_full(key)
+                .map(|(idx, _, projection)| (idx, Cow::Borrowed(projection)))),
+            Rows::Offsets { .. } => unreachable!("lazy projections are read by LazyProjections"),
+            Rows::Ranges(ranges) => Ok(ranges
+                .get_full(key)
+                .map(|(idx, projection)| (idx, Cow::Borrowed(projection)))),
+            Rows::All(rows) => Ok(rows
+                .iter()
+                .position(|(row_key, _)| row_key == key)
+                .map(|idx| (idx, Cow::Borrowed(&rows[idx].1)))),
+            Rows::Aggregated(_) => unreachable!("aggregates are looked up once finished"),
+            #[cfg(feature = "sqlite")]
+            Rows::Spilled(table) => Ok(table
+                .get_full(key)?
+                .map(|(idx, projection)| (idx, Cow::Owned(projection)))),
+        }
+    }
+
+    /// The key at the given position, in insertion order.
+    pub fn key_at(&self, idx: usize) -> Result<Cow<'_, Key>> {
+        match &self.rows {
+            Rows::Memory(data) => Ok(Cow::Borrowed(data.get_index(idx).unwrap().0)),
+            Rows::Offsets { offsets, .. } => Ok(Cow::Borrowed(offsets.get_index(idx).unwrap().0)),
+            Rows::Ranges(ranges) => Ok(Cow::Borrowed(ranges.key_at(idx))),
+            Rows::All(rows) => Ok(Cow::Borrowed(&rows[idx].0)),
+            Rows::Aggregated(_) => unreachable!("aggregates are looked up once finished"),
+            #[cfg(feature = "sqlite")]
+            Rows::Spilled(table) => Ok(Cow::Owned(table.key_at(idx)?)),
+        }
+    }
+
+    /// The projection at the given position, in insertion order.
+    pub fn projection_at(&self, idx: usize) -> Result<Cow<'_, Projection>> {
+        match &self.rows {
+            Rows::Memory(data) => Ok(Cow::Borrowed(data.get_index(idx).unwrap().1)),
+            Rows::Offsets { .. } => unreachable!("lazy projections are read by LazyProjections"),
+            Rows::Ranges(ranges) => Ok(Cow::Borrowed(ranges.projection_at(idx))),
+            Rows::All(rows) => Ok(Cow::Borrowed(&rows[idx].1)),
+            Rows::Aggregated(_) => unreachable!("aggregates are looked up once finished"),
+            #[cfg(feature = "sqlite")]
+            Rows::Spilled(table) => Ok(Cow::Owned(table.projection_at(idx)?)),
+        }
+    }
+
+    /// The in-memory rows of the primary source, or `None` if they were spilled.
+    pub fn into_primary(self) -> Option<Primary> {
+        match self.rows {
+            Rows::Memory(data) => Some(Primary::Keyed(data)),
+            Rows::All(rows) => Some(Primary::All(rows)),
+            _ => None,
+        }
+    }
+}
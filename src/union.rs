@@ -0,0 +1,213 @@
+//! Stacking the rows of all sources into one output, for `join: union`.
+//!
+//! Each source is read one record at a time, in spec order, and its rows are written as they
+//! are parsed, without indexing them by key, so every row is kept. The output has the key
+//! columns, if any, followed by every projected name in the order it is first projected; a row
+//! has absent values for the columns its source doesn't project.
+
+use std::collections::HashSet;
+use std::io::stdin;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use csv::StringRecord;
+use indexmap::IndexSet;
+
+use crate::assertions::Checks;
+use crate::dictionary::Dictionaries;
+use crate::error::{Category, ErrorCollector};
+use crate::input::{self, RecordReader};
+use crate::interrupt;
+use crate::logging::Logger;
+use crate::manifest::InputFile;
+use crate::output::OutputWriter;
+use crate::spec::{JoinSpec, ProjectedColumn, Source};
+use crate::{
+    check_schema, column_names, finish_outputs, finish_source, format_numbers, output_key_header,
+    warn_no_only_keys, with_read_retries, ColumnKind, JoinedColumn, Key, Layout, ReadContext,
+    RecordParser,
+};
+
+/// Writes the rows of every source as they are read, returning the number of rows written
+/// along with the files read for each source.
+pub fn write_union(
+    spec: &JoinSpec,
+    only_keys: Option<&HashSet<Key>>,
+    log: &Logger,
+) -> Result<(usize, Vec<Vec<InputFile>>)> {
+    let dictionaries = Dictionaries::load(spec)?;
+    let errors = ErrorCollector::new(spec.error_limit());
+    let ctx = ReadContext {
+        spec,
+        dictionaries: &dictionaries,
+        match_on: &[],
+        keep_all: false,
+        only_keys,
+        errors: &errors,
+        reread: false,
+    };
+    let key_len = spec.key.len();
+    let (_, targets) = projected_names(spec);
+    let targets: Vec<Vec<usize>> = targets
+        .into_iter()
+        .map(|targets| targets.into_iter().map(|idx| key_len + idx).collect())
+        .collect();
+    let formats: Vec<Vec<(usize, &ProjectedColumn)>> = spec
+        .sources
+        .iter()
+        .zip(&targets)
+        .map(|(source, targets)| {
+            source
+                .projections
+                .values()
+                .zip(targets)
+                .filter(|(col, _)| col.number_format().is_some())
+                .map(|(col, &target)| (target, col))
+                .collect()
+        })
+        .collect();
+
+    // The key columns are named by the header of the first file of the primary source, so it
+    // is opened before the outputs.
+    let primary = &spec.sources[0];
+    let primary_paths = input::source_paths(primary)?;
+    let first = open_file(primary, &primary_paths[0], &ctx, log)?;
+    let key_header = output_key_header(spec, first.1.key_header.clone());
+    let columns = column_names(union_columns(spec, key_header));
+    let layout = Layout::new(spec, &columns)?;
+    let mut writers = spec
+        .outputs()
+        .into_iter()
+        .map(|output| OutputWriter::open(output, &layout.key_cols, &layout.header))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut first = Some(first);
+    let mut primary_paths = Some(primary_paths);
+    let mut row: Vec<Option<String>> = Vec::with_capacity(columns.len());
+    let mut ordered = Vec::with_capacity(layout.header.len());
+    let mut written = 0usize;
+    let mut files = Vec::with_capacity(spec.sources.len());
+    for (source_idx, source) in spec.sources.iter().enumerate() {
+        let paths = match primary_paths.take() {
+            Some(paths) => paths,
+            None => input::source_paths(source)?,
+        };
+        let mut checks = Checks::new(source);
+        let mut source_files = Vec::new();
+        let mut first_header: Option<(PathBuf, StringRecord)> = None;
+        for path in paths {
+            let (mut records, mut parser) = match first.take() {
+                Some(file) => file,
+                None => open_file(source, &path, &ctx, log)?,
+            };
+            match &first_header {
+                None => first_header = Some((path.clone(), records.header().clone())),
+                Some((first_path, header)) if source.strict_schema => {
+                    check_schema(&path, first_path, header, records.header())?
+                }
+                Some(_) => {}
+            }
+            let mut record = StringRecord::new();
+            let mut idx = source.skip_rows;
+            loop {
+                let read = records.read_record(&mut record);
+                if let Ok(false) = read {
+                    break;
+                }
+                idx += 1;
+                interrupt::check(|| {
+                    format!("reading {} record(s) of {}", idx - 1, path.display())
+                })?;
+                let (key, projection) =
+                    match parser.parse(idx, read.map(|_| &record), &mut checks, log) {
+                        Ok(Some(parsed)) => parsed,
+                        Ok(None) => continue,
+                        Err(err) if Category::of(&err) == Category::Data => {
+                            errors.add(err)?;
+                            continue;
+                        }
+                        Err(err) => return Err(err),
+                    };
+                row.clear();
+                row.extend(key.into_iter().map(Some));
+                row.resize(columns.len(), None);
+                for (value, &target) in projection.into_iter().zip(&targets[source_idx]) {
+                    row[target] = Some(value);
+                }
+                format_numbers(spec, &formats[source_idx], &mut row)?;
+                let row = layout.arrange(&mut row, &mut ordered, written + 1);
+                interrupt::check(|| format!("writing {} row(s)", written))?;
+                for writer in writers.iter_mut() {
+                    writer.write_record(row)?;
+                }
+                written += 1;
+            }
+            log.info(format_args!(
+                "{}: closed after {} row(s)",
+                path.display(),
+                parser.rows
+            ));
+            source_files.push(parser.finish(0, Vec::new()));
+        }
+        finish_source(source, spec, checks, &source_files, log)?;
+        files.push(source_files);
+    }
+    errors.finish()?;
+    if only_keys.is_some() && written == 0 {
+        warn_no_only_keys(primary);
+    }
+    finish_outputs(spec, writers, written, log)?;
+    log.info(format_args!("wrote {} row(s)", written));
+    Ok((written, files))
+}
+
+/// The names projected by the sources, in the order they are first projected, with the
+/// position among them of each projection of each source.
+fn projected_names(spec: &JoinSpec) -> (IndexSet<&str>, Vec<Vec<usize>>) {
+    let mut names = IndexSet::new();
+    let targets = spec
+        .sources
+        .iter()
+        .map(|source| {
+            source
+                .projections
+                .values()
+                .map(|col| names.insert_full(col.name()).0)
+                .collect()
+        })
+        .collect();
+    (names, targets)
+}
+
+/// The columns of the output: the key columns, named by `key_header`, and then each projected
+/// name, with the sources projecting it.
+pub fn union_columns(spec: &JoinSpec, key_header: Vec<String>) -> Vec<JoinedColumn> {
+    let (names, targets) = projected_names(spec);
+    let mut columns: Vec<_> = key_header
+        .into_iter()
+        .map(|name| JoinedColumn::new(name, ColumnKind::Key, Vec::new()))
+        .collect();
+    columns.extend(names.iter().enumerate().map(|(idx, name)| {
+        let sources = (0..targets.len())
+            .filter(|&source_idx| targets[source_idx].contains(&idx))
+            .collect();
+        JoinedColumn::new(name.to_string(), ColumnKind::Projection, sources)
+    }));
+    columns
+}
+
+/// Opens a file of a source, along with its parser; a file that fails to open is opened again
+/// as `read_retries` allows.
+fn open_file<'a>(
+    source: &'a Source,
+    path: &Path,
+    ctx: &ReadContext<'a>,
+    log: &Logger,
+) -> Result<(RecordReader, RecordParser<'a>)> {
+    let records = with_read_retries(ctx.spec, source, || match path.as_os_str() == "-" {
+        true => RecordReader::from_reader(source, stdin()),
+        false => RecordReader::open(source, path),
+    })?;
+    let parser = RecordParser::new(source, path, &records, ctx, log)?;
+    Ok((records, parser))
+}
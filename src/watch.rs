@@ -0,0 +1,187 @@
+//! Running the join again whenever the spec or a file of a source changes, for `--watch`.
+//!
+//! The directories of the files are watched rather than the files themselves, since editors
+//! often save a file by replacing it, which ends a watch on the file. Events for other files in
+//! those directories, such as the outputs, are ignored.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use glob::Pattern;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::spec::load_spec;
+use crate::{input, interrupt, run, Args};
+
+/// How long the files must go without changing before the join runs again, as saving a file
+/// may take several writes.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How often waiting for changes checks for Ctrl-C.
+const POLL: Duration = Duration::from_millis(100);
+
+/// The files whose changes run the join again.
+#[derive(Default)]
+struct Watched {
+    files: HashSet<PathBuf>,
+    /// The glob patterns of sources, whose matches may come and go.
+    patterns: Vec<Pattern>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl Watched {
+    fn matches(&self, path: &Path) -> bool {
+        self.files.contains(path)
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.matches_path(path))
+    }
+}
+
+/// Runs the join, then again after each change to the spec or a file of a source, until
+/// Ctrl-C. A run that fails is reported, and the next change runs the join again; only the
+/// spec must load for watching to start, to tell which files to watch.
+pub fn watch(mut args: Args) -> Result<ExitCode> {
+    let Some(spec_path) = args.spec.clone() else {
+        bail!("--watch requires a spec file, as a spec read from stdin cannot change");
+    };
+    interrupt::install();
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).context("cannot watch for changes")?;
+    let mut watched = Watched::default();
+    let mut first = true;
+    loop {
+        let files = match watched_files(&args, &spec_path) {
+            Ok(files) => Some(files),
+            Err(err) if first => return Err(err),
+            Err(_) => None,
+        };
+        first = false;
+        let started = Instant::now();
+        match run(args.clone()) {
+            Ok(_) => {
+                tracing::info!(
+                    "[{}] join finished in {} ms",
+                    Local::now().format("%H:%M:%S"),
+                    started.elapsed().as_millis()
+                );
+                // The outputs are now those of this watch, for the next runs to replace.
+                args.force = true;
+            }
+            Err(err) => tracing::error!(
+                "[{}] join failed: {:?}",
+                Local::now().format("%H:%M:%S"),
+                err
+            ),
+        }
+        // A spec that no longer loads keeps the files of its last version watched, to run the
+        // join again once it is fixed.
+        if let Some(files) = files {
+            update_watches(&mut watcher, &mut watched, files)?;
+        }
+        tracing::info!(
+            "watching {} file(s) for changes; press Ctrl-C to stop",
+            watched.files.len() + watched.patterns.len()
+        );
+        if !wait_for_change(&events, &watched) {
+            return Ok(ExitCode::SUCCESS);
+        }
+    }
+}
+
+/// The spec and the files of its sources, as absolute paths; glob patterns are kept as they
+/// are. Fails for a source read from stdin, which cannot be watched.
+fn watched_files(args: &Args, spec_path: &Path) -> Result<Vec<PathBuf>> {
+    let file =
+        File::open(spec_path).with_context(|| format!("{}: cannot open", spec_path.display()))?;
+    let base_dir = spec_path.parent().unwrap_or(Path::new(""));
+    let spec = load_spec(file, spec_path, base_dir, &args.params)?;
+    let mut files = vec![absolute(spec_path)];
+    for source in spec.sources.iter() {
+        if source.path.as_os_str() == "-" {
+            bail!("--watch cannot watch a source read from stdin");
+        }
+        if input::as_url(&source.path).is_none() {
+            files.push(absolute(&source.path));
+        }
+    }
+    Ok(files)
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_owned())
+}
+
+/// Watches the directories of `files`, and of the files matching their glob patterns, and no
+/// longer those of files dropped from the spec.
+fn update_watches(
+    watcher: &mut RecommendedWatcher,
+    watched: &mut Watched,
+    files: Vec<PathBuf>,
+) -> Result<()> {
+    let mut updated = Watched::default();
+    for file in files {
+        if !input::is_glob_path(&file) {
+            updated.dirs.extend(file.parent().map(Path::to_owned));
+            updated.files.insert(file);
+            continue;
+        }
+        let pattern = file.to_string_lossy();
+        let matches = glob::glob(&pattern)
+            .with_context(|| format!("{}: invalid glob pattern", pattern))?
+            .filter_map(Result::ok);
+        updated
+            .dirs
+            .extend(matches.filter_map(|path| path.parent().map(Path::to_owned)));
+        updated.patterns.push(Pattern::new(&pattern)?);
+    }
+    for dir in watched.dirs.difference(&updated.dirs) {
+        // The directory may be gone, which ends its watch anyway.
+        let _ = watcher.unwatch(dir);
+    }
+    for dir in updated.dirs.difference(&watched.dirs) {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("{}: cannot watch for changes", dir.display()))?;
+    }
+    *watched = updated;
+    Ok(())
+}
+
+/// Waits until a watched file changes and then stays as it is for [`DEBOUNCE`]. Returns
+/// `false` on Ctrl-C.
+fn wait_for_change(events: &Receiver<notify::Result<Event>>, watched: &Watched) -> bool {
+    let mut changed: Option<Instant> = None;
+    loop {
+        if interrupt::interrupted() {
+            return false;
+        }
+        let timeout = match changed {
+            Some(at) => DEBOUNCE.saturating_sub(at.elapsed()).min(POLL),
+            None => POLL,
+        };
+        match events.recv_timeout(timeout) {
+            Ok(Ok(event)) if is_change(&event, watched) => changed = Some(Instant::now()),
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => tracing::warn!("warning: error watching for changes: {}", err),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+        if changed.is_some_and(|at| at.elapsed() >= DEBOUNCE) {
+            return true;
+        }
+    }
+}
+
+/// Whether the event changes a watched file; reading one, as the join does, doesn't.
+fn is_change(event: &Event, watched: &Watched) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+        && event.paths.iter().any(|path| watched.matches(path))
+}
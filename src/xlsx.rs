@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use calamine::{open_workbook_auto, Data, DataType, Reader};
+use csv::StringRecord;
+
+use crate::spec::SheetRef;
+
+/// Reads every row of a worksheet, the header included, as string records.
+pub fn read_sheet(path: &Path, sheet: Option<&SheetRef>) -> Result<Vec<StringRecord>> {
+    let mut workbook = open_workbook_auto(path)?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        None => names.first(),
+        Some(SheetRef::Index(index)) => names.get(*index),
+        Some(SheetRef::Name(name)) => names.iter().find(|candidate| *candidate == name),
+    };
+    let name = name.cloned().ok_or_else(|| {
+        anyhow!(
+            "{}: no sheet {} (available: {})",
+            path.display(),
+            sheet.map_or_else(|| "at all".to_owned(), SheetRef::to_string),
+            names.join(", ")
+        )
+    })?;
+
+    let range = workbook.worksheet_range(&name)?;
+    Ok(range
+        .rows()
+        .map(|row| row.iter().map(cell_to_string).collect())
+        .collect())
+}
+
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(value) | Data::DateTimeIso(value) | Data::DurationIso(value) => value.clone(),
+        Data::Int(value) => value.to_string(),
+        Data::Float(value) => float_to_string(*value),
+        Data::Bool(value) => value.to_string(),
+        Data::DateTime(value) => match cell.as_datetime() {
+            Some(datetime) if datetime.time() == chrono::NaiveTime::MIN => {
+                datetime.format("%Y-%m-%d").to_string()
+            }
+            Some(datetime) => datetime.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            None => float_to_string(value.as_f64()),
+        },
+        Data::Error(err) => err.to_string(),
+    }
+}
+
+fn float_to_string(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
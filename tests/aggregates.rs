@@ -0,0 +1,106 @@
+//! `agg` combines the values of the rows of each key of a lookup source.
+
+mod common;
+
+use common::fixture;
+
+/// A spec left joining the orders of each customer, with `projections`, to the customers.
+fn spec(projections: &str) -> String {
+    format!(
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    projections:
+{}",
+        projections
+    )
+}
+
+const PRIMARY: &str = "id,name\n1,a\n2,b\n3,c\n";
+const ORDERS: &str = "id,amount,status\n1,10,open\n2,5.5,open\n1,,void\n1,2.5,closed\n2,-1,open\n";
+
+#[test]
+fn each_aggregate_combines_the_values_of_a_key() {
+    let fixture = fixture(
+        PRIMARY,
+        ORDERS,
+        &spec(
+            "      - {from: status, to: last_status}
+      - {from: amount, to: {name: total, agg: sum}}
+      - {from: amount, to: {name: orders, agg: count}}
+      - {from: amount, to: {name: smallest, agg: min}}
+      - {from: amount, to: {name: largest, agg: max}}
+      - {from: amount, to: {name: average, agg: avg}}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,last_status,total,orders,smallest,largest,average\n\
+         1,a,closed,12.5,2,2.5,10,6.25\n\
+         2,b,open,4.5,2,-1,5.5,2.25\n\
+         3,c,,,,,,\n"
+    );
+}
+
+#[test]
+fn key_without_values_has_empty_aggregates_but_a_count_of_zero() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,amount\n1,\n1,\n",
+        &spec(
+            "      - {from: amount, to: {name: total, agg: sum}}
+      - {from: amount, to: {name: orders, agg: count}}
+      - {from: amount, to: {name: average, agg: avg}}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,total,orders,average\n1,a,,0,\n"
+    );
+}
+
+#[test]
+fn value_that_is_not_a_number_is_a_data_error() {
+    let fixture = fixture(
+        PRIMARY,
+        ORDERS,
+        &spec("      status: {name: status, agg: max}\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: record 1: projection status: cannot compute the max of \
+             \"open\", which is not a number"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn agg_on_the_primary_source_is_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        ORDERS,
+        &spec("      amount: amount\n").replacen(
+            "projections: {name: name}",
+            "projections: {name: {name: name, agg: count}}",
+            1,
+        ),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: primary.csv: agg is only supported on lookup sources"),
+        "{}",
+        stderr
+    );
+}
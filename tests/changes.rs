@@ -0,0 +1,129 @@
+//! `changed_since` leaves out the rows that are the same as in a previous output.
+
+mod common;
+
+use common::{fixture, Fixture};
+
+const PRIMARY: &str = "id,name\n1,a\n2,b\n3,c\n";
+const LOOKUP: &str = "id,score\n1,10\n2,25\n";
+
+/// A spec left joining the scores to the names, compared to `previous` with `options`.
+fn spec(output: &str, previous: &str, options: &str) -> String {
+    format!(
+        "output: {}
+key: [id]
+changed_since: {{path: {}{}}}
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    projections: {{score: score}}
+",
+        output, previous, options
+    )
+}
+
+fn changes_fixture(previous: &str, options: &str) -> Fixture {
+    let fixture = fixture(PRIMARY, LOOKUP, &spec("out.csv", "previous.csv", options));
+    fixture.write("previous.csv", previous);
+    fixture
+}
+
+#[test]
+fn unchanged_rows_are_left_out() {
+    let fixture = changes_fixture("id,name,score\n1,a,10\n2,b,20\n", "");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n2,b,25\n3,c,\n");
+}
+
+#[test]
+fn ignored_columns_are_not_compared() {
+    let fixture = changes_fixture(
+        "id,name,score,loaded_at\n1,a,10,monday\n2,b,20,monday\n",
+        ", ignore: [score, loaded_at]",
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n3,c,\n");
+}
+
+#[test]
+fn change_type_tells_new_changed_and_deleted_rows() {
+    let fixture = changes_fixture(
+        "id,name,score\n1,a,10\n2,b,20\n4,d,40\n",
+        ", change_type: change, emit_deleted: true",
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,change\n2,b,25,changed\n3,c,,new\n4,,,deleted\n"
+    );
+}
+
+#[test]
+fn output_with_a_change_type_is_compared_to_by_the_next_run() {
+    let fixture = changes_fixture("id,name,score\n1,a,10\n2,b,20\n", ", change_type: change");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,change\n2,b,25,changed\n3,c,,new\n"
+    );
+    fixture.write("lookup.csv", "id,score\n1,10\n2,25\n3,30\n");
+    fixture.write(
+        "next.yaml",
+        spec("next.csv", "out.csv", ", change_type: change"),
+    );
+    fixture.join(&["next.yaml"]);
+    assert_eq!(
+        fixture.read("next.csv"),
+        "id,name,score,change\n1,a,10,new\n3,c,30,changed\n"
+    );
+}
+
+#[test]
+fn previous_output_with_another_header_is_a_spec_error() {
+    let fixture = changes_fixture("id,name\n1,a\n", "");
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "Error: previous.csv: without the ignored columns, the header of the previous output is \
+         [\"id\", \"name\"], but that of the output is [\"id\", \"name\", \"score\"]\n"
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn repeated_key_in_the_previous_output_is_a_data_error() {
+    let fixture = changes_fixture("id,name,score\n1,a,10\n1,b,20\n", "");
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: previous.csv: record 2: key [\"1\"] appears more than once in the previous \
+             output"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn missing_previous_output_is_an_io_error() {
+    let fixture = fixture(PRIMARY, LOOKUP, &spec("out.csv", "previous.csv", ""));
+    let stderr = fixture.fail(&["join.yaml"], 3);
+    assert!(
+        stderr.starts_with("Error: previous.csv: cannot open previous output"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn emit_deleted_requires_a_change_type() {
+    let fixture = changes_fixture("id,name,score\n", ", emit_deleted: true");
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: changed_since.emit_deleted requires change_type"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,102 @@
+//! Runs join-csv on files written for each test, under the target directory.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::thread;
+
+/// The directory of a test, emptied when it is created, so that every run starts afresh.
+pub struct Fixture {
+    pub dir: PathBuf,
+}
+
+impl Fixture {
+    /// The fixture of the running test, named after the test and the file that has it.
+    pub fn new() -> Self {
+        let thread = thread::current();
+        let test = thread
+            .name()
+            .expect("a fixture is made on the thread of its test");
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join("tests")
+            .join(env!("CARGO_CRATE_NAME"))
+            .join(test.replace("::", "-"));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    /// Writes a file of the fixture, returning its path.
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    pub fn read(&self, name: &str) -> String {
+        String::from_utf8(self.read_bytes(name)).unwrap()
+    }
+
+    pub fn read_bytes(&self, name: &str) -> Vec<u8> {
+        fs::read(self.dir.join(name)).unwrap_or_else(|err| panic!("cannot read {}: {}", name, err))
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.dir.join(name).exists()
+    }
+
+    /// Runs join-csv with `args` in the directory of the fixture.
+    pub fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_join-csv"))
+            .args(args)
+            .current_dir(&self.dir)
+            .env_remove("RUST_BACKTRACE")
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap()
+    }
+
+    /// Runs join-csv with `args`, which must succeed, returning what it wrote to stderr.
+    pub fn join(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            output.status.success(),
+            "join-csv {:?} failed with {}:\n{}",
+            args,
+            output.status,
+            stderr
+        );
+        stderr
+    }
+
+    /// Runs join-csv with `args`, which must fail with `code`, returning what it wrote to
+    /// stderr.
+    pub fn fail(&self, args: &[&str], code: i32) -> String {
+        let output = self.run(args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(
+            output.status.code(),
+            Some(code),
+            "join-csv {:?} exited with {} instead of {}:\n{}",
+            args,
+            output.status,
+            code,
+            stderr
+        );
+        stderr
+    }
+}
+
+/// The fixture of the running test, joining `primary.csv` to `lookup.csv` by `spec`.
+pub fn fixture(primary: &str, lookup: &str, spec: &str) -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write("primary.csv", primary);
+    fixture.write("lookup.csv", lookup);
+    fixture.write("join.yaml", spec);
+    fixture
+}
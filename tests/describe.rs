@@ -0,0 +1,130 @@
+//! `--describe` prints the columns of the output as JSON without joining.
+
+mod common;
+
+use common::{fixture, Fixture};
+use serde_json::{json, Value};
+
+/// Describes the spec of the fixture, which must succeed, returning what it printed.
+fn describe(fixture: &Fixture) -> Value {
+    let output = fixture.run(&["join.yaml", "--describe"]);
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn columns_are_described_with_their_sources() {
+    let fixture = fixture(
+        "code,name\n",
+        "id,score\n",
+        "output: out.csv
+key: [id]
+emit_match_flags: true
+sources:
+  - path: primary.csv
+    key_aliases: {id: code}
+    projections: {name: name}
+  - path: lookup.csv
+    name: scores
+    join: left
+    projections: {score: points}
+",
+    );
+    assert_eq!(
+        describe(&fixture),
+        json!({
+            "join": "inner",
+            "key": ["code"],
+            "columns": [
+                {"name": "code", "kind": "key"},
+                {"name": "name", "kind": "projection", "sources": ["primary"]},
+                {"name": "points", "kind": "projection", "sources": ["scores"]},
+                {"name": "scores_matched", "kind": "match_flag", "sources": ["scores"]},
+            ],
+        })
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn coalesced_column_has_the_sources_of_its_projections() {
+    let fixture = fixture(
+        "id,email\n",
+        "id,email\n",
+        "output: out.csv
+key: [id]
+coalesce_columns:
+  - name: email
+    from: [{source: primary, column: email}, {source: lookup, column: email}]
+sources:
+  - path: primary.csv
+    projections: {email: email}
+  - path: lookup.csv
+    join: left
+    projections: {email: email}
+",
+    );
+    assert_eq!(
+        describe(&fixture)["columns"][1],
+        json!({"name": "email", "kind": "projection", "sources": ["primary", "lookup"]})
+    );
+}
+
+#[test]
+fn spec_without_a_key_describes_a_cross_join() {
+    let fixture = fixture(
+        "name\n",
+        "city\n",
+        "output: out.csv
+key: []
+allow_cross_join: true
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    projections: {city: city}
+",
+    );
+    let description = describe(&fixture);
+    assert_eq!(description["join"], "cross");
+    assert_eq!(description["key"], json!([]));
+}
+
+#[test]
+fn describing_reads_no_data() {
+    let fixture = fixture(
+        "id,name\n1,a\n1,\"unterminated\n",
+        "id,score\n",
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    projections: {score: score}
+",
+    );
+    assert_eq!(describe(&fixture)["columns"][0]["name"], "id");
+}
+
+#[test]
+fn missing_column_fails_the_description_as_the_join() {
+    let fixture = fixture(
+        "id,name\n",
+        "id,score\n",
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    projections: {grade: grade}
+",
+    );
+    let stderr = fixture.fail(&["join.yaml", "--describe"], 2);
+    assert!(
+        stderr.starts_with("Error: lookup.csv: missing column grade"),
+        "{}",
+        stderr
+    );
+}
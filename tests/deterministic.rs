@@ -0,0 +1,57 @@
+//! A run with `--deterministic` writes the same bytes every time.
+
+mod common;
+
+use common::Fixture;
+
+/// Runs the spec of the fixture, returning the bytes of its output and of its manifest.
+fn run(fixture: &Fixture, args: &[&str]) -> (Vec<u8>, Vec<u8>) {
+    let mut args = args.to_vec();
+    args.extend([
+        "join.yaml",
+        "--force",
+        "--deterministic",
+        "--manifest",
+        "out.json",
+    ]);
+    fixture.join(&args);
+    (
+        fixture.read_bytes("out.csv"),
+        fixture.read_bytes("out.json"),
+    )
+}
+
+#[test]
+fn deterministic_runs_write_the_same_bytes() {
+    let fixture = Fixture::new();
+    fixture.write(
+        "primary.csv",
+        "id,name,city\n3,c,Oslo\n1,a,Rome\n2,b,Oslo\n1,d,Lima\n5,e,Rome\n",
+    );
+    fixture.write("scores.csv", "id,score\n2,20\n1,10\n4,40\n");
+    fixture.write(
+        "join.yaml",
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {name: name, city: city}
+  - path: scores.csv
+    join: left
+    projections: {score: score}
+",
+    );
+    let first = run(&fixture, &[]);
+    assert_eq!(
+        String::from_utf8_lossy(&first.0),
+        "id,name,city,score\n3,c,Oslo,\n1,d,Lima,10\n2,b,Oslo,20\n5,e,Rome,\n"
+    );
+    assert!(!String::from_utf8_lossy(&first.1).contains("\"started\""));
+    for args in [&[][..], &["--parallel-join", "3"], &["--threads", "2"]] {
+        assert!(
+            run(&fixture, args) == first,
+            "a run with {:?} wrote other bytes than the first",
+            args
+        );
+    }
+}
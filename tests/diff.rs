@@ -0,0 +1,110 @@
+//! The `diff` subcommand compares two versions of a file by key.
+
+mod common;
+
+use common::Fixture;
+
+const OLD: &str = "id,name,city\n1,a,Oslo\n2,b,Rome\n3,c,Lima\n";
+const NEW: &str = "id,name,city\n1,a,Oslo\n2,b,Bern\n4,d,Kyiv\n";
+
+/// A fixture with the old and the new version of a file.
+fn versions(old: &str, new: &str) -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write("old.csv", old);
+    fixture.write("new.csv", new);
+    fixture
+}
+
+#[test]
+fn differences_are_written_with_only_the_changed_columns() {
+    let fixture = versions(OLD, NEW);
+    let stderr = fixture.fail(
+        &[
+            "diff", "--key", "id", "old.csv", "new.csv", "-o", "diff.csv",
+        ],
+        1,
+    );
+    assert_eq!(stderr, "1 added, 1 removed, 1 changed row(s)\n");
+    assert_eq!(
+        fixture.read("diff.csv"),
+        "id,change_type,city_old,city_new\n\
+         2,changed,Rome,Bern\n\
+         4,added,,Kyiv\n\
+         3,removed,Lima,\n"
+    );
+}
+
+#[test]
+fn differences_are_written_to_stdout_without_an_output() {
+    let fixture = versions(OLD, NEW);
+    let output = fixture.run(&["diff", "--key", "id", "old.csv", "new.csv"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "id,change_type,city_old,city_new\n\
+         2,changed,Rome,Bern\n\
+         4,added,,Kyiv\n\
+         3,removed,Lima,\n"
+    );
+}
+
+#[test]
+fn only_the_given_columns_are_compared() {
+    let fixture = versions(OLD, NEW);
+    fixture.fail(
+        &[
+            "diff",
+            "--key",
+            "id",
+            "--columns",
+            "name",
+            "old.csv",
+            "new.csv",
+            "-o",
+            "diff.csv",
+        ],
+        1,
+    );
+    assert_eq!(
+        fixture.read("diff.csv"),
+        "id,change_type,name_old,name_new\n4,added,,d\n3,removed,c,\n"
+    );
+}
+
+#[test]
+fn identical_versions_exit_with_success() {
+    let fixture = versions(OLD, "id,city,name\n3,Lima,c\n1,Oslo,a\n2,Rome,b\n");
+    let stderr = fixture.join(&[
+        "diff", "--key", "id", "old.csv", "new.csv", "-o", "diff.csv",
+    ]);
+    assert_eq!(stderr, "0 added, 0 removed, 0 changed row(s)\n");
+    assert_eq!(fixture.read("diff.csv"), "id,change_type\n");
+}
+
+#[test]
+fn column_of_only_one_version_is_a_spec_error() {
+    let fixture = versions(OLD, "id,name\n1,a\n");
+    let stderr = fixture.fail(
+        &[
+            "diff",
+            "--key",
+            "id",
+            "--columns",
+            "city",
+            "old.csv",
+            "new.csv",
+        ],
+        2,
+    );
+    assert_eq!(
+        stderr,
+        "Error: --columns: city is not a column of both old.csv and new.csv, besides the key\n"
+    );
+}
+
+#[test]
+fn missing_version_is_an_io_error() {
+    let fixture = versions(OLD, NEW);
+    let stderr = fixture.fail(&["diff", "--key", "id", "old.csv", "gone.csv"], 3);
+    assert!(stderr.starts_with("Error: gone.csv: "), "{}", stderr);
+}
@@ -0,0 +1,80 @@
+//! Sources without data rows, and joins that produce none.
+
+mod common;
+
+use common::fixture;
+
+/// A spec inner joining a primary source to a lookup source, with `policies` before the
+/// sources.
+fn spec(policies: &str) -> String {
+    format!(
+        "output: out.csv
+key: [id]
+{}sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    projections: {{score: score}}
+",
+        policies
+    )
+}
+
+#[test]
+fn join_without_rows_warns_by_default() {
+    let fixture = fixture("id,name\n1,a\n", "id,score\n2,20\n", &spec(""));
+    let stderr = fixture.join(&["join.yaml"]);
+    assert_eq!(stderr, "warning: the join produced no rows (join: inner)\n");
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n");
+}
+
+#[test]
+fn join_without_rows_fails_under_empty_output_error() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n2,20\n",
+        &spec("empty_output: error\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 5);
+    assert_eq!(stderr, "Error: the join produced no rows (join: inner)\n");
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn join_without_rows_is_quiet_under_empty_output_allow() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n2,20\n",
+        &spec("empty_output: allow\n"),
+    );
+    assert_eq!(fixture.join(&["join.yaml"]), "");
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n");
+}
+
+#[test]
+fn source_without_rows_warns_by_default() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n",
+        &spec("empty_output: allow\n"),
+    );
+    let stderr = fixture.join(&["join.yaml"]);
+    assert_eq!(
+        stderr,
+        "lookup.csv: warning: source lookup has no data rows (join: inner)\n"
+    );
+}
+
+#[test]
+fn source_without_rows_fails_under_empty_source_error() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n",
+        &spec("empty_source: error\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert_eq!(
+        stderr,
+        "Error: lookup.csv: source lookup has no data rows (join: inner)\n"
+    );
+}
@@ -0,0 +1,379 @@
+//! How failures are reported, and the exit codes of their categories.
+
+mod common;
+
+use common::{fixture, Fixture};
+use serde_json::{json, Value};
+
+const EXIT_SPEC: i32 = 2;
+const EXIT_IO: i32 = 3;
+const EXIT_DATA: i32 = 4;
+
+/// A primary source whose key repeats.
+const PRIMARY: &str = "id,x\n1,a\n1,b\n";
+const LOOKUP: &str = "id,y\n1,c\n";
+
+/// A spec of `rest` written after `output`.
+fn spec(rest: &str) -> String {
+    format!("output: out.csv\n{}", rest)
+}
+
+/// The first line of the report of a failure.
+fn first_line(stderr: &str) -> &str {
+    stderr.lines().next().unwrap()
+}
+
+#[test]
+fn missing_column_is_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec(
+            "key: [id]\nsources: [{path: primary.csv, projections: {z: z}}, {path: lookup.csv}]\n",
+        ),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_SPEC);
+    assert_eq!(
+        first_line(&stderr),
+        r#"Error: primary.csv: missing column z; headers found: ["id", "x"]"#
+    );
+}
+
+#[test]
+fn missing_key_column_is_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("key: [code]\nsources: [{path: primary.csv}, {path: lookup.csv}]\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_SPEC);
+    assert_eq!(
+        first_line(&stderr),
+        r#"Error: primary.csv: missing column code; headers found: ["id", "x"]"#
+    );
+}
+
+#[test]
+fn missing_key_column_index_is_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("key: [{index: 5}]\nsources: [{path: primary.csv}, {path: lookup.csv}]\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_SPEC);
+    assert_eq!(
+        first_line(&stderr),
+        "Error: primary.csv: key column index 5 is out of range, the file has 2 column(s)"
+    );
+}
+
+#[test]
+fn duplicate_output_column_is_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec(
+            "key: [id]
+sources:
+  - {path: primary.csv, projections: {x: v}}
+  - {path: lookup.csv, projections: {y: v}}
+",
+        ),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_SPEC);
+    assert_eq!(
+        stderr,
+        "Error: output column names are not unique; rename them, or set \
+         allow_duplicate_headers: true:\n  v: source primary projection x, source lookup \
+         projection y\n"
+    );
+}
+
+#[test]
+fn duplicate_key_is_a_data_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec(
+            "key: [id]
+require_unique_base_keys: true
+sources: [{path: primary.csv}, {path: lookup.csv}]
+",
+        ),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_DATA);
+    assert_eq!(
+        first_line(&stderr),
+        "Error: primary.csv: 1 record(s) have the key of an earlier record, but \
+         require_unique_base_keys requires the keys of the primary source to be unique, e.g. \
+         [[\"1\"]]"
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("key: [id]\nsources: [{path: primary.csv}, {path: missing.csv}]\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_IO);
+    assert!(
+        stderr.starts_with("Error: missing.csv: cannot open\n\nCaused by:\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn write_error_is_an_io_error() {
+    use std::fs::File;
+    use std::process::Command;
+
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("key: [id]\nsources: [{path: primary.csv}, {path: lookup.csv}]\n"),
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_join-csv"))
+        .args(["join.yaml", "--output", "-"])
+        .current_dir(&fixture.dir)
+        .env_remove("RUST_BACKTRACE")
+        .stdout(File::create("/dev/full").unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(EXIT_IO));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Error: No space left on device (os error 28)\n"
+    );
+}
+
+#[test]
+fn unparseable_record_is_a_data_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("key: [id]\nsources: [{path: primary.csv}, {path: lookup.csv}]\n"),
+    );
+    fixture.write("lookup.csv", "id,y\n1,c\n2,d,e\n");
+    let stderr = fixture.fail(&["join.yaml"], EXIT_DATA);
+    assert_eq!(
+        first_line(&stderr),
+        "Error: lookup.csv: failed to parse record 2 at line 3, byte 9"
+    );
+}
+
+/// Runs the spec of the fixture with `--error-format json`, which must fail with `code`,
+/// returning the report, which must be the only line written to stderr.
+fn json_error(fixture: &Fixture, code: i32) -> Value {
+    let stderr = fixture.fail(&["join.yaml", "--error-format", "json"], code);
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    serde_json::from_str(&stderr).unwrap()
+}
+
+#[test]
+fn json_report_of_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec(
+            "key: [id]\nsources: [{path: primary.csv, projections: {z: z}}, {path: lookup.csv}]\n",
+        ),
+    );
+    assert_eq!(
+        json_error(&fixture, EXIT_SPEC),
+        json!({
+            "category": "spec",
+            "exit_code": 2,
+            "message": "primary.csv: missing column z; headers found: [\"id\", \"x\"]",
+        })
+    );
+}
+
+#[test]
+fn json_report_of_an_io_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("key: [id]\nsources: [{path: primary.csv}, {path: missing.csv}]\n"),
+    );
+    let report = json_error(&fixture, EXIT_IO);
+    let message = report["message"].as_str().unwrap();
+    assert!(
+        message.starts_with("missing.csv: cannot open: "),
+        "{}",
+        message
+    );
+    assert_eq!(
+        report,
+        json!({ "category": "io", "exit_code": 3, "message": message })
+    );
+}
+
+#[test]
+fn json_report_of_a_record_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec(
+            "key: [id]
+sources:
+  - path: primary.csv
+  - path: lookup.csv
+    projections:
+      y: {name: y, map: {a: A}, unmapped: error}
+",
+        ),
+    );
+    assert_eq!(
+        json_error(&fixture, EXIT_DATA),
+        json!({
+            "category": "data",
+            "exit_code": 4,
+            "message": "lookup.csv: record 1: no entry for \"c\" in the map of projection y",
+            "path": "lookup.csv",
+            "record": 1,
+            "column": "y",
+            "key": ["1"],
+        })
+    );
+}
+
+#[test]
+fn json_report_of_collected_errors() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec(
+            "key: [id]
+error_mode: collect
+empty_keys: error
+sources:
+  - path: primary.csv
+  - path: lookup.csv
+    projections:
+      y: {name: y, map: {a: A}, unmapped: error}
+",
+        ),
+    );
+    fixture.write("primary.csv", "id,x\n1,a\n,b\n");
+    let empty = "primary.csv: record 2: empty key component id";
+    let unmapped = "lookup.csv: record 1: no entry for \"c\" in the map of projection y";
+    assert_eq!(
+        json_error(&fixture, EXIT_DATA),
+        json!({
+            "category": "data",
+            "exit_code": 4,
+            "message": format!("2 error(s):\n  1. {}\n  2. {}", empty, unmapped),
+            "errors": [
+                {
+                    "category": "data",
+                    "exit_code": 4,
+                    "message": empty,
+                    "path": "primary.csv",
+                    "record": 2,
+                    "column": "id",
+                },
+                {
+                    "category": "data",
+                    "exit_code": 4,
+                    "message": unmapped,
+                    "path": "lookup.csv",
+                    "record": 1,
+                    "column": "y",
+                    "key": ["1"],
+                },
+            ],
+        })
+    );
+}
+
+/// A spec collecting the errors of a primary source with blank keys and a lookup source whose
+/// values aren't in the map of its projection, with `rest` after the error options.
+fn collecting_spec(rest: &str) -> String {
+    spec(&format!(
+        "key: [id]
+error_mode: collect
+empty_keys: error
+{}sources:
+  - path: primary.csv
+  - path: lookup.csv
+    projections:
+      y: {{name: y, map: {{a: A}}, unmapped: error}}
+",
+        rest
+    ))
+}
+
+#[test]
+fn collected_errors_of_every_source_are_listed_in_order() {
+    let fixture = fixture(
+        "id,x\n,a\n2,b\n,c\n",
+        "id,y\n1,c\n2,a\n3,d\n",
+        &collecting_spec(""),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_DATA);
+    assert_eq!(
+        stderr,
+        "Error: 4 error(s):\n  \
+         1. primary.csv: record 1: empty key component id\n  \
+         2. primary.csv: record 3: empty key component id\n  \
+         3. lookup.csv: record 1: no entry for \"c\" in the map of projection y\n  \
+         4. lookup.csv: record 3: no entry for \"d\" in the map of projection y\n"
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn collecting_stops_at_max_errors() {
+    let fixture = fixture(
+        "id,x\n,a\n2,b\n,c\n",
+        "id,y\n1,c\n",
+        &collecting_spec("max_errors: 2\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_DATA);
+    assert_eq!(
+        stderr,
+        "Error: 2 error(s), stopping at max_errors:\n  \
+         1. primary.csv: record 1: empty key component id\n  \
+         2. primary.csv: record 3: empty key component id\n"
+    );
+}
+
+#[test]
+fn source_that_cannot_be_read_is_collected() {
+    let fixture = fixture(
+        "id,x\n,a\n",
+        "id,y\n1,a\n",
+        &collecting_spec("").replace("path: lookup.csv", "path: missing.csv"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_DATA);
+    let mut lines = stderr.lines();
+    assert_eq!(lines.next(), Some("Error: 2 error(s):"));
+    assert_eq!(
+        lines.next(),
+        Some("  1. primary.csv: record 1: empty key component id")
+    );
+    assert!(
+        lines.next().unwrap().starts_with("  2. missing.csv: "),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn first_error_fails_the_run_without_collecting() {
+    let fixture = fixture(
+        "id,x\n,a\n,c\n",
+        "id,y\n1,c\n",
+        &collecting_spec("").replace("error_mode: collect", "error_mode: first"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], EXIT_DATA);
+    assert_eq!(
+        stderr,
+        "Error: primary.csv: record 1: empty key component id\n"
+    );
+}
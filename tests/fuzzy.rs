@@ -0,0 +1,110 @@
+//! `fuzzy` matches the keys that a lookup source doesn't have exactly to ones close to them.
+
+mod common;
+
+use common::fixture;
+
+/// A spec left joining the codes of the lookup source to the names, matched by `fuzzy`.
+fn spec(fuzzy: &str) -> String {
+    format!(
+        "output: out.csv
+key: [name]
+fuzzy: {}
+sources:
+  - path: primary.csv
+    projections: {{city: city}}
+  - path: lookup.csv
+    join: left
+    projections: {{code: code}}
+",
+        fuzzy
+    )
+}
+
+#[test]
+fn keys_match_after_normalization() {
+    let fixture = fixture(
+        "name,city\nAcme Inc.,Oslo\n  acme   ltd,Rome\nOther,Lima\n",
+        "name,code\nACME INC,A1\nAcme Ltd,A2\n",
+        &spec("{flag_column: fuzzy}"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "name,city,code,fuzzy\n\
+         Acme Inc.,Oslo,A1,lookup\n  \
+         acme   ltd,Rome,A2,lookup\n\
+         Other,Lima,,\n"
+    );
+}
+
+#[test]
+fn exact_matches_are_not_flagged() {
+    let fixture = fixture(
+        "name,city\nAcme,Oslo\nacme,Rome\n",
+        "name,code\nAcme,A1\n",
+        &spec("{flag_column: fuzzy}"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "name,city,code,fuzzy\nAcme,Oslo,A1,\nacme,Rome,A1,lookup\n"
+    );
+}
+
+#[test]
+fn keys_match_within_the_edit_distance() {
+    let fixture = fixture(
+        "name,city\nAcne,Oslo\nAxne,Rome\n",
+        "name,code\nAcme,A1\n",
+        &spec("{max_distance: 1}"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "name,city,code\nAcne,Oslo,A1\nAxne,Rome,\n"
+    );
+}
+
+#[test]
+fn ambiguous_keys_are_left_unmatched() {
+    let fixture = fixture(
+        "name,city\nacme,Oslo\nAcmf,Rome\n",
+        "name,code\nAcme!,A1\nACME?,A2\nAcmg,A3\n",
+        &spec("{max_distance: 1}"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "name,city,code\nacme,Oslo,\nAcmf,Rome,\n"
+    );
+}
+
+#[test]
+fn audit_records_every_fuzzy_match() {
+    let fixture = fixture(
+        "name,city\nAcme,Oslo\nacme inc,Rome\n",
+        "name,code\nAcme,A1\nAcme Inc,A2\n",
+        &spec("{audit: audit.csv}"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("audit.csv"),
+        "source,name,matched_name\nlookup,acme inc,Acme Inc\n"
+    );
+}
+
+#[test]
+fn fuzzy_matching_of_a_sorted_join_is_a_spec_error() {
+    let fixture = fixture(
+        "name,city\n",
+        "name,code\n",
+        &spec("{max_distance: 1}\nsorted: true"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: sorted: true is not supported with fuzzy matching"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,91 @@
+//! Specs merged from the specs they include.
+
+mod common;
+
+use common::{fixture, Fixture};
+
+/// A spec including `lookup.yaml`, which has the lookup source, with `rest` after the include.
+fn including_fixture(rest: &str) -> Fixture {
+    let fixture = fixture(
+        "id,name\n1,a\n2,b\n",
+        "id,score\n1,10\n",
+        &format!(
+            "include: [lookup.yaml]\noutput: out.csv\nkey: [id]\n{}",
+            rest
+        ),
+    );
+    fixture.write(
+        "lookup.yaml",
+        "sources:
+  - path: lookup.csv
+    name: scores
+    join: left
+    projections: {score: score}
+",
+    );
+    fixture
+}
+
+#[test]
+fn local_sources_come_before_included_ones() {
+    let fixture = including_fixture("sources: [{path: primary.csv, projections: {name: name}}]\n");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n2,b,\n");
+}
+
+#[test]
+fn local_source_replaces_the_included_one_of_its_name() {
+    let fixture = including_fixture(
+        "sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    name: scores
+    projections: {score: points}
+",
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,points\n1,a,10\n");
+}
+
+#[test]
+fn included_sources_follow_the_order_of_the_includes() {
+    let fixture = including_fixture("");
+    fixture.write(
+        "join.yaml",
+        "include: [primary.yaml, lookup.yaml]\noutput: out.csv\nkey: [id]\n",
+    );
+    fixture.write(
+        "primary.yaml",
+        "sources: [{path: primary.csv, projections: {name: name}}]\n",
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n2,b,\n");
+}
+
+#[test]
+fn include_cycle_is_a_spec_error() {
+    let fixture = including_fixture("sources: [{path: primary.csv}]\n");
+    fixture.write("lookup.yaml", "include: [join.yaml]\n");
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: include cycle detected: "),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn unknown_source_field_is_named_by_its_path() {
+    let fixture =
+        including_fixture("sources: [{path: primary.csv}, {path: lookup.csv, jion: left}]\n");
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with(
+            "Error: join.yaml: invalid spec\n\nCaused by:\n    unknown field sources[1].jion; did \
+             you mean join?"
+        ),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,550 @@
+//! How keys are read, and which keys match across sources.
+
+mod common;
+
+use common::{fixture, Fixture};
+
+/// A spec of `rest` written after `output` and `key`.
+fn spec(rest: &str) -> String {
+    format!("output: out.csv\nkey: [id]\n{}", rest)
+}
+
+const BLANK_PRIMARY: &str = "id,name\n1,a\n,b\n";
+const BLANK_LOOKUP: &str = "id,score\n1,10\n  ,20\n";
+const BLANK_SOURCES: &str = "sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    projections: {score: score}
+";
+
+#[test]
+fn blank_keys_match_each_other_by_default() {
+    let fixture = fixture(BLANK_PRIMARY, "id,score\n1,10\n,20\n", &spec(BLANK_SOURCES));
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n,b,20\n");
+}
+
+#[test]
+fn blank_keys_are_an_error_under_empty_keys_error() {
+    let fixture = fixture(
+        BLANK_PRIMARY,
+        BLANK_LOOKUP,
+        &spec(&format!("empty_keys: error\n{}", BLANK_SOURCES)),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with("Error: primary.csv: record 2: empty key component id\n"),
+        "{}",
+        stderr
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn skipped_blank_keys_match_nothing() {
+    let fixture = fixture(
+        BLANK_PRIMARY,
+        BLANK_LOOKUP,
+        &spec(&format!("empty_keys: skip\n{}", BLANK_SOURCES)),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n");
+}
+
+const PADDED_SOURCES: &str = "sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    join: left
+    projections: {score: score}
+";
+
+#[test]
+fn padded_keys_match_only_when_trimmed() {
+    let fixture = fixture(
+        "id,name\nA1,a\n",
+        "id,score\n\" A1\t\",10\n",
+        &spec(PADDED_SOURCES),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\nA1,a,\n");
+
+    fixture.write(
+        "join.yaml",
+        format!(
+            "output: out.csv\nkey: [id]\ntrim_values: true\n{}",
+            PADDED_SOURCES
+        ),
+    );
+    fixture.join(&["join.yaml", "--force"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\nA1,a,10\n");
+}
+
+#[test]
+fn keys_match_when_trimmed_of_trim_chars() {
+    let fixture = fixture(
+        "id,name\nA1,a\n",
+        "id,score\n*A1*,10\n",
+        &spec(&format!(
+            "trim_values: true\ntrim_chars: '*'\n{}",
+            PADDED_SOURCES
+        )),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\nA1,a,10\n");
+}
+
+#[test]
+fn source_trim_values_trims_its_keys_and_projections() {
+    let fixture = fixture(
+        "id,name\nA1,a\n",
+        "id,score\n\" A1 \", 10 \n",
+        &spec(
+            "sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    join: left
+    trim_values: true
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\nA1,a,10\n");
+}
+
+#[test]
+fn skipped_rows_are_not_joined() {
+    let fixture = fixture(
+        "id,name\nTOTAL,2\n1,a\n2,b\n",
+        "id,score\n# exported today\nTOTAL,30\n1,10\n2,20\n",
+        &spec(
+            "sources:
+  - path: primary.csv
+    skip_rows: 1
+    projections: {name: name}
+  - path: lookup.csv
+    skip_rows: 2
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n2,b,20\n");
+}
+
+#[test]
+fn skipped_rows_count_in_record_numbers() {
+    let fixture = fixture(
+        "id,name\nTOTAL,2\n1,a\n,b\n",
+        "id,score\n1,10\n",
+        &spec(
+            "empty_keys: error
+sources:
+  - path: primary.csv
+    skip_rows: 1
+    projections: {name: name}
+  - path: lookup.csv
+    projections: {score: score}
+",
+        ),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with("Error: primary.csv: record 3: empty key component id\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn key_normalization_transforms_apply_in_order() {
+    // Every combination of the transforms, and some of them in another order, with the
+    // primary key as transformed, and whether it matches the lookup key 7ab transformed the
+    // same way.
+    let cases: &[(&str, &str, bool)] = &[
+        ("[]", " 007Ab ", false),
+        ("[trim]", "007Ab", false),
+        ("[lowercase]", " 007ab ", false),
+        ("[uppercase]", " 007AB ", false),
+        ("[strip_leading_zeros]", " 007Ab ", false),
+        ("[trim, lowercase]", "007ab", false),
+        ("[trim, uppercase]", "007AB", false),
+        ("[trim, strip_leading_zeros]", "7Ab", false),
+        ("[lowercase, strip_leading_zeros]", " 007ab ", false),
+        ("[lowercase, uppercase]", " 007AB ", false),
+        ("[uppercase, strip_leading_zeros]", " 007AB ", false),
+        ("[trim, lowercase, uppercase]", "007AB", false),
+        (
+            "[lowercase, uppercase, strip_leading_zeros]",
+            " 007AB ",
+            false,
+        ),
+        (
+            "[trim, lowercase, uppercase, strip_leading_zeros]",
+            "7AB",
+            true,
+        ),
+        ("[trim, lowercase, strip_leading_zeros]", "7ab", true),
+        ("[trim, strip_leading_zeros, lowercase]", "7ab", true),
+        ("[trim, uppercase, strip_leading_zeros]", "7AB", true),
+        ("[strip_leading_zeros, trim, lowercase]", "007ab", false),
+        (
+            "[lowercase, uppercase, trim, strip_leading_zeros]",
+            "7AB",
+            true,
+        ),
+    ];
+    let fixture = fixture("id,name\n 007Ab ,a\n", "id,score\n7ab,10\n", &spec(""));
+    for &(transforms, key, matches) in cases {
+        fixture.write(
+            "join.yaml",
+            format!(
+                "output: out.csv
+key: [id]
+key_normalization: {}
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    projections: {{score: score}}
+",
+                transforms
+            ),
+        );
+        fixture.join(&["join.yaml", "--force"]);
+        let score = if matches { "10" } else { "" };
+        assert_eq!(
+            fixture.read("out.csv"),
+            format!("id,name,score\n{},a,{}\n", key, score),
+            "key_normalization: {}",
+            transforms
+        );
+    }
+}
+
+#[test]
+fn spreadsheet_mangled_keys_match_clean_ones() {
+    let fixture = fixture(
+        "id,name\n0012345,formula\n0012346,apostrophe\n0012347,spaces\n0012348,digits\n\
+         '0012349,primary\n",
+        "id,score\n\"=\"\"0012345\"\"\",10\n'0012346,20\n\u{A0}\u{200B}0012347\u{200D}\u{A0},30\n\
+         \u{FF10}\u{FF10}\u{FF11}\u{FF12}\u{FF13}\u{FF14}\u{FF18},40\n0012349,50\n",
+        &spec(
+            "key_clean: spreadsheet
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    join: left
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score\n0012345,formula,10\n0012346,apostrophe,20\n0012347,spaces,30\n\
+         0012348,digits,40\n0012349,primary,50\n"
+    );
+}
+
+#[test]
+fn spreadsheet_mangled_keys_match_nothing_without_key_clean() {
+    let fixture = fixture(
+        "id,name\n0012345,formula\n0012346,apostrophe\n0012347,spaces\n",
+        "id,score\n\"=\"\"0012345\"\"\",10\n'0012346,20\n\u{A0}0012347\u{200B},30\n",
+        &spec(
+            "sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    join: left
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score\n0012345,formula,\n0012346,apostrophe,\n0012347,spaces,\n"
+    );
+}
+
+/// A fixture keying the same ids, quoted in different ways, in a primary source and two
+/// lookup sources, the padded one with `unquote_padded_keys`.
+fn quoted_fixture(padded: &str, options: &str) -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write(
+        "primary.csv",
+        "id,name\nc d,plain\n\"a,b\",comma\nsay \"hi\",quotes\n",
+    );
+    fixture.write(
+        "quoted.csv",
+        "id,score\n\"c d\",1\n\"a,b\",2\n\"say \"\"hi\"\"\",3\n",
+    );
+    fixture.write("padded.csv", padded);
+    fixture.write(
+        "join.yaml",
+        format!(
+            "output: out.csv
+key: [id]
+{}sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: quoted.csv
+    join: left
+    projections: {{score: score}}
+  - path: padded.csv
+    join: left
+    unquote_padded_keys: true
+    projections: {{rank: rank}}
+",
+            options
+        ),
+    );
+    fixture
+}
+
+#[test]
+fn keys_quoted_differently_match() {
+    let fixture = quoted_fixture(
+        "id,rank\n  \"c d\",x\n\t\"say \"\"hi\"\"\",z\n\"a,b\",y\n",
+        "",
+    );
+    let stderr = fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,rank\nc d,plain,1,x\n\"a,b\",comma,2,y\n\"say \"\"hi\"\"\",quotes,3,z\n"
+    );
+    assert_eq!(
+        stderr,
+        "padded.csv: warning: unquoted 2 key cell(s) with whitespace before the opening quote\n"
+    );
+}
+
+#[test]
+fn padded_keys_are_read_with_their_quotes_by_default() {
+    let fixture = quoted_fixture("id,rank\n  \"c d\",x\n", "");
+    let spec = fixture.read("join.yaml");
+    fixture.write(
+        "join.yaml",
+        spec.replace("    unquote_padded_keys: true\n", ""),
+    );
+    assert_eq!(fixture.join(&["join.yaml"]), "");
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,rank\nc d,plain,1,\n\"a,b\",comma,2,\n\"say \"\"hi\"\"\",quotes,3,\n"
+    );
+}
+
+#[test]
+fn padded_quoted_keys_keep_the_padding_inside_their_quotes() {
+    let fixture = quoted_fixture("id,rank\n \" c d\",x\n", "");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,rank\nc d,plain,1,\n\"a,b\",comma,2,\n\"say \"\"hi\"\"\",quotes,3,\n"
+    );
+}
+
+#[test]
+fn space_after_a_closing_quote_is_trimmed() {
+    let padded = "id,rank\n\"c d\" ,x\n\"say \"\"hi\"\"\"\t,z\n";
+    let fixture = quoted_fixture(padded, "");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,rank\nc d,plain,1,\n\"a,b\",comma,2,\n\"say \"\"hi\"\"\",quotes,3,\n"
+    );
+
+    let fixture = quoted_fixture(padded, "trim_values: true\n");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,rank\nc d,plain,1,x\n\"a,b\",comma,2,\n\"say \"\"hi\"\"\",quotes,3,z\n"
+    );
+}
+
+/// Users joined to the sessions whose name holds their id, captured by a `key_regex` with
+/// `options`.
+fn session_fixture(sessions: &str, options: &str) -> Fixture {
+    fixture(
+        "id,name\n1234,a\n77,b\n",
+        sessions,
+        &spec(&format!(
+            "sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    key_regex: {{id: {{column: session, regex: '^user:(\\d+):'{}}}}}
+    projections: {{started: started}}
+",
+            options
+        )),
+    )
+}
+
+#[test]
+fn key_regex_captures_the_key_from_a_column() {
+    let fixture = session_fixture(
+        "session,started\nuser:1234:web,monday\nuser:5:app,friday\n",
+        "",
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,started\n1234,a,monday\n77,b,\n"
+    );
+}
+
+#[test]
+fn key_regex_group_zero_captures_the_whole_match() {
+    let fixture = session_fixture("session,started\nuser:1234:web,monday\n", ", group: 0");
+    let spec = fixture
+        .read("join.yaml")
+        .replace("'^user:(\\d+):'", "'\\d+'");
+    fixture.write("join.yaml", spec);
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,started\n1234,a,monday\n77,b,\n"
+    );
+}
+
+#[test]
+fn key_regex_that_does_not_match_is_an_error_by_default() {
+    let fixture = session_fixture("session,started\nuser:1234:web,monday\nguest,friday\n", "");
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: record 2: key_regex id does not match \"guest\"; on_no_match may \
+             skip the record or read the component as empty"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn on_no_match_skip_drops_the_record_with_a_warning() {
+    let fixture = session_fixture(
+        "session,started\nuser:1234:web,monday\nguest,friday\n",
+        ", on_no_match: skip",
+    );
+    let stderr = fixture.join(&["join.yaml"]);
+    assert_eq!(
+        stderr,
+        "lookup.csv: skipped 1 record(s) that a key_regex does not match\n"
+    );
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,started\n1234,a,monday\n77,b,\n"
+    );
+}
+
+#[test]
+fn on_no_match_empty_reads_the_component_as_empty() {
+    let fixture = session_fixture("session,started\nguest,friday\n", ", on_no_match: empty");
+    fixture.write("primary.csv", "id,name\n,a\n");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,started\n,a,friday\n");
+}
+
+#[test]
+fn key_regex_without_its_group_is_a_spec_error() {
+    let fixture = session_fixture("session,started\n", ", group: 2");
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "Error: lookup.csv: key_regex id: invalid regex\n\n\
+         Caused by:\n    \
+         the regex has no group 2, only 1 group(s)\n"
+    );
+}
+
+#[test]
+fn key_regex_for_an_unknown_key_column_is_a_spec_error() {
+    let fixture = session_fixture("session,started\n", "");
+    let spec = fixture
+        .read("join.yaml")
+        .replace("key_regex: {id:", "key_regex: {user:");
+    fixture.write("join.yaml", spec);
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "Error: lookup.csv: key_regex for unknown key column user\n"
+    );
+}
+
+/// Sales keyed by region and product, joined to a table of regions keyed by `key`.
+fn region_fixture(key: &str) -> Fixture {
+    fixture(
+        "region,product,units\neu,p1,3\nus,p1,4\neu,p2,5\napac,p1,6\n",
+        "region,manager\neu,ana\nus,bo\n",
+        &format!(
+            "output: out.csv
+key: [region, product]
+sources:
+  - path: primary.csv
+    projections: {{units: units}}
+  - path: lookup.csv
+    join: left
+    key: {}
+    projections: {{manager: manager}}
+",
+            key
+        ),
+    )
+}
+
+#[test]
+fn lookup_keyed_by_some_key_columns_matches_their_values() {
+    let fixture = region_fixture("[region]");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "region,product,units,manager\neu,p1,3,ana\nus,p1,4,bo\neu,p2,5,ana\napac,p1,6,\n"
+    );
+}
+
+#[test]
+fn lookup_key_of_an_unknown_column_is_a_spec_error() {
+    let fixture = region_fixture("[country]");
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "Error: lookup.csv: key has unknown key column country\n"
+    );
+}
+
+#[test]
+fn lookup_key_out_of_order_is_a_spec_error() {
+    let fixture = region_fixture("[product, region]");
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "Error: lookup.csv: key must list key columns once each, in the order of the spec's \
+         key\n"
+    );
+}
+
+#[test]
+fn key_of_the_primary_source_is_a_spec_error() {
+    let fixture = region_fixture("[region]");
+    let spec = fixture.read("join.yaml").replacen(
+        "    projections: {units: units}",
+        "    key: [region]\n    projections: {units: units}",
+        1,
+    );
+    fixture.write("join.yaml", spec);
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "Error: primary.csv: key is only supported on lookup sources\n"
+    );
+}
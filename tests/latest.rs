@@ -0,0 +1,122 @@
+//! `latest_by` keeps the record of each key with the latest value of a column.
+
+mod common;
+
+use common::fixture;
+
+/// A spec left joining the latest status of each key, by `updated_at` with `options`, to the
+/// names.
+fn spec(options: &str) -> String {
+    format!(
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    latest_by: {{column: updated_at{}}}
+    projections: {{status: status}}
+",
+        options
+    )
+}
+
+const PRIMARY: &str = "id,name\n1,a\n2,b\n";
+
+#[test]
+fn record_with_the_latest_date_is_kept() {
+    let fixture = fixture(
+        PRIMARY,
+        "id,status,updated_at\n\
+         1,shipped,2024-03-01T10:00:00Z\n\
+         1,paid,2024-02-01 09:00:00\n\
+         1,returned,2024-03-01T11:00:00+02:00\n\
+         2,paid,2024-01-02\n\
+         2,open,2024-01-01\n",
+        &spec(""),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,status\n1,a,shipped\n2,b,paid\n"
+    );
+}
+
+#[test]
+fn numbers_are_compared_as_numbers() {
+    let fixture = fixture(
+        PRIMARY,
+        "id,status,updated_at\n1,new,9\n1,old,10\n1,older,1e1\n",
+        &spec(""),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,status\n1,a,older\n2,b,\n");
+}
+
+#[test]
+fn invalid_value_is_a_data_error_by_default() {
+    let fixture = fixture(
+        PRIMARY,
+        "id,status,updated_at\n1,paid,2024-01-01\n1,open,yesterday\n",
+        &spec(""),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: record 2: latest_by column updated_at: cannot parse \
+             \"yesterday\" as a number or a date"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn on_invalid_skip_drops_the_record_with_a_warning() {
+    let fixture = fixture(
+        PRIMARY,
+        "id,status,updated_at\n1,paid,2024-01-01\n1,open,yesterday\n2,open,\n",
+        &spec(", on_invalid: skip"),
+    );
+    let stderr = fixture.join(&["join.yaml"]);
+    assert_eq!(
+        stderr,
+        "lookup.csv: skipped 2 record(s) with a latest_by value that is neither a number nor a \
+         date\n"
+    );
+    assert_eq!(fixture.read("out.csv"), "id,name,status\n1,a,paid\n2,b,\n");
+}
+
+#[test]
+fn on_invalid_oldest_keeps_the_record_only_without_a_valid_one() {
+    let fixture = fixture(
+        PRIMARY,
+        "id,status,updated_at\n1,open,yesterday\n1,paid,2024-01-01\n2,open,\n",
+        &spec(", on_invalid: oldest"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,status\n1,a,paid\n2,b,open\n"
+    );
+}
+
+#[test]
+fn latest_by_of_a_spilled_source_is_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        "id,status,updated_at\n",
+        &spec("").replace("    latest_by:", "    spill_after: 10\n    latest_by:"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: latest_by is not supported with primary_passthrough, sorted: \
+             true, range, agg or spill_after"
+        ),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,114 @@
+//! `lazy_projections` reads the projections of a lookup source again for the keys it matches.
+
+mod common;
+
+use common::fixture;
+
+/// A spec left joining the lookup source with `options` to the names.
+fn spec(options: &str) -> String {
+    format!(
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    {}projections: {{score: score, note: note}}
+",
+        options
+    )
+}
+
+const PRIMARY: &str = "id,name\n1,a\n2,b\n3,c\n";
+const LOOKUP: &str = "id,score,note\n\
+    2,20,\"two\nlines\"\n\
+    1,10,\"a, b\"\n\
+    4,40,unmatched\n\
+    1,11,\"\"\"quoted\"\"\"\n";
+
+#[test]
+fn lazy_projections_are_joined_like_others() {
+    let fixture = fixture(PRIMARY, LOOKUP, &spec(""));
+    fixture.join(&["join.yaml", "--output", "eager.csv"]);
+    fixture.write("join.yaml", spec("lazy_projections: true\n    "));
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,note\n1,a,11,\"\"\"quoted\"\"\"\n2,b,20,\"two\nlines\"\n3,c,,\n"
+    );
+    assert_eq!(fixture.read("out.csv"), fixture.read("eager.csv"));
+}
+
+#[test]
+fn lazy_projections_are_read_from_the_file_of_their_record() {
+    let fixture = fixture(
+        PRIMARY,
+        "",
+        &spec("lazy_projections: true\n    ").replace("lookup.csv", "scores*.csv"),
+    );
+    fixture.write("scores1.csv", "id,score,note\n1,10,first\n");
+    fixture.write("scores2.csv", "id,note,score\n3,third,30\n");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score,note\n1,a,10,first\n2,b,,\n3,c,30,third\n"
+    );
+}
+
+#[test]
+fn lazy_projections_of_the_primary_source_are_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("").replacen(
+            "projections: {name: name}",
+            "lazy_projections: true\n    projections: {name: name}",
+            1,
+        ),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with(
+            "Error: primary.csv: lazy_projections is only supported on lookup sources"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn lazy_projections_of_a_spilled_source_are_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("lazy_projections: true\n    spill_after: 10\n    "),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: lazy_projections requires a key, and is not supported with \
+             join: presence or union, sorted: true, range, agg, spill_after or latest_by"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn lazy_projections_of_stdin_are_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec("lazy_projections: true\n    ").replace("path: lookup.csv", "path: '-'"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with(
+            "Error: -: lazy_projections requires local CSV files in UTF-8, to seek in, not \
+             stdin, a URL, a spreadsheet or another encoding"
+        ),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,45 @@
+//! Warnings are tracing events, written as plain lines unless `--log` or `RUST_LOG` says
+//! otherwise.
+
+mod common;
+
+use common::{fixture, Fixture};
+
+const WARNING: &str = "primary.csv: warning: column x appears at positions [1, 2]";
+
+/// A primary source whose column x repeats, joined to a lookup source.
+fn duplicate_column_fixture() -> Fixture {
+    fixture(
+        "id,x,x\n1,a,b\n",
+        "id,y\n1,c\n",
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+  - path: lookup.csv
+    projections: {y: y}
+",
+    )
+}
+
+#[test]
+fn warnings_are_plain_lines_by_default() {
+    let fixture = duplicate_column_fixture();
+    assert_eq!(fixture.join(&["join.yaml"]), format!("{}\n", WARNING));
+}
+
+#[test]
+fn log_level_filters_warnings_and_steps() {
+    let fixture = duplicate_column_fixture();
+    let stderr = fixture.join(&["join.yaml", "--force", "--log", "info"]);
+    let warning = stderr.lines().find(|line| line.ends_with(WARNING)).unwrap();
+    assert!(warning.contains(" WARN "), "{}", warning);
+    for step in ["spec loaded", "source read", "join written"] {
+        assert!(stderr.contains(step), "no {:?} in:\n{}", step, stderr);
+    }
+    assert_eq!(
+        fixture.join(&["join.yaml", "--force", "--log", "error"]),
+        ""
+    );
+    assert_eq!(fixture.read("out.csv"), "id,y\n1,c\n");
+}
@@ -0,0 +1,85 @@
+//! The manifest of a run, written only once the run has succeeded.
+
+mod common;
+
+use common::Fixture;
+use serde_json::Value;
+
+const EXIT_EXPECTATION: i32 = 5;
+
+/// A primary source with scores for some of its keys.
+fn scored_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write("primary.csv", "id,name\n1,a\n2,b\n");
+    fixture.write("scores.csv", "id,score\n1,10\n");
+    fixture.write("join.yaml", spec(""));
+    fixture
+}
+
+/// A spec that left joins the scores to the primary source, with `extra` appended.
+fn spec(extra: &str) -> String {
+    format!(
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: scores.csv
+    join: left
+    projections: {{score: score}}
+{}",
+        extra
+    )
+}
+
+#[test]
+fn manifest_describes_a_successful_run() {
+    let fixture = scored_fixture();
+    fixture.join(&["join.yaml", "--manifest", "run.json"]);
+    let manifest: Value = serde_json::from_str(&fixture.read("run.json")).unwrap();
+    assert_eq!(
+        manifest["outputs"],
+        serde_json::json!([{ "path": "out.csv", "rows": 2 }])
+    );
+    let rows: Vec<_> = manifest["sources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|source| {
+            (
+                source["name"].as_str().unwrap(),
+                source["rows"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(rows, [("primary", 2), ("scores", 1)]);
+    let file = &manifest["sources"][1]["files"][0];
+    assert_eq!(file["size"], fixture.read("scores.csv").len());
+    assert_eq!(file["sha256"].as_str().unwrap().len(), 64);
+    assert!(manifest["started"].is_string());
+    assert!(manifest["finished"].is_string());
+}
+
+#[test]
+fn manifest_is_not_written_by_a_failed_run() {
+    let fixture = scored_fixture();
+    fixture.write("join.yaml", spec("expect_rows: {min: 3}\n"));
+    fixture.fail(&["join.yaml", "--manifest", "run.json"], EXIT_EXPECTATION);
+    assert!(!fixture.exists("run.json"));
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn failed_run_keeps_the_manifest_of_the_last_success() {
+    let fixture = scored_fixture();
+    fixture.join(&["join.yaml", "--manifest", "run.json"]);
+    let (output, manifest) = (fixture.read("out.csv"), fixture.read("run.json"));
+    fixture.write("scores.csv", "id,score\n1,10\n2,20\n");
+    fixture.write("join.yaml", spec("expect_rows: {max: 1}\n"));
+    fixture.fail(
+        &["join.yaml", "--force", "--manifest", "run.json"],
+        EXIT_EXPECTATION,
+    );
+    assert_eq!(fixture.read("out.csv"), output);
+    assert_eq!(fixture.read("run.json"), manifest);
+}
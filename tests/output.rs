@@ -0,0 +1,382 @@
+//! The bytes of csv and fixed output under the output options.
+
+mod common;
+
+use common::{fixture, Fixture};
+
+/// A spec of `options` followed by the output, the key and the sources.
+fn spec(options: &str) -> String {
+    format!(
+        "{}output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    projections: {{score: score}}
+",
+        options
+    )
+}
+
+#[test]
+fn crlf_terminates_every_line() {
+    let fixture = fixture(
+        "id,name\n1,\"two\nlines\"\n2,b\n",
+        "id,score\n1,10\n",
+        &spec("line_terminator: crlf\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read_bytes("out.csv"),
+        b"id,name,score\r\n1,\"two\nlines\",10\r\n2,b,\r\n"
+    );
+}
+
+#[test]
+fn lf_is_the_default_terminator() {
+    let fixture = fixture("id,name\r\n1,a\r\n", "id,score\r\n1,10\r\n", &spec(""));
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read_bytes("out.csv"), b"id,name,score\n1,a,10\n");
+}
+
+#[test]
+fn crlf_terminates_fixed_lines() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,10\n",
+        &spec(
+            "line_terminator: crlf
+output_format: fixed
+fixed_header: true
+widths: {id: 3, name: 5, score: 5}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read_bytes("out.csv"),
+        b"id name score\r\n1  a    10   \r\n"
+    );
+}
+
+#[test]
+fn fixed_values_are_padded_to_the_widths_of_their_columns() {
+    let fixture = fixture(
+        "id,name\n1,a\n22,bb\n",
+        "id,score\n1,10\n",
+        &spec(
+            "output_format: fixed
+null_value: '-'
+widths: {id: {width: 4, align: right, pad: '0'}, name: 3, score: {width: 4, align: right}}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "0001a    10\n0022bb    -\n");
+}
+
+#[test]
+fn fixed_header_is_padded_with_spaces() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,10\n",
+        &spec(
+            "output_format: fixed
+fixed_header: true
+widths: {id: {width: 4, align: right, pad: '0'}, name: 4, score: 5}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "  idnamescore\n0001a   10   \n");
+}
+
+#[test]
+fn fixed_value_wider_than_its_column_is_a_data_error() {
+    let fixture = fixture(
+        "id,name\n1,abcd\n",
+        "id,score\n1,1\n",
+        &spec("output_format: fixed\nwidths: {id: 2, name: 3, score: 2}\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert_eq!(
+        stderr,
+        "Error: fixed output column name: value \"abcd\" has 4 character(s), more than its \
+         width of 3\n"
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn on_overflow_truncate_keeps_the_first_characters() {
+    let fixture = fixture(
+        "id,name\n1,abcd\n",
+        "id,score\n1,1\n",
+        &spec("output_format: fixed\non_overflow: truncate\nwidths: {id: 2, name: 3, score: 2}\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "1 abc1 \n");
+}
+
+#[test]
+fn fixed_column_without_a_width_is_a_spec_error() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,1\n",
+        &spec("output_format: fixed\nwidths: {id: 2, name: 3}\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "Error: fixed output requires a width for every column; score has none\n"
+    );
+}
+
+#[test]
+fn width_of_no_output_column_is_a_spec_error() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,1\n",
+        &spec("output_format: fixed\nwidths: {id: 2, name: 3, score: 2, grade: 1}\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(stderr, "Error: widths: grade is not an output column\n");
+}
+
+#[test]
+fn quote_style_always_quotes_plain_values() {
+    let fixture = fixture(
+        "id,name\n1,a\n2,b\n",
+        "id,score\n1,10\n",
+        &spec("quote_style: always\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "\"id\",\"name\",\"score\"\n\"1\",\"a\",\"10\"\n\"2\",\"b\",\"\"\n"
+    );
+}
+
+#[test]
+fn quote_style_non_numeric_quotes_other_values() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,1.5\n",
+        &spec("quote_style: non_numeric\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "\"id\",\"name\",\"score\"\n1,\"a\",1.5\n"
+    );
+}
+
+#[test]
+fn quote_style_necessary_quotes_only_values_that_need_it() {
+    let fixture = fixture(
+        "id,name\n1,\"a,b\"\n2,\"say \"\"hi\"\"\"\n",
+        "id,score\n1,10\n",
+        &spec(""),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score\n1,\"a,b\",10\n2,\"say \"\"hi\"\"\",\n"
+    );
+}
+
+#[test]
+fn excel_bom_starts_the_output() {
+    let fixture = fixture(
+        "id,name\n1,Zoë\n",
+        "id,score\n1,10\n",
+        &spec("excel_bom: true\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    let bytes = fixture.read_bytes("out.csv");
+    assert_eq!(bytes[..3], [0xEF, 0xBB, 0xBF]);
+    assert_eq!(bytes[3..], *"id,name,score\n1,Zoë,10\n".as_bytes());
+}
+
+#[test]
+fn output_has_no_bom_by_default() {
+    let fixture = fixture("\u{FEFF}id,name\n1,a\n", "id,score\n1,10\n", &spec(""));
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read_bytes("out.csv"), b"id,name,score\n1,a,10\n");
+}
+
+#[test]
+fn excel_bom_is_written_once_when_appending() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,10\n",
+        &spec("excel_bom: true\nappend: true\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read_bytes("out.csv"),
+        b"\xEF\xBB\xBFid,name,score\n1,a,10\n1,a,10\n"
+    );
+}
+
+#[test]
+fn existing_output_is_refused() {
+    let fixture = fixture("id,name\n1,a\n", "id,score\n1,10\n", &spec(""));
+    fixture.write("out.csv", "keep me\n");
+    let stderr = fixture.fail(&["join.yaml"], 3);
+    assert_eq!(
+        stderr,
+        "Error: out.csv: output already exists; pass --force or set overwrite: true to replace \
+         it\n"
+    );
+    assert_eq!(fixture.read("out.csv"), "keep me\n");
+}
+
+#[test]
+fn force_replaces_an_existing_output() {
+    let fixture = fixture("id,name\n1,a\n", "id,score\n1,10\n", &spec(""));
+    fixture.write("out.csv", "old\n");
+    fixture.join(&["join.yaml", "--force"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n");
+}
+
+#[test]
+fn overwrite_replaces_an_existing_output() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,10\n",
+        &spec("overwrite: true\n"),
+    );
+    fixture.write("out.csv", "old\n");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n");
+}
+
+/// A primary source with a value holding a tab and a line break, and a lookup source with a
+/// backslash, joined to tab-separated output with `options`.
+fn tsv_fixture(options: &str) -> Fixture {
+    fixture(
+        "id,name\n1,\"a\tb\nc\"\n2,plain\n",
+        "id,score\n1,C:\\dir\n",
+        &spec(&format!("delimiter: \"\\t\"\n{}", options)),
+    )
+}
+
+#[test]
+fn tsv_values_round_trip_through_a_reader() {
+    let fixture = tsv_fixture("");
+    fixture.join(&["join.yaml"]);
+    let output = fixture.read("out.csv");
+    assert_eq!(
+        output,
+        "id\tname\tscore\n1\t\"a\tb\nc\"\tC:\\dir\n2\tplain\t\n"
+    );
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader(output.as_bytes());
+    let rows: Vec<Vec<String>> = reader
+        .records()
+        .map(|record| record.unwrap().iter().map(str::to_owned).collect())
+        .collect();
+    assert_eq!(rows, [["1", "a\tb\nc", "C:\\dir"], ["2", "plain", ""]]);
+}
+
+#[test]
+fn tsv_values_round_trip_through_join_csv() {
+    let fixture = tsv_fixture("");
+    fixture.join(&["join.yaml"]);
+    fixture.write(
+        "reread.yaml",
+        "output: reread.csv
+key: [id]
+sources:
+  - path: out.csv
+    delimiter: \"\\t\"
+    projections: {name: name, score: score}
+",
+    );
+    fixture.join(&["reread.yaml"]);
+    assert_eq!(
+        fixture.read("reread.csv"),
+        "id,name,score\n1,\"a\tb\nc\",C:\\dir\n2,plain,\n"
+    );
+}
+
+#[test]
+fn escaped_tsv_values_round_trip_through_unescaping() {
+    let fixture = tsv_fixture("quote_style: escape\nnull_value: \\N\n");
+    fixture.join(&["join.yaml"]);
+    let output = fixture.read("out.csv");
+    assert_eq!(
+        output,
+        "id\tname\tscore\n1\ta\\tb\\nc\tC:\\\\dir\n2\tplain\t\\N\n"
+    );
+    // As a TSV reader would, with \N for an absent value.
+    let unescape = |value: &str| {
+        if value == "\\N" {
+            return None;
+        }
+        let mut unescaped = String::new();
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            unescaped.push(match chars.next().unwrap() {
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                escaped => escaped,
+            });
+        }
+        Some(unescaped)
+    };
+    let rows: Vec<Vec<Option<String>>> = output
+        .lines()
+        .skip(1)
+        .map(|line| line.split('\t').map(unescape).collect())
+        .collect();
+    let cells = |cells: [Option<&str>; 3]| cells.map(|cell| cell.map(str::to_owned)).to_vec();
+    assert_eq!(
+        rows,
+        [
+            cells([Some("1"), Some("a\tb\nc"), Some("C:\\dir")]),
+            cells([Some("2"), Some("plain"), None]),
+        ]
+    );
+}
+
+#[test]
+fn listed_outputs_take_the_top_level_options_they_dont_set() {
+    let fixture = fixture(
+        "id,name\n1,a\n2,b\n",
+        "id,score\n1,10\n",
+        "null_value: n/a
+quote_style: always
+output:
+  - path: out.csv
+  - path: dash.csv
+    null_value: '-'
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    join: left
+    projections: {score: score}
+",
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "\"id\",\"name\",\"score\"\n\"1\",\"a\",\"10\"\n\"2\",\"b\",\"n/a\"\n"
+    );
+    assert_eq!(
+        fixture.read("dash.csv"),
+        "\"id\",\"name\",\"score\"\n\"1\",\"a\",\"10\"\n\"2\",\"b\",\"-\"\n"
+    );
+}
@@ -0,0 +1,167 @@
+//! `--parallel-join` and `--threads` write the same bytes as a run on one thread.
+
+mod common;
+
+use std::fmt::Write;
+
+use common::Fixture;
+
+const ROWS: usize = 5000;
+
+/// Writes a primary source whose keys repeat, and two lookup sources that have only some of
+/// them, one left and one inner joined.
+fn generated_fixture(spec: &str) -> Fixture {
+    let fixture = Fixture::new();
+    let mut state = 7u64;
+    let mut next = move |bound: usize| {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) as usize % bound
+    };
+    let mut primary = String::from("id,name,amount\n");
+    for idx in 0..ROWS {
+        writeln!(primary, "{},name{},{}", next(ROWS / 2), idx, next(1000)).unwrap();
+    }
+    let mut labels = String::from("id,label\n");
+    for id in (0..ROWS / 2).step_by(3) {
+        writeln!(labels, "{},label{}", id, next(100)).unwrap();
+    }
+    let mut scores = String::from("id,score\n");
+    for id in (0..ROWS / 2).filter(|id| id % 5 != 0) {
+        writeln!(scores, "{},{}", id, next(100)).unwrap();
+    }
+    fixture.write("primary.csv", primary);
+    fixture.write("labels.csv", labels);
+    fixture.write("scores.csv", scores);
+    fixture.write("join.yaml", spec);
+    fixture
+}
+
+/// Asserts that the spec of the fixture writes the same bytes with `flag` at any thread count.
+fn assert_same_output(fixture: &Fixture, flag: &str) {
+    fixture.join(&["join.yaml", "--output", "single.csv"]);
+    let single = fixture.read_bytes("single.csv");
+    assert!(single.len() > ROWS);
+    for threads in ["1", "2", "4", "7"] {
+        let output = format!("parallel-{}.csv", threads);
+        fixture.join(&["join.yaml", "--output", &output, flag, threads]);
+        assert!(
+            fixture.read_bytes(&output) == single,
+            "{} {} wrote other bytes than a run on one thread",
+            flag,
+            threads
+        );
+    }
+}
+
+#[test]
+fn parallel_join_writes_the_same_bytes() {
+    let fixture = generated_fixture(
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {name: name, amount: amount}
+  - path: labels.csv
+    join: left
+    projections: {label: label}
+  - path: scores.csv
+    projections: {score: score}
+",
+    );
+    assert_same_output(&fixture, "--parallel-join");
+}
+
+#[test]
+fn parallel_join_keeps_the_order_of_passthrough_rows() {
+    let fixture = generated_fixture(
+        "output: out.csv
+key: [id]
+primary_passthrough: true
+sources:
+  - path: primary.csv
+    projections: {name: name, amount: amount}
+  - path: labels.csv
+    join: left
+    projections: {label: label}
+  - path: scores.csv
+    join: left
+    projections: {score: score}
+",
+    );
+    assert_same_output(&fixture, "--parallel-join");
+}
+
+#[test]
+fn sources_read_on_threads_make_the_same_join() {
+    let fixture = generated_fixture(
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {name: name, amount: amount}
+  - path: labels.csv
+    join: left
+    projections: {label: label}
+  - path: scores.csv
+    projections: {score: score}
+",
+    );
+    assert_same_output(&fixture, "--threads");
+}
+
+#[test]
+fn errors_of_sources_read_on_threads_are_reported_in_source_order() {
+    let fixture = generated_fixture(
+        "output: out.csv
+key: [id]
+error_mode: collect
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: labels.csv
+    projections: {grade: grade}
+  - path: scores.csv
+    projections: {points: points}
+",
+    );
+    let stderr = fixture.fail(&["join.yaml", "--threads", "3"], 2);
+    let labels = stderr
+        .find("labels.csv: missing column grade")
+        .expect(&stderr);
+    let scores = stderr
+        .find("scores.csv: missing column points")
+        .expect(&stderr);
+    assert!(labels < scores, "{}", stderr);
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn first_error_of_sources_read_on_threads_is_the_first_in_source_order() {
+    let fixture = generated_fixture(
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: labels.csv
+    projections: {grade: grade}
+  - path: scores.csv
+    projections: {points: points}
+",
+    );
+    let stderr = fixture.fail(&["join.yaml", "--threads", "3"], 2);
+    assert!(
+        stderr.starts_with("Error: labels.csv: missing column grade"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn threads_of_zero_are_refused() {
+    let fixture = generated_fixture("output: out.csv\nkey: [id]\nsources: [{path: primary.csv}]\n");
+    let stderr = fixture.fail(&["join.yaml", "--threads", "0"], 2);
+    assert!(stderr.contains("--threads <N>"), "{}", stderr);
+}
@@ -0,0 +1,106 @@
+//! `max_output_rows` and `max_output_size` roll csv output over into numbered parts.
+
+mod common;
+
+use common::fixture;
+
+/// A spec of `options` followed by the output at `path`, the key and the sources.
+fn spec(path: &str, options: &str) -> String {
+    format!(
+        "{}output: {}
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+",
+        options, path
+    )
+}
+
+const PRIMARY: &str = "id,name\n1,a\n2,b\n3,c\n4,d\n5,e\n";
+
+#[test]
+fn parts_take_max_output_rows_each_with_a_header() {
+    let fixture = fixture(PRIMARY, "", &spec("out.csv", "max_output_rows: 2\n"));
+    fixture.join(&["join.yaml"]);
+    assert!(!fixture.exists("out.csv"));
+    assert_eq!(fixture.read("out.part0001.csv"), "id,name\n1,a\n2,b\n");
+    assert_eq!(fixture.read("out.part0002.csv"), "id,name\n3,c\n4,d\n");
+    assert_eq!(fixture.read("out.part0003.csv"), "id,name\n5,e\n");
+}
+
+#[test]
+fn parts_stay_within_max_output_size() {
+    // The header takes 8 bytes and each row 4, so two rows fit in 16 bytes.
+    let fixture = fixture(PRIMARY, "", &spec("out.csv", "max_output_size: 16\n"));
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.part0001.csv"), "id,name\n1,a\n2,b\n");
+    assert_eq!(fixture.read("out.part0002.csv"), "id,name\n3,c\n4,d\n");
+    assert_eq!(fixture.read("out.part0003.csv"), "id,name\n5,e\n");
+}
+
+#[test]
+fn row_larger_than_max_output_size_takes_a_part_of_its_own() {
+    let fixture = fixture(
+        "id,name\n1,a\n2,bbbbbbbbbbbbbbbb\n3,c\n",
+        "",
+        &spec("out.csv", "max_output_size: 16\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.part0001.csv"), "id,name\n1,a\n");
+    assert_eq!(
+        fixture.read("out.part0002.csv"),
+        "id,name\n2,bbbbbbbbbbbbbbbb\n"
+    );
+    assert_eq!(fixture.read("out.part0003.csv"), "id,name\n3,c\n");
+}
+
+#[test]
+fn output_within_the_limits_is_one_unnumbered_file() {
+    let fixture = fixture(PRIMARY, "", &spec("out.csv", "max_output_rows: 5\n"));
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), PRIMARY);
+    assert!(!fixture.exists("out.part0001.csv"));
+}
+
+#[test]
+fn always_suffix_numbers_a_single_part() {
+    let fixture = fixture(
+        PRIMARY,
+        "",
+        &spec("out.csv", "max_output_rows: 5\nalways_suffix: true\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.part0001.csv"), PRIMARY);
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn part_placeholder_is_replaced_by_the_number() {
+    let fixture = fixture(PRIMARY, "", &spec("out-{part}.csv", "max_output_rows: 3\n"));
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out-0001.csv"), "id,name\n1,a\n2,b\n3,c\n");
+    assert_eq!(fixture.read("out-0002.csv"), "id,name\n4,d\n5,e\n");
+}
+
+#[test]
+fn max_output_rows_of_zero_is_a_spec_error() {
+    let fixture = fixture(PRIMARY, "", &spec("out.csv", "max_output_rows: 0\n"));
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: max_output_rows must be positive"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn existing_part_is_refused() {
+    let fixture = fixture(PRIMARY, "", &spec("out.csv", "max_output_rows: 2\n"));
+    fixture.write("out.part0002.csv", "kept\n");
+    let stderr = fixture.fail(&["join.yaml"], 3);
+    assert!(stderr.contains("out.part0002.csv"), "{}", stderr);
+    assert_eq!(fixture.read("out.part0002.csv"), "kept\n");
+    assert!(!fixture.exists("out.csv"));
+    assert!(!fixture.exists("out.part0001.csv"));
+}
@@ -0,0 +1,92 @@
+//! The `profile` subcommand prints statistics of the columns of a file.
+
+mod common;
+
+use common::Fixture;
+use serde_json::{json, Value};
+
+const PEOPLE: &str = "id,city,note\n1,Oslo,\n2,Rome,x\n2,Oslo,\n3,Oslo,longer\n";
+
+/// Profiles `people.csv` with `args`, which must succeed, returning what it printed.
+fn profile(fixture: &Fixture, args: &[&str]) -> String {
+    let args = [&["profile", "people.csv"][..], args].concat();
+    let output = fixture.run(&args);
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn columns_are_printed_as_a_table() {
+    let fixture = Fixture::new();
+    fixture.write("people.csv", PEOPLE);
+    assert_eq!(
+        profile(&fixture, &["--key", "id"]),
+        "#  column  non-empty  distinct  length  top values\n\
+         0  id      4          3         1..1    \"2\" (2), \"1\" (1), \"3\" (1)\n\
+         1  city    4          2         4..4    \"Oslo\" (3), \"Rome\" (1)\n\
+         2  note    2          2         1..6    \"longer\" (1), \"x\" (1)\n\
+         4 record(s)\n\
+         key id is not unique: 1 value(s) occur in more than one record, 2 record(s) in all\n"
+    );
+}
+
+#[test]
+fn columns_are_printed_as_json() {
+    let fixture = Fixture::new();
+    fixture.write("people.csv", PEOPLE);
+    let profile: Value =
+        serde_json::from_str(&profile(&fixture, &["--key", "id,city", "--json"])).unwrap();
+    assert_eq!(profile["records"], 4);
+    assert_eq!(
+        profile["columns"][2],
+        json!({
+            "name": "note",
+            "position": 2,
+            "non_empty": 2,
+            "distinct": 2,
+            "distinct_capped": false,
+            "min_length": 1,
+            "max_length": 6,
+            "top_values": [{"value": "longer", "count": 1}, {"value": "x", "count": 1}],
+        })
+    );
+    assert_eq!(
+        profile["key"],
+        json!({
+            "columns": ["id", "city"],
+            "unique": true,
+            "duplicate_groups": 0,
+            "duplicate_records": 0,
+        })
+    );
+}
+
+#[test]
+fn missing_key_column_is_a_spec_error() {
+    let fixture = Fixture::new();
+    fixture.write("people.csv", PEOPLE);
+    let stderr = fixture.fail(&["profile", "people.csv", "--key", "code"], 2);
+    assert_eq!(
+        stderr,
+        "Error: people.csv: missing key column code; available columns are: id, city, note\n"
+    );
+}
+
+#[test]
+fn unparseable_record_is_a_data_error() {
+    let fixture = Fixture::new();
+    fixture.write("people.csv", "id,city\n1,Oslo\n2,Rome,extra\n");
+    let stderr = fixture.fail(&["profile", "people.csv"], 4);
+    assert!(
+        stderr.starts_with("Error: people.csv: failed to parse record 2"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let fixture = Fixture::new();
+    let stderr = fixture.fail(&["profile", "people.csv"], 3);
+    assert!(stderr.starts_with("Error: people.csv: "), "{}", stderr);
+}
@@ -0,0 +1,295 @@
+//! What the projections of a source output.
+
+mod common;
+
+use common::{fixture, Fixture};
+
+/// A spec of `rest` written after `output` and `key`.
+fn spec(rest: &str) -> String {
+    format!("output: out.csv\nkey: [id]\n{}", rest)
+}
+
+#[test]
+fn projected_key_column_is_trimmed_like_the_key() {
+    let fixture = fixture(
+        "id,name\n\" A1 \",a\n",
+        "id,score\nA1,10\n",
+        &spec(
+            "trim_values: true
+sources:
+  - path: primary.csv
+    projections: {id: original_id, name: name}
+  - path: lookup.csv
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,original_id,name,score\nA1,A1,a,10\n"
+    );
+}
+
+#[test]
+fn projected_key_column_of_a_trimmed_source_is_trimmed() {
+    let fixture = fixture(
+        "id,name\n\" A1 \",a\n",
+        "id,score\nA1,10\n",
+        &spec(
+            "sources:
+  - path: primary.csv
+    trim_values: true
+    projections: {id: original_id, name: name}
+  - path: lookup.csv
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,original_id,name,score\nA1,A1,a,10\n"
+    );
+}
+
+#[test]
+fn projected_key_column_is_trimmed_but_not_normalized() {
+    let fixture = fixture(
+        "id,name\n\" Ab-007 \",a\n",
+        "id,score\nab-007,10\n",
+        &spec(
+            "trim_values: true
+trim_chars: \" -\"
+key_normalization: [trim, lowercase]
+sources:
+  - path: primary.csv
+    projections: {id: original_id}
+  - path: lookup.csv
+    projections: {score: score, id: lookup_id}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,original_id,score,lookup_id\nab-007,Ab-007,10,ab-007\n"
+    );
+}
+
+#[test]
+fn projected_key_column_keeps_its_leading_zeros() {
+    let fixture = fixture(
+        "id,name\n007,a\n",
+        "id,score\n7,10\n",
+        &spec(
+            "key_normalization: [strip_leading_zeros]
+sources:
+  - path: primary.csv
+    projections: {id: original_id}
+  - path: lookup.csv
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,original_id,score\n7,007,10\n");
+}
+
+#[test]
+fn key_column_is_projected_next_to_the_key() {
+    let fixture = fixture(
+        "id,name\n007,a\n8,b\n",
+        "id,score\n7,10\n08,20\n",
+        &spec(
+            "key_types: [int]
+sources:
+  - path: primary.csv
+    projections: {id: original_id, name: name}
+  - path: lookup.csv
+    projections: {id: lookup_id, score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,original_id,name,lookup_id,score\n7,007,a,7,10\n8,8,b,08,20\n"
+    );
+}
+
+#[test]
+fn key_column_projected_under_the_key_name_is_a_conflict() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,10\n",
+        &spec("sources: [{path: primary.csv, projections: {id: id}}, {path: lookup.csv}]\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "primary.csv: warning: column id is read as key column id and also projected as id, so \
+         its values are output twice; set allow_key_projections: true if intended\n\
+         Error: output column names are not unique; rename them, or set \
+         allow_duplicate_headers: true:\n  id: key column 1, source primary projection id\n"
+    );
+}
+
+#[test]
+fn empty_cells_take_if_empty_and_absent_rows_take_the_default() {
+    let fixture = fixture(
+        "id\n1\n2\n3\n",
+        "id,score,grade\n1,,\n2,20,B\n",
+        &spec(
+            "null_value: n/a
+sources:
+  - path: primary.csv
+  - path: lookup.csv
+    join: left
+    projections:
+      score: {name: score, if_empty: '0', default: none}
+      grade: {name: grade, if_empty: '-'}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,score,grade\n1,0,-\n2,20,B\n3,none,n/a\n"
+    );
+}
+
+#[test]
+fn empty_cells_are_kept_without_if_empty() {
+    let fixture = fixture(
+        "id\n1\n2\n",
+        "id,score\n1,\n",
+        &spec(
+            "null_value: n/a
+sources:
+  - path: primary.csv
+  - path: lookup.csv
+    join: left
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,score\n1,\n2,n/a\n");
+}
+
+/// A fixture mapping the status codes of the lookup source, with `unmapped` for the codes the
+/// map doesn't have.
+fn mapped_fixture(unmapped: &str) -> Fixture {
+    fixture(
+        "id\n1\n2\n3\n",
+        "id,status\n1,A\n2,X\n3,\n",
+        &spec(&format!(
+            "sources:
+  - path: primary.csv
+  - path: lookup.csv
+    projections:
+      status: {{name: status, map: {{A: active, '': blank}}, unmapped: {}}}
+",
+            unmapped
+        )),
+    )
+}
+
+#[test]
+fn unmapped_keep_outputs_the_value() {
+    let fixture = mapped_fixture("keep");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,status\n1,active\n2,X\n3,blank\n"
+    );
+}
+
+#[test]
+fn unmapped_empty_outputs_an_empty_value() {
+    let fixture = mapped_fixture("empty");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,status\n1,active\n2,\n3,blank\n"
+    );
+}
+
+#[test]
+fn unmapped_error_fails_the_run() {
+    let fixture = mapped_fixture("error");
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: record 2: no entry for \"X\" in the map of projection status"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn unmapped_literal_outputs_the_literal() {
+    let fixture = mapped_fixture("unknown");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,status\n1,active\n2,unknown\n3,blank\n"
+    );
+}
+
+/// A fixture projecting the dates of the lookup source as `date` values in `format`.
+fn dated_fixture(dates: &str, format: &str) -> Fixture {
+    fixture(
+        "id\n1\n2\n",
+        &format!("id,since\n{}", dates),
+        &spec(&format!(
+            "sources:
+  - path: primary.csv
+  - path: lookup.csv
+    projections:
+      since: {{name: since, type: date{}}}
+",
+            format
+        )),
+    )
+}
+
+#[test]
+fn dates_in_their_format_are_output_as_iso_8601() {
+    let fixture = dated_fixture("1,03/02/2024\n2,31/12/1999\n", ", format: '%d/%m/%Y'");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,since\n1,2024-02-03\n2,1999-12-31\n"
+    );
+}
+
+#[test]
+fn dates_are_iso_8601_without_a_format() {
+    let fixture = dated_fixture("1,2024-02-03\n2,03/02/2024\n", "");
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: record 2: projection since: cannot parse \"03/02/2024\" as date\n"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn dates_not_in_their_format_name_it() {
+    let fixture = dated_fixture("1,2024-02-03\n2,03/02/2024\n", ", format: '%d/%m/%Y'");
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: record 1: projection since: cannot parse \"2024-02-03\" as date \
+             (%d/%m/%Y)\n"
+        ),
+        "{}",
+        stderr
+    );
+}
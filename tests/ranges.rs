@@ -0,0 +1,134 @@
+//! `range` matches a key component against the intervals of the rows of a lookup source.
+
+mod common;
+
+use common::fixture;
+
+/// Orders of customers on dates, and the rates in effect for each customer from one date to
+/// another, with `options` on the range.
+fn spec(options: &str) -> String {
+    format!(
+        "output: out.csv
+key: [customer, date]
+sources:
+  - path: primary.csv
+    projections: {{amount: amount}}
+  - path: lookup.csv
+    join: left
+    range: {{key: date, from: start, to: end{}}}
+    projections: {{rate: rate}}
+",
+        options
+    )
+}
+
+const ORDERS: &str = "customer,date,amount\n\
+    a,2024-01-15,10\n\
+    a,2024-03-01,20\n\
+    b,2024-01-15,30\n\
+    b,2023-12-31,40\n";
+
+#[test]
+fn rows_match_the_range_that_contains_their_value() {
+    let fixture = fixture(
+        ORDERS,
+        "customer,start,end,rate\n\
+         a,2024-01-01,2024-01-31,1.5\n\
+         a,2024-02-01,,2\n\
+         b,2024-01-01,2024-06-30,3\n",
+        &spec(""),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "customer,date,amount,rate\n\
+         a,2024-01-15,10,1.5\n\
+         a,2024-03-01,20,2\n\
+         b,2024-01-15,30,3\n\
+         b,2023-12-31,40,\n"
+    );
+}
+
+#[test]
+fn empty_lower_bound_leaves_the_range_open() {
+    let fixture = fixture(
+        ORDERS,
+        "customer,start,end,rate\na,,2024-01-31,1\nb,,,2\n",
+        &spec(""),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "customer,date,amount,rate\n\
+         a,2024-01-15,10,1\n\
+         a,2024-03-01,20,\n\
+         b,2024-01-15,30,2\n\
+         b,2023-12-31,40,2\n"
+    );
+}
+
+#[test]
+fn int_ranges_are_ordered_numerically() {
+    let fixture = fixture(
+        "customer,date,amount\na,9,1\na,10,2\na,100,3\n",
+        "customer,start,end,rate\na,1,9,low\na,10,99,mid\n",
+        &spec("").replace(
+            "key: [customer, date]",
+            "key: [customer, date]\nkey_types: [string, int]",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "customer,date,amount,rate\na,9,1,low\na,10,2,mid\na,100,3,\n"
+    );
+}
+
+#[test]
+fn overlapping_ranges_are_a_data_error() {
+    let fixture = fixture(
+        ORDERS,
+        "customer,start,end,rate\na,2024-01-01,2024-02-15,1\na,2024-02-01,,2\n",
+        &spec(""),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: the ranges of keys [\"a\", \"2024-01-01\", \"2024-02-15\"] and \
+             [\"a\", \"2024-02-01\", \"\"] overlap"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn on_overlap_first_matches_the_row_read_first() {
+    let fixture = fixture(
+        ORDERS,
+        "customer,start,end,rate\na,2024-01-01,2024-02-15,1\na,2024-01-10,,2\n",
+        &spec(", on_overlap: first"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "customer,date,amount,rate\n\
+         a,2024-01-15,10,1\n\
+         a,2024-03-01,20,2\n\
+         b,2024-01-15,30,\n\
+         b,2023-12-31,40,\n"
+    );
+}
+
+#[test]
+fn range_of_a_column_outside_the_key_is_a_spec_error() {
+    let fixture = fixture(
+        ORDERS,
+        "customer,start,end,rate\n",
+        &spec("").replace("range: {key: date", "range: {key: day"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(stderr.starts_with("Error: "), "{}", stderr);
+    assert!(stderr.contains("day"), "{}", stderr);
+}
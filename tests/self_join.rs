@@ -0,0 +1,71 @@
+//! Joining a file to itself, as employees to their managers.
+
+mod common;
+
+use common::Fixture;
+
+const EMPLOYEES: &str = "id,name,manager_id
+1,Ada,
+2,Grace,1
+3,Linus,1
+4,Ken,2
+5,Dennis,9
+";
+
+fn employees_fixture(spec: &str) -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write("employees.csv", EMPLOYEES);
+    fixture.write("join.yaml", spec);
+    fixture
+}
+
+#[test]
+fn employees_are_joined_to_their_managers() {
+    let fixture = employees_fixture(
+        "output: out.csv
+key: [id]
+sources:
+  - path: employees.csv
+    name: employees
+    projections: {name: name, manager_id: manager_id}
+  - path: employees.csv
+    name: managers
+    join: left
+    match_on: [manager_id]
+    projections: {name: manager_name}
+",
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,manager_id,manager_name
+1,Ada,,
+2,Grace,1,Ada
+3,Linus,1,Ada
+4,Ken,2,Grace
+5,Dennis,9,
+"
+    );
+}
+
+#[test]
+fn a_file_listed_twice_needs_distinct_names() {
+    let fixture = employees_fixture(
+        "output: out.csv
+key: [id]
+sources:
+  - path: employees.csv
+  - path: employees.csv
+    join: left
+    match_on: [manager_id]
+    projections: {name: manager_name}
+",
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert_eq!(
+        stderr,
+        "Error: join.yaml: sources[0] and sources[1] are both named employees; set a distinct \
+         name on one of them\n"
+    );
+    assert!(!fixture.exists("out.csv"));
+}
@@ -0,0 +1,84 @@
+//! `sorted: true` merges sources sorted by the key as they are read.
+
+mod common;
+
+use common::fixture;
+
+/// A spec merging the scores into the names of the primary source, with `options`.
+fn spec(options: &str) -> String {
+    format!(
+        "output: out.csv
+key: [id]
+sorted: true
+{}sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    projections: {{score: score}}
+",
+        options
+    )
+}
+
+#[test]
+fn sorted_sources_are_merged_by_key() {
+    let fixture = fixture(
+        "id,name\n1,a\n3,c\n4,d\n",
+        "id,score\n0,0\n1,10\n2,20\n4,40\n",
+        &spec(""),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score\n1,a,10\n3,c,\n4,d,40\n"
+    );
+}
+
+#[test]
+fn int_keys_are_sorted_numerically() {
+    let fixture = fixture(
+        "id,name\n2,b\n10,j\n",
+        "id,score\n2,20\n10,100\n",
+        &spec("key_types: [int]\n"),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n2,b,20\n10,j,100\n");
+}
+
+#[test]
+fn last_record_of_a_repeated_key_is_kept() {
+    let fixture = fixture(
+        "id,name\n1,a\n2,b\n",
+        "id,score\n1,10\n1,11\n2,20\n",
+        &spec(""),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,11\n2,b,20\n");
+}
+
+#[test]
+fn source_out_of_order_is_a_data_error() {
+    let fixture = fixture("id,name\n1,a\n2,b\n", "id,score\n2,20\n1,10\n", &spec(""));
+    let stderr = fixture.fail(&["join.yaml"], 4);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: record 2: key [\"1\"] sorts before the previous key [\"2\"], but \
+             sorted: true requires every source to be sorted by the key"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!fixture.exists("out.csv"));
+}
+
+#[test]
+fn sorted_presence_join_is_a_spec_error() {
+    let fixture = fixture("id\n1\n", "id\n1\n", &spec("join: presence\n"));
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: sorted: true is not supported with join: presence"),
+        "{}",
+        stderr
+    );
+}
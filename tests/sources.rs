@@ -0,0 +1,94 @@
+//! Which files a source path names, and how they are read.
+
+mod common;
+
+use common::{fixture, Fixture};
+
+/// A primary source left joined to the scores of `data[12].csv`.
+fn scores_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write("primary.csv", "id,name\n1,a\n2,b\n");
+    fixture.write("data1.csv", "id,score\n1,10\n");
+    fixture.write("data2.csv", "id,score\n2,20\n");
+    fixture.write(
+        "join.yaml",
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: data[12].csv
+    join: left
+    projections: {score: score}
+",
+    );
+    fixture
+}
+
+#[test]
+fn glob_is_expanded_when_no_file_has_its_name() {
+    let fixture = scores_fixture();
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n2,b,20\n");
+}
+
+#[test]
+fn existing_file_with_glob_characters_is_read_as_is() {
+    let fixture = scores_fixture();
+    fixture.write("data[12].csv", "id,score\n2,30\n");
+    fixture.join(&["join.yaml"]);
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,\n2,b,30\n");
+}
+
+/// A primary source left joined to the scores of `lookup.csv`, both read through a memory
+/// mapping.
+fn mapped_fixture(scores: &str) -> Fixture {
+    fixture(
+        "id,name\n1,a\n2,b\n",
+        scores,
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    mmap: true
+    projections: {name: name}
+  - path: lookup.csv
+    mmap: true
+    join: left
+    projections: {score: score}
+",
+    )
+}
+
+#[test]
+fn mapped_files_are_read_like_others() {
+    let fixture = mapped_fixture("id,score\n2,20\n1,10\n");
+    assert_eq!(fixture.join(&["join.yaml"]), "");
+    assert_eq!(fixture.read("out.csv"), "id,name,score\n1,a,10\n2,b,20\n");
+}
+
+#[test]
+fn mapped_empty_file_is_a_missing_column() {
+    let fixture = mapped_fixture("");
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: lookup.csv: missing column id; headers found: []"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn mmap_of_stdin_is_a_spec_error() {
+    let fixture = mapped_fixture("id,score\n1,10\n");
+    let spec = fixture
+        .read("join.yaml")
+        .replacen("path: primary.csv", "path: '-'", 1);
+    fixture.write("join.yaml", spec);
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: -: mmap only applies to local files, not to stdin or URLs"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,68 @@
+//! `spill_after` moves a lookup source to a table on disk once it has too many keys.
+
+mod common;
+
+use common::fixture;
+
+/// A spec left joining the scores, spilled after `spill_after` keys, to the names.
+fn spec(spill_after: usize) -> String {
+    format!(
+        "output: out.csv
+key: [id]
+sources:
+  - path: primary.csv
+    projections: {{name: name}}
+  - path: lookup.csv
+    join: left
+    spill_after: {}
+    projections: {{score: score, grade: grade}}
+",
+        spill_after
+    )
+}
+
+const PRIMARY: &str = "id,name\n1,a\n2,b\n3,c\n4,d\n";
+const LOOKUP: &str = "id,score,grade\n3,30,C\n1,10,A\n2,20,\n1,11,B\n";
+const JOINED: &str = "id,name,score,grade\n1,a,11,B\n2,b,20,\n3,c,30,C\n4,d,,\n";
+
+#[test]
+fn spilled_source_is_joined_like_one_in_memory() {
+    let fixture = fixture(PRIMARY, LOOKUP, &spec(100));
+    fixture.join(&["join.yaml", "--output", "memory.csv"]);
+    assert_eq!(fixture.read("memory.csv"), JOINED);
+    fixture.write("join.yaml", spec(1));
+    let stderr = fixture.join(&["join.yaml", "-v"]);
+    assert!(
+        stderr.contains("lookup.csv: spilled to disk after 2 distinct key(s)"),
+        "{}",
+        stderr
+    );
+    assert_eq!(fixture.read("out.csv"), JOINED);
+}
+
+#[test]
+fn source_within_spill_after_stays_in_memory() {
+    let fixture = fixture(PRIMARY, LOOKUP, &spec(3));
+    let stderr = fixture.join(&["join.yaml", "-v"]);
+    assert!(!stderr.contains("spilled"), "{}", stderr);
+    assert_eq!(fixture.read("out.csv"), JOINED);
+}
+
+#[test]
+fn spill_after_on_the_primary_source_is_a_spec_error() {
+    let fixture = fixture(
+        PRIMARY,
+        LOOKUP,
+        &spec(1).replacen(
+            "projections: {name: name}",
+            "spill_after: 1\n    projections: {name: name}",
+            1,
+        ),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: primary.csv: spill_after is only supported on lookup sources"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,115 @@
+//! `join: union` stacks the rows of every source instead of joining them.
+
+mod common;
+
+use common::fixture;
+
+/// A spec stacking the primary and lookup sources, with `rest` after the join.
+fn spec(rest: &str) -> String {
+    format!(
+        "output: out.csv
+key: [id]
+join: union
+{}",
+        rest
+    )
+}
+
+#[test]
+fn rows_of_every_source_are_stacked_in_spec_order() {
+    let fixture = fixture(
+        "id,name,city\n2,b,Oslo\n1,a,Rome\n",
+        "id,name,score\n1,x,10\n3,z,30\n",
+        &spec(
+            "sources:
+  - path: primary.csv
+    projections: {name: name, city: city}
+  - path: lookup.csv
+    projections: {name: name, score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,city,score\n2,b,Oslo,\n1,a,Rome,\n1,x,,10\n3,z,,30\n"
+    );
+}
+
+#[test]
+fn columns_a_source_doesnt_project_take_the_null_value() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "id,score\n1,10\n",
+        &spec(
+            "null_value: n/a
+sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    projections: {score: score}
+",
+        ),
+    );
+    fixture.join(&["join.yaml"]);
+    assert_eq!(
+        fixture.read("out.csv"),
+        "id,name,score\n1,a,n/a\n1,n/a,10\n"
+    );
+}
+
+#[test]
+fn union_of_sorted_sources_is_a_spec_error() {
+    let fixture = fixture(
+        "id\n1\n",
+        "id\n1\n",
+        &spec("sorted: true\nsources: [{path: primary.csv}, {path: lookup.csv}]\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: sorted: true is not supported with join: union"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn join_of_a_source_of_a_union_is_a_spec_error() {
+    let fixture = fixture(
+        "id\n1\n",
+        "id\n1\n",
+        &spec("sources: [{path: primary.csv}, {path: lookup.csv, join: left}]\n"),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with(
+            "Error: lookup.csv: join, passthrough, emit_match_flags, emit_provenance, range, \
+             match_on, key, agg, spill_after and latest_by are not supported with join: union"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn source_of_a_union_without_a_key_column_is_a_spec_error() {
+    let fixture = fixture(
+        "id,name\n1,a\n",
+        "code,name\n1,x\n",
+        &spec(
+            "sources:
+  - path: primary.csv
+    projections: {name: name}
+  - path: lookup.csv
+    projections: {name: name}
+",
+        ),
+    );
+    let stderr = fixture.fail(&["join.yaml"], 2);
+    assert!(
+        stderr.starts_with("Error: lookup.csv: missing column id"),
+        "{}",
+        stderr
+    );
+    assert!(!fixture.exists("out.csv"));
+}